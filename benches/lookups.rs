@@ -65,6 +65,45 @@ fn benchmark_single_lookup_ipv4(c: &mut Criterion) {
     group.finish();
 }
 
+fn benchmark_embedded_lookup_ipv4(c: &mut Criterion) {
+    let ips = generate_random_ipv4(1000, 7);
+
+    let mut group = c.benchmark_group("embedded_lookup_ipv4");
+    group.throughput(Throughput::Elements(ips.len() as u64));
+    group.bench_function("random_1000", |b| {
+        b.iter(|| {
+            for ip in &ips {
+                black_box(ip_alloc_lookup::embedded::lookup_v4(*ip));
+            }
+        });
+    });
+    group.finish();
+}
+
+fn benchmark_direct_index_ipv4(c: &mut Criterion) {
+    let db = GeoIpDb::new();
+    let index = db.direct_index_v4();
+    let ips = generate_random_ipv4(1000, 7);
+
+    let mut group = c.benchmark_group("direct_index_ipv4");
+    group.throughput(Throughput::Elements(ips.len() as u64));
+    group.bench_function("binary_search_1000", |b| {
+        b.iter(|| {
+            for ip in &ips {
+                black_box(db.lookup_v4(*ip));
+            }
+        });
+    });
+    group.bench_function("direct_index_1000", |b| {
+        b.iter(|| {
+            for ip in &ips {
+                black_box(index.lookup(*ip));
+            }
+        });
+    });
+    group.finish();
+}
+
 fn benchmark_single_lookup_ipv6(c: &mut Criterion) {
     let db = GeoIpDb::new();
     
@@ -214,6 +253,43 @@ fn benchmark_batch_lookups_mixed(c: &mut Criterion) {
     group.finish();
 }
 
+fn benchmark_lookup_many_sorted(c: &mut Criterion) {
+    let db = GeoIpDb::new();
+    let mut ips = generate_mixed_ips(10_000, 0xDEADBEEF);
+    ips.sort();
+    
+    let mut group = c.benchmark_group("lookup_many_sorted");
+    group.throughput(Throughput::Elements(ips.len() as u64));
+    
+    group.bench_function("lookup_many_10000", |b| {
+        b.iter(|| black_box(db.lookup_many(&ips)))
+    });
+    
+    group.bench_function("lookup_many_sorted_10000", |b| {
+        b.iter(|| black_box(db.lookup_many_sorted(&ips)))
+    });
+    
+    // A log's worth of clients clustered in few networks
+    let mut rng = StdRng::seed_from_u64(0xC0FFEE);
+    let mut clustered: Vec<IpAddr> = (0..10_000)
+        .map(|_| {
+            let base = [0x2E04_0000u32, 0x5050_0000, 0x9165_0000][rng.gen_range(0..3)];
+            IpAddr::V4(Ipv4Addr::from(base + rng.gen_range(0..0x1_0000)))
+        })
+        .collect();
+    clustered.sort();
+    
+    group.bench_function("clustered_lookup_many_10000", |b| {
+        b.iter(|| black_box(db.lookup_many(&clustered)))
+    });
+    
+    group.bench_function("clustered_lookup_many_sorted_10000", |b| {
+        b.iter(|| black_box(db.lookup_many_sorted(&clustered)))
+    });
+    
+    group.finish();
+}
+
 fn benchmark_worst_case_ipv4(c: &mut Criterion) {
     let db = GeoIpDb::new();
     let ips = generate_random_ipv4(5_000, 0xBADCAFE);
@@ -269,6 +345,18 @@ fn benchmark_cache_performance(c: &mut Criterion) {
         })
     });
     
+    group.bench_function("hot_ipv4_cursor", |b| {
+        b.iter(|| {
+            let mut cursor = db.cursor();
+            for _ in 0..100 {
+                for ip in &hot_ipv4 {
+                    let result = cursor.lookup(IpAddr::V4(*ip));
+                    black_box(result);
+                }
+            }
+        })
+    });
+    
     group.bench_function("hot_ipv6_repeated", |b| {
         b.iter(|| {
             for _ in 0..100 {
@@ -298,12 +386,15 @@ criterion_group!(
     benches,
     benchmark_db_creation,
     benchmark_single_lookup_ipv4,
+    benchmark_embedded_lookup_ipv4,
+    benchmark_direct_index_ipv4,
     benchmark_single_lookup_ipv6,
     benchmark_unified_lookup,
     benchmark_is_eu_method,
     benchmark_batch_lookups_ipv4,
     benchmark_batch_lookups_ipv6,
     benchmark_batch_lookups_mixed,
+    benchmark_lookup_many_sorted,
     benchmark_worst_case_ipv4,
     benchmark_worst_case_ipv6,
     benchmark_cache_performance,
@@ -1,9 +1,17 @@
 //! Build-time generation of embedded RIPE IP allocation tables.
 //!
-//! This build script parses a RIPE NCC delegated statistics file and converts it
+//! This build script parses a RIPE NCC delegated statistics file (`ripe-data.txt`,
+//! or a gzip/zstd-compressed `ripe-data.txt.gz` / `ripe-data.txt.zst`) and converts it
 //! into compact, sorted Rust data structures that are embedded into the final
 //! binary.
 //!
+//! If a `data/` directory exists (or the directory named by `IP_ALLOC_DATA_DIR`),
+//! all files in it are merged instead, e.g. the delegated files of all five RIRs.
+//! Files named `overrides*` are applied last and replace the blocks with the
+//! same start address. The `embed-<registry>` features (`embed-ripencc`,
+//! `embed-arin`, ...) restrict the embedded records to those registries; with
+//! none of them enabled, every record is embedded.
+//!
 //! ## Why a build script?
 //!
 //! - Avoids parsing large text files at runtime
@@ -16,8 +24,26 @@
 //!
 //! - A sorted IPv4 range table using `u32` addresses
 //! - A sorted IPv6 range table using `u128` addresses
+//! - The space listed by `available` and `reserved` records, as merged
+//!   `start, end` spans per address family
+//! - The source file's version/header line (registry, serial, dates)
+//! - The EU membership list and the country-to-region map read from
+//!   `country-policy.txt` (or the file named by `IP_ALLOC_POLICY_FILE`)
+//!
+//! The range tables are packed little-endian binary files included with
+//! `include_bytes!` and read in place by `src/embedded.rs`.
 //!
 //! These tables are later included by the library and used for binary search.
+//! The output is a pure function of the inputs (no timestamps, canonical
+//! ordering), so builds are reproducible, and the SHA-256 of the snapshot is
+//! embedded to record which data a binary was built from.
+//! Setting `IP_ALLOC_EMBED_COUNTRIES` (e.g. `EU` or `DE,AT,CH`) embeds only the
+//! ranges of the listed countries and regions.
+//! Invalid records, duplicated lines and overlapping blocks fail the build with
+//! a report naming the offending lines, as lookups would silently return wrong
+//! answers for them.
+//! When the `embedded-data` feature is disabled, the range tables are empty and
+//! `ripe-data.txt` is not read; the country policy tables are always generated.
 //!
 //! ## IPv6 handling
 //!
@@ -25,31 +51,77 @@
 //! these prefixes are expanded into inclusive `[start, end]` ranges to allow
 //! direct numeric comparison at runtime.
 
+use std::collections::HashSet;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
 
 /// Build script: parses `ripe-data.txt` and emits `generated_data.rs` into `OUT_DIR`.
 ///
-/// The generated file contains two sorted tables:
-/// - `IPV4_RANGES: &[(u32, u32, &str)]`
-/// - `IPV6_RANGES: &[(u128, u128, &str)]`
+/// The range tables are written as packed binary files next to it
+/// (`ipv4_ranges.bin`, `ipv6_ranges.bin`), one little-endian
+/// `start, end, country: [u8; 2], assigned: u8` entry per range, sorted by
+/// start. An empty country code is stored as two zero bytes. Compiling a byte
+/// blob is far cheaper for rustc than hundreds of thousands of tuple literals.
+///
+/// The generated file contains:
+/// - `SOURCE_HEADER: &str`
+/// - `SOURCE_SHA256: &str`, the lowercase hex SHA-256 of the (decompressed) snapshot
+/// - `SOURCE_FORMAT: &str`, `"standard"`, `"extended"` or empty if unknown
+/// - `IPV4_RANGES_BLOB: &[u8]` and `IPV6_RANGES_BLOB: &[u8]` (via `include_bytes!`)
+/// - `IPV4_INDEX_BLOB: &[u8]`, 65537 little-endian `u32`s: for each /16, the
+///   number of IPv4 ranges starting before it, then the total range count
+/// - `EU_COUNTRIES: &[&str]`
+/// - `COUNTRY_REGIONS: &[(&str, u8)]`, sorted by country code
 ///
 /// These tables are included by the library at compile time for fast, offline lookups.
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
-    println!("cargo:rerun-if-changed=ripe-data.txt");
+    println!("cargo:rerun-if-env-changed=IP_ALLOC_DATA_DIR");
+    println!("cargo:rerun-if-env-changed=IP_ALLOC_POLICY_FILE");
+    println!("cargo:rerun-if-env-changed=IP_ALLOC_EMBED_COUNTRIES");
 
-    // Read the RIPE data file
-    let ripe_content = fs::read_to_string("ripe-data.txt")
-        .expect("Failed to read ripe-data.txt - make sure it's in the project root");
+    let policy_path =
+        std::env::var("IP_ALLOC_POLICY_FILE").unwrap_or_else(|_| "country-policy.txt".to_string());
+    println!("cargo:rerun-if-changed={policy_path}");
+    let policy_content = fs::read_to_string(&policy_path)
+        .unwrap_or_else(|e| panic!("Failed to read country policy file {policy_path}: {e}"));
+    let country_regions = parse_country_policy(&policy_content)
+        .unwrap_or_else(|e| panic!("Invalid country policy file {policy_path}: {e}"));
 
-    // Parse IPv4 and IPv6 separately
-    let (v4_ranges, v6_ranges) = parse_ripe_data(&ripe_content);
+    // Read the RIPE data files. Without the `embedded-data` feature the range
+    // tables are emitted empty and no data file is needed at all.
+    #[cfg(feature = "embedded-data")]
+    let sources = read_sources();
+    #[cfg(not(feature = "embedded-data"))]
+    let sources: Vec<Source> = Vec::new();
+
+    // Parse IPv4 and IPv6 separately and merge the files, refusing to build
+    // from data that would make lookups wrong
+    let registries = selected_registries();
+    let mut free = FreeSpans::default();
+    let (mut v4_ranges, mut v6_ranges) = merge_sources(&sources, &registries, &mut free)
+        .unwrap_or_else(|report| panic!("Invalid RIPE data:\n{report}"));
 
     println!("cargo:warning=Parsed {} IPv4 ranges from RIPE data", v4_ranges.len());
     println!("cargo:warning=Parsed {} IPv6 ranges from RIPE data", v6_ranges.len());
 
+    // Optionally keep only the ranges of selected countries/regions
+    if let Ok(spec) = std::env::var("IP_ALLOC_EMBED_COUNTRIES")
+        && !spec.trim().is_empty()
+    {
+        let filter = EmbedFilter::parse(&spec)
+            .unwrap_or_else(|e| panic!("Invalid IP_ALLOC_EMBED_COUNTRIES {spec:?}: {e}"));
+        v4_ranges.retain(|r| filter.matches(&r.country, &country_regions));
+        v6_ranges.retain(|r| filter.matches(&r.country, &country_regions));
+        println!(
+            "cargo:warning=IP_ALLOC_EMBED_COUNTRIES={spec}: keeping {} IPv4 and {} IPv6 ranges",
+            v4_ranges.len(),
+            v6_ranges.len()
+        );
+    }
+
     // Generate Rust code
     let out_dir = std::env::var("OUT_DIR").unwrap();
     let dest_path = Path::new(&out_dir).join("generated_data.rs");
@@ -59,110 +131,715 @@ fn main() {
     // Write the header
     writeln!(file, "// Auto-generated from RIPE data at build time").unwrap();
     writeln!(file, "// DO NOT EDIT - changes will be overwritten").unwrap();
-    writeln!(file, "").unwrap();
+    writeln!(file).unwrap();
+
+    // Write the version/header line so the library can report which snapshot it
+    // embeds; with several files, that of the first one that has a header
+    let source_header = sources
+        .iter()
+        .filter(|source| !source.is_override())
+        .find_map(|source| {
+            source
+                .content
+                .lines()
+                .map(normalize_line)
+                .find(|line| {
+                    let registry = line.split('|').nth(1).map(str::trim);
+                    line.starts_with(|c: char| c.is_ascii_digit())
+                        && (registries.is_empty() || registry.is_some_and(|r| registries.contains(&r)))
+                })
+        })
+        .unwrap_or("");
+    writeln!(file, "pub const SOURCE_HEADER: &str = {:?};", source_header).unwrap();
+    let source_format = sources
+        .iter()
+        .filter(|source| !source.is_override())
+        .find_map(|source| is_extended(&source.content));
+    let source_format = match source_format {
+        Some(true) => "extended",
+        Some(false) => "standard",
+        None => "",
+    };
+    writeln!(file, "pub const SOURCE_FORMAT: &str = {:?};", source_format).unwrap();
+    let all_content: Vec<&[u8]> = sources.iter().map(|source| source.content.as_bytes()).collect();
+    writeln!(file, "pub const SOURCE_SHA256: &str = {:?};", sha256_hex(&all_content.concat())).unwrap();
+    writeln!(file).unwrap();
+
+    // Lookups binary-search the tables, so they must be sorted and disjoint
+    assert!(v4_ranges.windows(2).all(|w| w[0].end < w[1].start), "IPv4 ranges are not sorted");
+    assert!(v6_ranges.windows(2).all(|w| w[0].end < w[1].start), "IPv6 ranges are not sorted");
 
     // Write IPv4 ranges
+    let mut v4_blob = Vec::with_capacity(v4_ranges.len() * 11);
+    let mut v4_starts = Vec::with_capacity(v4_ranges.len());
+    for range in &v4_ranges {
+		v4_starts.push(range.start);
+		v4_blob.extend_from_slice(&range.start.to_le_bytes());
+		v4_blob.extend_from_slice(&range.end.to_le_bytes());
+		push_entry_tail(&mut v4_blob, &range.country, range.assigned);
+	}
+    fs::write(Path::new(&out_dir).join("ipv4_ranges.bin"), &v4_blob).unwrap();
+    writeln!(
+        file,
+        "pub static IPV4_RANGES_BLOB: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/ipv4_ranges.bin\"));"
+    )
+    .unwrap();
+
+    // Write the /16 index into the IPv4 table: entry `p` is the number of
+    // ranges starting before `p << 16`, so the range holding an address of
+    // /16 `p` is found between entries `p` and `p + 1`
+    let mut v4_index = Vec::with_capacity(V4_INDEX_LEN * 4);
+    for prefix in 0..V4_INDEX_LEN as u64 {
+        let below = v4_starts.partition_point(|&start| u64::from(start) < prefix << 16);
+        v4_index.extend_from_slice(&(below as u32).to_le_bytes());
+    }
+    fs::write(Path::new(&out_dir).join("ipv4_index.bin"), &v4_index).unwrap();
     writeln!(
         file,
-        "pub const IPV4_RANGES: &[(u32, u32, &str)] = &["
+        "pub static IPV4_INDEX_BLOB: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/ipv4_index.bin\"));"
     )
     .unwrap();
 
-    for (start, count, country) in &v4_ranges {
-		if *count == 0 {
-			continue; // shouldn't happen, but avoids underflow
-		}
-		let end = start.saturating_add(count.saturating_sub(1));
-		writeln!(file, "    ({}, {}, \"{}\"),", start, end, country).unwrap();
+    // Write IPv6 ranges
+    let mut v6_blob = Vec::with_capacity(v6_ranges.len() * 35);
+    for range in &v6_ranges {
+		v6_blob.extend_from_slice(&range.start.to_le_bytes());
+		v6_blob.extend_from_slice(&range.end.to_le_bytes());
+		push_entry_tail(&mut v6_blob, &range.country, range.assigned);
 	}
+    fs::write(Path::new(&out_dir).join("ipv6_ranges.bin"), &v6_blob).unwrap();
+    writeln!(
+        file,
+        "pub static IPV6_RANGES_BLOB: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/ipv6_ranges.bin\"));"
+    )
+    .unwrap();
 
+    // Write the unallocated spans as `start, end` pairs
+    let mut free_v4_blob = Vec::new();
+    for (start, end) in merge_spans(free.v4, |n| n.checked_add(1)) {
+        free_v4_blob.extend_from_slice(&start.to_le_bytes());
+        free_v4_blob.extend_from_slice(&end.to_le_bytes());
+    }
+    let mut free_v6_blob = Vec::new();
+    for (start, end) in merge_spans(free.v6, |n| n.checked_add(1)) {
+        free_v6_blob.extend_from_slice(&start.to_le_bytes());
+        free_v6_blob.extend_from_slice(&end.to_le_bytes());
+    }
+    fs::write(Path::new(&out_dir).join("ipv4_unallocated.bin"), &free_v4_blob).unwrap();
+    fs::write(Path::new(&out_dir).join("ipv6_unallocated.bin"), &free_v6_blob).unwrap();
+    writeln!(
+        file,
+        "pub static IPV4_UNALLOCATED_BLOB: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/ipv4_unallocated.bin\"));"
+    )
+    .unwrap();
+    writeln!(
+        file,
+        "pub static IPV6_UNALLOCATED_BLOB: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/ipv6_unallocated.bin\"));"
+    )
+    .unwrap();
+
+    // Write the country policy tables
+    writeln!(file).unwrap();
+    writeln!(file, "pub const EU_COUNTRIES: &[&str] = &[").unwrap();
+    for (country, region) in &country_regions {
+        if *region == EUROPEAN_UNION {
+            writeln!(file, "    \"{}\",", country).unwrap();
+        }
+    }
     writeln!(file, "];").unwrap();
-    writeln!(file, "").unwrap();
+    writeln!(file).unwrap();
 
-    // Write IPv6 ranges
-    if v6_ranges.is_empty() {
-        // If no IPv6 data, create an empty array
-        writeln!(file, "pub const IPV6_RANGES: &[(u128, u128, &str)] = &[];").unwrap();
+    writeln!(file, "pub const COUNTRY_REGIONS: &[(&str, u8)] = &[").unwrap();
+    for (country, region) in &country_regions {
+        writeln!(file, "    (\"{}\", {}),", country, region).unwrap();
+    }
+    writeln!(file, "];").unwrap();
+
+    println!("cargo:warning=Generated data file with {} IPv4 ranges and {} IPv6 ranges", 
+        v4_ranges.len(), v6_ranges.len());
+}
+
+/// Entries of the IPv4 /16 index: one per /16 plus an end marker.
+const V4_INDEX_LEN: usize = (1 << 16) + 1;
+
+/// Lowercase hex SHA-256 of `bytes`.
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Append the `country: [u8; 2], assigned: u8` part of a blob entry.
+fn push_entry_tail(blob: &mut Vec<u8>, country: &str, assigned: bool) {
+    let country: [u8; 2] = match country.as_bytes() {
+        [] => [0, 0],
+        &[a, b] if a.is_ascii_alphanumeric() && b.is_ascii_alphanumeric() => [a, b],
+        _ => panic!("Unsupported country code {:?} in RIPE data", country),
+    };
+    blob.extend_from_slice(&country);
+    blob.push(u8::from(assigned));
+}
+
+/// Accepted names of the vendored snapshot, in order of preference.
+#[cfg(feature = "embedded-data")]
+const RIPE_DATA_FILES: &[&str] = &["ripe-data.txt", "ripe-data.txt.gz", "ripe-data.txt.zst"];
+
+/// Default directory whose files are all merged into the embedded tables.
+#[cfg(feature = "embedded-data")]
+const DATA_DIR: &str = "data";
+
+/// Files in the data directory whose name starts with this prefix are applied
+/// last and replace the blocks with the same start address in the other files.
+const OVERRIDES_PREFIX: &str = "overrides";
+
+/// One input file and its decompressed content.
+struct Source {
+    path: PathBuf,
+    content: String,
+}
+
+impl Source {
+    fn is_override(&self) -> bool {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(OVERRIDES_PREFIX))
+    }
+}
+
+/// Read the input files.
+///
+/// If the data directory (`IP_ALLOC_DATA_DIR`, default `data/`) exists, every
+/// non-hidden file in it is read in file name order, override files last.
+/// Otherwise the first snapshot in `RIPE_DATA_FILES` that exists is used.
+#[cfg(feature = "embedded-data")]
+fn read_sources() -> Vec<Source> {
+    let data_dir = std::env::var("IP_ALLOC_DATA_DIR").unwrap_or_else(|_| DATA_DIR.to_string());
+
+    let mut paths = Vec::new();
+    if Path::new(&data_dir).is_dir() {
+        // A directory entry makes cargo rerun when files are added or removed
+        println!("cargo:rerun-if-changed={data_dir}");
+        for entry in fs::read_dir(&data_dir)
+            .unwrap_or_else(|e| panic!("Failed to read data directory {data_dir}: {e}"))
+        {
+            let path = entry.unwrap_or_else(|e| panic!("Failed to read data directory {data_dir}: {e}")).path();
+            let hidden = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'));
+            if path.is_file() && !hidden {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            panic!("Data directory {data_dir} contains no files");
+        }
     } else {
-        writeln!(
-            file,
-            "pub const IPV6_RANGES: &[(u128, u128, &str)] = &["
+        let path = RIPE_DATA_FILES
+            .iter()
+            .find(|path| Path::new(path).exists())
+            .expect("Failed to find ripe-data.txt (or .gz/.zst) - make sure it's in the project root");
+        paths.push(PathBuf::from(path));
+    }
+
+    let mut sources: Vec<Source> = paths
+        .into_iter()
+        .map(|path| {
+            // Only track files that exist: a missing path makes cargo rerun on every build
+            println!("cargo:rerun-if-changed={}", path.display());
+            let content = read_snapshot(&path);
+            Source { path, content }
+        })
+        .collect();
+    sources.sort_by(|a, b| (a.is_override(), &a.path).cmp(&(b.is_override(), &b.path)));
+    sources
+}
+
+/// Registries that can be selected with an `embed-<registry>` feature.
+const REGISTRIES: &[(&str, bool)] = &[
+    ("afrinic", cfg!(feature = "embed-afrinic")),
+    ("apnic", cfg!(feature = "embed-apnic")),
+    ("arin", cfg!(feature = "embed-arin")),
+    ("lacnic", cfg!(feature = "embed-lacnic")),
+    ("ripencc", cfg!(feature = "embed-ripencc")),
+];
+
+/// Registries whose records are embedded; empty means all of them.
+fn selected_registries() -> Vec<&'static str> {
+    REGISTRIES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect()
+}
+
+/// Parse and merge the input files into sorted IPv4/IPv6 range lists.
+///
+/// Blocks of override files replace the blocks of the other files that start
+/// at the same address. Unless `registries` is empty, only the records of the
+/// listed registries are kept from the other files.
+///
+/// # Errors
+/// Returns a report naming the offending lines if a record cannot be parsed,
+/// covers no addresses, runs past the end of the address space, or if blocks
+/// overlap (including duplicated lines) after the overrides are applied.
+/// A selected registry without any records only gets a warning, so that
+/// builds with all features enabled still succeed with the vendored
+/// RIPE-only snapshot.
+fn merge_sources(
+    sources: &[Source],
+    registries: &[&str],
+    free: &mut FreeSpans,
+) -> Result<(Vec<V4Range>, Vec<V6Range>), String> {
+    let mut v4_ranges: Vec<V4Range> = Vec::new();
+    let mut v6_ranges: Vec<V6Range> = Vec::new();
+    let mut problems = Vec::new();
+
+    // An enabled registry without data is most likely a missing file
+    for registry in registries {
+        let found = sources.iter().filter(|source| !source.is_override()).any(|source| {
+            source.content.lines().any(|line| line.split('|').next().map(str::trim) == Some(registry))
+        });
+        if !found {
+            println!(
+                "cargo:warning=the embed-{registry} feature is enabled, but no data file has records of registry \
+                 {registry}; add its delegated file to the data directory to embed them"
+            );
+        }
+    }
+
+    for (idx, source) in sources.iter().enumerate() {
+        let keep = if source.is_override() { &[][..] } else { registries };
+        let (v4, v6) = parse_ripe_data(&source.content, idx, keep, &mut problems, free);
+        println!(
+            "cargo:warning={}: {} IPv4 and {} IPv6 ranges",
+            source.path.display(),
+            v4.len(),
+            v6.len()
+        );
+        let without_id = records_without_id(&source.content);
+        if without_id > 0 {
+            println!(
+                "cargo:warning={}: {without_id} records lack the opaque id of the extended format \
+                 (a concatenated standard file?); they are embedded like the others",
+                source.path.display()
+            );
+        }
+
+        if source.is_override() {
+            let v4_starts: HashSet<u32> = v4.iter().map(|r| r.start).collect();
+            let v6_starts: HashSet<u128> = v6.iter().map(|r| r.start).collect();
+            v4_ranges.retain(|r| !v4_starts.contains(&r.start));
+            v6_ranges.retain(|r| !v6_starts.contains(&r.start));
+        }
+        v4_ranges.extend(v4);
+        v6_ranges.extend(v6);
+    }
+
+    v4_ranges.sort();
+    v6_ranges.sort();
+    find_overlaps(&v4_ranges, sources, |ip| Ipv4Addr::from(ip).into(), &mut problems);
+    find_overlaps(&v6_ranges, sources, |ip| Ipv6Addr::from(ip).into(), &mut problems);
+
+    if problems.is_empty() {
+        return Ok((v4_ranges, v6_ranges));
+    }
+
+    const MAX_REPORTED: usize = 20;
+    let mut report = String::new();
+    for problem in problems.iter().take(MAX_REPORTED) {
+        report.push_str(&problem.describe(sources));
+    }
+    if problems.len() > MAX_REPORTED {
+        report.push_str(&format!("  ... and {} more\n", problems.len() - MAX_REPORTED));
+    }
+    Err(report)
+}
+
+/// Record an overlap for every block that starts before an earlier block of
+/// the sorted `ranges` has ended.
+fn find_overlaps<T: Copy + Ord>(
+    ranges: &[Block<T>],
+    sources: &[Source],
+    addr: fn(T) -> IpAddr,
+    problems: &mut Vec<Problem>,
+) {
+    let Some(first) = ranges.first() else {
+        return;
+    };
+    // The earlier block reaching furthest
+    let mut reach = first;
+    for range in &ranges[1..] {
+        if range.start <= reach.end {
+            let what = if (range.start, range.end, &range.country) == (reach.start, reach.end, &reach.country) {
+                "duplicates"
+            } else {
+                "overlaps"
+            };
+            problems.push(Problem {
+                source: range.source,
+                line: range.line,
+                message: format!(
+                    "block {}-{} {} {}-{} from {} line {}",
+                    addr(range.start),
+                    addr(range.end),
+                    what,
+                    addr(reach.start),
+                    addr(reach.end),
+                    sources[reach.source].path.display(),
+                    reach.line
+                ),
+            });
+        }
+        if range.end > reach.end {
+            reach = range;
+        }
+    }
+}
+
+/// A problem found in the input, reported with the offending line.
+struct Problem {
+    source: usize,
+    line: usize,
+    message: String,
+}
+
+impl Problem {
+    fn describe(&self, sources: &[Source]) -> String {
+        let source = &sources[self.source];
+        let text = source.content.lines().nth(self.line - 1).unwrap_or("");
+        format!(
+            "  {} line {}: {}\n    {}\n",
+            source.path.display(),
+            self.line,
+            self.message,
+            text
         )
-        .unwrap();
+    }
+}
+
+/// Read a snapshot file, decompressing gzip and zstd files.
+#[cfg(feature = "embedded-data")]
+fn read_snapshot(path: &Path) -> String {
+    use std::io::Read;
 
-        for (start, end, country) in &v6_ranges {
-			writeln!(file, "    ({}, {}, \"{}\"),", start, end, country).unwrap();
-		}
+    let path_str = path.display();
+    let file = fs::File::open(path).unwrap_or_else(|e| panic!("Failed to open {path_str}: {e}"));
+    let extension = path.extension().and_then(|e| e.to_str());
 
-        writeln!(file, "];").unwrap();
+    let mut reader: Box<dyn Read> = match extension {
+        Some("gz") => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Some("zst") => {
+            let decoder = ruzstd::decoding::StreamingDecoder::new(file)
+                .unwrap_or_else(|e| panic!("Failed to read zstd frame header of {path_str}: {e}"));
+            Box::new(decoder)
+        }
+        _ => Box::new(file),
+    };
+
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .unwrap_or_else(|e| panic!("Failed to read {path_str}: {e}"));
+    content
+}
+
+/// Numeric code of `Region::EuropeanUnion`.
+const EUROPEAN_UNION: u8 = 1;
+
+/// Map a `Region` variant name to its numeric code (see `Region` in `database.rs`).
+fn region_code(name: &str) -> Option<u8> {
+    Some(match name {
+        "EuropeanUnion" => EUROPEAN_UNION,
+        "EuropeNonEu" => 2,
+        "EasternEurope" => 3,
+        "Turkey" => 4,
+        "MiddleEast" => 5,
+        "NorthAfrica" => 6,
+        "CentralAsia" => 7,
+        "GulfStates" => 8,
+        "Americas" => 9,
+        "SubSaharanAfrica" => 10,
+        "SouthAsia" => 11,
+        "EastAsia" => 12,
+        "SoutheastAsia" => 13,
+        "Oceania" => 14,
+        "Other" => 255,
+        _ => return None,
+    })
+}
+
+/// Parse `CC|Region` policy lines into `(country, region code)` pairs sorted by country.
+///
+/// Blank lines and `#` comments are skipped. Unknown regions, malformed country
+/// codes and duplicate countries are rejected.
+fn parse_country_policy(content: &str) -> Result<Vec<(String, u8)>, String> {
+    let mut entries: Vec<(String, u8)> = Vec::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (country, region) = line
+            .split_once('|')
+            .ok_or_else(|| format!("line {}: expected CC|Region", idx + 1))?;
+        let country = country.trim();
+        if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(format!("line {}: invalid country code {:?}", idx + 1, country));
+        }
+        let region = region_code(region.trim())
+            .ok_or_else(|| format!("line {}: unknown region {:?}", idx + 1, region.trim()))?;
+        if entries.iter().any(|(c, _)| c == country) {
+            return Err(format!("line {}: duplicate country {}", idx + 1, country));
+        }
+        entries.push((country.to_string(), region));
     }
 
-    println!("cargo:warning=Generated data file with {} IPv4 ranges and {} IPv6 ranges", 
-        v4_ranges.len(), v6_ranges.len());
+    entries.sort();
+    Ok(entries)
+}
+
+/// Selection of embedded ranges from `IP_ALLOC_EMBED_COUNTRIES`.
+///
+/// The variable is a comma-separated list of country codes (`DE`) and `Region`
+/// variant names (`EuropeNonEu`); `EU` is shorthand for `EuropeanUnion`, i.e.
+/// all EU member states plus ranges registered to `EU` itself.
+struct EmbedFilter {
+    countries: Vec<String>,
+    regions: Vec<u8>,
+}
+
+impl EmbedFilter {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = EmbedFilter { countries: Vec::new(), regions: Vec::new() };
+
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if token.eq_ignore_ascii_case("EU") {
+                filter.regions.push(EUROPEAN_UNION);
+            } else if let Some(region) = region_code(token) {
+                filter.regions.push(region);
+            } else if token.len() == 2 && token.bytes().all(|b| b.is_ascii_alphabetic()) {
+                filter.countries.push(token.to_ascii_uppercase());
+            } else {
+                return Err(format!("unknown country or region {:?}", token));
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// Whether ranges of `country` are embedded, classifying it the way the
+    /// library does (`EU` is EU, unlisted countries are `Other`).
+    fn matches(&self, country: &str, country_regions: &[(String, u8)]) -> bool {
+        if self.countries.iter().any(|c| c == country) {
+            return true;
+        }
+        let region = if country == "EU" {
+            EUROPEAN_UNION
+        } else {
+            country_regions
+                .binary_search_by(|(c, _)| c.as_str().cmp(country))
+                .map_or(255, |idx| country_regions[idx].1)
+        };
+        self.regions.contains(&region)
+    }
+}
+
+/// Strip a UTF-8 byte order mark, a stray `\r` and surrounding whitespace, as
+/// left by Windows tools and some mirrors.
+fn normalize_line(line: &str) -> &str {
+    line.strip_prefix('\u{FEFF}').unwrap_or(line).trim()
+}
+
+/// One block of addresses parsed from a record line.
+///
+/// Fields are ordered so that sorting gives a canonical order by start address.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Block<T> {
+    start: T,
+    /// Last address (inclusive).
+    end: T,
+    country: String,
+    /// `true` for records with status `assigned`.
+    assigned: bool,
+    /// Index of the input file in `sources`.
+    source: usize,
+    /// 1-based line number in that file.
+    line: usize,
+}
+
+/// Spans of `available` and `reserved` records, embedded so that lookups can
+/// tell unallocated space from space of registries that are not embedded.
+#[derive(Debug, Default)]
+struct FreeSpans {
+    v4: Vec<(u32, u32)>,
+    v6: Vec<(u128, u128)>,
+}
+
+/// Sort `spans` and merge overlapping and adjacent ones.
+fn merge_spans<T: Ord + Copy>(mut spans: Vec<(T, T)>, next: fn(T) -> Option<T>) -> Vec<(T, T)> {
+    spans.sort_unstable();
+    let mut merged: Vec<(T, T)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if next(last.1).is_none_or(|after| start <= after) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// An IPv4 block.
+type V4Range = Block<u32>;
+/// An IPv6 block.
+type V6Range = Block<u128>;
+
+/// Whether `content` is in the extended delegated format, judged by its first
+/// record: an opaque id after the status, or an `available`/`reserved` record.
+/// `None` if it has no records.
+fn is_extended(content: &str) -> Option<bool> {
+    content.lines().map(normalize_line).find_map(|line| {
+        let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+        let is_header = parts[0].starts_with(|c: char| c.is_ascii_digit());
+        if line.starts_with('#') || parts.len() < 7 || is_header || parts[1] == "*" {
+            return None;
+        }
+        Some(parts.len() >= 8 || is_unallocated(parts[6]))
+    })
+}
+
+/// Number of `ipv4`/`ipv6` records without the opaque id in `content` if it
+/// is in the extended format, e.g. from a standard file appended to it.
+fn records_without_id(content: &str) -> usize {
+    if is_extended(content) != Some(true) {
+        return 0;
+    }
+    content
+        .lines()
+        .map(normalize_line)
+        .filter(|line| {
+            let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+            parts.len() == 7 && (parts[2] == "ipv4" || parts[2] == "ipv6") && parts[1] != "*" && !is_unallocated(parts[6])
+        })
+        .count()
+}
+
+/// Status of the extended format's records for space that is not delegated.
+fn is_unallocated(status: &str) -> bool {
+    status == "available" || status == "reserved"
 }
 
 /// Parse RIPE delegated stats content into sorted IPv4/IPv6 range lists for codegen.
 ///
-/// For IPv4 lines, returns `(start_u32, count, country)`.
 /// For IPv6 lines, RIPE’s “count” field is a prefix length; this converts it into an
-/// inclusive end address and returns `(start_u128, end_u128, country)`.
+/// inclusive end address. Invalid `ipv4`/`ipv6` records are added to `problems`.
+/// `available` and `reserved` records of the extended format have no country
+/// and go to `free` instead. Records of registries not in `registries` are
+/// skipped unless it is empty.
 ///
 /// The returned vectors are sorted by start address to enable binary search at runtime.
-fn parse_ripe_data(content: &str) -> (Vec<(u32, u32, String)>, Vec<(u128, u128, String)>) {
+fn parse_ripe_data(
+    content: &str,
+    source: usize,
+    registries: &[&str],
+    problems: &mut Vec<Problem>,
+    free: &mut FreeSpans,
+) -> (Vec<V4Range>, Vec<V6Range>) {
     let mut v4_ranges = Vec::new();
     let mut v6_ranges = Vec::new();
 
-    for line in content.lines() {
-        // Skip comments and summary lines
-        if line.starts_with('#') || line.starts_with('2') {
+    for (idx, line) in content.lines().enumerate() {
+        // Skip comments; the header and summary lines are rejected by the field
+        // checks below (too few fields / type column is not ipv4 or ipv6)
+        let line = normalize_line(line);
+        if line.starts_with('#') {
             continue;
         }
 
-        let parts: Vec<&str> = line.split('|').collect();
+        let parts: Vec<&str> = line.split('|').map(str::trim).collect();
 
         if parts.len() < 7 {
             continue;
         }
+        if !registries.is_empty() && !registries.contains(&parts[0]) {
+            continue;
+        }
+        if is_unallocated(parts[6]) {
+            // Malformed ones only cost precision, so they are not reported
+            match parts[2] {
+                "ipv4" => {
+                    if let (Ok(start), Ok(count)) = (parts[3].parse::<Ipv4Addr>(), parts[4].parse::<u32>())
+                        && let Some(end) = count.checked_sub(1).and_then(|n| u32::from(start).checked_add(n))
+                    {
+                        free.v4.push((start.into(), end));
+                    }
+                }
+                "ipv6" => {
+                    if let (Ok(start), Ok(len)) = (parts[3].parse::<Ipv6Addr>(), parts[4].parse::<u32>())
+                        && len <= 128
+                    {
+                        let start = u128::from(start);
+                        free.v6.push((start, start | u128::MAX.checked_shr(len).unwrap_or(0)));
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
 
         let country = parts[1].to_string();
+        let assigned = parts[6] == "assigned";
         let ip_type = parts[2];
         let start_str = parts[3];
         let count_str = parts[4];
+        let mut problem = |message: &str| {
+            problems.push(Problem { source, line: idx + 1, message: message.to_string() })
+        };
 
         if ip_type == "ipv4" {
             // Parse IPv4
-            if let Ok(start_ip) = start_str.parse::<std::net::Ipv4Addr>() {
-                if let Ok(count) = count_str.parse::<u32>() {
-					if count == 0 { continue; }
-                    let start_u32: u32 = start_ip.into();
-                    v4_ranges.push((start_u32, count, country));
-                }
+            let Ok(start_ip) = start_str.parse::<Ipv4Addr>() else {
+                problem("invalid IPv4 start address");
+                continue;
+            };
+            let Ok(count) = count_str.parse::<u32>() else {
+                problem("invalid IPv4 address count");
+                continue;
+            };
+            if count == 0 {
+                problem("IPv4 block with zero addresses");
+                continue;
             }
+            let start: u32 = start_ip.into();
+            let Some(end) = start.checked_add(count - 1) else {
+                problem("IPv4 block runs past 255.255.255.255");
+                continue;
+            };
+            v4_ranges.push(Block { start, end, country, assigned, source, line: idx + 1 });
         } else if ip_type == "ipv6" {
             // Parse IPv6
-            if let Ok(start_ip) = start_str.parse::<std::net::Ipv6Addr>() {
-                if let Ok(prefix_len) = count_str.parse::<u32>() {
-                    let start_u128: u128 = start_ip.into();
-                    
-                    // Calculate the number of addresses in this prefix
-                    // For IPv6, the count field is actually the prefix length
-                    // We need to calculate the end address
-                    let host_bits = 128 - prefix_len;
-                    let count = if host_bits >= 128 {
-						u128::MAX
-					} else {
-						1u128 << host_bits
-					};
-					let end = start_u128.saturating_add(count).saturating_sub(1);
-					v6_ranges.push((start_u128, end, country));
-                }
+            let Ok(start_ip) = start_str.parse::<Ipv6Addr>() else {
+                problem("invalid IPv6 start address");
+                continue;
+            };
+            // For IPv6, the count field is actually the prefix length
+            let Some(prefix_len) = count_str.parse::<u32>().ok().filter(|&len| len <= 128) else {
+                problem("invalid IPv6 prefix length");
+                continue;
+            };
+            let start: u128 = start_ip.into();
+
+            // Calculate the end address of this prefix
+            let host_mask = u128::MAX.checked_shr(prefix_len).unwrap_or(0);
+            if start & host_mask != 0 {
+                problem("IPv6 start address has bits set beyond the prefix length");
+                continue;
             }
+            let end = start | host_mask;
+            v6_ranges.push(Block { start, end, country, assigned, source, line: idx + 1 });
         }
     }
 
-    // Sort ranges for binary search
-    v4_ranges.sort_by_key(|r| r.0);
-    v6_ranges.sort_by_key(|r| r.0);
+    // Sort ranges for binary search. Whole entries are compared so the output
+    // does not depend on the order of records in the file.
+    v4_ranges.sort();
+    v6_ranges.sort();
 
     (v4_ranges, v6_ranges)
-}
\ No newline at end of file
+}
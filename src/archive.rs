@@ -0,0 +1,369 @@
+//! Zero-copy snapshots with [rkyv](https://docs.rs/rkyv).
+//!
+//! Like the binary cache, an archive stores only country codes and status
+//! flags. The EU flag and region are derived at lookup time, so an archive
+//! never disagrees with the running crate version's classification.
+//!
+//! The archive follows the header shared by all binary formats of this crate
+//! (see `binary.rs`), whose length keeps it 16-byte aligned.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use rkyv::rancor;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+
+use crate::binary::{self, HEADER_LEN};
+use crate::bogon::FreeSpace;
+use crate::database::{GeoInfo, GeoIpDb};
+use crate::parser::{DelegatedFormat, SourceMetadata};
+
+const MAGIC: &[u8; 8] = b"IPALLOCR";
+const FORMAT_VERSION: u32 = 2;
+
+/// Serialized form of a [`GeoIpDb`].
+#[derive(Archive, Serialize, Deserialize)]
+struct Snapshot {
+    /// Summary header line of the source file, empty if unknown.
+    header: String,
+    /// Record format: `0` unknown, `1` standard, `2` extended.
+    format: u8,
+    v4: Vec<Entry<u32>>,
+    v6: Vec<Entry<u128>>,
+    /// Space listed as `available` or `reserved`, see [`GeoIpDb::coverage`].
+    free_v4: Vec<Span<u32>>,
+    free_v6: Vec<Span<u128>>,
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+struct Span<T> {
+    start: T,
+    end: T,
+}
+
+#[derive(Archive, Serialize, Deserialize)]
+struct Entry<T> {
+    start: T,
+    end: T,
+    country: [u8; 2],
+    assigned: bool,
+}
+
+impl GeoIpDb {
+    /// Serialize the database as an rkyv archive for
+    /// [`ArchivedGeoIpDb::from_bytes`].
+    ///
+    /// Write the bytes to a file to memory-map it later.
+    pub fn to_rkyv_bytes(&self) -> AlignedVec {
+        let snapshot = Snapshot {
+            header: self
+                .metadata
+                .as_ref()
+                .map(SourceMetadata::to_header_line)
+                .unwrap_or_default(),
+            format: match self.metadata.as_ref().and_then(|meta| meta.format) {
+                None => 0,
+                Some(DelegatedFormat::Standard) => 1,
+                Some(DelegatedFormat::Extended) => 2,
+            },
+            v4: self.v4_ranges.iter().map(entry).collect(),
+            v6: self.v6_ranges.iter().map(entry).collect(),
+            free_v4: self.unallocated.v4.iter().map(span).collect(),
+            free_v6: self.unallocated.v6.iter().map(span).collect(),
+        };
+        let archive = rkyv::to_bytes::<rancor::Error>(&snapshot)
+            .expect("serializing plain tables cannot fail");
+        let mut out = AlignedVec::with_capacity(HEADER_LEN + archive.len());
+        out.extend_from_slice(&binary::header(MAGIC, FORMAT_VERSION, &archive));
+        out.extend_from_slice(&archive);
+        out
+    }
+}
+
+fn span<T: Copy>(&(start, end): &(T, T)) -> Span<T> {
+    Span { start, end }
+}
+
+fn entry<T: Copy>(&(start, end, info): &(T, T, GeoInfo)) -> Entry<T> {
+    Entry {
+        start,
+        end,
+        country: info.country_code,
+        assigned: info.assigned,
+    }
+}
+
+/// A read-only view of an rkyv archive written by [`GeoIpDb::to_rkyv_bytes`].
+///
+/// Opening an archive validates it but copies nothing: lookups binary-search
+/// the archived tables in place, without building a [`GeoIpDb`]. This removes
+/// even the decoding step of the binary cache, which matters for
+/// multi-registry datasets opened by many short-lived processes.
+///
+/// The bytes must be aligned to 16 bytes, the alignment of the archived IPv6
+/// addresses. The [`AlignedVec`] returned by [`GeoIpDb::to_rkyv_bytes`] is, and
+/// so is a memory-mapped file (mappings start on a page boundary), which lets
+/// every process mapping the file share one copy of the tables. A `Vec<u8>`
+/// from [`std::fs::read`] may not be; copy it into an [`AlignedVec`] first.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "embedded-data")] {
+/// use ip_alloc_lookup::{ArchivedGeoIpDb, GeoIpDb};
+///
+/// let db = GeoIpDb::new();
+/// let bytes = db.to_rkyv_bytes();
+///
+/// let archived = ArchivedGeoIpDb::from_bytes(&bytes).unwrap();
+/// let info = archived.lookup("46.4.0.1".parse().unwrap()).unwrap();
+/// assert_eq!(info.country_code_str(), "DE");
+/// # }
+/// ```
+///
+/// [`AlignedVec`]: rkyv::util::AlignedVec
+#[derive(Clone, Copy)]
+pub struct ArchivedGeoIpDb<'a> {
+    snapshot: &'a ArchivedSnapshot,
+}
+
+impl<'a> ArchivedGeoIpDb<'a> {
+    /// Open an archive in place.
+    ///
+    /// Fails with [`IncompatibleFormat`] if the bytes were written by a
+    /// release with a different archive layout or do not match their hash,
+    /// and with another [`io::ErrorKind::InvalidData`] error if they are not a
+    /// valid archive (including misaligned bytes) or the tables are not sorted
+    /// and disjoint.
+    ///
+    /// [`IncompatibleFormat`]: crate::IncompatibleFormat
+    pub fn from_bytes(bytes: &'a [u8]) -> io::Result<Self> {
+        let data = binary::open(bytes, MAGIC, FORMAT_VERSION)?;
+        let snapshot = rkyv::access::<ArchivedSnapshot, rancor::Error>(data)
+            .map_err(|e| invalid(format!("invalid rkyv archive: {e}")))?;
+        // Lookups binary-search the tables; refuse anything that would make them lie.
+        if !is_sorted(&snapshot.v4, |x| x.to_native())
+            || !is_sorted(&snapshot.v6, |x| x.to_native())
+        {
+            return Err(invalid(
+                "rkyv archive tables are not sorted or overlap".to_string(),
+            ));
+        }
+        let disjoint = |spans: &[(u128, u128)]| spans.windows(2).all(|w| w[0].1 < w[1].0);
+        let free_v4: Vec<_> = snapshot
+            .free_v4
+            .iter()
+            .map(|s| (s.start.to_native().into(), s.end.to_native().into()))
+            .collect();
+        let free_v6: Vec<_> = snapshot
+            .free_v6
+            .iter()
+            .map(|s| (s.start.to_native(), s.end.to_native()))
+            .collect();
+        if !disjoint(&free_v4)
+            || !disjoint(&free_v6)
+            || free_v4.iter().chain(&free_v6).any(|s| s.0 > s.1)
+        {
+            return Err(invalid(
+                "rkyv archive unallocated spans are not sorted".to_string(),
+            ));
+        }
+        Ok(Self { snapshot })
+    }
+
+    /// Look up an IP address (IPv4 or IPv6).
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoInfo> {
+        match ip {
+            IpAddr::V4(v4) => self.lookup_v4(v4),
+            IpAddr::V6(v6) => self.lookup_v6(v6),
+        }
+    }
+
+    /// Look up a single IPv4 address.
+    pub fn lookup_v4(&self, ip: Ipv4Addr) -> Option<GeoInfo> {
+        let ip = u32::from(ip);
+        let ranges = &self.snapshot.v4;
+        let idx = ranges
+            .partition_point(|r| r.start.to_native() <= ip)
+            .checked_sub(1)?;
+        let range = &ranges[idx];
+        (ip <= range.end.to_native()).then(|| geo_info(&range.country, range.assigned))
+    }
+
+    /// Look up a single IPv6 address.
+    pub fn lookup_v6(&self, ip: Ipv6Addr) -> Option<GeoInfo> {
+        let ip = u128::from(ip);
+        let ranges = &self.snapshot.v6;
+        let idx = ranges
+            .partition_point(|r| r.start.to_native() <= ip)
+            .checked_sub(1)?;
+        let range = &ranges[idx];
+        (ip <= range.end.to_native()).then(|| geo_info(&range.country, range.assigned))
+    }
+
+    /// Return `true` if the IP is covered by the archive and classified as EU.
+    pub fn is_eu(&self, ip: IpAddr) -> bool {
+        self.lookup(ip).is_some_and(|info| info.is_eu)
+    }
+
+    /// Number of IPv4 and IPv6 ranges in the archive.
+    pub fn range_counts(&self) -> (usize, usize) {
+        (self.snapshot.v4.len(), self.snapshot.v6.len())
+    }
+
+    /// Metadata of the source the archived database was loaded from, if known.
+    pub fn source_metadata(&self) -> Option<SourceMetadata> {
+        let format = match self.snapshot.format {
+            1 => Some(DelegatedFormat::Standard),
+            2 => Some(DelegatedFormat::Extended),
+            _ => None,
+        };
+        SourceMetadata::from_header_line(&self.snapshot.header)
+            .map(|meta| SourceMetadata { format, ..meta })
+    }
+
+    /// Copy the archive into an owned [`GeoIpDb`].
+    pub fn to_db(&self) -> GeoIpDb {
+        GeoIpDb {
+            v4_ranges: Arc::new(
+                self.snapshot
+                    .v4
+                    .iter()
+                    .map(|r| {
+                        (
+                            r.start.to_native(),
+                            r.end.to_native(),
+                            geo_info(&r.country, r.assigned),
+                        )
+                    })
+                    .collect(),
+            ),
+            v6_ranges: Arc::new(
+                self.snapshot
+                    .v6
+                    .iter()
+                    .map(|r| {
+                        (
+                            r.start.to_native(),
+                            r.end.to_native(),
+                            geo_info(&r.country, r.assigned),
+                        )
+                    })
+                    .collect(),
+            ),
+            metadata: self.source_metadata(),
+            unallocated: Arc::new(FreeSpace {
+                v4: self
+                    .snapshot
+                    .free_v4
+                    .iter()
+                    .map(|s| (s.start.to_native(), s.end.to_native()))
+                    .collect(),
+                v6: self
+                    .snapshot
+                    .free_v6
+                    .iter()
+                    .map(|s| (s.start.to_native(), s.end.to_native()))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+fn geo_info(country: &[u8; 2], assigned: bool) -> GeoInfo {
+    GeoInfo::from_record(std::str::from_utf8(country).unwrap_or(""), assigned)
+}
+
+fn is_sorted<E, T: Ord>(ranges: &[ArchivedEntry<E>], native: impl Fn(&E::Archived) -> T) -> bool
+where
+    E: Archive,
+{
+    ranges.iter().all(|r| native(&r.start) <= native(&r.end))
+        && ranges
+            .windows(2)
+            .all(|w| native(&w[0].end) < native(&w[1].start))
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{IpRange, RangeStart};
+
+    #[test]
+    fn test_archive_round_trip() {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "2|ripencc|1700000000|3|19830705|20240101|+0100\n\
+             ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated|a1\n\
+             ripencc|CH|ipv4|46.5.0.0|256|20090101|assigned|a2\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated|a1\n\
+             ripencc||ipv4|46.5.1.0|256||available\n",
+        );
+        let bytes = db.to_rkyv_bytes();
+        let archived = ArchivedGeoIpDb::from_bytes(&bytes).unwrap();
+
+        assert_eq!(archived.range_counts(), (2, 1));
+        for ip in [
+            "46.4.0.1",
+            "46.5.0.255",
+            "46.5.1.0",
+            "2a01:4f8::1",
+            "2a01:4f9::",
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(archived.lookup(ip).as_ref(), db.lookup(ip), "{ip}");
+        }
+        assert!(
+            archived
+                .lookup("46.5.0.1".parse().unwrap())
+                .unwrap()
+                .assigned
+        );
+        assert!(archived.is_eu("2a01:4f8::1".parse().unwrap()));
+
+        let meta = archived.source_metadata().unwrap();
+        assert_eq!(meta.end_date, "20240101");
+        assert_eq!(meta.format, Some(DelegatedFormat::Extended));
+        assert_eq!(archived.to_db().diff(&db).changes.len(), 0);
+        assert!(archived.to_db().is_bogon("46.5.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_archive_rejects_bad_input() {
+        let err = ArchivedGeoIpDb::from_bytes(b"not an archive")
+            .err()
+            .unwrap();
+        assert_eq!(
+            crate::IncompatibleFormat::of(&err),
+            Some(&crate::IncompatibleFormat::WrongMagic)
+        );
+
+        // Unsorted or overlapping tables archive fine but must not be opened.
+        let db = |starts: &[&str]| {
+            GeoIpDb::from_ranges(
+                starts
+                    .iter()
+                    .map(|start| IpRange {
+                        start: RangeStart::V4(start.parse().unwrap()),
+                        count: 256,
+                        country: "DE".to_string(),
+                        assigned: false,
+                        date: String::new(),
+                    })
+                    .collect(),
+            )
+        };
+        let mut unsorted = db(&["10.0.0.0", "11.0.0.0"]);
+        Arc::make_mut(&mut unsorted.v4_ranges).reverse();
+        for db in [unsorted, db(&["10.0.0.0", "10.0.0.128"])] {
+            let err = ArchivedGeoIpDb::from_bytes(&db.to_rkyv_bytes())
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
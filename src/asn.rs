@@ -0,0 +1,480 @@
+//! Origin AS lookups from the iptoasn.com `ip2asn` datasets.
+//!
+//! The `ip2asn-v4.tsv`, `ip2asn-v6.tsv` and `ip2asn-combined.tsv` files list
+//! routed ranges with their origin AS, one tab-separated line per range:
+//!
+//! ```text
+//! range_start  range_end     AS_number  country_code AS_description
+//! 1.0.0.0      1.0.0.255     13335      US           CLOUDFLARENET
+//! ```
+//!
+//! [`AsnDb`] loads them into sorted tables with the same binary-search lookup
+//! as [`GeoIpDb`](crate::GeoIpDb), so the two can enrich the same records side
+//! by side. Ranges announced by AS 0 ("Not routed") are skipped.
+//!
+//! It also reads the pipe-delimited answers of Team Cymru's bulk IP-to-ASN
+//! whois service (`whois -h whois.cymru.com " -v 8.8.8.8"` or a
+//! `begin`/`end` batch), which cover just the queried addresses:
+//!
+//! ```text
+//! AS      | IP               | BGP Prefix          | CC | Registry | Allocated  | AS Name
+//! 15169   | 8.8.8.8          | 8.8.8.0/24          | US | arin     | 2023-12-28 | GOOGLE, US
+//! ```
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::{fs, io, path::Path};
+
+use crate::IpNet;
+use crate::source::layer;
+
+/// The origin AS of a routed range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsnInfo {
+    pub asn: u32,
+    /// Country code of the AS registration as given in the file (e.g. `US`).
+    pub country: String,
+    /// Free-text AS name, e.g. `CLOUDFLARENET`.
+    pub description: String,
+}
+
+/// In-memory ip-to-ASN database built from `ip2asn` TSV files.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "embedded-data")] {
+/// use ip_alloc_lookup::{AsnDb, GeoIpDb};
+///
+/// let tsv = "46.4.0.0\t46.4.255.255\t24940\tDE\tHETZNER-AS\n";
+/// let asn = AsnDb::from_ip2asn_str(tsv);
+/// let geo = GeoIpDb::new();
+///
+/// let ip = "46.4.0.1".parse().unwrap();
+/// assert_eq!(asn.asn_of(ip), Some(24940));
+/// assert_eq!(geo.lookup(ip).unwrap().country_code_str(), "DE");
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AsnDb {
+    v4_ranges: Vec<(u32, u32, u32)>,
+    v6_ranges: Vec<(u128, u128, u32)>,
+    /// Distinct AS records; ranges refer to them by index.
+    records: Vec<AsnInfo>,
+}
+
+impl AsnDb {
+    /// Build a database from `ip2asn` TSV content (IPv4, IPv6 or combined).
+    ///
+    /// Lines that cannot be parsed and ranges of AS 0 are skipped.
+    pub fn from_ip2asn_str(content: &str) -> Self {
+        let mut db = AsnDb::default();
+        let mut index: HashMap<(u32, &str, &str), u32> = HashMap::new();
+
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 3 {
+                continue;
+            }
+            let (Ok(start), Ok(end), Ok(asn)) = (
+                fields[0].trim().parse::<IpAddr>(),
+                fields[1].trim().parse::<IpAddr>(),
+                fields[2].trim().parse::<u32>(),
+            ) else {
+                continue;
+            };
+            if asn == 0 {
+                continue;
+            }
+
+            let country = fields.get(3).map_or("", |s| s.trim());
+            let description = fields.get(4).map_or("", |s| s.trim());
+            let idx = *index.entry((asn, country, description)).or_insert_with(|| {
+                db.records.push(AsnInfo {
+                    asn,
+                    country: country.to_string(),
+                    description: description.to_string(),
+                });
+                (db.records.len() - 1) as u32
+            });
+
+            match (start, end) {
+                (IpAddr::V4(s), IpAddr::V4(e)) if s <= e => {
+                    db.v4_ranges.push((s.into(), e.into(), idx));
+                }
+                (IpAddr::V6(s), IpAddr::V6(e)) if s <= e => {
+                    db.v6_ranges.push((s.into(), e.into(), idx));
+                }
+                _ => {}
+            }
+        }
+
+        db.v4_ranges.sort_by_key(|r| r.0);
+        db.v6_ranges.sort_by_key(|r| r.0);
+        db
+    }
+
+    /// Load an `ip2asn` TSV file.
+    ///
+    /// Call [`AsnDb::extend_from_ip2asn_file`] to add the other address family
+    /// when using the separate v4/v6 files.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read.
+    pub fn from_ip2asn_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_ip2asn_str(&fs::read_to_string(path)?))
+    }
+
+    /// Add the ranges of another `ip2asn` TSV file, e.g. `ip2asn-v6.tsv` after
+    /// loading `ip2asn-v4.tsv`.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read.
+    pub fn extend_from_ip2asn_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let other = Self::from_ip2asn_file(path)?;
+        let offset = self.records.len() as u32;
+
+        self.records.extend(other.records);
+        self.v4_ranges.extend(
+            other
+                .v4_ranges
+                .into_iter()
+                .map(|(s, e, i)| (s, e, i + offset)),
+        );
+        self.v6_ranges.extend(
+            other
+                .v6_ranges
+                .into_iter()
+                .map(|(s, e, i)| (s, e, i + offset)),
+        );
+        self.v4_ranges.sort_by_key(|r| r.0);
+        self.v6_ranges.sort_by_key(|r| r.0);
+        Ok(())
+    }
+
+    /// Build a database from the output of Team Cymru's bulk whois service.
+    ///
+    /// The columns are taken from the header line (`AS | IP | ...`), which
+    /// the service prints in bulk mode; without one, lines of three fields
+    /// are read as `AS | IP | AS Name` and longer ones in the order of `-v`
+    /// output (`AS | IP | BGP Prefix | CC | Registry | Allocated | AS Name`).
+    /// Each answer covers its BGP
+    /// prefix, or only the queried address if the output has no prefix
+    /// column. Where prefixes nest, the more specific one wins, and of two
+    /// answers for the same prefix the first. The `, CC` suffix the service
+    /// appends to AS names is dropped if the output has a `CC` column.
+    ///
+    /// Lines that cannot be parsed and addresses that are not routed (AS
+    /// `NA`) are skipped.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::AsnDb;
+    ///
+    /// let db = AsnDb::from_cymru_str("\
+    /// Bulk mode; whois.cymru.com [2025-01-01 00:00:00 +0000]
+    /// 24940   | 46.4.0.1         | 46.4.0.0/16         | DE | ripencc  | 2009-01-20 | HETZNER-AS, DE
+    /// NA      | 10.0.0.1         | NA                  |    | other    |            | NA
+    /// ");
+    /// let info = db.lookup("46.4.200.1".parse().unwrap()).unwrap();
+    /// assert_eq!((info.asn, info.country.as_str()), (24940, "DE"));
+    /// assert_eq!(info.description, "HETZNER-AS");
+    /// assert_eq!(db.asn_of("10.0.0.1".parse().unwrap()), None);
+    /// ```
+    pub fn from_cymru_str(content: &str) -> Self {
+        let mut db = AsnDb::default();
+        let mut index: HashMap<(u32, &str, &str), u32> = HashMap::new();
+        let mut columns = None;
+        // Covered range and record of each answer, in the order of the file
+        let mut answers: Vec<(IpNet, u32)> = Vec::new();
+
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+            if fields.len() < 3 {
+                continue;
+            }
+            if fields[0].eq_ignore_ascii_case("AS") {
+                columns = Some(CymruColumns::from_header(&fields));
+                continue;
+            }
+            let cols = columns.unwrap_or_else(|| CymruColumns::default_for(fields.len()));
+            let field = |col: Option<usize>| col.and_then(|i| fields.get(i)).copied();
+
+            let (Some(Ok(asn)), Some(Ok(ip))) = (
+                field(Some(cols.asn)).map(str::parse::<u32>),
+                field(Some(cols.ip)).map(str::parse::<IpAddr>),
+            ) else {
+                continue;
+            };
+            if asn == 0 {
+                continue;
+            }
+            let net = field(cols.prefix)
+                .and_then(|prefix| prefix.parse::<IpNet>().ok())
+                .filter(|net| net.contains(ip))
+                .unwrap_or_else(|| IpNet::from(ip));
+
+            let country = field(cols.country).unwrap_or("");
+            let name = field(cols.name).unwrap_or("");
+            let description = name
+                .strip_suffix(country)
+                .and_then(|rest| rest.strip_suffix(", "))
+                .filter(|_| !country.is_empty())
+                .unwrap_or(name);
+            let idx = *index.entry((asn, country, description)).or_insert_with(|| {
+                db.records.push(AsnInfo {
+                    asn,
+                    country: country.to_string(),
+                    description: description.to_string(),
+                });
+                (db.records.len() - 1) as u32
+            });
+            answers.push((net, idx));
+        }
+
+        // Most specific first; the sort is stable, so earlier answers win ties
+        answers.sort_by_key(|(net, _)| std::cmp::Reverse(net.prefix_len()));
+        let span = |net: &IpNet| (u128_of(net.network()), u128_of(net.last()));
+        db.v4_ranges = layer(
+            answers
+                .iter()
+                .filter(|(net, _)| net.network().is_ipv4())
+                .map(|(net, idx)| (span(net).0, span(net).1, *idx)),
+        )
+        .into_iter()
+        .map(|(start, end, idx)| (start as u32, end as u32, idx))
+        .collect();
+        db.v6_ranges = layer(
+            answers
+                .iter()
+                .filter(|(net, _)| net.network().is_ipv6())
+                .map(|(net, idx)| (span(net).0, span(net).1, *idx)),
+        );
+        db
+    }
+
+    /// Load a file holding the output of Team Cymru's bulk whois service,
+    /// see [`AsnDb::from_cymru_str`].
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read.
+    pub fn from_cymru_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_cymru_str(&fs::read_to_string(path)?))
+    }
+
+    /// Add the answers of Team Cymru's bulk whois service for addresses this
+    /// database does not cover, e.g. to fill the gaps of an `ip2asn`
+    /// dataset for the addresses of a list. Ranges already loaded keep
+    /// their records.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::AsnDb;
+    ///
+    /// let mut db = AsnDb::from_ip2asn_str("46.4.0.0\t46.4.255.255\t24940\tDE\tHETZNER-AS\n");
+    /// db.extend_from_cymru_str("\
+    /// 15169   | 8.8.8.8          | 8.8.8.0/24          | US | arin     | 2023-12-28 | GOOGLE, US
+    /// 24940   | 46.4.0.1         | 46.0.0.0/8          | DE | ripencc  | 2009-01-20 | HETZNER-AS, DE
+    /// ");
+    /// assert_eq!(db.asn_of("8.8.8.8".parse().unwrap()), Some(15169));
+    /// assert_eq!(db.asn_of("46.5.0.1".parse().unwrap()), Some(24940));
+    /// assert_eq!(db.len(), (4, 0));
+    /// ```
+    pub fn extend_from_cymru_str(&mut self, content: &str) {
+        let other = Self::from_cymru_str(content);
+        let offset = self.records.len() as u32;
+        self.records.extend(other.records);
+
+        let v4 = self.v4_ranges.iter().copied().chain(
+            other
+                .v4_ranges
+                .into_iter()
+                .map(|(s, e, i)| (s, e, i + offset)),
+        );
+        self.v4_ranges = layer(v4.map(|(s, e, i)| (u128::from(s), u128::from(e), i)))
+            .into_iter()
+            .map(|(s, e, i)| (s as u32, e as u32, i))
+            .collect();
+        let v6 = self.v6_ranges.iter().copied().chain(
+            other
+                .v6_ranges
+                .into_iter()
+                .map(|(s, e, i)| (s, e, i + offset)),
+        );
+        self.v6_ranges = layer(v6);
+    }
+
+    /// Look up the origin AS record of an address.
+    pub fn lookup(&self, ip: IpAddr) -> Option<&AsnInfo> {
+        let idx = match ip {
+            IpAddr::V4(v4) => find(&self.v4_ranges, u32::from(v4)),
+            IpAddr::V6(v6) => find(&self.v6_ranges, u128::from(v6)),
+        }?;
+        self.records.get(idx as usize)
+    }
+
+    /// Return the origin AS number of an address, if it is routed.
+    pub fn asn_of(&self, ip: IpAddr) -> Option<u32> {
+        self.lookup(ip).map(|info| info.asn)
+    }
+
+    /// Look up a single IPv4 address.
+    pub fn lookup_v4(&self, ip: Ipv4Addr) -> Option<&AsnInfo> {
+        self.lookup(IpAddr::V4(ip))
+    }
+
+    /// Look up a single IPv6 address.
+    pub fn lookup_v6(&self, ip: Ipv6Addr) -> Option<&AsnInfo> {
+        self.lookup(IpAddr::V6(ip))
+    }
+
+    /// Number of IPv4 and IPv6 ranges loaded.
+    pub fn len(&self) -> (usize, usize) {
+        (self.v4_ranges.len(), self.v6_ranges.len())
+    }
+
+    /// Return `true` if no ranges are loaded.
+    pub fn is_empty(&self) -> bool {
+        self.v4_ranges.is_empty() && self.v6_ranges.is_empty()
+    }
+}
+
+/// Positions of the columns of Team Cymru's whois output.
+#[derive(Debug, Clone, Copy)]
+struct CymruColumns {
+    asn: usize,
+    ip: usize,
+    prefix: Option<usize>,
+    country: Option<usize>,
+    name: Option<usize>,
+}
+
+impl CymruColumns {
+    fn from_header(fields: &[&str]) -> Self {
+        let position = |name: &str| fields.iter().position(|f| f.eq_ignore_ascii_case(name));
+        CymruColumns {
+            asn: 0,
+            ip: position("IP").unwrap_or(1),
+            prefix: position("BGP Prefix"),
+            country: position("CC"),
+            name: position("AS Name"),
+        }
+    }
+
+    /// Columns of headerless output with `len` fields: `-v` (verbose) output
+    /// has seven, the default output three.
+    fn default_for(len: usize) -> Self {
+        if len > 3 {
+            CymruColumns {
+                asn: 0,
+                ip: 1,
+                prefix: Some(2),
+                country: Some(3),
+                name: Some(6),
+            }
+        } else {
+            CymruColumns {
+                asn: 0,
+                ip: 1,
+                prefix: None,
+                country: None,
+                name: Some(2),
+            }
+        }
+    }
+}
+
+fn u128_of(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4).into(),
+        IpAddr::V6(v6) => v6.into(),
+    }
+}
+
+fn find<T: Ord + Copy>(ranges: &[(T, T, u32)], ip: T) -> Option<u32> {
+    let idx = ranges
+        .partition_point(|&(start, _, _)| start <= ip)
+        .checked_sub(1)?;
+    let (_, end, record) = ranges[idx];
+    (ip <= end).then_some(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V4: &str = "\
+1.0.0.0\t1.0.0.255\t13335\tUS\tCLOUDFLARENET
+1.0.1.0\t1.0.3.255\t0\tNone\tNot routed
+46.4.0.0\t46.4.255.255\t24940\tDE\tHETZNER-AS
+88.198.0.0\t88.198.255.255\t24940\tDE\tHETZNER-AS
+garbage line
+";
+    const V6: &str = "2a01:4f8::\t2a01:4f8:ffff:ffff:ffff:ffff:ffff:ffff\t24940\tDE\tHETZNER-AS\n";
+
+    #[test]
+    fn test_lookup_v4() {
+        let db = AsnDb::from_ip2asn_str(V4);
+        assert_eq!(db.len(), (3, 0));
+        // Both Hetzner ranges share one record
+        assert_eq!(db.records.len(), 2);
+
+        let info = db.lookup("1.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(info.asn, 13335);
+        assert_eq!(info.description, "CLOUDFLARENET");
+        assert_eq!(db.asn_of("88.198.0.1".parse().unwrap()), Some(24940));
+        assert_eq!(db.asn_of("1.0.2.1".parse().unwrap()), None);
+        assert_eq!(db.asn_of("46.5.0.0".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_extend_with_v6_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let (v4, v6) = (
+            dir.path().join("ip2asn-v4.tsv"),
+            dir.path().join("ip2asn-v6.tsv"),
+        );
+        fs::write(&v4, V4).unwrap();
+        fs::write(&v6, V6).unwrap();
+
+        let mut db = AsnDb::from_ip2asn_file(&v4).unwrap();
+        db.extend_from_ip2asn_file(&v6).unwrap();
+
+        assert_eq!(db.asn_of("2a01:4f8::1".parse().unwrap()), Some(24940));
+        assert_eq!(db.asn_of("1.0.0.1".parse().unwrap()), Some(13335));
+    }
+
+    #[test]
+    fn test_from_cymru_str() {
+        let db = AsnDb::from_cymru_str(
+            "Bulk mode; whois.cymru.com [2025-01-01 00:00:00 +0000]\n\
+             AS      | IP               | BGP Prefix          | CC | Registry | Allocated  | AS Name\n\
+             3320    | 80.128.0.1       | 80.128.0.0/11       | DE | ripencc  | 2001-01-01 | DTAG Internet service provider operations, DE\n\
+             8881    | 80.130.0.1       | 80.130.0.0/16       | DE | ripencc  | 2001-01-01 | VERSATEL, DE\n\
+             3320    | 80.128.1.1       | 80.128.0.0/11       | DE | ripencc  | 2001-01-01 | DTAG Internet service provider operations, DE\n\
+             24940   | 2a01:4f8::1      | 2a01:4f8::/32       | DE | ripencc  | 2005-12-12 | HETZNER-AS, DE\n\
+             NA      | 10.0.0.1         | NA                  |    | other    |            | NA\n",
+        );
+        let asn = |ip: &str| db.asn_of(ip.parse().unwrap());
+
+        // The /16 carves a hole into the /11; the repeated answer adds nothing
+        assert_eq!(db.len(), (3, 1));
+        assert_eq!(db.records.len(), 3);
+        assert_eq!(asn("80.129.255.255"), Some(3320));
+        assert_eq!(asn("80.130.7.1"), Some(8881));
+        assert_eq!(asn("80.131.0.0"), Some(3320));
+        assert_eq!(asn("2a01:4f8:1::1"), Some(24940));
+        assert_eq!(asn("10.0.0.1"), None);
+        let info = db.lookup("80.128.0.1".parse().unwrap()).unwrap();
+        assert_eq!(
+            info.description,
+            "DTAG Internet service provider operations"
+        );
+        assert_eq!(info.country, "DE");
+
+        // Default output has no prefix: only the queried addresses are covered
+        let db = AsnDb::from_cymru_str("15169   | 8.8.8.8          | GOOGLE, US\n");
+        assert_eq!(db.asn_of("8.8.8.8".parse().unwrap()), Some(15169));
+        assert_eq!(db.asn_of("8.8.8.9".parse().unwrap()), None);
+        assert_eq!(db.records[0].description, "GOOGLE, US");
+    }
+}
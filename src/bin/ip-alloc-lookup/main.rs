@@ -0,0 +1,308 @@
+//! Command-line access to the allocation data.
+//!
+//! ```text
+//! ip-alloc-lookup [--data <delegated file>] <command> [options]
+//! ```
+//!
+//! Without `--data` the embedded snapshot is used.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+use ip_alloc_lookup::enrich::{LogFormat, enrich_log};
+use ip_alloc_lookup::{ExportFormat, GeoIpDb, ParsedDelegated};
+
+#[cfg(feature = "serve")]
+mod serve;
+mod stats;
+mod validate;
+
+const USAGE: &str = "\
+usage: ip-alloc-lookup [--data <delegated file>] <command> [options]
+
+commands:
+  enrich [--format combined|json] [--output <file>] [<log file>]
+                            append country and region to each access log line
+                            (stdin to stdout by default)
+  export --format <format> [--countries <CC,CC,...>] [--output <file>]
+                            write the ranges as csv, nftables, haproxy or mmdb
+                            (to stdout by default)
+  ranges <CC> [--v4|--v6] [--aggregate]
+                            prefixes allocated to a country; --aggregate joins
+                            adjacent blocks
+  serve [--listen <addr>]   HTTP lookup service (default 127.0.0.1:8080),
+                            needs the `serve` feature
+  stats [<delegated file>]  range counts, per-region coverage, top countries
+                            and snapshot date
+  validate <delegated file> [--min-coverage <percent>]
+                            strict checks; exits non-zero on errors. Coverage
+                            is relative to the --data file or embedded snapshot
+
+Without --data the embedded snapshot is used.";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(msg) => {
+            eprintln!("ip-alloc-lookup: {msg}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut args = Args::new(args);
+    let data = args.option("--data")?;
+    let Some(command) = args.next() else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+
+    match command.as_str() {
+        "enrich" => enrich_command(args, data),
+        "export" => export_command(args, data),
+        "ranges" => ranges_command(args, data),
+        "serve" => serve_command(args, data),
+        "stats" => stats_command(args, data),
+        "validate" => validate_command(args, data),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        other => Err(format!("unknown command `{other}`\n\n{USAGE}")),
+    }
+}
+
+fn enrich_command(mut args: Args, data: Option<String>) -> Result<(), String> {
+    let format: LogFormat = match args.option("--format")? {
+        Some(name) => name.parse().map_err(|e| format!("{e}"))?,
+        None => LogFormat::Combined,
+    };
+    let output = args.option("--output")?;
+    let input = args.next();
+    args.finish()?;
+
+    let db = load_db(data.as_deref())?;
+    let reader: Box<dyn BufRead> = match &input {
+        Some(path) => Box::new(BufReader::new(
+            File::open(path).map_err(|e| format!("{path}: {e}"))?,
+        )),
+        None => Box::new(io::stdin().lock()),
+    };
+    let result = match &output {
+        Some(path) => File::create(path)
+            .and_then(|file| enrich_log(&db, format, reader, BufWriter::new(file))),
+        None => enrich_log(&db, format, reader, BufWriter::new(io::stdout().lock())),
+    };
+    match result {
+        Ok(summary) => {
+            eprintln!(
+                "{} lines, {} classified, {} without an address",
+                summary.lines, summary.classified, summary.without_ip
+            );
+            Ok(())
+        }
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        Err(e) => Err(format!("enrich: {e}")),
+    }
+}
+
+fn export_command(mut args: Args, data: Option<String>) -> Result<(), String> {
+    let format: ExportFormat = args
+        .option("--format")?
+        .ok_or("export needs --format csv|nftables|haproxy|mmdb")?
+        .parse()
+        .map_err(|e| format!("{e}"))?;
+    let countries = args.option("--countries")?.unwrap_or_default();
+    let countries: Vec<&str> = countries.split(',').filter(|cc| !cc.is_empty()).collect();
+    let output = args.option("--output")?;
+    args.finish()?;
+
+    let db = load_db(data.as_deref())?;
+    let result = match &output {
+        Some(path) => {
+            File::create(path).and_then(|file| db.export(format, &countries, BufWriter::new(file)))
+        }
+        None => db.export(format, &countries, BufWriter::new(io::stdout().lock())),
+    };
+    match result {
+        // The reader of the output stopped early, e.g. `| head`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => {
+            result.map_err(|e| format!("export to {}: {e}", output.as_deref().unwrap_or("stdout")))
+        }
+    }
+}
+
+fn ranges_command(mut args: Args, data: Option<String>) -> Result<(), String> {
+    let (v4, v6) = (args.flag("--v4"), args.flag("--v6"));
+    let aggregate = args.flag("--aggregate");
+    let country = args.next().ok_or("ranges needs a country code")?;
+    args.finish()?;
+
+    let db = load_db(data.as_deref())?;
+    let cidrs = if aggregate {
+        db.country_cidrs_aggregated(&country)
+    } else {
+        db.country_cidrs(&country)
+    };
+    // Neither flag means both families
+    let mut out = BufWriter::new(io::stdout().lock());
+    let result = cidrs
+        .iter()
+        .filter(|net| {
+            if net.network().is_ipv4() {
+                v4 || !v6
+            } else {
+                v6 || !v4
+            }
+        })
+        .try_for_each(|net| writeln!(out, "{net}"))
+        .and_then(|()| out.flush());
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(format!("stdout: {e}")),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "serve")]
+fn serve_command(mut args: Args, data: Option<String>) -> Result<(), String> {
+    let listen = args
+        .option("--listen")?
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    args.finish()?;
+    let db = load_db(data.as_deref())?;
+    serve::run(db, &listen).map_err(|e| format!("serve on {listen}: {e}"))
+}
+
+#[cfg(not(feature = "serve"))]
+fn serve_command(_: Args, _: Option<String>) -> Result<(), String> {
+    Err("built without the `serve` feature; reinstall with `--features serve`".to_string())
+}
+
+fn stats_command(mut args: Args, data: Option<String>) -> Result<(), String> {
+    let file = args.next().or(data);
+    args.finish()?;
+    let db = load_db(file.as_deref())?;
+    print!("{}", stats::render(&db));
+    Ok(())
+}
+
+fn validate_command(mut args: Args, data: Option<String>) -> Result<(), String> {
+    let min_coverage = args
+        .option("--min-coverage")?
+        .map(|percent| {
+            percent
+                .parse::<f64>()
+                .map_err(|_| format!("invalid --min-coverage `{percent}`"))
+        })
+        .transpose()?;
+    let file = args.next().ok_or("validate needs a delegated file")?;
+    args.finish()?;
+
+    let content = std::fs::read(&file).map_err(|e| format!("{file}: {e}"))?;
+    let parsed = ParsedDelegated::parse_bytes(&content);
+    let reference = load_db(data.as_deref())?;
+    let report = validate::check(&parsed, &reference, min_coverage);
+    println!("{file}: {report}");
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(format!("{file} failed validation"))
+    }
+}
+
+/// Load the delegated file at `path`, or the embedded snapshot.
+fn load_db(path: Option<&str>) -> Result<GeoIpDb, String> {
+    match path {
+        Some(path) => GeoIpDb::from_ripe_delegated_file(path).map_err(|e| format!("{path}: {e}")),
+        None => Ok(GeoIpDb::new()),
+    }
+}
+
+/// Remaining command-line arguments.
+struct Args {
+    args: Vec<String>,
+}
+
+impl Args {
+    fn new(args: Vec<String>) -> Self {
+        Args { args }
+    }
+
+    /// Remove `--name <value>` (or `--name=<value>`) from anywhere in the
+    /// arguments and return the value.
+    fn option(&mut self, name: &str) -> Result<Option<String>, String> {
+        let prefix = format!("{name}=");
+        let Some(idx) = self
+            .args
+            .iter()
+            .position(|arg| arg == name || arg.starts_with(&prefix))
+        else {
+            return Ok(None);
+        };
+        let arg = self.args.remove(idx);
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Ok(Some(value.to_string()));
+        }
+        if idx < self.args.len() {
+            Ok(Some(self.args.remove(idx)))
+        } else {
+            Err(format!("{name} needs a value"))
+        }
+    }
+
+    /// Remove the flag `name` from anywhere in the arguments and return
+    /// whether it was present.
+    fn flag(&mut self, name: &str) -> bool {
+        let len = self.args.len();
+        self.args.retain(|arg| arg != name);
+        self.args.len() != len
+    }
+
+    /// Fail if any arguments are left over.
+    fn finish(self) -> Result<(), String> {
+        match self.args.first() {
+            Some(arg) => Err(format!("unexpected argument `{arg}`")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Iterator for Args {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        (!self.args.is_empty()).then(|| self.args.remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Args {
+        Args::new(list.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn test_args() {
+        let mut a = args(&["serve", "--listen", "0.0.0.0:80", "--data=ripe.txt"]);
+        assert_eq!(a.option("--data").unwrap().as_deref(), Some("ripe.txt"));
+        assert_eq!(a.next().as_deref(), Some("serve"));
+        assert_eq!(a.option("--listen").unwrap().as_deref(), Some("0.0.0.0:80"));
+        assert!(a.finish().is_ok());
+
+        let mut a = args(&["ranges", "--v6", "DE"]);
+        assert!(a.flag("--v6"));
+        assert!(!a.flag("--v4"));
+        assert_eq!(a.collect::<Vec<_>>(), ["ranges", "DE"]);
+
+        assert!(args(&["--listen"]).option("--listen").is_err());
+        assert_eq!(
+            args(&["DE"]).finish().unwrap_err(),
+            "unexpected argument `DE`"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! `serve`: a minimal HTTP/1.1 lookup service on the standard library.
+//!
+//! `GET /lookup/{ip}` answers with a JSON object; every response closes the
+//! connection. Connections are handled by a fixed pool of worker threads,
+//! which is plenty for a sidecar serving other services; when all workers
+//! are busy, new connections wait in the listen backlog.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use ip_alloc_lookup::GeoIpDb;
+
+/// Requests whose head does not arrive within this time are dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest request head (request line and headers) read.
+const MAX_HEAD: u64 = 16 * 1024;
+/// Connections handled at the same time.
+const WORKERS: usize = 32;
+
+/// Listen on `listen` and answer lookups until the process is stopped.
+pub fn run(db: GeoIpDb, listen: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+    let db = Arc::new(db);
+    // A rendezvous channel: accepting blocks until a worker is free.
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(0);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..WORKERS {
+        let db = Arc::clone(&db);
+        let receiver = Arc::clone(&receiver);
+        thread::spawn(move || {
+            loop {
+                let Ok(stream) = receiver.lock().unwrap().recv() else {
+                    return;
+                };
+                // The client went away; nothing to report
+                let _ = handle(stream, &db);
+            }
+        });
+    }
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        if sender.send(stream).is_err() {
+            return Err(io::Error::other("all serve workers have stopped"));
+        }
+    }
+    Ok(())
+}
+
+fn handle(stream: TcpStream, db: &GeoIpDb) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut head = BufReader::new(stream).take(MAX_HEAD);
+
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    // Skip the headers; nothing in them changes the answer
+    let mut header = String::new();
+    while head.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => respond(db, method, target),
+        _ => (400, error_json("malformed request")),
+    };
+
+    let mut stream = head.into_inner().into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        reason(status),
+        body.len(),
+    )?;
+    stream.flush()
+}
+
+/// Status code and JSON body for a request.
+fn respond(db: &GeoIpDb, method: &str, target: &str) -> (u16, String) {
+    let path = target.split('?').next().unwrap_or_default();
+    let Some(ip) = path.strip_prefix("/lookup/") else {
+        return (404, error_json("not found"));
+    };
+    if method != "GET" {
+        return (405, error_json("method not allowed"));
+    }
+    let Ok(ip) = ip.parse::<IpAddr>() else {
+        return (400, error_json("invalid IP address"));
+    };
+    match db.lookup_range(ip) {
+        Some(m) => (
+            200,
+            format!(
+                r#"{{"ip":"{ip}","country":"{}","region":"{}","is_eu":{},"range":{{"start":"{}","end":"{}","cidr":"{}"}}}}"#,
+                escape(m.info.country_code_str()),
+                m.info.region_enum(),
+                m.info.is_eu,
+                m.start,
+                m.end,
+                m.cidr_string(),
+            ),
+        ),
+        None => (
+            404,
+            format!(r#"{{"ip":"{ip}","error":"address not covered"}}"#),
+        ),
+    }
+}
+
+fn error_json(msg: &str) -> String {
+    format!(r#"{{"error":"{msg}"}}"#)
+}
+
+/// Escape `s` for use inside a JSON string.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_respond() {
+        let db =
+            GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|768|20250101|allocated\n");
+
+        let (status, body) = respond(&db, "GET", "/lookup/46.4.2.1?pretty");
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            r#"{"ip":"46.4.2.1","country":"DE","region":"European Union","is_eu":true,"range":{"start":"46.4.0.0","end":"46.4.2.255","cidr":"46.4.0.0/23,46.4.2.0/24"}}"#
+        );
+
+        assert_eq!(respond(&db, "GET", "/lookup/8.8.8.8").0, 404);
+        assert_eq!(respond(&db, "GET", "/lookup/nope").0, 400);
+        assert_eq!(respond(&db, "POST", "/lookup/46.4.0.1").0, 405);
+        assert_eq!(respond(&db, "GET", "/").0, 404);
+        assert_eq!(escape("a\"b\\\n"), "a\\\"b\\\\\\u000a");
+    }
+}
@@ -0,0 +1,169 @@
+//! `stats`: a summary of a snapshot for eyeballing it before deploying it.
+
+use std::cmp::Reverse;
+use std::fmt::Write;
+
+use ip_alloc_lookup::GeoIpDb;
+
+/// Countries listed per address family.
+const TOP_COUNTRIES: usize = 10;
+
+/// Render the report for `db`.
+pub fn render(db: &GeoIpDb) -> String {
+    let mut out = String::new();
+    // Writing to a String cannot fail
+    let _ = write_report(db, &mut out);
+    out
+}
+
+fn write_report(db: &GeoIpDb, out: &mut String) -> std::fmt::Result {
+    match db.source_metadata() {
+        Some(meta) => {
+            write!(
+                out,
+                "source:   {}, snapshot {}",
+                meta.registry,
+                date(&meta.end_date)
+            )?;
+            if let Some(format) = meta.format {
+                write!(out, " ({} format)", format.as_str())?;
+            }
+            writeln!(out)?;
+        }
+        None => writeln!(out, "source:   unknown")?,
+    }
+
+    let stats = db.stats();
+    let countries = db.country_stats();
+    let v4_total: u64 = countries.iter().map(|c| c.v4_addresses).sum();
+    writeln!(
+        out,
+        "ranges:   {} IPv4, {} IPv6 ({} and {} EU)",
+        stats.total_v4_ranges, stats.total_v6_ranges, stats.eu_v4_ranges, stats.eu_v6_ranges
+    )?;
+    writeln!(
+        out,
+        "EU share: {:.1}% of IPv4, {:.1}% of IPv6 addresses",
+        stats.eu_address_share_v4(),
+        stats.eu_address_share_v6()
+    )?;
+    writeln!(
+        out,
+        "coverage: {} IPv4 addresses ({:.1}% of the address space), {} countries",
+        v4_total,
+        percent(v4_total, 1 << 32),
+        countries.len()
+    )?;
+
+    writeln!(out, "\nby region:")?;
+    writeln!(
+        out,
+        "  {:<16} {:>9} {:>14} {:>6} {:>9} {:>14}",
+        "region", "v4 ranges", "v4 addresses", "share", "v6 ranges", "v6 /48s"
+    )?;
+    let mut regions = stats.regions.clone();
+    regions.sort_by_key(|r| Reverse(r.v4_addresses));
+    for region in &regions {
+        writeln!(
+            out,
+            "  {:<16} {:>9} {:>14} {:>5.1}% {:>9} {:>14}",
+            region.region.as_str(),
+            region.v4_ranges,
+            region.v4_addresses,
+            stats.v4_share(region.region),
+            region.v6_ranges,
+            slash48s(region.v6_addresses)
+        )?;
+    }
+
+    let mut by_v4 = countries.clone();
+    by_v4.sort_by_key(|c| Reverse(c.v4_addresses));
+    writeln!(out, "\ntop countries by IPv4 addresses:")?;
+    for country in by_v4.iter().take(TOP_COUNTRIES) {
+        writeln!(
+            out,
+            "  {}  {:>14}  {:>5.1}%",
+            country.country_code_str(),
+            country.v4_addresses,
+            percent(country.v4_addresses, v4_total)
+        )?;
+    }
+
+    let mut by_v6 = countries;
+    by_v6.sort_by_key(|c| Reverse(c.v6_addresses));
+    writeln!(out, "\ntop countries by IPv6 /48s:")?;
+    for country in by_v6.iter().filter(|c| c.v6_ranges > 0).take(TOP_COUNTRIES) {
+        writeln!(
+            out,
+            "  {}  {:>14}",
+            country.country_code_str(),
+            slash48s(country.v6_addresses)
+        )?;
+    }
+    Ok(())
+}
+
+/// `20240101` as `2024-01-01`; anything else unchanged.
+fn date(raw: &str) -> String {
+    if raw.len() == 8 && raw.bytes().all(|b| b.is_ascii_digit()) {
+        format!("{}-{}-{}", &raw[..4], &raw[4..6], &raw[6..])
+    } else {
+        raw.to_string()
+    }
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+/// Number of /48 networks in `addresses` IPv6 addresses, rounded up.
+fn slash48s(addresses: u128) -> u128 {
+    addresses.div_ceil(1 << 80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "2|ripencc|1700000000|4|19830705|20240101|+0100\n\
+             ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+             ripencc|CH|ipv4|46.5.0.0|256|20090101|assigned\n\
+             ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        );
+        let report = render(&db);
+
+        assert!(report.starts_with("source:   ripencc, snapshot 2024-01-01"));
+        assert!(report.contains("ranges:   3 IPv4, 1 IPv6 (2 and 1 EU)"));
+        assert!(report.contains("EU share: 100.0% of IPv4, 100.0% of IPv6 addresses"));
+        assert!(report.contains("coverage: 1114368 IPv4 addresses"));
+        assert!(report.contains("3 countries"));
+
+        let eu = report
+            .lines()
+            .find(|line| line.trim_start().starts_with("European Union"))
+            .unwrap();
+        assert!(eu.contains("1114112"), "{eu}");
+        assert!(eu.contains("65536"), "{eu}");
+
+        // FR has the most IPv4 space; only DE has IPv6
+        let top_v4 = report.split("top countries by IPv4").nth(1).unwrap();
+        assert!(
+            top_v4
+                .lines()
+                .nth(1)
+                .unwrap()
+                .trim_start()
+                .starts_with("FR")
+        );
+        let top_v6 = report.split("top countries by IPv6").nth(1).unwrap();
+        assert_eq!(top_v6.lines().filter(|l| l.starts_with("  ")).count(), 1);
+    }
+}
@@ -0,0 +1,355 @@
+//! `validate`: strict checks of a delegated file, for gating data refreshes.
+
+use std::fmt;
+
+use ip_alloc_lookup::{DataSource, GeoIpDb, IpRange, ParsedDelegated, RangeStart};
+
+/// Problems listed per kind; the rest are only counted.
+const MAX_SAMPLES: usize = 10;
+
+/// Outcome of [`check`].
+pub struct Report {
+    /// One-line description of the checked content.
+    pub summary: String,
+    /// Problems that make the file unfit for use.
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    /// How much of the reference data the file covers.
+    pub coverage: Option<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.summary)?;
+        for error in &self.errors {
+            writeln!(f, "error: {error}")?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "warning: {warning}")?;
+        }
+        if let Some(coverage) = &self.coverage {
+            writeln!(f, "coverage: {coverage}")?;
+        }
+        match self.errors.len() {
+            0 => write!(f, "ok"),
+            1 => write!(f, "FAILED: 1 error"),
+            n => write!(f, "FAILED: {n} errors"),
+        }
+    }
+}
+
+/// Check `parsed` strictly: summary counts, malformed lines, ranges that
+/// overlap or run past the end of the address space, and, against
+/// `reference`, the share of its address space the file still covers.
+///
+/// Coverage below `min_coverage` percent (of either family) is an error.
+pub fn check(parsed: &ParsedDelegated, reference: &GeoIpDb, min_coverage: Option<f64>) -> Report {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let counts = parsed.record_counts();
+    let summary = match parsed.metadata() {
+        Some(meta) => format!(
+            "{} snapshot {}: {} IPv4 and {} IPv6 records",
+            meta.registry, meta.end_date, counts.ipv4, counts.ipv6
+        ),
+        None => {
+            warnings.push("no version header line".to_string());
+            format!("{} IPv4 and {} IPv6 records", counts.ipv4, counts.ipv6)
+        }
+    };
+
+    if let Err(e) = parsed.verify() {
+        errors.push(e.to_string());
+    }
+
+    let diagnostics = parsed.diagnostics();
+    if diagnostics.malformed > 0 {
+        let mut error = format!("{} malformed lines", diagnostics.malformed);
+        for sample in &diagnostics.malformed_samples {
+            error.push_str(&format!(
+                "\n    line {} ({}): {}",
+                sample.line_number, sample.reason, sample.line
+            ));
+        }
+        errors.push(error);
+    }
+    if diagnostics.without_id > 0 {
+        warnings.push(format!(
+            "{} records without the opaque id of the extended format (a concatenated file?)",
+            diagnostics.without_id
+        ));
+    }
+
+    if parsed.ranges.is_empty() {
+        errors.push("no IPv4 or IPv6 records".to_string());
+    } else if counts.ipv4 == 0 || counts.ipv6 == 0 {
+        let family = if counts.ipv4 == 0 { "IPv4" } else { "IPv6" };
+        warnings.push(format!("no {family} records"));
+    }
+
+    let (mut v4, mut v6) = (Vec::new(), Vec::new());
+    let mut too_long = Vec::new();
+    for range in &parsed.ranges {
+        let (start, max, spans) = match range.start {
+            RangeStart::V4(start) => (u32::from(start).into(), u32::MAX.into(), &mut v4),
+            RangeStart::V6(start) => (u128::from(start), u128::MAX, &mut v6),
+        };
+        let end = start.checked_add(range.count.saturating_sub(1));
+        if end.is_none_or(|end| end > max) {
+            too_long.push(describe(range));
+        }
+        spans.push((start, end.unwrap_or(u128::MAX).min(max), range));
+    }
+    if !too_long.is_empty() {
+        errors.push(samples(
+            too_long,
+            "ranges run past the end of the address space",
+        ));
+    }
+    let overlaps: Vec<String> = overlaps(&mut v4).chain(overlaps(&mut v6)).collect();
+    if !overlaps.is_empty() {
+        errors.push(samples(overlaps, "overlapping ranges"));
+    }
+
+    let coverage = coverage(&v4, &v6, reference);
+    if let Some(min) = min_coverage {
+        for &(family, share) in &coverage {
+            if share < min {
+                errors.push(format!(
+                    "covers {share:.1}% of the reference {family} space, below {min}%"
+                ));
+            }
+        }
+    }
+
+    Report {
+        summary,
+        errors,
+        warnings,
+        coverage: (!coverage.is_empty()).then(|| {
+            coverage
+                .iter()
+                .enumerate()
+                .map(|(i, (family, share))| match i {
+                    0 => format!("{share:.1}% of the reference {family} addresses"),
+                    _ => format!("{share:.1}% of its {family} addresses"),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        }),
+    }
+}
+
+/// Ranges of `spans` (`start`, `end`, range) that overlap an earlier one.
+fn overlaps<'a>(spans: &'a mut [(u128, u128, &IpRange)]) -> impl Iterator<Item = String> + 'a {
+    spans.sort_by_key(|&(start, end, _)| (start, end));
+    let mut furthest: Option<(u128, &IpRange)> = None;
+    spans.iter().filter_map(move |&(start, end, range)| {
+        let overlap = match furthest {
+            Some((furthest_end, other)) if start <= furthest_end => {
+                Some(format!("{} overlaps {}", describe(range), describe(other)))
+            }
+            _ => None,
+        };
+        if furthest.is_none_or(|(furthest_end, _)| end > furthest_end) {
+            furthest = Some((end, range));
+        }
+        overlap
+    })
+}
+
+/// Share of the reference IPv4 and IPv6 addresses that the parsed `v4` and
+/// `v6` spans cover, in percent, for each family the reference has addresses
+/// of. Only addresses in both count, so the share never exceeds 100%.
+fn coverage(
+    v4: &[(u128, u128, &IpRange)],
+    v6: &[(u128, u128, &IpRange)],
+    reference: &GeoIpDb,
+) -> Vec<(&'static str, f64)> {
+    let (mut ref_v4, mut ref_v6) = (Vec::new(), Vec::new());
+    for range in reference.ranges().unwrap_or_default() {
+        let (start, spans) = match range.start {
+            RangeStart::V4(start) => (u32::from(start).into(), &mut ref_v4),
+            RangeStart::V6(start) => (u128::from(start), &mut ref_v6),
+        };
+        spans.push((start, start.saturating_add(range.count - 1)));
+    }
+    let share = |spans: &[(u128, u128, &IpRange)], mut reference: Vec<(u128, u128)>| {
+        reference.sort_unstable();
+        let total = reference.iter().fold(0u128, |n, &(start, end)| {
+            n.saturating_add(end - start).saturating_add(1)
+        });
+        // An empty reference family has nothing to cover
+        (total > 0).then(|| covered(&merge(spans), &reference) as f64 * 100.0 / total as f64)
+    };
+    [("IPv4", share(v4, ref_v4)), ("IPv6", share(v6, ref_v6))]
+        .into_iter()
+        .filter_map(|(family, share)| Some((family, share?)))
+        .collect()
+}
+
+/// The sorted `spans` with overlapping and adjacent ones merged.
+fn merge(spans: &[(u128, u128, &IpRange)]) -> Vec<(u128, u128)> {
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(spans.len());
+    for &(start, end, _) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Number of addresses in both `a` and `b`, two sorted lists of disjoint
+/// spans.
+fn covered(a: &[(u128, u128)], b: &[(u128, u128)]) -> u128 {
+    let (mut i, mut j, mut n) = (0, 0, 0u128);
+    while i < a.len() && j < b.len() {
+        let start = a[i].0.max(b[j].0);
+        let end = a[i].1.min(b[j].1);
+        if start <= end {
+            n = n.saturating_add(end - start).saturating_add(1);
+        }
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    n
+}
+
+fn samples(mut list: Vec<String>, what: &str) -> String {
+    let mut out = format!("{} {what}", list.len());
+    let more = list.len().saturating_sub(MAX_SAMPLES);
+    list.truncate(MAX_SAMPLES);
+    for item in list {
+        out.push_str(&format!("\n    {item}"));
+    }
+    if more > 0 {
+        out.push_str(&format!("\n    ... and {more} more"));
+    }
+    out
+}
+
+/// `46.4.0.0-46.4.255.255 (DE)`
+fn describe(range: &IpRange) -> String {
+    let start = match range.start {
+        RangeStart::V4(v4) => v4.to_string(),
+        RangeStart::V6(v6) => v6.to_string(),
+    };
+    format!("{start}-{} ({})", range.end(), range.country)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference() -> GeoIpDb {
+        GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        )
+    }
+
+    #[test]
+    fn test_check() {
+        let good = ParsedDelegated::parse(
+            "2|ripencc|1700000000|2|19830705|20240101|+0100\n\
+             ripencc|*|ipv4|*|1|summary\n\
+             ripencc|*|ipv6|*|1|summary\n\
+             ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated|a1\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated|a1\n",
+        );
+        let report = check(&good, &reference(), Some(99.0));
+        assert!(report.is_ok(), "{report}");
+        assert!(report.warnings.is_empty(), "{report}");
+        assert!(report.to_string().ends_with("\nok"));
+
+        let bad = ParsedDelegated::parse(
+            "ripencc|*|ipv4|*|4|summary\n\
+             ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated|a1\n\
+             ripencc|CH|ipv4|46.4.0.128|256|20090101|allocated|a2\n\
+             ripencc|FR|ipv4|255.255.255.0|512|20090101|allocated|a3\n\
+             ripencc|FR|ipv4|2.0.0.0|many|20090101|allocated|a3\n",
+        );
+        let report = check(&bad, &reference(), Some(50.0));
+        assert!(!report.is_ok());
+        let text = report.to_string();
+        for expected in [
+            "announces 4 ipv4 records but 3 were parsed",
+            "1 malformed lines\n    line 5 (invalid count/prefix length)",
+            "46.4.0.128-46.4.1.127 (CH) overlaps 46.4.0.0-46.4.0.255 (DE)",
+            "255.255.255.0-255.255.255.255 (FR)",
+            "of the reference IPv6 space, below 50%",
+            "warning: no version header line",
+            "warning: no IPv6 records",
+            "FAILED: 6 errors",
+        ] {
+            assert!(text.contains(expected), "{expected:?} not in\n{text}");
+        }
+    }
+
+    #[test]
+    fn test_mixed_formats_are_accepted() {
+        let mixed = ParsedDelegated::parse(
+            "2|ripencc|1700000000|2|19830705|20240101|+0100\n\
+             ripencc|*|ipv4|*|1|summary\n\
+             ripencc|*|ipv6|*|1|summary\n\
+             ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated|a1\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        );
+        let report = check(&mixed, &reference(), Some(99.0));
+        assert!(report.is_ok(), "{report}");
+        assert!(
+            report
+                .warnings
+                .iter()
+                .any(|w| w.starts_with("1 records without the opaque id")),
+            "{report}"
+        );
+    }
+
+    #[test]
+    fn test_coverage_counts_overlap_only() {
+        // Twice the size of the reference, but only half of it inside.
+        let parsed = ParsedDelegated::parse(
+            "ripencc|DE|ipv4|46.3.128.0|65536|20090101|allocated|a1\n\
+             ripencc|FR|ipv4|80.0.0.0|65536|20090101|allocated|a2\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated|a1\n",
+        );
+        let report = check(&parsed, &reference(), Some(60.0));
+        assert_eq!(
+            report.coverage.as_deref(),
+            Some("50.0% of the reference IPv4 addresses, 100.0% of its IPv6 addresses")
+        );
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_coverage_skips_empty_reference_family() {
+        let ipv4_only =
+            GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n");
+        let parsed = ParsedDelegated::parse(
+            "ripencc|*|ipv4|*|1|summary\n\
+             ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated|a1\n",
+        );
+        let report = check(&parsed, &ipv4_only, Some(99.0));
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(
+            report.coverage.as_deref(),
+            Some("100.0% of the reference IPv4 addresses")
+        );
+
+        let report = check(&parsed, &GeoIpDb::from_ripe_delegated_str(""), Some(99.0));
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(report.coverage, None);
+    }
+}
@@ -6,11 +6,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cache_path = "cache/ripe-data.txt";
 
     // 1) Update cache from real RIPE URL
-    let bytes = eu_geoip::GeoIpDb::update_cache(cache_path)?;
+    let bytes = ip_alloc_lookup::GeoIpDb::update_cache(cache_path)?;
     println!("Downloaded {bytes} bytes into {cache_path}");
 
     // 2) Load DB from cache (not embedded)
-    let db = eu_geoip::GeoIpDb::from_ripe_delegated_file(cache_path)?;
+    let db = ip_alloc_lookup::GeoIpDb::from_ripe_delegated_file(cache_path)?;
 
     // 3) Try a lookup
     let ip: IpAddr = "88.198.0.1".parse()?; // commonly DE (Hetzner)
@@ -0,0 +1,568 @@
+//! Pre-parsed binary cache files.
+//!
+//! Parsing a complete delegated file takes seconds, which is paid again at every
+//! process start. After a successful download the parsed tables are therefore
+//! also written to `<cache file>.bin`, and [`GeoIpDb::from_cache_or_embedded`]
+//! loads that instead of the text file when it is still current.
+//!
+//! A binary cache is only used when it was built from the exact text file next
+//! to it: it records the size and SHA-256 of its source, and both must match
+//! the text file. Hashing is an order of magnitude cheaper than parsing, and
+//! catches a text file that was edited or replaced after the download. Anything
+//! else (stale or corrupt binary, unknown format version) falls back to
+//! parsing the text file.
+//!
+//! ## Layout
+//!
+//! All integers are little-endian. Every binary format of this crate starts
+//! with the same 48-byte header, checked by [`open`] before anything else is
+//! read:
+//!
+//! | Field | Size |
+//! |---|---|
+//! | magic number, e.g. `IPALLOC\0` | 8 |
+//! | format version (`u32`) | 4 |
+//! | reserved, zero | 4 |
+//! | SHA-256 of the data following the header | 32 |
+//!
+//! A file with another magic number or format version, or whose data does not
+//! match the hash, is rejected with [`IncompatibleFormat`] rather than
+//! misread. Bump the version whenever the layout of a format changes.
+//!
+//! The binary cache data follows:
+//!
+//! | Field | Size |
+//! |---|---|
+//! | source size in bytes (`u64`) | 8 |
+//! | source SHA-256, lowercase hex | 64 |
+//! | header line length (`u32`), then the header line (UTF-8, may be empty) | 4 + n |
+//! | record format: `0` unknown, `1` standard, `2` extended | 1 |
+//! | IPv4 entry count (`u64`), then `start: u32, end: u32, country: [u8; 2], assigned: u8` per entry | 8 + 11n |
+//! | IPv6 entry count (`u64`), then `start: u128, end: u128, country: [u8; 2], assigned: u8` per entry | 8 + 35n |
+//! | unallocated IPv4 span count (`u64`), then `start: u32, end: u32` per span | 8 + 8n |
+//! | unallocated IPv6 span count (`u64`), then `start: u128, end: u128` per span | 8 + 32n |
+//!
+//! Only country codes and the `assigned` status flag (`0` or `1`) are stored;
+//! the EU flag and region are derived again when loading, so a binary cache
+//! never disagrees with the running crate version's classification.
+//!
+//! The same encoding backs [`GeoIpDb::to_bytes`] / [`GeoIpDb::from_bytes`] for
+//! storing a database outside the filesystem. Such buffers have no source file:
+//! the source size is `0`, and the source hash is the one from the metadata or
+//! 64 zeros if it is unknown.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fs, io};
+
+use sha2::{Digest, Sha256};
+
+use crate::bogon::FreeSpace;
+use crate::cache::sha256_hex;
+use crate::database::{GeoInfo, GeoIpDb};
+use crate::parser::{DelegatedFormat, SourceMetadata};
+
+const MAGIC: &[u8; 8] = b"IPALLOC\0";
+const FORMAT_VERSION: u32 = 5;
+
+/// Length of the header shared by all binary formats. A multiple of 16, so
+/// data that needs 16-byte alignment stays aligned after it.
+pub(crate) const HEADER_LEN: usize = 48;
+
+/// Why a buffer was rejected as written in a different binary format.
+///
+/// Loaders return it wrapped in an [`io::Error`] of kind
+/// [`io::ErrorKind::InvalidData`]; [`IncompatibleFormat::of`] gets it back.
+/// Other errors of that kind mean the data passed these checks but is
+/// malformed.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::{GeoIpDb, IncompatibleFormat};
+///
+/// let err = GeoIpDb::from_bytes(b"definitely not a database").err().unwrap();
+/// assert_eq!(IncompatibleFormat::of(&err), Some(&IncompatibleFormat::WrongMagic));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IncompatibleFormat {
+    /// The data does not start with the magic number of the expected format.
+    WrongMagic,
+    /// The data was written with another version of the format, typically by
+    /// an older or newer release of this crate. Rebuild it from the source.
+    Version { found: u32, expected: u32 },
+    /// The data does not match the hash in its header: it was truncated or
+    /// modified after it was written.
+    HashMismatch,
+}
+
+impl IncompatibleFormat {
+    /// The [`IncompatibleFormat`] an error returned by a loader wraps, if any.
+    pub fn of(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+impl fmt::Display for IncompatibleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IncompatibleFormat::WrongMagic => write!(f, "not a file of the expected format"),
+            IncompatibleFormat::Version { found, expected } => write!(
+                f,
+                "format version {found} is not supported (expected {expected})"
+            ),
+            IncompatibleFormat::HashMismatch => {
+                write!(f, "data does not match the hash in its header")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IncompatibleFormat {}
+
+impl From<IncompatibleFormat> for io::Error {
+    fn from(err: IncompatibleFormat) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// The header for `data` in the format identified by `magic` and `version`.
+pub(crate) fn header(magic: &[u8; 8], version: u32, data: &[u8]) -> [u8; HEADER_LEN] {
+    let mut out = [0; HEADER_LEN];
+    out[..8].copy_from_slice(magic);
+    out[8..12].copy_from_slice(&version.to_le_bytes());
+    out[16..].copy_from_slice(&Sha256::digest(data));
+    out
+}
+
+/// Check the header of `bytes` and return the data following it.
+pub(crate) fn open<'a>(bytes: &'a [u8], magic: &[u8; 8], version: u32) -> io::Result<&'a [u8]> {
+    if bytes.get(..8) != Some(magic) {
+        return Err(IncompatibleFormat::WrongMagic.into());
+    }
+    let found = u32::from_le_bytes(bytes.get(8..12).ok_or_else(truncated)?.try_into().unwrap());
+    if found != version {
+        return Err(IncompatibleFormat::Version {
+            found,
+            expected: version,
+        }
+        .into());
+    }
+    let (head, data) = bytes.split_at_checked(HEADER_LEN).ok_or_else(truncated)?;
+    if head[16..] != Sha256::digest(data)[..] {
+        return Err(IncompatibleFormat::HashMismatch.into());
+    }
+    Ok(data)
+}
+
+fn truncated() -> io::Error {
+    invalid("truncated file header")
+}
+
+/// Source hash written by [`GeoIpDb::to_bytes`] when the source is unknown.
+const UNKNOWN_SHA256: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+impl GeoIpDb {
+    /// Serialize the database into a compact, versioned byte buffer, e.g. to
+    /// stash a prebuilt database in a key-value store or an asset bundle.
+    ///
+    /// The buffer starts with a magic number and format version, so
+    /// [`GeoIpDb::from_bytes`] rejects anything else instead of misreading it.
+    /// Source metadata is kept.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "embedded-data")] {
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let db = GeoIpDb::new();
+    /// let bytes = db.to_bytes();
+    /// let restored = GeoIpDb::from_bytes(&bytes).unwrap();
+    /// assert_eq!(restored.country_str("46.4.0.1".parse().unwrap()), Some("DE"));
+    /// # }
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sha256 = self
+            .metadata
+            .as_ref()
+            .and_then(|meta| meta.sha256.as_deref())
+            .filter(|sha| sha.len() == UNKNOWN_SHA256.len())
+            .unwrap_or(UNKNOWN_SHA256);
+        encode(self, 0, sha256)
+    }
+
+    /// Restore a database written by [`GeoIpDb::to_bytes`].
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the buffer is truncated,
+    /// corrupt, or was written by an incompatible version of this crate.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut db = decode(bytes)?.db;
+        if let Some(meta) = db.metadata.as_mut()
+            && meta.sha256.as_deref() == Some(UNKNOWN_SHA256)
+        {
+            meta.sha256 = None;
+        }
+        Ok(db)
+    }
+}
+
+/// Path of the binary cache belonging to `cache_path` (`<file name>.bin`).
+pub(crate) fn binary_cache_path(cache_path: &Path) -> PathBuf {
+    let mut name = cache_path.as_os_str().to_owned();
+    name.push(".bin");
+    PathBuf::from(name)
+}
+
+/// Atomically write the binary cache for the text file at `cache_path`.
+pub(crate) fn write_binary_cache(
+    cache_path: &Path,
+    db: &GeoIpDb,
+    source_len: u64,
+    source_sha256: &str,
+) -> io::Result<()> {
+    let path = binary_cache_path(cache_path);
+    let tmp_path = path.with_extension("bin.tmp");
+    fs::write(&tmp_path, encode(db, source_len, source_sha256))?;
+    fs::rename(&tmp_path, &path)
+}
+
+/// Load the binary cache of `cache_path` if it was built from the current text file.
+pub(crate) fn load_binary_cache(cache_path: &Path) -> Option<GeoIpDb> {
+    let bytes = fs::read(binary_cache_path(cache_path)).ok()?;
+    let decoded = decode(&bytes).ok()?;
+
+    // Cheap size check first, so a stale binary does not cost a full hash.
+    if fs::metadata(cache_path).ok()?.len() != decoded.source_len {
+        return None;
+    }
+    let source = fs::read(cache_path).ok()?;
+    (sha256_hex(&source) == decoded.source_sha256).then_some(decoded.db)
+}
+
+/// A decoded binary cache.
+pub(crate) struct Decoded {
+    pub(crate) db: GeoIpDb,
+    pub(crate) source_len: u64,
+    pub(crate) source_sha256: String,
+}
+
+pub(crate) fn encode(db: &GeoIpDb, source_len: u64, source_sha256: &str) -> Vec<u8> {
+    let header = db
+        .metadata
+        .as_ref()
+        .map(SourceMetadata::to_header_line)
+        .unwrap_or_default();
+
+    let mut out = Vec::with_capacity(
+        HEADER_LEN + 100 + header.len() + db.v4_ranges.len() * 11 + db.v6_ranges.len() * 35,
+    );
+    out.resize(HEADER_LEN, 0);
+    out.extend_from_slice(&source_len.to_le_bytes());
+    out.extend_from_slice(source_sha256.as_bytes());
+    out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    out.push(match db.metadata.as_ref().and_then(|meta| meta.format) {
+        None => 0,
+        Some(DelegatedFormat::Standard) => 1,
+        Some(DelegatedFormat::Extended) => 2,
+    });
+
+    out.extend_from_slice(&(db.v4_ranges.len() as u64).to_le_bytes());
+    for (start, end, geo) in db.v4_ranges.iter() {
+        out.extend_from_slice(&start.to_le_bytes());
+        out.extend_from_slice(&end.to_le_bytes());
+        out.extend_from_slice(&geo.country_code);
+        out.push(u8::from(geo.assigned));
+    }
+
+    out.extend_from_slice(&(db.v6_ranges.len() as u64).to_le_bytes());
+    for (start, end, geo) in db.v6_ranges.iter() {
+        out.extend_from_slice(&start.to_le_bytes());
+        out.extend_from_slice(&end.to_le_bytes());
+        out.extend_from_slice(&geo.country_code);
+        out.push(u8::from(geo.assigned));
+    }
+
+    out.extend_from_slice(&(db.unallocated.v4.len() as u64).to_le_bytes());
+    for (start, end) in &db.unallocated.v4 {
+        out.extend_from_slice(&start.to_le_bytes());
+        out.extend_from_slice(&end.to_le_bytes());
+    }
+    out.extend_from_slice(&(db.unallocated.v6.len() as u64).to_le_bytes());
+    for (start, end) in &db.unallocated.v6 {
+        out.extend_from_slice(&start.to_le_bytes());
+        out.extend_from_slice(&end.to_le_bytes());
+    }
+
+    let head = self::header(MAGIC, FORMAT_VERSION, &out[HEADER_LEN..]);
+    out[..HEADER_LEN].copy_from_slice(&head);
+    out
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> io::Result<Decoded> {
+    let mut r = Reader {
+        bytes: open(bytes, MAGIC, FORMAT_VERSION)?,
+    };
+
+    let source_len = u64::from_le_bytes(r.array()?);
+    let source_sha256 = std::str::from_utf8(r.take(64)?)
+        .map_err(|_| invalid("invalid source hash"))?
+        .to_string();
+    let header_len = u32::from_le_bytes(r.array()?) as usize;
+    let header =
+        std::str::from_utf8(r.take(header_len)?).map_err(|_| invalid("invalid header line"))?;
+    let format = match r.array()? {
+        [0] => None,
+        [1] => Some(DelegatedFormat::Standard),
+        [2] => Some(DelegatedFormat::Extended),
+        _ => return Err(invalid("invalid record format in binary cache")),
+    };
+
+    let v4_len = r.len_prefix(11)?;
+    let mut v4_ranges = Vec::with_capacity(v4_len);
+    for _ in 0..v4_len {
+        let start = u32::from_le_bytes(r.array()?);
+        let end = u32::from_le_bytes(r.array()?);
+        v4_ranges.push((start, end, r.geo()?));
+    }
+
+    let v6_len = r.len_prefix(35)?;
+    let mut v6_ranges = Vec::with_capacity(v6_len);
+    for _ in 0..v6_len {
+        let start = u128::from_le_bytes(r.array()?);
+        let end = u128::from_le_bytes(r.array()?);
+        v6_ranges.push((start, end, r.geo()?));
+    }
+
+    let free_v4_len = r.len_prefix(8)?;
+    let mut free_v4 = Vec::with_capacity(free_v4_len);
+    for _ in 0..free_v4_len {
+        free_v4.push((
+            u32::from_le_bytes(r.array()?),
+            u32::from_le_bytes(r.array()?),
+        ));
+    }
+    let free_v6_len = r.len_prefix(32)?;
+    let mut free_v6 = Vec::with_capacity(free_v6_len);
+    for _ in 0..free_v6_len {
+        free_v6.push((
+            u128::from_le_bytes(r.array()?),
+            u128::from_le_bytes(r.array()?),
+        ));
+    }
+
+    if !r.bytes.is_empty() {
+        return Err(invalid("trailing data after binary cache tables"));
+    }
+    // Lookups binary-search the tables; refuse anything that would make them lie.
+    if !is_sorted_ranges(&v4_ranges) || !is_sorted_ranges(&v6_ranges) {
+        return Err(invalid("binary cache tables are not sorted or overlap"));
+    }
+    if !is_disjoint_spans(&free_v4) || !is_disjoint_spans(&free_v6) {
+        return Err(invalid("binary cache unallocated spans are not sorted"));
+    }
+
+    Ok(Decoded {
+        db: GeoIpDb {
+            v4_ranges: Arc::new(v4_ranges),
+            v6_ranges: Arc::new(v6_ranges),
+            metadata: SourceMetadata::from_header_line(header).map(|meta| SourceMetadata {
+                sha256: Some(source_sha256.clone()),
+                format,
+                ..meta
+            }),
+            unallocated: Arc::new(FreeSpace {
+                v4: free_v4,
+                v6: free_v6,
+            }),
+        },
+        source_len,
+        source_sha256,
+    })
+}
+
+fn is_sorted_ranges<T: Ord + Copy>(ranges: &[(T, T, GeoInfo)]) -> bool {
+    ranges.iter().all(|&(start, end, _)| start <= end) && ranges.windows(2).all(|w| w[0].1 < w[1].0)
+}
+
+fn is_disjoint_spans<T: Ord + Copy>(spans: &[(T, T)]) -> bool {
+    spans.iter().all(|&(start, end)| start <= end) && spans.windows(2).all(|w| w[0].1 < w[1].0)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Cursor over the encoded bytes; every read is bounds-checked.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < n {
+            return Err(invalid("truncated binary cache file"));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("length checked by take"))
+    }
+
+    /// Read an entry count, rejecting counts the remaining bytes cannot hold.
+    fn len_prefix(&mut self, entry_size: usize) -> io::Result<usize> {
+        let len = u64::from_le_bytes(self.array()?);
+        match usize::try_from(len) {
+            Ok(len) if len <= self.bytes.len() / entry_size => Ok(len),
+            _ => Err(invalid("truncated binary cache file")),
+        }
+    }
+
+    fn geo(&mut self) -> io::Result<GeoInfo> {
+        let cc: [u8; 2] = self.array()?;
+        let assigned = match self.array()? {
+            [0] => false,
+            [1] => true,
+            _ => return Err(invalid("invalid status flag in binary cache")),
+        };
+        Ok(GeoInfo::from_record(
+            std::str::from_utf8(&cc).unwrap_or("??"),
+            assigned,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+2|ripencc|1735776000|3|19830705|20250101|+0100
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|US|ipv4|8.8.8.0|256|20250101|assigned
+ripencc|FR|ipv6|2a01:cb00::|32|20250101|allocated
+ripencc||ipv4|46.4.1.0|256||available
+";
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let db = GeoIpDb::from_ripe_delegated_str(SAMPLE);
+        let bytes = encode(&db, 123, &"ab".repeat(32));
+
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.source_len, 123);
+        assert_eq!(decoded.source_sha256, "ab".repeat(32));
+        let meta = decoded.db.source_metadata().unwrap();
+        assert_eq!(
+            meta.to_header_line(),
+            db.source_metadata().unwrap().to_header_line()
+        );
+        assert_eq!(meta.sha256, Some("ab".repeat(32)));
+        assert_eq!(meta.format, Some(DelegatedFormat::Standard));
+        assert_eq!(decoded.db.stats().total_v4_ranges, 2);
+
+        let info = decoded.db.lookup("46.4.0.1".parse().unwrap()).unwrap();
+        assert_eq!(info.country_code_str(), "DE");
+        assert!(info.is_eu);
+        assert!(!info.assigned);
+        assert!(
+            decoded
+                .db
+                .lookup("8.8.8.8".parse().unwrap())
+                .unwrap()
+                .assigned
+        );
+        assert!(decoded.db.lookup("2a01:cb00::1".parse().unwrap()).is_some());
+        assert!(decoded.db.is_bogon("46.4.1.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_to_bytes_round_trip() {
+        let db = GeoIpDb::from_ripe_delegated_str(SAMPLE);
+        let restored = GeoIpDb::from_bytes(&db.to_bytes()).unwrap();
+        assert!(db.diff(&restored).is_empty());
+        assert_eq!(restored.source_metadata().unwrap().end_date, "20250101");
+        assert_eq!(restored.source_metadata().unwrap().sha256, None);
+
+        let hashed = encode(&db, 7, &"ab".repeat(32));
+        let restored = GeoIpDb::from_bytes(&hashed).unwrap();
+        let again = GeoIpDb::from_bytes(&restored.to_bytes()).unwrap();
+        assert_eq!(
+            again.source_metadata().unwrap().sha256,
+            Some("ab".repeat(32))
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_corrupt_input() {
+        let db = GeoIpDb::from_ripe_delegated_str(SAMPLE);
+        let bytes = encode(&db, 0, &"0".repeat(64));
+
+        let incompatible =
+            |bytes: &[u8]| IncompatibleFormat::of(&decode(bytes).err().unwrap()).cloned();
+        assert_eq!(
+            incompatible(&bytes[..bytes.len() - 1]),
+            Some(IncompatibleFormat::HashMismatch)
+        );
+        assert_eq!(
+            incompatible(b"not a cache"),
+            Some(IncompatibleFormat::WrongMagic)
+        );
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[8] = 3;
+        assert_eq!(
+            incompatible(&wrong_version),
+            Some(IncompatibleFormat::Version {
+                found: 3,
+                expected: FORMAT_VERSION
+            })
+        );
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            incompatible(&flipped),
+            Some(IncompatibleFormat::HashMismatch)
+        );
+
+        // Data that passes the header checks can still be malformed.
+        let mut malformed = bytes[..HEADER_LEN].to_vec();
+        malformed.extend_from_slice(&[0; 7]);
+        let head = header(MAGIC, FORMAT_VERSION, &malformed[HEADER_LEN..]);
+        malformed[..HEADER_LEN].copy_from_slice(&head);
+        let err = decode(&malformed).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(IncompatibleFormat::of(&err).is_none());
+        assert!(incompatible(&bytes[..20]).is_none());
+
+        // Lookups assume disjoint ranges, so overlapping tables are rejected.
+        let overlapping = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated\n\
+             ripencc|FR|ipv4|46.4.0.128|256|20090101|allocated\n",
+        );
+        let err = decode(&encode(&overlapping, 0, &"0".repeat(64)))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_stale_binary_cache_is_ignored() {
+        let fetcher = |_: &str| -> io::Result<Vec<u8>> { Ok(SAMPLE.as_bytes().to_vec()) };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ripe.txt");
+        GeoIpDb::update_cache_with_fetcher(&path, "mem://ripe", &fetcher).unwrap();
+
+        assert!(binary_cache_path(&path).exists());
+        let db = load_binary_cache(&path).unwrap();
+        assert_eq!(db.stats().total_v4_ranges, 2);
+
+        // A text file edited by hand no longer matches the binary cache.
+        fs::write(&path, "ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n").unwrap();
+        assert!(load_binary_cache(&path).is_none());
+    }
+}
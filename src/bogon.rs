@@ -0,0 +1,365 @@
+//! Telling unallocated addresses from special-purpose ones and from those
+//! missing in the loaded data.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::parser::RangeStart;
+use crate::{GeoIpDb, IpRange, RangeMatch};
+
+/// IPv4 special-purpose blocks (RFC 6890 and successors) as network and
+/// prefix length, plus multicast and the reserved 240.0.0.0/4.
+const SPECIAL_V4: &[(Ipv4Addr, u8)] = &[
+    (Ipv4Addr::new(0, 0, 0, 0), 8),
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    (Ipv4Addr::new(100, 64, 0, 0), 10),
+    (Ipv4Addr::new(127, 0, 0, 0), 8),
+    (Ipv4Addr::new(169, 254, 0, 0), 16),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    (Ipv4Addr::new(192, 0, 0, 0), 24),
+    (Ipv4Addr::new(192, 0, 2, 0), 24),
+    (Ipv4Addr::new(192, 88, 99, 0), 24),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+    (Ipv4Addr::new(198, 18, 0, 0), 15),
+    (Ipv4Addr::new(198, 51, 100, 0), 24),
+    (Ipv4Addr::new(203, 0, 113, 0), 24),
+    (Ipv4Addr::new(224, 0, 0, 0), 4),
+    (Ipv4Addr::new(240, 0, 0, 0), 4),
+];
+
+/// IPv6 special-purpose blocks, plus multicast.
+const SPECIAL_V6: &[(Ipv6Addr, u8)] = &[
+    (Ipv6Addr::UNSPECIFIED, 128),
+    (Ipv6Addr::LOCALHOST, 128),
+    (Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0), 96),
+    (Ipv6Addr::new(0x64, 0xff9b, 1, 0, 0, 0, 0, 0), 48),
+    (Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 0), 64),
+    (Ipv6Addr::new(0x2001, 0, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x2002, 0, 0, 0, 0, 0, 0, 0), 16),
+    (Ipv6Addr::new(0x3fff, 0, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7),
+    (Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10),
+    (Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0), 8),
+];
+
+/// IPv6 blocks IANA has allocated to the RIRs, from its IPv6 global unicast
+/// address assignments registry. The rest of `2000::/3` is still held by
+/// IANA.
+const IANA_RIR_V6: &[(Ipv6Addr, u8)] = &[
+    (Ipv6Addr::new(0x2001, 0x0200, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x0400, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x0600, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x0800, 0, 0, 0, 0, 0, 0), 22),
+    (Ipv6Addr::new(0x2001, 0x0c00, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x0e00, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x1200, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x1400, 0, 0, 0, 0, 0, 0), 22),
+    (Ipv6Addr::new(0x2001, 0x1800, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x1a00, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x1c00, 0, 0, 0, 0, 0, 0), 22),
+    (Ipv6Addr::new(0x2001, 0x2000, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0x2001, 0x3000, 0, 0, 0, 0, 0, 0), 21),
+    (Ipv6Addr::new(0x2001, 0x3800, 0, 0, 0, 0, 0, 0), 22),
+    (Ipv6Addr::new(0x2001, 0x4000, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x4200, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x4400, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x4600, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x4800, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x4a00, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x4c00, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2001, 0x5000, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0x2001, 0x8000, 0, 0, 0, 0, 0, 0), 19),
+    (Ipv6Addr::new(0x2001, 0xa000, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0x2001, 0xb000, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0x2003, 0, 0, 0, 0, 0, 0, 0), 18),
+    (Ipv6Addr::new(0x2400, 0, 0, 0, 0, 0, 0, 0), 12),
+    (Ipv6Addr::new(0x2600, 0, 0, 0, 0, 0, 0, 0), 12),
+    (Ipv6Addr::new(0x2610, 0, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2620, 0, 0, 0, 0, 0, 0, 0), 23),
+    (Ipv6Addr::new(0x2630, 0, 0, 0, 0, 0, 0, 0), 12),
+    (Ipv6Addr::new(0x2800, 0, 0, 0, 0, 0, 0, 0), 12),
+    (Ipv6Addr::new(0x2a00, 0, 0, 0, 0, 0, 0, 0), 12),
+    (Ipv6Addr::new(0x2a10, 0, 0, 0, 0, 0, 0, 0), 12),
+    (Ipv6Addr::new(0x2c00, 0, 0, 0, 0, 0, 0, 0), 12),
+];
+
+/// Space the loaded registries list as `available` or `reserved`, as sorted
+/// and disjoint IPv4 and IPv6 spans.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FreeSpace {
+    pub(crate) v4: Vec<(u32, u32)>,
+    pub(crate) v6: Vec<(u128, u128)>,
+}
+
+impl FreeSpace {
+    /// Collect the spans of `available` and `reserved` records, see
+    /// [`ParsedDelegated::unallocated`](crate::ParsedDelegated::unallocated).
+    pub(crate) fn from_records<'a>(records: impl IntoIterator<Item = &'a IpRange>) -> Self {
+        let (mut v4, mut v6) = (Vec::new(), Vec::new());
+        for record in records {
+            match (record.start, record.end()) {
+                (RangeStart::V4(start), IpAddr::V4(end)) => v4.push((start.into(), end.into())),
+                (RangeStart::V6(start), IpAddr::V6(end)) => v6.push((start.into(), end.into())),
+                _ => {}
+            }
+        }
+        Self::from_spans(v4, v6)
+    }
+
+    /// Sort `v4` and `v6` and merge overlapping and adjacent spans.
+    pub(crate) fn from_spans(v4: Vec<(u32, u32)>, v6: Vec<(u128, u128)>) -> Self {
+        FreeSpace {
+            v4: merge_spans(v4, |n| n.checked_add(1)),
+            v6: merge_spans(v6, |n| n.checked_add(1)),
+        }
+    }
+
+    /// The space free in either `self` or `other`.
+    pub(crate) fn union(&self, other: &FreeSpace) -> Self {
+        Self::from_spans(
+            self.v4.iter().chain(&other.v4).copied().collect(),
+            self.v6.iter().chain(&other.v6).copied().collect(),
+        )
+    }
+
+    /// The spans as records without a country, e.g. for
+    /// [`DataSource`](crate::DataSource) implementations.
+    pub(crate) fn to_records(&self) -> Vec<IpRange> {
+        let record = |start: RangeStart, count: u128| IpRange {
+            start,
+            count,
+            country: String::new(),
+            assigned: false,
+            date: String::new(),
+        };
+        let v4 = self
+            .v4
+            .iter()
+            .map(|&(start, end)| record(RangeStart::V4(start.into()), u128::from(end - start) + 1));
+        let v6 = self.v6.iter().map(|&(start, end)| {
+            record(
+                RangeStart::V6(start.into()),
+                (end - start).saturating_add(1),
+            )
+        });
+        v4.chain(v6).collect()
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => span_contains(&self.v4, u32::from(ip)),
+            IpAddr::V6(ip) => span_contains(&self.v6, u128::from(ip)),
+        }
+    }
+}
+
+fn merge_spans<T: Ord + Copy>(mut spans: Vec<(T, T)>, next: fn(T) -> Option<T>) -> Vec<(T, T)> {
+    spans.sort_unstable();
+    let mut merged: Vec<(T, T)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if next(last.1).is_none_or(|after| start <= after) => {
+                last.1 = last.1.max(end);
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged.shrink_to_fit();
+    merged
+}
+
+fn span_contains<T: Ord + Copy>(spans: &[(T, T)], ip: T) -> bool {
+    let idx = spans.partition_point(|&(start, _)| start <= ip);
+    idx > 0 && ip <= spans[idx - 1].1
+}
+
+fn in_v6_prefixes(prefixes: &[(Ipv6Addr, u8)], ip: Ipv6Addr) -> bool {
+    prefixes
+        .iter()
+        .any(|&(net, len)| u128::from(ip) >> (128 - len) == u128::from(net) >> (128 - len))
+}
+
+/// Return `true` if `ip` is in IANA special-purpose space (private,
+/// loopback, link-local, shared, documentation, benchmarking and similar
+/// blocks), multicast, or the reserved 240.0.0.0/4: space no registry
+/// delegates.
+///
+/// IPv4-mapped IPv6 addresses are checked as IPv4.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::is_special_purpose;
+///
+/// assert!(is_special_purpose("192.168.1.1".parse().unwrap()));
+/// assert!(is_special_purpose("2001:db8::1".parse().unwrap()));
+/// assert!(!is_special_purpose("46.4.0.1".parse().unwrap()));
+/// ```
+pub fn is_special_purpose(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => SPECIAL_V4
+            .iter()
+            .any(|&(net, len)| u32::from(ip) >> (32 - len) == u32::from(net) >> (32 - len)),
+        IpAddr::V6(ip) => in_v6_prefixes(SPECIAL_V6, ip),
+    }
+}
+
+/// What the database knows about an address, returned by
+/// [`GeoIpDb::coverage`].
+#[derive(Debug, Clone, Copy)]
+pub enum Coverage {
+    /// Covered by a block of the database.
+    Allocated(RangeMatch),
+    /// Special-purpose space, see [`is_special_purpose`].
+    SpecialPurpose,
+    /// Not delegated by any registry: listed as `available` or `reserved` by
+    /// one of the loaded registries, or IPv6 space IANA has not allocated to
+    /// any RIR.
+    Unallocated,
+    /// Not in any loaded source, neither as a block nor as unallocated
+    /// space. The address is managed by a registry whose data is not loaded
+    /// (or was loaded without its `available` and `reserved` records).
+    NotLoaded,
+}
+
+impl GeoIpDb {
+    /// Look up `ip` like [`GeoIpDb::lookup_range`], and tell why it is not
+    /// covered when it is not.
+    ///
+    /// Unallocated space is recognized from the `available` and `reserved`
+    /// records of extended delegated files, which the embedded snapshot and
+    /// databases parsed from such files keep, and from IANA's IPv6
+    /// allocations to the RIRs. IANA has handed out all of the IPv4 unicast
+    /// space, so an IPv4 address missing from the loaded data is only
+    /// [`Coverage::Unallocated`] if a loaded registry says so.
+    /// IPv4-mapped IPv6 addresses are looked up as IPv4.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::{Coverage, GeoIpDb};
+    ///
+    /// let db = GeoIpDb::from_ripe_delegated_str("\
+    /// ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated|3cbd9d5e
+    /// ripencc||ipv4|46.4.1.0|256||available
+    /// ");
+    /// let coverage = |ip: &str| db.coverage(ip.parse().unwrap());
+    /// assert!(matches!(coverage("46.4.0.1"), Coverage::Allocated(_)));
+    /// assert!(matches!(coverage("10.0.0.1"), Coverage::SpecialPurpose));
+    /// assert!(matches!(coverage("46.4.1.1"), Coverage::Unallocated));
+    /// assert!(matches!(coverage("4000::1"), Coverage::Unallocated));
+    /// // ARIN space, and no ARIN file is loaded
+    /// assert!(matches!(coverage("8.8.8.8"), Coverage::NotLoaded));
+    /// ```
+    pub fn coverage(&self, ip: IpAddr) -> Coverage {
+        let ip = ip.to_canonical();
+        if let Some(block) = self.lookup_range(ip) {
+            return Coverage::Allocated(block);
+        }
+        if is_special_purpose(ip) {
+            return Coverage::SpecialPurpose;
+        }
+        match ip {
+            _ if self.unallocated.contains(ip) => Coverage::Unallocated,
+            IpAddr::V6(v6) if !in_v6_prefixes(IANA_RIR_V6, v6) => Coverage::Unallocated,
+            _ => Coverage::NotLoaded,
+        }
+    }
+
+    /// Return `true` if no registry has delegated `ip`, and it is not
+    /// special-purpose space either: an address that should not appear as
+    /// the source of public traffic ([`Coverage::Unallocated`]).
+    ///
+    /// Addresses of registries whose data is not loaded are not bogons; see
+    /// [`GeoIpDb::coverage`] for how unallocated space is recognized.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let db = GeoIpDb::from_ripe_delegated_str("\
+    /// ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated|3cbd9d5e
+    /// ripencc||ipv4|46.4.1.0|256||reserved
+    /// ");
+    /// assert!(db.is_bogon("46.4.1.1".parse().unwrap()));
+    /// assert!(db.is_bogon("4000::1".parse().unwrap()));
+    /// assert!(!db.is_bogon("46.4.0.1".parse().unwrap()));
+    /// assert!(!db.is_bogon("192.168.0.1".parse().unwrap()));
+    /// assert!(!db.is_bogon("8.8.8.8".parse().unwrap()));
+    /// ```
+    pub fn is_bogon(&self, ip: IpAddr) -> bool {
+        matches!(self.coverage(ip), Coverage::Unallocated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_special_purpose() {
+        for ip in [
+            "0.1.2.3",
+            "100.127.255.255",
+            "172.31.0.1",
+            "198.19.255.255",
+            "239.1.1.1",
+            "255.255.255.255",
+            "::ffff:127.0.0.1",
+            "::",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:1ff::1",
+        ] {
+            assert!(is_special_purpose(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "1.1.1.1",
+            "100.128.0.1",
+            "172.32.0.1",
+            "2001:200::1",
+            "2a01:4f8::1",
+        ] {
+            assert!(!is_special_purpose(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_coverage() {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated\n\
+             ripencc||ipv4|46.4.2.0|512||reserved\n\
+             ripencc||ipv6|2a01:4f9::|32||available\n",
+        );
+        let coverage = |ip: &str| db.coverage(ip.parse().unwrap());
+
+        match coverage("::ffff:46.4.0.1") {
+            Coverage::Allocated(block) => assert_eq!(block.cidr_string(), "46.4.0.0/24"),
+            other => panic!("{other:?}"),
+        }
+        assert!(matches!(coverage("192.168.0.1"), Coverage::SpecialPurpose));
+        assert!(matches!(coverage("8000::1"), Coverage::Unallocated));
+        assert!(matches!(coverage("46.4.3.255"), Coverage::Unallocated));
+        assert!(matches!(coverage("2a01:4f9::1"), Coverage::Unallocated));
+        assert!(matches!(coverage("46.4.1.0"), Coverage::NotLoaded));
+        assert!(matches!(coverage("46.4.4.0"), Coverage::NotLoaded));
+        assert!(matches!(coverage("2a02::1"), Coverage::NotLoaded));
+
+        assert!(db.is_bogon("46.4.2.0".parse().unwrap()));
+        assert!(db.is_bogon("8000::1".parse().unwrap()));
+        assert!(!db.is_bogon("46.4.1.0".parse().unwrap()));
+        assert!(!db.is_bogon("2a02::1".parse().unwrap()));
+        assert!(!db.is_bogon("2a01:4f8::1".parse().unwrap()));
+        assert!(!db.is_bogon("fe80::1".parse().unwrap()));
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-data")]
+    fn test_embedded_reserved_space() {
+        let db = GeoIpDb::new();
+        assert!(db.is_bogon("5.159.192.1".parse().unwrap()));
+        assert!(!db.is_bogon("8.8.8.8".parse().unwrap()));
+        assert!(!db.is_bogon("46.4.0.1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,224 @@
+//! Metadata sidecar files describing a downloaded cache file.
+//!
+//! Every cache update writes `<cache file>.meta` next to the data file. It records
+//! where and when the data was fetched, the server's `ETag`, a SHA-256 of the
+//! content and the number of records parsed from it. Loaders use the hash to
+//! detect a cache file that was truncated or replaced behind their back, and
+//! applications can use the timestamp to decide when to refresh.
+//!
+//! The sidecar is a plain `key=value` text file, one entry per line:
+//!
+//! ```text
+//! downloaded_at=1735689600
+//! source_url=https://ftp.ripe.net/pub/stats/ripencc/delegated-ripencc-extended-latest
+//! etag="5f3c-62a1b"
+//! sha256=9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+//! ipv4=91234
+//! ipv6=40321
+//! asn=38765
+//! ```
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+use sha2::{Digest, Sha256};
+
+use crate::parser::RecordCounts;
+
+/// Metadata persisted next to a cache file by the cache update functions.
+///
+/// # Examples
+/// ```
+/// use std::io;
+/// use ip_alloc_lookup::{CacheInfo, GeoIpDb};
+///
+/// let fetcher = |_: &str| -> io::Result<Vec<u8>> {
+///     Ok(b"ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n".to_vec())
+/// };
+/// let dir = std::env::temp_dir().join("ip-alloc-lookup-cache-info-doc");
+/// let path = dir.join("ripe.txt");
+/// GeoIpDb::update_cache_with_fetcher(&path, "mem://ripe", &fetcher).unwrap();
+///
+/// let info = CacheInfo::read(&path).unwrap();
+/// assert_eq!(info.source_url, "mem://ripe");
+/// assert_eq!(info.record_counts.ipv4, 1);
+/// assert!(info.matches_file(&path).unwrap());
+/// # std::fs::remove_dir_all(dir).ok();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheInfo {
+    /// When the download completed (whole seconds).
+    pub downloaded_at: SystemTime,
+    /// URL the cache file was fetched from.
+    pub source_url: String,
+    /// `ETag` response header, if the server sent one.
+    pub etag: Option<String>,
+    /// Lowercase hex SHA-256 of the cache file content.
+    pub sha256: String,
+    /// Records parsed from the cache file, per type.
+    pub record_counts: RecordCounts,
+}
+
+impl CacheInfo {
+    /// Path of the sidecar file belonging to `cache_path` (`<file name>.meta`).
+    pub fn sidecar_path<P: AsRef<Path>>(cache_path: P) -> PathBuf {
+        let mut name = cache_path.as_ref().as_os_str().to_owned();
+        name.push(".meta");
+        PathBuf::from(name)
+    }
+
+    /// Read the sidecar of `cache_path`.
+    ///
+    /// # Errors
+    /// Returns an error of kind [`io::ErrorKind::NotFound`] if there is no
+    /// sidecar, or [`io::ErrorKind::InvalidData`] if it cannot be parsed.
+    pub fn read<P: AsRef<Path>>(cache_path: P) -> io::Result<Self> {
+        let content = fs::read_to_string(Self::sidecar_path(cache_path))?;
+        Self::parse(&content).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "malformed cache metadata file")
+        })
+    }
+
+    /// Write this metadata as the sidecar of `cache_path`.
+    ///
+    /// # Errors
+    /// Returns an error if the sidecar file cannot be written.
+    pub fn write<P: AsRef<Path>>(&self, cache_path: P) -> io::Result<()> {
+        let secs = self
+            .downloaded_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut out = format!("downloaded_at={secs}\nsource_url={}\n", self.source_url);
+        if let Some(etag) = &self.etag {
+            out.push_str(&format!("etag={etag}\n"));
+        }
+        out.push_str(&format!(
+            "sha256={}\nipv4={}\nipv6={}\nasn={}\n",
+            self.sha256, self.record_counts.ipv4, self.record_counts.ipv6, self.record_counts.asn
+        ));
+
+        fs::write(Self::sidecar_path(cache_path), out)
+    }
+
+    /// Time elapsed since the download, or `None` if the clock went backwards.
+    pub fn age(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(self.downloaded_at).ok()
+    }
+
+    /// Whether the download is older than `max_age`. A download time in the
+    /// future counts as fresh.
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.age().is_some_and(|age| age > max_age)
+    }
+
+    /// Whether the cache file at `cache_path` still has the recorded content hash.
+    ///
+    /// # Errors
+    /// Returns an error if the cache file cannot be read.
+    pub fn matches_file<P: AsRef<Path>>(&self, cache_path: P) -> io::Result<bool> {
+        Ok(self.matches(&fs::read(cache_path)?))
+    }
+
+    /// Whether `content` has the recorded content hash.
+    pub(crate) fn matches(&self, content: &[u8]) -> bool {
+        self.sha256 == sha256_hex(content)
+    }
+
+    fn parse(content: &str) -> Option<Self> {
+        let mut downloaded_at = None;
+        let mut source_url = None;
+        let mut etag = None;
+        let mut sha256 = None;
+        let mut record_counts = RecordCounts::default();
+
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let (key, value) = line.split_once('=')?;
+            match key {
+                "downloaded_at" => {
+                    downloaded_at = Some(UNIX_EPOCH + Duration::from_secs(value.parse().ok()?))
+                }
+                "source_url" => source_url = Some(value.to_string()),
+                "etag" => etag = Some(value.to_string()),
+                "sha256" => sha256 = Some(value.to_string()),
+                "ipv4" => record_counts.ipv4 = value.parse().ok()?,
+                "ipv6" => record_counts.ipv6 = value.parse().ok()?,
+                "asn" => record_counts.asn = value.parse().ok()?,
+                // Ignore keys added by newer versions
+                _ => {}
+            }
+        }
+
+        Some(CacheInfo {
+            downloaded_at: downloaded_at?,
+            source_url: source_url?,
+            etag,
+            sha256: sha256?,
+            record_counts,
+        })
+    }
+}
+
+/// Lowercase hex SHA-256 of `bytes`.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+/// Lowercase hex of a finished digest, e.g. from a streaming [`Sha256`].
+pub(crate) fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("ripe.txt");
+        fs::write(&cache_path, "abc").unwrap();
+
+        let info = CacheInfo {
+            downloaded_at: UNIX_EPOCH + Duration::from_secs(1_735_689_600),
+            source_url: "https://example.invalid/ripe".to_string(),
+            etag: Some("\"5f3c-62a1b\"".to_string()),
+            sha256: sha256_hex(b"abc"),
+            record_counts: RecordCounts {
+                ipv4: 3,
+                ipv6: 2,
+                asn: 1,
+            },
+        };
+        info.write(&cache_path).unwrap();
+
+        assert_eq!(
+            CacheInfo::sidecar_path(&cache_path),
+            dir.path().join("ripe.txt.meta")
+        );
+        assert_eq!(CacheInfo::read(&cache_path).unwrap(), info);
+        assert_eq!(
+            info.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(info.matches_file(&cache_path).unwrap());
+
+        fs::write(&cache_path, "abd").unwrap();
+        assert!(!info.matches_file(&cache_path).unwrap());
+    }
+
+    #[test]
+    fn test_read_missing_or_malformed_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("ripe.txt");
+
+        let err = CacheInfo::read(&cache_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        fs::write(CacheInfo::sidecar_path(&cache_path), "source_url=x\n").unwrap();
+        let err = CacheInfo::read(&cache_path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,142 @@
+//! Comparing the classification of two addresses.
+
+use std::net::IpAddr;
+
+use crate::GeoIpDb;
+
+/// Result of comparing two addresses, see [`GeoIpDb::same_country`] and
+/// [`GeoIpDb::same_allocation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Same {
+    Yes,
+    No,
+    /// The database cannot tell, e.g. because an address is not covered.
+    Unknown,
+}
+
+impl Same {
+    /// `Some(true)` for [`Same::Yes`], `Some(false)` for [`Same::No`] and
+    /// [`None`] for [`Same::Unknown`].
+    pub fn known(self) -> Option<bool> {
+        match self {
+            Same::Yes => Some(true),
+            Same::No => Some(false),
+            Same::Unknown => None,
+        }
+    }
+}
+
+impl From<bool> for Same {
+    fn from(same: bool) -> Self {
+        if same { Same::Yes } else { Same::No }
+    }
+}
+
+impl GeoIpDb {
+    /// Compare the countries of `a` and `b`.
+    ///
+    /// Returns [`Same::Unknown`] if either address is not covered, or if the
+    /// answer depends on the member state of a range registered to the EU as
+    /// a whole ([`GeoInfo::country_unknown`](crate::GeoInfo::country_unknown)):
+    /// such a range is in the same country as another EU range only maybe,
+    /// but never in the same country as a non-EU one. IPv4-mapped IPv6
+    /// addresses are compared as IPv4.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "embedded-data")] {
+    /// use ip_alloc_lookup::{GeoIpDb, Same};
+    ///
+    /// let db = GeoIpDb::new();
+    /// let login = "46.4.0.1".parse().unwrap();
+    /// let card = "5.1.48.1".parse().unwrap();
+    /// assert_eq!(db.same_country(login, card), Same::No);
+    /// assert_eq!(db.same_country(login, "10.0.0.1".parse().unwrap()), Same::Unknown);
+    /// # }
+    /// ```
+    pub fn same_country(&self, a: IpAddr, b: IpAddr) -> Same {
+        let (Some(a), Some(b)) = (self.lookup(a.to_canonical()), self.lookup(b.to_canonical()))
+        else {
+            return Same::Unknown;
+        };
+        match (a.country_unknown(), b.country_unknown()) {
+            (false, false) => Same::from(a.country_code == b.country_code),
+            (true, true) => Same::Unknown,
+            (true, false) if b.is_eu => Same::Unknown,
+            (false, true) if a.is_eu => Same::Unknown,
+            _ => Same::No,
+        }
+    }
+
+    /// Return whether `a` and `b` are in the same delegated block, as
+    /// reported by [`GeoIpDb::lookup_range`].
+    ///
+    /// Returns [`Same::Unknown`] if either address is not covered. Blocks
+    /// merged by [`GeoIpDb::optimize`] count as one. IPv4-mapped IPv6
+    /// addresses are compared as IPv4.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::{GeoIpDb, Same};
+    ///
+    /// let db = GeoIpDb::from_ripe_delegated_str("\
+    /// ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated
+    /// ripencc|DE|ipv4|46.4.1.0|256|20090101|allocated
+    /// ");
+    /// let ip = |s: &str| s.parse().unwrap();
+    /// assert_eq!(db.same_allocation(ip("46.4.0.1"), ip("46.4.0.200")), Same::Yes);
+    /// assert_eq!(db.same_allocation(ip("46.4.0.1"), ip("46.4.1.1")), Same::No);
+    /// assert_eq!(db.same_country(ip("46.4.0.1"), ip("46.4.1.1")), Same::Yes);
+    /// ```
+    pub fn same_allocation(&self, a: IpAddr, b: IpAddr) -> Same {
+        let (Some(a), Some(b)) = (
+            self.lookup_range(a.to_canonical()),
+            self.lookup_range(b.to_canonical()),
+        ) else {
+            return Same::Unknown;
+        };
+        Same::from(a.start == b.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_country() {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated\n\
+             ripencc|EU|ipv4|46.4.1.0|256|20090101|allocated\n\
+             ripencc|EU|ipv4|46.4.2.0|256|20090101|allocated\n\
+             ripencc|CH|ipv4|46.4.3.0|256|20090101|allocated\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        );
+        let same = |a: &str, b: &str| db.same_country(a.parse().unwrap(), b.parse().unwrap());
+
+        assert_eq!(same("46.4.0.1", "2a01:4f8::1"), Same::Yes);
+        assert_eq!(same("::ffff:46.4.0.1", "46.4.0.2"), Same::Yes);
+        assert_eq!(same("46.4.0.1", "46.4.3.1"), Same::No);
+        // The member state of EU-registered ranges is not known
+        assert_eq!(same("46.4.1.1", "46.4.2.1"), Same::Unknown);
+        assert_eq!(same("46.4.0.1", "46.4.1.1"), Same::Unknown);
+        assert_eq!(same("46.4.1.1", "46.4.3.1"), Same::No);
+        assert_eq!(same("46.4.0.1", "10.0.0.1"), Same::Unknown);
+
+        assert_eq!(Same::Unknown.known(), None);
+        assert_eq!(Same::No.known(), Some(false));
+    }
+
+    #[test]
+    fn test_same_allocation() {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|512|20090101|allocated\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        );
+        let same = |a: &str, b: &str| db.same_allocation(a.parse().unwrap(), b.parse().unwrap());
+
+        assert_eq!(same("46.4.0.1", "::ffff:46.4.1.255"), Same::Yes);
+        assert_eq!(same("46.4.0.1", "2a01:4f8::1"), Same::No);
+        assert_eq!(same("46.4.0.1", "46.4.2.0"), Same::Unknown);
+    }
+}
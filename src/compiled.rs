@@ -0,0 +1,214 @@
+//! Country sets compiled into bitmaps for fast membership tests.
+//!
+//! [`GeoIpDb::compile_set`] answers one fixed question, "is this address in
+//! one of these countries?", faster than a lookup followed by a comparison:
+//! IPv4 addresses are first checked against roaring bitmaps of /24 blocks,
+//! which settle most queries with a single bit test, and only addresses in a
+//! /24 that is partly inside the set fall back to an exact range search.
+
+use std::net::IpAddr;
+
+use roaring::RoaringBitmap;
+
+use crate::GeoIpDb;
+
+/// A set of countries compiled by [`GeoIpDb::compile_set`].
+///
+/// The set is a snapshot: it does not change when the database does.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "embedded-data")] {
+/// use ip_alloc_lookup::GeoIpDb;
+///
+/// let db = GeoIpDb::new();
+/// let dach = db.compile_set(&["DE", "AT", "CH"]);
+/// assert!(dach.contains("46.4.0.1".parse().unwrap()));
+/// assert!(!dach.contains("8.8.8.8".parse().unwrap()));
+/// # }
+/// ```
+///
+/// # Feature
+/// Available only when the crate is built with the `roaring` feature.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompiledSet {
+    /// /24 blocks entirely inside the set.
+    full_v4: RoaringBitmap,
+    /// /24 blocks partly inside the set; checked against `v4_ranges`.
+    partial_v4: RoaringBitmap,
+    /// Merged IPv4 ranges of the set touching a partial /24 block.
+    v4_ranges: Vec<(u32, u32)>,
+    /// Merged IPv6 ranges of the set.
+    v6_ranges: Vec<(u128, u128)>,
+}
+
+impl CompiledSet {
+    /// Return `true` if `ip` belongs to one of the countries of the set.
+    #[inline]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(v4) => {
+                let ip = u32::from(v4);
+                let block = ip >> 8;
+                self.full_v4.contains(block)
+                    || (self.partial_v4.contains(block) && in_ranges(&self.v4_ranges, ip))
+            }
+            IpAddr::V6(v6) => in_ranges(&self.v6_ranges, u128::from(v6)),
+        }
+    }
+
+    /// Approximate heap usage of the compiled set, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.full_v4.serialized_size()
+            + self.partial_v4.serialized_size()
+            + self.v4_ranges.capacity() * size_of::<(u32, u32)>()
+            + self.v6_ranges.capacity() * size_of::<(u128, u128)>()
+    }
+}
+
+impl GeoIpDb {
+    /// Compile the ranges of `countries` into a [`CompiledSet`].
+    ///
+    /// Country codes are matched case-insensitively, like
+    /// [`GeoIpDb::is_country`].
+    ///
+    /// # Feature
+    /// Available only when the crate is built with the `roaring` feature.
+    pub fn compile_set(&self, countries: &[&str]) -> CompiledSet {
+        let in_set = |code: &[u8; 2]| {
+            countries
+                .iter()
+                .any(|c| c.as_bytes().eq_ignore_ascii_case(code))
+        };
+
+        // Merged ranges always lie within the IPv4 space they came from
+        let v4: Vec<(u32, u32)> = merge(
+            self.v4_ranges
+                .iter()
+                .filter(|r| in_set(&r.2.country_code))
+                .map(|r| (r.0.into(), r.1.into())),
+        )
+        .into_iter()
+        .map(|(start, end)| (start as u32, end as u32))
+        .collect();
+        let mut set = CompiledSet {
+            v6_ranges: merge(
+                self.v6_ranges
+                    .iter()
+                    .filter(|r| in_set(&r.2.country_code))
+                    .map(|r| (r.0, r.1)),
+            ),
+            ..CompiledSet::default()
+        };
+
+        for &(start, end) in &v4 {
+            let (first, last) = (start >> 8, end >> 8);
+            // Blocks the range covers only in part
+            let head_partial = start & 0xFF != 0;
+            let tail_partial = end & 0xFF != 0xFF;
+            if head_partial {
+                set.partial_v4.insert(first);
+            }
+            if tail_partial {
+                set.partial_v4.insert(last);
+            }
+
+            let full_first = first + u32::from(head_partial);
+            let full_last = last.checked_sub(u32::from(tail_partial));
+            if let Some(full_last) = full_last
+                && full_first <= full_last
+            {
+                set.full_v4.insert_range(full_first..=full_last);
+            }
+        }
+
+        set.v4_ranges = v4
+            .into_iter()
+            .filter(|&(start, end)| {
+                set.partial_v4.contains(start >> 8) || set.partial_v4.contains(end >> 8)
+            })
+            .collect();
+        set
+    }
+}
+
+/// Merge sorted ranges, joining overlapping and adjacent ones.
+fn merge<I: Iterator<Item = (u128, u128)>>(ranges: I) -> Vec<(u128, u128)> {
+    let mut merged: Vec<(u128, u128)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged.shrink_to_fit();
+    merged
+}
+
+/// Return `true` if a range of the sorted, disjoint `ranges` contains `ip`.
+fn in_ranges<T: Copy + Ord>(ranges: &[(T, T)], ip: T) -> bool {
+    let idx = ranges.partition_point(|r| r.0 <= ip);
+    idx > 0 && ip <= ranges[idx - 1].1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::IpRange;
+
+    #[test]
+    fn test_matches_lookup() {
+        let range = |start: &str, count: u128, country: &str| {
+            IpRange::new(start.parse::<IpAddr>().unwrap(), count, country)
+        };
+        let db = GeoIpDb::from_ranges(vec![
+            // Partial blocks at both ends, merged with an adjacent range
+            range("10.0.0.128", 384, "DE"),
+            range("10.0.2.0", 64, "de"),
+            range("10.0.2.64", 64, "FR"),
+            // Exactly one /24, and two ranges filling a /24 together
+            range("10.1.0.0", 256, "AT"),
+            range("10.2.0.0", 128, "DE"),
+            range("10.2.0.128", 128, "AT"),
+            range("255.255.255.0", 256, "DE"),
+            range("2001:db8::", 1 << 96, "DE"),
+        ]);
+        let set = db.compile_set(&["de", "AT"]);
+
+        for ip in [
+            "10.0.0.0",
+            "10.0.0.127",
+            "10.0.0.128",
+            "10.0.1.77",
+            "10.0.2.63",
+            "10.0.2.64",
+            "10.1.0.255",
+            "10.1.1.0",
+            "10.2.0.127",
+            "10.2.0.128",
+            "255.255.255.255",
+            "2001:db8::1",
+            "2001:db9::",
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            let expected = db.is_country(ip, "DE") || db.is_country(ip, "AT");
+            assert_eq!(set.contains(ip), expected, "{ip}");
+        }
+        assert!(set.full_v4.contains(0x0A_00_01));
+        assert!(set.full_v4.contains(0x0A_02_00));
+        assert!(set.partial_v4.contains(0x0A_00_00));
+    }
+
+    #[test]
+    fn test_matches_embedded_database() {
+        let db = GeoIpDb::new();
+        let set = db.compile_set(&["DE", "FR"]);
+        for (start, end, info) in db.v4_ranges.iter().step_by(101) {
+            for ip in [*start, *end, start.wrapping_sub(1), end.wrapping_add(1)] {
+                let ip = IpAddr::V4(ip.into());
+                let expected = db.is_country(ip, "DE") || db.is_country(ip, "FR");
+                assert_eq!(set.contains(ip), expected, "{ip} {info:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Loading ranges from arbitrary CSV files.
+//!
+//! Internal datasets (corporate address plans, VPN egress ranges, ...) rarely
+//! come in the delegated format. [`GeoIpDb::from_csv`] reads them directly,
+//! with a [`CsvSchema`] telling it which columns hold the range and the
+//! country code.
+//!
+//! Fields may be quoted with double quotes (`"..."`, with `""` for a literal
+//! quote). Quoted fields cannot span lines.
+
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::IpAddr;
+
+use crate::GeoIpDb;
+use crate::net::IpNet;
+use crate::parser::IpRange;
+
+/// Column layout of a CSV file for [`GeoIpDb::from_csv`]. Columns are 0-based.
+///
+/// The start column holds an address, or a CIDR prefix such as `10.0.0.0/8`
+/// when `end_or_prefix_col` is `None`. The optional second column holds either
+/// the last address of the range (`10.0.0.255`) or a prefix length (`24` or
+/// `/24`).
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::{CsvSchema, GeoIpDb};
+///
+/// let csv = "\
+/// network,site,country
+/// 10.1.0.0/16,Berlin,DE
+/// 10.2.0.0/16,\"Paris, 2nd floor\",FR
+/// ";
+/// let schema = CsvSchema {
+///     start_col: 0,
+///     end_or_prefix_col: None,
+///     country_col: 2,
+///     has_header: true,
+///     ..CsvSchema::default()
+/// };
+/// let db = GeoIpDb::from_csv(csv.as_bytes(), schema).unwrap();
+/// assert!(db.is_eu("10.2.3.4".parse().unwrap()));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvSchema {
+    pub start_col: usize,
+    pub end_or_prefix_col: Option<usize>,
+    pub country_col: usize,
+    /// Field separator, `,` by default.
+    pub delimiter: char,
+    /// Skip the first line.
+    pub has_header: bool,
+}
+
+impl Default for CsvSchema {
+    /// `start,end,country` without a header line.
+    fn default() -> Self {
+        CsvSchema {
+            start_col: 0,
+            end_or_prefix_col: Some(1),
+            country_col: 2,
+            delimiter: ',',
+            has_header: false,
+        }
+    }
+}
+
+impl GeoIpDb {
+    /// Build a database from CSV rows laid out as described by `schema`.
+    ///
+    /// Blank lines are ignored. Country codes must be two ASCII letters and are
+    /// upper-cased, so the EU and region classification applies as usual.
+    ///
+    /// # Errors
+    /// Returns the reader's error, or an error of kind
+    /// [`io::ErrorKind::InvalidData`] naming the first row that is missing a
+    /// column or has an invalid address, prefix or country code.
+    pub fn from_csv<R: Read>(reader: R, schema: CsvSchema) -> io::Result<Self> {
+        read_ranges(reader, &schema).map(Self::from_ranges)
+    }
+
+    /// Build a database from the DB-IP "IP to Country Lite" CSV
+    /// (`dbip-country-lite-YYYY-MM.csv`, once decompressed).
+    ///
+    /// The file lists IPv4 and IPv6 ranges as `start,end,country`; ranges DB-IP
+    /// marks as unknown (`ZZ`, e.g. private and reserved space) are left out,
+    /// so lookups there return [`None`] as they would for RIPE data. The file
+    /// is geolocation data, not allocation data, so the same address can
+    /// resolve differently than with [`GeoIpDb::new`]. DB-IP publishes it
+    /// under CC BY 4.0, which requires attribution.
+    ///
+    /// # Errors
+    /// As for [`GeoIpDb::from_csv`].
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let csv = "\
+    /// 1.0.0.0,1.0.0.255,AU
+    /// 10.0.0.0,10.255.255.255,ZZ
+    /// 46.4.0.0,46.4.255.255,DE
+    /// 2a01:4f8::,2a01:4f8:ffff:ffff:ffff:ffff:ffff:ffff,DE
+    /// ";
+    /// let db = GeoIpDb::from_dbip_country_lite(csv.as_bytes()).unwrap();
+    /// assert!(db.is_eu("2a01:4f8::1".parse().unwrap()));
+    /// assert_eq!(db.lookup("10.0.0.1".parse().unwrap()), None);
+    /// ```
+    pub fn from_dbip_country_lite<R: Read>(reader: R) -> io::Result<Self> {
+        let mut ranges = read_ranges(reader, &CsvSchema::default())?;
+        ranges.retain(|range| range.country != DBIP_UNKNOWN_COUNTRY);
+        Ok(Self::from_ranges(ranges))
+    }
+}
+
+/// Read an RFC 8805 geofeed (`prefix,country,region,city,postal_code`).
+///
+/// Comment lines (`#`) are skipped, as are prefixes whose country is left
+/// empty, which geofeeds use for space without a disclosed location.
+pub(crate) fn read_geofeed<R: Read>(reader: R) -> io::Result<Vec<IpRange>> {
+    let schema = CsvSchema {
+        end_or_prefix_col: None,
+        country_col: 1,
+        ..CsvSchema::default()
+    };
+    let mut ranges = Vec::new();
+
+    for (idx, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let fields = split_fields(trimmed, schema.delimiter);
+        if fields
+            .get(1)
+            .is_none_or(|country| country.trim().is_empty())
+        {
+            continue;
+        }
+
+        let range = parse_row(trimmed, &schema).map_err(|msg| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {msg}", idx + 1),
+            )
+        })?;
+        ranges.push(range);
+    }
+
+    Ok(ranges)
+}
+
+/// Country code DB-IP uses for ranges it has no country for.
+pub(crate) const DBIP_UNKNOWN_COUNTRY: &str = "ZZ";
+
+/// Read every row of `reader` as described by `schema`.
+pub(crate) fn read_ranges<R: Read>(reader: R, schema: &CsvSchema) -> io::Result<Vec<IpRange>> {
+    let mut ranges = Vec::new();
+
+    for (idx, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if (idx == 0 && schema.has_header) || line.trim().is_empty() {
+            continue;
+        }
+
+        let range = parse_row(&line, schema).map_err(|msg| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {msg}", idx + 1),
+            )
+        })?;
+        ranges.push(range);
+    }
+
+    Ok(ranges)
+}
+
+fn parse_row(line: &str, schema: &CsvSchema) -> Result<IpRange, String> {
+    let fields = split_fields(line, schema.delimiter);
+    let field = |col: usize| {
+        fields
+            .get(col)
+            .map(|f| f.trim())
+            .ok_or_else(|| format!("missing column {col}"))
+    };
+
+    let start = field(schema.start_col)?;
+    let (start, count) = match schema.end_or_prefix_col {
+        None => {
+            let net: IpNet = start
+                .parse()
+                .map_err(|_| format!("invalid prefix {start:?}"))?;
+            (net.network(), prefix_count(net))
+        }
+        Some(col) => {
+            let start: IpAddr = start
+                .parse()
+                .map_err(|_| format!("invalid address {start:?}"))?;
+            let end_or_prefix = field(col)?;
+            (start, range_count(start, end_or_prefix)?)
+        }
+    };
+
+    let country = field(schema.country_col)?;
+    if country.len() != 2 || !country.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return Err(format!("invalid country code {country:?}"));
+    }
+
+    Ok(IpRange {
+        start: start.into(),
+        count,
+        country: country.to_ascii_uppercase(),
+        assigned: false,
+        date: String::new(),
+    })
+}
+
+/// Number of addresses from `start` to an end address or prefix length.
+fn range_count(start: IpAddr, end_or_prefix: &str) -> Result<u128, String> {
+    if let Ok(end) = end_or_prefix.parse::<IpAddr>() {
+        let span = match (start, end) {
+            (IpAddr::V4(s), IpAddr::V4(e)) if s <= e => u128::from(u32::from(e) - u32::from(s)),
+            (IpAddr::V6(s), IpAddr::V6(e)) if s <= e => u128::from(e) - u128::from(s),
+            _ => return Err(format!("end address {end} does not follow {start}")),
+        };
+        return Ok(span.saturating_add(1));
+    }
+
+    let prefix_len = end_or_prefix.trim_start_matches('/');
+    prefix_len
+        .parse()
+        .ok()
+        .and_then(|len| IpNet::new(start, len))
+        .map(prefix_count)
+        .ok_or_else(|| format!("invalid end address or prefix length {end_or_prefix:?}"))
+}
+
+fn prefix_count(net: IpNet) -> u128 {
+    let bits = if net.network().is_ipv4() { 32 } else { 128 };
+    1u128
+        .checked_shl(bits - u32::from(net.prefix_len()))
+        .unwrap_or(u128::MAX)
+}
+
+/// Split one CSV line, honoring double-quoted fields.
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_end_and_prefix_columns() {
+        let csv = "\
+10.0.0.0,10.0.0.255,de
+10.1.0.0,/16,FR
+
+2001:db8::,32,US
+";
+        let db = GeoIpDb::from_csv(csv.as_bytes(), CsvSchema::default()).unwrap();
+
+        let info = db.lookup("10.0.0.255".parse().unwrap()).unwrap();
+        assert_eq!(info.country_code_str(), "DE");
+        assert!(info.is_eu);
+        assert!(db.lookup("10.0.1.0".parse().unwrap()).is_none());
+        assert!(db.is_eu("10.1.255.255".parse().unwrap()));
+        assert!(db.lookup("2001:db8:ffff::1".parse().unwrap()).is_some());
+    }
+
+    #[test]
+    fn test_custom_delimiter_and_quotes() {
+        let csv = "country;\"net; quoted\"\nPL;\"192.168.0.0/24\"\n";
+        let schema = CsvSchema {
+            start_col: 1,
+            end_or_prefix_col: None,
+            country_col: 0,
+            delimiter: ';',
+            has_header: true,
+        };
+        let db = GeoIpDb::from_csv(csv.as_bytes(), schema).unwrap();
+        assert!(db.is_eu("192.168.0.7".parse().unwrap()));
+        assert_eq!(
+            split_fields(r#"a,"b ""c"", d",e"#, ','),
+            ["a", r#"b "c", d"#, "e"]
+        );
+    }
+
+    #[test]
+    fn test_dbip_country_lite() {
+        // Ranges are not aligned to prefixes, and unknown space is `ZZ`
+        let csv = "\
+0.0.0.0,0.255.255.255,ZZ
+1.0.1.0,1.0.3.255,CN
+1.0.4.0,1.0.7.255,AU
+2001:67c:2e8::,2001:67c:2e8:ffff:ffff:ffff:ffff:ffff,NL
+";
+        let db = GeoIpDb::from_dbip_country_lite(csv.as_bytes()).unwrap();
+        assert_eq!(db.stats().total_v4_ranges, 2);
+        assert!(db.lookup("0.1.2.3".parse().unwrap()).is_none());
+        let info = db.lookup("1.0.3.255".parse().unwrap()).unwrap();
+        assert_eq!(info.country_code_str(), "CN");
+        assert!(db.is_eu("2001:67c:2e8::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_rows_are_reported() {
+        for (csv, expected) in [
+            (
+                "10.0.0.0,10.0.0.255,DE\n10.0.1.0,10.0.0.0,DE\n",
+                "line 2: end address",
+            ),
+            ("10.0.0.0,24\n", "line 1: missing column 2"),
+            ("10.0.0.0,24,Germany\n", "line 1: invalid country code"),
+            (
+                "10.0.0.0,33,DE\n",
+                "line 1: invalid end address or prefix length",
+            ),
+        ] {
+            let err = GeoIpDb::from_csv(csv.as_bytes(), CsvSchema::default())
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().starts_with(expected), "{err}");
+        }
+    }
+}
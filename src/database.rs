@@ -31,14 +31,23 @@
 //! ## Regional classification
 //!
 //! Region grouping (e.g. EU vs non-EU) is derived from the country code using a
-//! fixed mapping. This mapping is a policy decision and may evolve over time.
-
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+//! fixed mapping. This mapping is a policy decision and may evolve over time; it
+//! is read from `country-policy.txt` by the build script, together with the EU
+//! membership list.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use std::{fs, io, path::Path};
 
-#[cfg(feature = "download")]
-pub const RIPE_EXTENDED_LATEST_URL: &str =
-    "https://ftp.ripe.net/pub/stats/ripencc/delegated-ripencc-extended-latest";
+use crate::bogon::FreeSpace;
+use crate::cache::CacheInfo;
+use crate::embedded::{COUNTRY_REGIONS, IPV4_RANGES, IPV6_RANGES, SOURCE_FORMAT, SOURCE_HEADER, SOURCE_SHA256};
+use crate::net::{IpNet, cidrs_covering};
+use crate::parser::{DelegatedFormat, IpRange, ParsedDelegated, RangeStart, SourceMetadata, VerifyError};
 
 /// Compact classification result for a single IP range.
 ///
@@ -47,12 +56,21 @@ pub const RIPE_EXTENDED_LATEST_URL: &str =
 ///
 /// `region` is stored as a small numeric code; use [`GeoInfo::region_enum`]
 /// for a typed view.
-#[derive(Debug, Clone, Copy)]
+///
+/// `assigned` tells whether the matched record was `assigned` to an end user
+/// (e.g. provider-independent space) rather than `allocated` to an LIR for
+/// further assignment. Sources without a status (CSV files) report `false`.
+///
+/// More fields may be added in later versions, so values built in code start
+/// from [`GeoInfo::new`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(C)]
+#[non_exhaustive]
 pub struct GeoInfo {
     pub country_code: [u8; 2],
     pub is_eu: bool,
     pub region: u8,
+    pub assigned: bool,
 }
 
 /// High-level region classification derived from the country code.
@@ -60,32 +78,180 @@ pub struct GeoInfo {
 /// This is not a geolocation signal; it is a coarse grouping intended for
 /// policy-style decisions (e.g. "EU vs non-EU").
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Region {
-    EuropeanUnion = 1,
-    EuropeNonEu   = 2,
-    EasternEurope = 3,
-    Turkey        = 4,
-    MiddleEast    = 5,
-    NorthAfrica   = 6,
-    CentralAsia   = 7,
-    GulfStates    = 8,
-    Other         = 255,
+    EuropeanUnion    = 1,
+    EuropeNonEu      = 2,
+    EasternEurope    = 3,
+    Turkey           = 4,
+    MiddleEast       = 5,
+    NorthAfrica      = 6,
+    CentralAsia      = 7,
+    GulfStates       = 8,
+    Americas         = 9,
+    SubSaharanAfrica = 10,
+    SouthAsia        = 11,
+    EastAsia         = 12,
+    SoutheastAsia    = 13,
+    Oceania          = 14,
+    Other            = 255,
 }
 
 impl Region {
+    /// Every region, in code order.
+    pub const ALL: [Region; 15] = [
+        Region::EuropeanUnion,
+        Region::EuropeNonEu,
+        Region::EasternEurope,
+        Region::Turkey,
+        Region::MiddleEast,
+        Region::NorthAfrica,
+        Region::CentralAsia,
+        Region::GulfStates,
+        Region::Americas,
+        Region::SubSaharanAfrica,
+        Region::SouthAsia,
+        Region::EastAsia,
+        Region::SoutheastAsia,
+        Region::Oceania,
+        Region::Other,
+    ];
+
+	/// Map a numeric region code to a [`Region`]; unknown codes map to [`Region::Other`].
+    fn from_code(code: u8) -> Self {
+        match code {
+            1 => Region::EuropeanUnion,
+            2 => Region::EuropeNonEu,
+            3 => Region::EasternEurope,
+            4 => Region::Turkey,
+            5 => Region::MiddleEast,
+            6 => Region::NorthAfrica,
+            7 => Region::CentralAsia,
+            8 => Region::GulfStates,
+            9 => Region::Americas,
+            10 => Region::SubSaharanAfrica,
+            11 => Region::SouthAsia,
+            12 => Region::EastAsia,
+            13 => Region::SoutheastAsia,
+            14 => Region::Oceania,
+            _ => Region::Other,
+        }
+    }
+
 	/// Return a human-readable label for this region.
     pub fn as_str(self) -> &'static str {
         match self {
-            Region::EuropeanUnion => "European Union",
-            Region::EuropeNonEu   => "Europe (non-EU)",
-            Region::EasternEurope => "Eastern Europe",
-            Region::Turkey        => "Turkey",
-            Region::MiddleEast    => "Middle East",
-            Region::NorthAfrica   => "North Africa",
-            Region::CentralAsia   => "Central Asia",
-            Region::GulfStates    => "Gulf States",
-            Region::Other         => "Other",
+            Region::EuropeanUnion    => "European Union",
+            Region::EuropeNonEu      => "Europe (non-EU)",
+            Region::EasternEurope    => "Eastern Europe",
+            Region::Turkey           => "Turkey",
+            Region::MiddleEast       => "Middle East",
+            Region::NorthAfrica      => "North Africa",
+            Region::CentralAsia      => "Central Asia",
+            Region::GulfStates       => "Gulf States",
+            Region::Americas         => "Americas",
+            Region::SubSaharanAfrica => "Sub-Saharan Africa",
+            Region::SouthAsia        => "South Asia",
+            Region::EastAsia         => "East Asia",
+            Region::SoutheastAsia    => "Southeast Asia",
+            Region::Oceania          => "Oceania",
+            Region::Other            => "Other",
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    /// Writes the label returned by [`Region::as_str`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Error returned when converting a string or numeric code into a [`Region`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRegionError(String);
+
+impl fmt::Display for ParseRegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown region: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseRegionError {}
+
+impl TryFrom<u8> for Region {
+    type Error = ParseRegionError;
+
+	/// Convert the numeric code stored in [`GeoInfo::region`].
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        let region = Region::from_code(code);
+        if region as u8 == code {
+            Ok(region)
+        } else {
+            Err(ParseRegionError(code.to_string()))
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = ParseRegionError;
+
+	/// Parse a region label (`"European Union"`, as written by `Display`) or variant
+	/// name (`"EuropeanUnion"`), ignoring ASCII case.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::Region;
+	///
+	/// let region: Region = "Europe (non-EU)".parse().unwrap();
+	/// assert_eq!(region, Region::EuropeNonEu);
+	/// assert_eq!(region.to_string().parse::<Region>().unwrap(), region);
+	/// assert_eq!("gulfstates".parse::<Region>().unwrap(), Region::GulfStates);
+	/// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Region::ALL
+            .into_iter()
+            .find(|r| {
+                r.as_str().eq_ignore_ascii_case(s) || format!("{r:?}").eq_ignore_ascii_case(s)
+            })
+            .ok_or_else(|| ParseRegionError(s.to_string()))
+    }
+}
+
+impl fmt::Display for GeoInfo {
+    /// Writes the country code and region, e.g. `DE (European Union)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.country_code_str(), self.region_enum())
+    }
+}
+
+/// GDPR personal-data transfer classification derived from the country code.
+///
+/// Transfers within the EEA need no extra safeguards, transfers to countries
+/// with an EU adequacy decision are treated alike, and everything else needs a
+/// transfer mechanism such as standard contractual clauses.
+///
+/// The United States are classified as [`DataTransferClass::ThirdCountry`]: the
+/// EU-US Data Privacy Framework only covers certified recipients, which cannot be
+/// told from an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataTransferClass {
+    /// EU member states plus Iceland, Liechtenstein and Norway.
+    Eea,
+    /// Countries and territories with an EU adequacy decision.
+    Adequate,
+    /// Everything else.
+    ThirdCountry,
+}
+
+impl DataTransferClass {
+	/// Return a human-readable label for this class.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DataTransferClass::Eea          => "EEA",
+            DataTransferClass::Adequate     => "Adequacy decision",
+            DataTransferClass::ThirdCountry => "Third country",
         }
     }
 }
@@ -99,6 +265,57 @@ fn cc2(country: &str) -> [u8; 2] {
 
 /// For display/testing convenience.
 impl GeoInfo {
+	/// Build a result by hand, e.g. for a custom lookup table or a test double.
+	///
+	/// `country` is an ISO-3166 alpha-2 code; the flags are taken as given rather
+	/// than derived from it, and `assigned` is `false`.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::{GeoInfo, Region};
+	///
+	/// let info = GeoInfo::new("DE", true, Region::EuropeanUnion);
+	/// assert_eq!(info.country_code_str(), "DE");
+	/// assert_eq!(info.region_enum(), Region::EuropeanUnion);
+	/// assert!(info.is_eu && !info.assigned);
+	/// ```
+	pub fn new(country: &str, is_eu: bool, region: Region) -> Self {
+		GeoInfo {
+			country_code: cc2(country),
+			is_eu,
+			region: region as u8,
+			assigned: false,
+		}
+	}
+
+	/// Classify a 2-letter country code (EU flag and region).
+	///
+	/// The registry code `EU` (a European allocation without a specific member
+	/// state) is classified as EU; see [`GeoInfo::country_unknown`].
+	pub(crate) fn from_country(country: &str) -> Self {
+		GeoInfo {
+			country_code: cc2(country),
+			is_eu: is_eu_code(country),
+			region: determine_region(country) as u8,
+			assigned: false,
+		}
+	}
+
+	/// Classify a record's country code and status flag.
+	pub(crate) fn from_record(country: &str, assigned: bool) -> Self {
+		GeoInfo { assigned, ..Self::from_country(country) }
+	}
+
+	/// Return `true` if the range is registered to the European Union as a whole
+	/// (country code `EU`) rather than to a specific country.
+	///
+	/// Such ranges count as EU ([`GeoInfo::is_eu`] is `true`, region
+	/// [`Region::EuropeanUnion`]), but the member state is not known, so
+	/// country-specific predicates such as [`GeoInfo::is_eurozone`] return `false`.
+	pub fn country_unknown(&self) -> bool {
+		self.country_code == UNSPECIFIED_EU_CODE.as_bytes()
+	}
+
 	/// Return the ISO-3166 alpha-2 country code as a string slice.
 	///
 	/// This is intended for display/logging and should always be valid ASCII.
@@ -108,94 +325,260 @@ impl GeoInfo {
         std::str::from_utf8(&self.country_code).unwrap_or("??")
     }
 	
+	/// Return `true` if the country belongs to the EU VAT territory.
+	///
+	/// This differs from [`GeoInfo::is_eu`]: Monaco is inside the VAT area (treated as
+	/// France), while EU-associated territories with their own country code and
+	/// outside the VAT area (Åland, the French overseas departments, ...) are not.
+	/// Exclusions that share their country's code (Canary Islands, Ceuta, Melilla,
+	/// Mount Athos, Büsingen, ...) cannot be told apart and count as inside.
+	pub fn is_eu_vat_territory(&self) -> bool {
+		let code = self.country_code_str();
+		is_eu_code(code) || VAT_TERRITORY_EXTRA.contains(&code)
+	}
+
+	/// Return `true` if the country is a member of the euro area.
+	///
+	/// Only the member states count; countries using the euro under a monetary
+	/// agreement (Monaco, San Marino, ...) or unilaterally are not included.
+	pub fn is_eurozone(&self) -> bool {
+		EUROZONE_COUNTRIES.contains(&self.country_code_str())
+	}
+
+	/// Return `true` if the country is a member of the Schengen area.
+	///
+	/// Schengen includes non-EU countries (Iceland, Liechtenstein, Norway,
+	/// Switzerland) and excludes EU members Cyprus and Ireland.
+	pub fn is_schengen(&self) -> bool {
+		SCHENGEN_COUNTRIES.contains(&self.country_code_str())
+	}
+
+	/// Return `true` if the country is a member of the European Free Trade
+	/// Association (Iceland, Liechtenstein, Norway, Switzerland).
+	pub fn is_efta(&self) -> bool {
+		EFTA_COUNTRIES.contains(&self.country_code_str())
+	}
+
+	/// Classify the country for GDPR data transfers (EEA, adequacy decision or
+	/// third country).
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::{DataTransferClass, GeoIpDb};
+	///
+	/// let db = GeoIpDb::new();
+	/// let info = db.lookup("46.4.0.1".parse().unwrap()).unwrap();
+	/// assert_eq!(info.data_transfer_class(), DataTransferClass::Eea);
+	/// # }
+	/// ```
+	pub fn data_transfer_class(&self) -> DataTransferClass {
+		let code = self.country_code_str();
+		if is_eu_code(code) || EEA_EXTRA.contains(&code) {
+			DataTransferClass::Eea
+		} else if ADEQUACY_COUNTRIES.contains(&code) {
+			DataTransferClass::Adequate
+		} else {
+			DataTransferClass::ThirdCountry
+		}
+	}
+
 	/// Interpret the stored numeric `region` code as a [`Region`] enum.
 	///
 	/// Unknown or unsupported codes map to [`Region::Other`].
     pub fn region_enum(&self) -> Region {
-        match self.region {
-            1 => Region::EuropeanUnion,
-            2 => Region::EuropeNonEu,
-            3 => Region::EasternEurope,
-            4 => Region::Turkey,
-            5 => Region::MiddleEast,
-            6 => Region::NorthAfrica,
-            7 => Region::CentralAsia,
-            8 => Region::GulfStates,
-            _ => Region::Other,
-        }
+        Region::from_code(self.region)
     }
 }
 
 
+/// Sorted IPv4 and IPv6 range tables with a `T` per range, searched with
+/// binary search.
+///
+/// This is the machinery behind [`GeoIpDb`], which is a `RangeDb` of
+/// [`GeoInfo`]. Any other `Copy` payload works the same way: build a table
+/// of your own ranges with [`RangeDb::build`] and look addresses up in it.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::RangeDb;
+///
+/// #[derive(Debug, Clone, Copy, PartialEq)]
+/// struct TenantId(u32);
+///
+/// let tenants = RangeDb::build([
+///     ("10.1.0.0".parse().unwrap(), "10.1.255.255".parse().unwrap(), TenantId(1)),
+///     ("10.2.0.0".parse().unwrap(), "10.2.0.255".parse().unwrap(), TenantId(2)),
+///     ("2001:db8::".parse().unwrap(), "2001:db8::ffff".parse().unwrap(), TenantId(2)),
+/// ])
+/// .unwrap();
+/// assert_eq!(tenants.lookup("10.2.0.7".parse().unwrap()), Some(&TenantId(2)));
+/// assert_eq!(tenants.lookup("10.3.0.1".parse().unwrap()), None);
+/// ```
+pub struct RangeDb<T = GeoInfo> {
+    pub(crate) v4_ranges: Arc<Vec<(u32, u32, T)>>,
+    pub(crate) v6_ranges: Arc<Vec<(u128, u128, T)>>,
+    pub(crate) metadata: Option<SourceMetadata>,
+    /// Space the sources list as not delegated, see [`GeoIpDb::coverage`].
+    pub(crate) unallocated: Arc<FreeSpace>,
+}
+
+/// Cloning shares the range tables, so it is cheap: clones can be handed to
+/// worker threads and tasks without wrapping the database in an [`Arc`].
+/// Methods that modify the tables of a clone, such as [`GeoIpDb::optimize`],
+/// copy them first.
+impl<T> Clone for RangeDb<T> {
+    fn clone(&self) -> Self {
+        RangeDb {
+            v4_ranges: Arc::clone(&self.v4_ranges),
+            v6_ranges: Arc::clone(&self.v6_ranges),
+            metadata: self.metadata.clone(),
+            unallocated: Arc::clone(&self.unallocated),
+        }
+    }
+}
+
 /// Offline, in-memory lookup database for allocation-based IP classification.
 ///
 /// The default constructor (`new`) uses range tables generated at build time.
 /// Lookups are performed with binary search and do not allocate.
-pub struct GeoIpDb {
-    v4_ranges: Vec<(u32, u32, GeoInfo)>,
-    v6_ranges: Vec<(u128, u128, GeoInfo)>,
+///
+/// Each range stores a `C`, [`GeoInfo`] by default. A database classified
+/// into a type of your own is made with [`GeoIpDb::classify_into`]; its
+/// lookups then return that type directly.
+pub type GeoIpDb<C = GeoInfo> = RangeDb<C>;
+
+/// A classification stored per range in a [`GeoIpDb`], computed once from
+/// the [`GeoInfo`] of each range when the database is built.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "embedded-data")] {
+/// use ip_alloc_lookup::{Classify, GeoInfo, GeoIpDb, Region};
+///
+/// #[derive(Debug, Clone, Copy, PartialEq)]
+/// enum Market {
+///     Dach,
+///     Europe,
+///     RestOfWorld,
+/// }
+///
+/// impl Classify for Market {
+///     fn classify(info: &GeoInfo) -> Self {
+///         match info.country_code_str() {
+///             "DE" | "AT" | "CH" => Market::Dach,
+///             _ if info.region_enum() == Region::EuropeanUnion => Market::Europe,
+///             _ => Market::RestOfWorld,
+///         }
+///     }
+/// }
+///
+/// let db: GeoIpDb<Market> = GeoIpDb::new().classify_into();
+/// assert_eq!(db.lookup("46.4.0.1".parse().unwrap()), Some(&Market::Dach));
+/// # }
+/// ```
+pub trait Classify: Copy + PartialEq {
+    /// Classify a range from its country, region and registry status.
+    fn classify(info: &GeoInfo) -> Self;
+}
+
+impl Classify for GeoInfo {
+    fn classify(info: &GeoInfo) -> Self {
+        *info
+    }
+}
+
+/// ISO-3166 alpha-2 codes of the EU member states, as used by [`GeoInfo::is_eu`].
+///
+/// Generated from `country-policy.txt` (or `IP_ALLOC_POLICY_FILE`) at build time.
+pub const EU_COUNTRIES: &[&str] = crate::embedded::EU_COUNTRIES;
+
+// Country field RIPE uses for allocations to the EU as a whole
+const UNSPECIFIED_EU_CODE: &str = "EU";
+
+/// EU member state or the unspecified `EU` code.
+fn is_eu_code(country: &str) -> bool {
+    country == UNSPECIFIED_EU_CODE || EU_COUNTRIES.contains(&country)
 }
 
-// EU member states (27 countries as of 2025)
-const EU_COUNTRIES: &[&str] = &[
-    "AT", "BE", "BG", "HR", "CY", "CZ", "DK", "EE", "FI", "FR",
-    "DE", "GR", "HU", "IE", "IT", "LV", "LT", "LU", "MT", "NL",
-    "PL", "PT", "RO", "SK", "SI", "ES", "SE",
+// Euro area member states (21 countries as of 2026)
+const EUROZONE_COUNTRIES: &[&str] = &[
+    "AT", "BE", "BG", "HR", "CY", "EE", "FI", "FR", "DE", "GR",
+    "IE", "IT", "LV", "LT", "LU", "MT", "NL", "PT", "SK", "SI",
+    "ES",
+];
+
+// Schengen area members (29 countries as of 2025)
+const SCHENGEN_COUNTRIES: &[&str] = &[
+    "AT", "BE", "BG", "HR", "CZ", "DK", "EE", "FI", "FR", "DE",
+    "GR", "HU", "IS", "IT", "LV", "LI", "LT", "LU", "MT", "NL",
+    "NO", "PL", "PT", "RO", "SK", "SI", "ES", "SE", "CH",
+];
+
+// EFTA member states
+const EFTA_COUNTRIES: &[&str] = &["CH", "IS", "LI", "NO"];
+
+// Non-EU members of the European Economic Area
+const EEA_EXTRA: &[&str] = &["IS", "LI", "NO"];
+
+// Countries and territories with an EU adequacy decision under the GDPR
+const ADEQUACY_COUNTRIES: &[&str] = &[
+    "AD", "AR", "CA", "CH", "FO", "GB", "GG", "IL", "IM", "JE",
+    "JP", "KR", "NZ", "UY",
 ];
 
-// Include the generated data from build.rs
-include!(concat!(env!("OUT_DIR"), "/generated_data.rs"));
+// Non-member countries that are part of the EU VAT territory
+const VAT_TERRITORY_EXTRA: &[&str] = &["MC"];
 
 impl GeoIpDb {
     /// Construct a database using the embedded range tables generated at build time.
 	///
 	/// This is the fastest and most predictable option: no I/O and no parsing at runtime.
 	///
+	/// Without the `embedded-data` feature (enabled by default) no tables are
+	/// embedded and this returns an empty database; load data at runtime instead.
+	///
 	/// # Examples
 	/// ```
-	/// use offline_ripe_geoip::GeoIpDb;
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
 	///
 	/// let db = GeoIpDb::new();
 	/// let info = db.lookup("46.4.0.1".parse().unwrap());
 	/// assert!(info.is_some());
+	/// # }
 	/// ```
     pub fn new() -> Self {
         let mut v4_ranges = Vec::with_capacity(IPV4_RANGES.len());
         let mut v6_ranges = Vec::with_capacity(IPV6_RANGES.len());
 
         // Process IPv4 ranges
-        for &(start, end, country) in IPV4_RANGES {
-            let is_eu = EU_COUNTRIES.contains(&country);
-            let region = determine_region(country);
-
-            let geo_info = GeoInfo {
-				country_code: cc2(country),
-				is_eu,
-				region: region as u8,
-			};
-
-            v4_ranges.push((start, end, geo_info));
+        for (start, end, country, assigned) in IPV4_RANGES.iter() {
+            v4_ranges.push((start, end, GeoInfo::from_record(country, assigned)));
         }
 
         // Process IPv6 ranges
-        for &(start, end, country) in IPV6_RANGES {
-            let is_eu = EU_COUNTRIES.contains(&country);
-            let region = determine_region(country);
-
-            let geo_info = GeoInfo {
-				country_code: cc2(country),
-				is_eu,
-				region: region as u8,
-			};
-
-            v6_ranges.push((start, end, geo_info));
+        for (start, end, country, assigned) in IPV6_RANGES.iter() {
+            v6_ranges.push((start, end, GeoInfo::from_record(country, assigned)));
         }
 
         // Data should already be sorted from build.rs, but let's be safe
         //v4_ranges.sort_by_key(|r| r.0);
         //v6_ranges.sort_by_key(|r| r.0);
 
-        GeoIpDb { v4_ranges, v6_ranges }
+        let metadata = SourceMetadata::from_header_line(SOURCE_HEADER)
+            .map(|meta| SourceMetadata {
+                sha256: Some(SOURCE_SHA256.to_string()),
+                format: DelegatedFormat::from_name(SOURCE_FORMAT),
+                ..meta
+            });
+
+        GeoIpDb {
+            v4_ranges: Arc::new(v4_ranges),
+            v6_ranges: Arc::new(v6_ranges),
+            metadata,
+            unallocated: Arc::new(crate::embedded::unallocated()),
+        }
     }
 	
 	/// Build a database by parsing RIPE delegated stats content at runtime.
@@ -205,43 +588,97 @@ impl GeoIpDb {
 	///
 	/// # Examples
 	/// ```
-	/// use offline_ripe_geoip::GeoIpDb;
+	/// use ip_alloc_lookup::GeoIpDb;
 	///
 	/// let data = "ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n";
 	/// let db = GeoIpDb::from_ripe_delegated_str(data);
 	/// assert!(db.lookup("46.4.0.1".parse().unwrap()).is_some());
 	/// ```
     pub fn from_ripe_delegated_str(content: &str) -> Self {
-        let parsed = crate::parse_ripe_delegated(content);
+        Self::from_parsed(ParsedDelegated::parse(content))
+    }
+
+    /// Build a database from RIPE delegated stats content given as raw bytes.
+	///
+	/// Equivalent to [`GeoIpDb::from_ripe_delegated_str`] without validating the
+	/// whole content as UTF-8 first; see [`ParsedDelegated::parse_bytes`].
+    pub fn from_ripe_delegated_bytes(content: &[u8]) -> Self {
+        Self::from_parsed(ParsedDelegated::parse_bytes(content))
+    }
+
+    /// Build a database from RIPE delegated stats content, rejecting incomplete files.
+	///
+	/// Unlike [`GeoIpDb::from_ripe_delegated_str`], this checks the record counts
+	/// announced by the file's `summary` lines against the records actually parsed.
+	/// A truncated download keeps its original header, so it is reported here
+	/// instead of silently producing a smaller database.
+	///
+	/// # Errors
+	/// Returns a [`VerifyError`] if the summary lines are missing or do not match.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let data = "\
+	/// ripencc|*|ipv4|*|2|summary
+	/// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+	/// ";
+	/// assert!(GeoIpDb::from_ripe_delegated_str_strict(data).is_err());
+	/// ```
+    pub fn from_ripe_delegated_str_strict(content: &str) -> Result<Self, VerifyError> {
+        let parsed = ParsedDelegated::parse(content);
+        parsed.verify()?;
+        Ok(Self::from_parsed(parsed))
+    }
+
+    /// Build a database from a parsed file, keeping its header metadata.
+    pub(crate) fn from_parsed(parsed: ParsedDelegated) -> Self {
+        let metadata = parsed.metadata().cloned();
+        let unallocated = FreeSpace::from_records(parsed.unallocated());
+        let mut db = Self::from_ranges(parsed.ranges);
+        db.metadata = metadata;
+        db.unallocated = Arc::new(unallocated);
+        db
+    }
 
+    /// Build the sorted lookup tables from parsed allocation ranges.
+    pub(crate) fn from_ranges(ranges: Vec<IpRange>) -> Self {
         let mut v4_ranges: Vec<(u32, u32, GeoInfo)> = Vec::new();
         let mut v6_ranges: Vec<(u128, u128, GeoInfo)> = Vec::new();
 
-        for r in parsed {
-            let is_eu = EU_COUNTRIES.contains(&r.country.as_str());
-            let region = determine_region(&r.country);
-
-            let geo = GeoInfo {
-                country_code: cc2(&r.country),
-                is_eu,
-                region: region as u8,
-            };
-
-            if let Some(v4) = r.start_v4 {
-                let start: u32 = v4.into();
-                let end = start.saturating_add((r.count as u32).saturating_sub(1));
-                v4_ranges.push((start, end, geo));
-            } else if let Some(v6) = r.start_v6 {
-                let start: u128 = v6.into();
-                let end = start.saturating_add(r.count.saturating_sub(1));
-                v6_ranges.push((start, end, geo));
+        for r in ranges {
+            let geo = GeoInfo::from_record(&r.country, r.assigned);
+
+            match (r.start, r.end()) {
+                (RangeStart::V4(start), IpAddr::V4(end)) => {
+                    v4_ranges.push((start.into(), end.into(), geo));
+                }
+                (RangeStart::V6(start), IpAddr::V6(end)) => {
+                    v6_ranges.push((start.into(), end.into(), geo));
+                }
+                _ => unreachable!("IpRange::end has the family of its start"),
             }
         }
 
-        v4_ranges.sort_by_key(|r| r.0);
-        v6_ranges.sort_by_key(|r| r.0);
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::slice::ParallelSliceMut;
+            v4_ranges.par_sort_by_key(|r| r.0);
+            v6_ranges.par_sort_by_key(|r| r.0);
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            v4_ranges.sort_by_key(|r| r.0);
+            v6_ranges.sort_by_key(|r| r.0);
+        }
 
-        GeoIpDb { v4_ranges, v6_ranges }
+        GeoIpDb {
+            v4_ranges: Arc::new(v4_ranges),
+            v6_ranges: Arc::new(v6_ranges),
+            metadata: None,
+            unallocated: Arc::default(),
+        }
     }
 
     /// Load RIPE delegated stats content from a file and build a database.
@@ -249,26 +686,135 @@ impl GeoIpDb {
 	/// # Errors
 	/// Returns an error if the file cannot be read.
     pub fn from_ripe_delegated_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let content = fs::read_to_string(path)?;
-        Ok(Self::from_ripe_delegated_str(&content))
+        let content = fs::read(path)?;
+        Ok(Self::from_ripe_delegated_bytes(&content))
+    }
+
+    /// Load RIPE delegated stats content from a file, rejecting incomplete files.
+	///
+	/// See [`GeoIpDb::from_ripe_delegated_str_strict`] for the checks performed.
+	///
+	/// # Errors
+	/// Returns an error if the file cannot be read, or an error of kind
+	/// [`io::ErrorKind::InvalidData`] if its summary counts do not match.
+    pub fn from_ripe_delegated_file_strict<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let parsed = ParsedDelegated::parse_bytes(&fs::read(path)?);
+        parsed
+            .verify()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self::from_parsed(parsed))
     }
 
     /// Try to load the database from a cache file, falling back to embedded data.
 	///
 	/// This is a convenience helper for "use cache if present, otherwise use the
-	/// built-in tables".
+	/// built-in tables". If the cache has a [`CacheInfo`] sidecar, the file is
+	/// only used when its content still matches the recorded hash, so a
+	/// truncated or replaced file falls back to the embedded data as well.
+	/// Caches without a sidecar are trusted as before.
+	///
+	/// When the pre-parsed binary copy written by the cache update functions
+	/// (`<file name>.bin`) matches the text file, it is loaded instead and text
+	/// parsing is skipped entirely.
     pub fn from_cache_or_embedded<P: AsRef<Path>>(cache_path: P) -> Self {
-        match Self::from_ripe_delegated_file(cache_path) {
-            Ok(db) => db,
-            Err(_) => Self::new(),
+        let cache_path = cache_path.as_ref();
+        if let Some(db) = crate::binary::load_binary_cache(cache_path) {
+            return db;
+        }
+
+        let Ok(content) = fs::read(cache_path) else {
+            return Self::default();
+        };
+
+        if let Ok(info) = CacheInfo::read(cache_path)
+            && !info.matches(&content)
+        {
+            return Self::default();
+        }
+
+        Self::from_ripe_delegated_bytes(&content)
+    }
+
+    /// Load a cache file like [`GeoIpDb::from_cache_or_embedded`], but fail
+    /// instead of falling back, for reloads that must keep the current data
+    /// when the file is missing or incomplete.
+    ///
+    /// The binary copy is used if it matches; otherwise the text file must
+    /// match its [`CacheInfo`] sidecar or, without one, pass
+    /// [`ParsedDelegated::verify`].
+    #[cfg(feature = "tokio")]
+    pub(crate) fn from_cache_checked(cache_path: &Path) -> io::Result<Self> {
+        if let Some(db) = crate::binary::load_binary_cache(cache_path) {
+            return Ok(db);
+        }
+        let content = fs::read(cache_path)?;
+        let parsed = ParsedDelegated::parse_bytes(&content);
+        match CacheInfo::read(cache_path) {
+            Ok(info) if info.matches(&content) => {}
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "cache file does not match its sidecar",
+                ));
+            }
+            Err(_) => parsed
+                .verify()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        }
+        Ok(Self::from_parsed(parsed))
+    }
+}
+
+impl<T: Copy> RangeDb<T> {
+    /// Build a table from `(first, last, value)` ranges, in any order.
+	///
+	/// Both ends of a range are inclusive and must be of the same address
+	/// family. Returns an [`io::ErrorKind::InvalidInput`] error for a range
+	/// that is reversed, mixes families or overlaps another one.
+    pub fn build<I: IntoIterator<Item = (IpAddr, IpAddr, T)>>(ranges: I) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        let mut v4_ranges = Vec::new();
+        let mut v6_ranges = Vec::new();
+        for (first, last, value) in ranges {
+            match (first, last) {
+                (IpAddr::V4(a), IpAddr::V4(b)) if a <= b => v4_ranges.push((a.into(), b.into(), value)),
+                (IpAddr::V6(a), IpAddr::V6(b)) if a <= b => v6_ranges.push((a.into(), b.into(), value)),
+                _ => return Err(invalid(format!("invalid range {first}-{last}"))),
+            }
+        }
+        v4_ranges.sort_by_key(|r| r.0);
+        v6_ranges.sort_by_key(|r| r.0);
+        if let Some(w) = v4_ranges.windows(2).find(|w| w[1].0 <= w[0].1) {
+            let (a, b) = (Ipv4Addr::from(w[1].0), Ipv4Addr::from(w[0].1));
+            return Err(invalid(format!("range starting at {a} overlaps the range ending at {b}")));
         }
+        if let Some(w) = v6_ranges.windows(2).find(|w| w[1].0 <= w[0].1) {
+            let (a, b) = (Ipv6Addr::from(w[1].0), Ipv6Addr::from(w[0].1));
+            return Err(invalid(format!("range starting at {a} overlaps the range ending at {b}")));
+        }
+        Ok(RangeDb {
+            v4_ranges: Arc::new(v4_ranges),
+            v6_ranges: Arc::new(v6_ranges),
+            metadata: None,
+            unallocated: Arc::default(),
+        })
+    }
+
+    /// Return the number of ranges, IPv4 and IPv6 together.
+    pub fn len(&self) -> usize {
+        self.v4_ranges.len() + self.v6_ranges.len()
+    }
+
+    /// Return `true` if the table has no ranges.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
     /// Look up a single IPv4 address.
 	///
 	/// Returns [`None`] if the address is not covered by the embedded/loaded ranges.
 	#[inline]
-    pub fn lookup_v4(&self, ip: Ipv4Addr) -> Option<&GeoInfo> {
+    pub fn lookup_v4(&self, ip: Ipv4Addr) -> Option<&T> {
 		let ip_u32: u32 = ip.into();
 		
 		match self.v4_ranges.binary_search_by_key(&ip_u32, |&(start, _, _)| start) {
@@ -289,7 +835,7 @@ impl GeoIpDb {
 	///
 	/// Returns [`None`] if the address is not covered by the embedded/loaded ranges.
 	#[inline]
-	pub fn lookup_v6(&self, ip: Ipv6Addr) -> Option<&GeoInfo> {
+	pub fn lookup_v6(&self, ip: Ipv6Addr) -> Option<&T> {
 		let ip_u128: u128 = ip.into();
 		let ranges = &self.v6_ranges;
 
@@ -325,114 +871,813 @@ impl GeoIpDb {
 	///
 	/// # Examples
 	/// ```
-	/// use offline_ripe_geoip::GeoIpDb;
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
 	///
 	/// let db = GeoIpDb::new();
 	/// let info = db.lookup("46.4.0.1".parse().unwrap()).unwrap();
 	/// assert_eq!(info.country_code_str(), "DE");
+	/// # }
 	/// ```
-    pub fn lookup(&self, ip: IpAddr) -> Option<&GeoInfo> {
+    pub fn lookup(&self, ip: IpAddr) -> Option<&T> {
         match ip {
             IpAddr::V4(v4) => self.lookup_v4(v4),
             IpAddr::V6(v6) => self.lookup_v6(v6),
         }
     }
 
-    /// Return `true` if the IP is covered by the database and classified as EU.
+    /// Look up the address of a socket, such as [`TcpStream::peer_addr`]
+	/// returns.
 	///
-	/// Addresses not found in the database return `false`.
-	#[inline]
-    pub fn is_eu(&self, ip: IpAddr) -> bool {
-        self.lookup(ip).map(|info| info.is_eu).unwrap_or(false)
-    }
-
-    /// Return basic statistics about the loaded database.
+	/// IPv4-mapped IPv6 addresses (`[::ffff:46.4.0.1]:443`, as reported by
+	/// dual-stack listeners) are looked up as the IPv4 address they carry.
 	///
-	/// This can be useful for sanity checks (e.g., validating that data loaded correctly).
-    pub fn stats(&self) -> DbStats {
-        let total_v4_ranges = self.v4_ranges.len();
-        let total_v6_ranges = self.v6_ranges.len();
-        let eu_v4_ranges = self.v4_ranges.iter().filter(|(_, _, info)| info.is_eu).count();
-        let eu_v6_ranges = self.v6_ranges.iter().filter(|(_, _, info)| info.is_eu).count();
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use std::net::SocketAddr;
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// let peer: SocketAddr = "[::ffff:46.4.0.1]:51234".parse().unwrap();
+	/// assert_eq!(db.lookup_socket(peer).unwrap().country_code_str(), "DE");
+	/// # }
+	/// ```
+	///
+	/// [`TcpStream::peer_addr`]: std::net::TcpStream::peer_addr
+	pub fn lookup_socket<A: Into<SocketAddr>>(&self, addr: A) -> Option<&T> {
+		self.lookup(addr.into().ip().to_canonical())
+	}
 
-        DbStats {
-            total_v4_ranges,
-            total_v6_ranges,
-            eu_v4_ranges,
-            eu_v6_ranges,
-            non_eu_v4_ranges: total_v4_ranges - eu_v4_ranges,
-            non_eu_v6_ranges: total_v6_ranges - eu_v6_ranges,
-        }
-    }
-}
+    /// Look up a batch of addresses, returning one result per input in order.
+	///
+	/// Each address is binary-searched independently. When the input is sorted,
+	/// [`GeoIpDb::lookup_many_sorted`] is faster.
+	pub fn lookup_many(&self, ips: &[IpAddr]) -> Vec<Option<&T>> {
+		ips.iter().map(|&ip| self.lookup(ip)).collect()
+	}
 
-#[cfg(feature = "download")]
-impl GeoIpDb {
-    /// Download RIPE delegated data from `url` and atomically replace `cache_path`.
+    /// Look up a batch of addresses that is sorted, or mostly sorted, such as
+	/// the client addresses of an access log grouped by network.
 	///
-	/// The download is written to a temporary file next to the destination and then
-	/// renamed into place.
+	/// Instead of a full binary search per address, each search gallops forward
+	/// from the previous match of the same address family, so nearby addresses
+	/// cost a few comparisons. An address smaller than its predecessor falls
+	/// back to a binary search, so results are the same as
+	/// [`GeoIpDb::lookup_many`] for any input order.
 	///
-	/// # Errors
-	/// Returns an error if the download fails or the cache file cannot be written.
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use std::net::IpAddr;
+	/// use ip_alloc_lookup::GeoIpDb;
 	///
-	/// # Feature
-	/// Available only when the crate is built with the `download` feature.
-    pub fn update_cache_from_url<P: AsRef<Path>>(cache_path: P, url: &str) -> io::Result<u64> {
-        let cache_path = cache_path.as_ref();
-
-        // Ensure parent dir exists
-        if let Some(parent) = cache_path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
-        // Download
-        let resp = reqwest::blocking::get(url)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
-            .error_for_status()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-        let bytes = resp
-            .bytes()
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-        // Write to a temp file next to the destination (so rename is atomic on most OSes)
-        let tmp_path = cache_path.with_extension("tmp");
-        {
-            let mut f = fs::File::create(&tmp_path)?;
-            use std::io::Write;
-            f.write_all(&bytes)?;
-            f.sync_all()?;
-        }
-
-        // Replace existing cache atomically-ish
-        if cache_path.exists() {
-            // On Windows rename can fail if target exists, so remove first.
-            let _ = fs::remove_file(cache_path);
-        }
-        fs::rename(&tmp_path, cache_path)?;
-
-        Ok(bytes.len() as u64)
-    }
+	/// let db = GeoIpDb::new();
+	/// let ips: Vec<IpAddr> = ["46.4.0.1", "46.4.0.2", "2a01:4f8::1"]
+	///     .iter()
+	///     .map(|s| s.parse().unwrap())
+	///     .collect();
+	/// let results = db.lookup_many_sorted(&ips);
+	/// assert!(results.iter().all(|r| r.unwrap().country_code_str() == "DE"));
+	/// # }
+	/// ```
+	pub fn lookup_many_sorted(&self, ips: &[IpAddr]) -> Vec<Option<&T>> {
+		let (mut hint_v4, mut hint_v6) = (0, 0);
+		ips.iter()
+			.map(|&ip| match ip {
+				IpAddr::V4(v4) => find_from(&self.v4_ranges, &mut hint_v4, v4.into()),
+				IpAddr::V6(v6) => find_from(&self.v6_ranges, &mut hint_v6, v6.into()),
+			})
+			.collect()
+	}
 
-    /// Convenience wrapper around [`GeoIpDb::update_cache_from_url`] using the
-	/// RIPE “extended latest” endpoint.
+    /// Return the number of heap bytes held by the database.
+	///
+	/// This counts the allocated capacity of the IPv4 and IPv6 range tables, of
+	/// the unallocated space kept for [`GeoIpDb::coverage`] and of the source
+	/// metadata strings, i.e. what the database keeps alive beyond
+	/// `size_of::<GeoIpDb>()`. Embedded data is copied into the tables by
+	/// [`GeoIpDb::new`], so this applies to it as well. Clones share their
+	/// tables, which are counted for each of them.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
 	///
-	/// # Feature
-	/// Available only when the crate is built with the `download` feature.
-    pub fn update_cache<P: AsRef<Path>>(cache_path: P) -> io::Result<u64> {
-        Self::update_cache_from_url(cache_path, RIPE_EXTENDED_LATEST_URL)
+	/// let db = GeoIpDb::new();
+	/// println!("{:.1} MiB", db.memory_bytes() as f64 / (1024.0 * 1024.0));
+	/// ```
+    pub fn memory_bytes(&self) -> usize {
+        let v4 = self.v4_ranges.capacity() * std::mem::size_of::<(u32, u32, T)>();
+        let v6 = self.v6_ranges.capacity() * std::mem::size_of::<(u128, u128, T)>();
+        let free = self.unallocated.v4.capacity() * std::mem::size_of::<(u32, u32)>()
+            + self.unallocated.v6.capacity() * std::mem::size_of::<(u128, u128)>();
+        let metadata = self.metadata.as_ref().map_or(0, |m| {
+            m.version.capacity()
+                + m.registry.capacity()
+                + m.start_date.capacity()
+                + m.end_date.capacity()
+                + m.utc_offset.capacity()
+                + m.sha256.as_ref().map_or(0, String::capacity)
+        });
+        v4 + v6 + free + metadata
     }
 }
 
-impl Default for GeoIpDb {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+impl GeoIpDb {
+    /// Return `true` if the IP is covered by the database and classified as EU.
+	///
+	/// Addresses not found in the database return `false`.
+	#[inline]
+    pub fn is_eu(&self, ip: IpAddr) -> bool {
+        self.lookup(ip).map(|info| info.is_eu).unwrap_or(false)
+    }
+
+	/// Return `true` if the address of a socket is covered by the database and
+	/// classified as EU. See [`GeoIpDb::lookup_socket`].
+	#[inline]
+	pub fn is_eu_socket<A: Into<SocketAddr>>(&self, addr: A) -> bool {
+		self.lookup_socket(addr).is_some_and(|info| info.is_eu)
+	}
+
+    /// Create a [`LookupCursor`] for runs of lookups that tend to repeat the
+	/// same few blocks, such as the packets of a flow.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// let mut cursor = db.cursor();
+	/// for _ in 0..3 {
+	///     let info = cursor.lookup("46.4.0.1".parse().unwrap()).unwrap();
+	///     assert_eq!(info.country_code_str(), "DE");
+	/// }
+	/// # }
+	/// ```
+	pub fn cursor(&self) -> LookupCursor<'_> {
+		LookupCursor { db: self, last_v4: 0, last_v6: 0 }
+	}
+
+    /// Look up an address and return the matched allocation block along with its
+	/// classification.
+	///
+	/// Useful for debugging: it shows which block an address fell into, not only the
+	/// resulting country. Returns [`None`] if the address is not covered.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// let ip = "46.4.0.1".parse().unwrap();
+	/// let m = db.lookup_range(ip).unwrap();
+	/// assert!(m.start <= ip && ip <= m.end);
+	/// assert_eq!(m.info.country_code_str(), "DE");
+	/// # }
+	/// ```
+    pub fn lookup_range(&self, ip: IpAddr) -> Option<RangeMatch> {
+        match ip {
+            IpAddr::V4(v4) => {
+                let (start, end, info) = find_range(&self.v4_ranges, u32::from(v4))?;
+                Some(RangeMatch {
+                    start: IpAddr::V4(start.into()),
+                    end: IpAddr::V4(end.into()),
+                    prefix_len: exact_prefix_len(start.into(), end.into(), 32),
+                    info,
+                })
+            }
+            IpAddr::V6(v6) => {
+                let (start, end, info) = find_range(&self.v6_ranges, u128::from(v6))?;
+                Some(RangeMatch {
+                    start: IpAddr::V6(start.into()),
+                    end: IpAddr::V6(end.into()),
+                    prefix_len: exact_prefix_len(start, end, 128),
+                    info,
+                })
+            }
+        }
+    }
+
+    /// Look up an address and return its classification along with the matched
+	/// block rendered as CIDR prefixes, e.g. `"46.4.0.0/23,46.4.2.0/24"`.
+	///
+	/// Meant for logging next to a decision, so it can later be traced back to
+	/// the allocation that drove it. See [`RangeMatch::cidr_string`].
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|768|20250101|allocated\n");
+	/// let (info, cidr) = db.lookup_with_cidr("46.4.1.1".parse().unwrap()).unwrap();
+	/// assert_eq!(info.country_code_str(), "DE");
+	/// assert_eq!(cidr, "46.4.0.0/23,46.4.2.0/24");
+	/// ```
+    pub fn lookup_with_cidr(&self, ip: IpAddr) -> Option<(GeoInfo, String)> {
+        let matched = self.lookup_range(ip)?;
+        Some((matched.info, matched.cidr_string()))
+    }
+
+    /// Return the two-byte country code of the range covering `ip`.
+	///
+	/// Returns [`None`] if the address is not covered by the database.
+	#[inline]
+    pub fn country(&self, ip: IpAddr) -> Option<[u8; 2]> {
+        self.lookup(ip).map(|info| info.country_code)
+    }
+
+    /// Return the country code of the range covering `ip` as a string slice.
+	///
+	/// Returns [`None`] if the address is not covered by the database.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// assert_eq!(db.country_str("46.4.0.1".parse().unwrap()), Some("DE"));
+	/// # }
+	/// ```
+	#[inline]
+    pub fn country_str(&self, ip: IpAddr) -> Option<&str> {
+        self.lookup(ip).map(|info| info.country_code_str())
+    }
+
+    /// Return `true` if the IP is covered by the database and allocated to `country`.
+	///
+	/// `country` is compared case-insensitively. Addresses not found in the database
+	/// return `false`, as does anything that is not a two-letter code (so the `"??"`
+	/// fallback of [`GeoInfo::country_code_str`] never matches).
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// assert!(db.is_country("46.4.0.1".parse().unwrap(), "de"));
+	/// assert!(!db.is_country("46.4.0.1".parse().unwrap(), "FR"));
+	/// # }
+	/// ```
+	#[inline]
+    pub fn is_country(&self, ip: IpAddr, country: &str) -> bool {
+        country.len() == 2
+            && country.bytes().all(|b| b.is_ascii_alphabetic())
+            && self
+                .lookup(ip)
+                .is_some_and(|info| info.country_code.eq_ignore_ascii_case(country.as_bytes()))
+    }
+
+    /// Return `true` if the IP is covered by the database and its country is a
+	/// member of the euro area.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// assert!(db.is_eurozone("46.4.0.1".parse().unwrap()));
+	/// # }
+	/// ```
+	#[inline]
+    pub fn is_eurozone(&self, ip: IpAddr) -> bool {
+        self.lookup(ip).is_some_and(|info| info.is_eurozone())
+    }
+
+    /// Return `true` if the IP is covered by the database and its country is a
+	/// member of the Schengen area.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// assert!(db.is_schengen("46.4.0.1".parse().unwrap()));
+	/// # }
+	/// ```
+	#[inline]
+    pub fn is_schengen(&self, ip: IpAddr) -> bool {
+        self.lookup(ip).is_some_and(|info| info.is_schengen())
+    }
+
+    /// Return `true` if the IP is covered by the database and its country is an
+	/// EFTA member.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// assert!(!db.is_efta("46.4.0.1".parse().unwrap()));
+	/// ```
+	#[inline]
+    pub fn is_efta(&self, ip: IpAddr) -> bool {
+        self.lookup(ip).is_some_and(|info| info.is_efta())
+    }
+
+    /// Return `true` if the IP is covered by the database and its country is in the
+	/// EU VAT territory.
+	///
+	/// Use this rather than [`GeoIpDb::is_eu`] for VAT decisions; see
+	/// [`GeoInfo::is_eu_vat_territory`] for the rules and their limits.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// assert!(db.is_eu_vat_territory("46.4.0.1".parse().unwrap()));
+	/// # }
+	/// ```
+	#[inline]
+    pub fn is_eu_vat_territory(&self, ip: IpAddr) -> bool {
+        self.lookup(ip).is_some_and(|info| info.is_eu_vat_territory())
+    }
+
+    /// Return the header metadata (registry, serial, dates) of the loaded data.
+	///
+	/// For the embedded tables this describes the snapshot `ripe-data.txt` the crate
+	/// was built from, including its SHA-256 ([`SourceMetadata::sha256`]); for
+	/// runtime-loaded data it describes the loaded file.
+	/// Returns [`None`] if the source had no version/header line.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// if let Some(meta) = db.source_metadata() {
+	///     println!("{} serial {} ({})", meta.registry, meta.serial, meta.end_date);
+	/// }
+	/// ```
+    pub fn source_metadata(&self) -> Option<&SourceMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Return how old the loaded data is, measured from the end date in its
+    /// header ([`SourceMetadata::published_at`]).
+	///
+	/// Returns [`None`] if the source had no header or no valid end date, e.g.
+	/// for CSV data. To measure the age of a download instead, use
+	/// [`CacheInfo::age`].
+    pub fn data_age(&self) -> Option<Duration> {
+        let published = self.metadata.as_ref()?.published_at()?;
+        // A header dated in the future counts as fresh
+        Some(SystemTime::now().duration_since(published).unwrap_or_default())
+    }
+
+    /// Return `true` if the data is older than `max_age`, or if its age is unknown.
+	///
+	/// # Examples
+	/// ```
+	/// use std::time::Duration;
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new();
+	/// if db.is_stale(Duration::from_secs(60 * 86_400)) {
+	///     eprintln!("classification data is over 60 days old");
+	/// }
+	/// ```
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.data_age().is_none_or(|age| age > max_age)
+    }
+
+    /// Return basic statistics about the loaded database.
+	///
+	/// This can be useful for sanity checks (e.g., validating that data loaded correctly).
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::{GeoIpDb, Region};
+	///
+	/// let data = "\
+	/// ripencc|FR|ipv4|2.0.0.0|768|20100712|allocated
+	/// ripencc|CH|ipv4|46.5.0.0|256|20090101|assigned
+	/// ";
+	/// let stats = GeoIpDb::from_ripe_delegated_str(data).stats();
+	/// assert_eq!(stats.region(Region::EuropeanUnion).unwrap().v4_addresses, 768);
+	/// assert_eq!(stats.v4_share(Region::EuropeanUnion), 75.0);
+	/// assert_eq!(stats.eu_address_share_v4(), 75.0);
+	/// ```
+    pub fn stats(&self) -> DbStats {
+        let total_v4_ranges = self.v4_ranges.len();
+        let total_v6_ranges = self.v6_ranges.len();
+        let eu_v4_ranges = self.v4_ranges.iter().filter(|(_, _, info)| info.is_eu).count();
+        let eu_v6_ranges = self.v6_ranges.iter().filter(|(_, _, info)| info.is_eu).count();
+
+        let new = |info: &GeoInfo| RegionStats {
+            region: info.region_enum(),
+            v4_ranges: 0,
+            v6_ranges: 0,
+            v4_addresses: 0,
+            v6_addresses: 0,
+        };
+        let mut regions: BTreeMap<u8, RegionStats> = BTreeMap::new();
+        for (start, end, info) in self.v4_ranges.iter() {
+            let stats = regions.entry(info.region).or_insert_with(|| new(info));
+            stats.v4_ranges += 1;
+            stats.v4_addresses += u64::from(end - start) + 1;
+        }
+        for (start, end, info) in self.v6_ranges.iter() {
+            let stats = regions.entry(info.region).or_insert_with(|| new(info));
+            stats.v6_ranges += 1;
+            stats.v6_addresses = stats.v6_addresses.saturating_add((end - start).saturating_add(1));
+        }
+
+        DbStats {
+            total_v4_ranges,
+            total_v6_ranges,
+            eu_v4_ranges,
+            eu_v6_ranges,
+            non_eu_v4_ranges: total_v4_ranges - eu_v4_ranges,
+            non_eu_v6_ranges: total_v6_ranges - eu_v6_ranges,
+            regions: regions.into_values().collect(),
+        }
+    }
+
+    /// Merge adjacent ranges with identical classification and release spare capacity.
+	///
+	/// Delegated files list every allocation separately, so neighbouring blocks of
+	/// the same country are common; coalescing them makes runtime-loaded data
+	/// considerably smaller without changing any lookup result. Afterwards
+	/// [`GeoIpDb::lookup_range`] reports the merged blocks.
+	///
+	/// Returns the number of ranges removed.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let data = "\
+	/// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+	/// ripencc|DE|ipv4|46.4.1.0|256|20250101|allocated
+	/// ";
+	/// let mut db = GeoIpDb::from_ripe_delegated_str(data);
+	/// assert_eq!(db.optimize(), 1);
+	/// assert_eq!(db.stats().total_v4_ranges, 1);
+	/// ```
+    pub fn optimize(&mut self) -> usize {
+        let before = self.v4_ranges.len() + self.v6_ranges.len();
+        coalesce(Arc::make_mut(&mut self.v4_ranges), |x: u32| x.checked_add(1));
+        coalesce(Arc::make_mut(&mut self.v6_ranges), |x: u128| x.checked_add(1));
+        before - (self.v4_ranges.len() + self.v6_ranges.len())
+    }
+
+    /// Build a database storing [`Classify::classify`] of every range instead
+	/// of its [`GeoInfo`], so that lookups return `C` directly.
+	///
+	/// Adjacent ranges with the same classification are merged, which for a
+	/// handful of classes leaves far fewer ranges. See [`Classify`] for an
+	/// example.
+    pub fn classify_into<C: Classify>(&self) -> GeoIpDb<C> {
+        let mut v4_ranges: Vec<_> = self
+            .v4_ranges
+            .iter()
+            .map(|(start, end, info)| (*start, *end, C::classify(info)))
+            .collect();
+        let mut v6_ranges: Vec<_> = self
+            .v6_ranges
+            .iter()
+            .map(|(start, end, info)| (*start, *end, C::classify(info)))
+            .collect();
+        coalesce(&mut v4_ranges, |x: u32| x.checked_add(1));
+        coalesce(&mut v6_ranges, |x: u128| x.checked_add(1));
+        GeoIpDb {
+            v4_ranges: Arc::new(v4_ranges),
+            v6_ranges: Arc::new(v6_ranges),
+            metadata: self.metadata.clone(),
+            unallocated: Arc::clone(&self.unallocated),
+        }
+    }
+
+    /// Build a smaller database with only the ranges of `countries`.
+	///
+	/// `countries` are ISO-3166 alpha-2 codes, matched case-insensitively.
+	/// Other addresses are not covered by the result, so services that only
+	/// care about a few countries need not keep the rest in memory. The source
+	/// metadata is kept.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::{GeoIpDb, EU_COUNTRIES};
+	///
+	/// let eu = GeoIpDb::new().subset(EU_COUNTRIES);
+	/// assert!(eu.is_eu("46.4.0.1".parse().unwrap()));
+	/// assert_eq!(eu.lookup("5.1.48.1".parse().unwrap()), None); // RU
+	/// # }
+	/// ```
+    pub fn subset(&self, countries: &[&str]) -> GeoIpDb {
+        self.filtered(|info| {
+            countries
+                .iter()
+                .any(|country| info.country_code.eq_ignore_ascii_case(country.as_bytes()))
+        })
+    }
+
+    /// Build a smaller database without the ranges of `countries`, the
+	/// complement of [`GeoIpDb::subset`].
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let db = GeoIpDb::new().without(&["RU", "BY"]);
+	/// assert_eq!(db.lookup("5.1.48.1".parse().unwrap()), None);
+	/// assert_eq!(db.country_str("46.4.0.1".parse().unwrap()), Some("DE"));
+	/// # }
+	/// ```
+    pub fn without(&self, countries: &[&str]) -> GeoIpDb {
+        self.filtered(|info| {
+            !countries
+                .iter()
+                .any(|country| info.country_code.eq_ignore_ascii_case(country.as_bytes()))
+        })
+    }
+
+    /// Drop the ranges of countries outside `regions`, in place, and return
+	/// the number of ranges removed.
+	///
+	/// If the tables are shared with a clone, the clone keeps all of them.
+	///
+	/// # Examples
+	/// ```
+	/// # #[cfg(feature = "embedded-data")] {
+	/// use ip_alloc_lookup::{GeoIpDb, Region};
+	///
+	/// let mut db = GeoIpDb::new();
+	/// db.retain_regions(&[Region::EuropeanUnion, Region::EuropeNonEu]);
+	/// assert_eq!(db.country_str("46.4.0.1".parse().unwrap()), Some("DE"));
+	/// assert!(db.stats().regions.iter().all(|r| {
+	///     matches!(r.region, Region::EuropeanUnion | Region::EuropeNonEu)
+	/// }));
+	/// # }
+	/// ```
+    pub fn retain_regions(&mut self, regions: &[Region]) -> usize {
+        let keep = |info: &GeoInfo| regions.contains(&info.region_enum());
+        let before = self.len();
+        if self.v4_ranges.iter().any(|r| !keep(&r.2)) {
+            let v4_ranges = Arc::make_mut(&mut self.v4_ranges);
+            v4_ranges.retain(|r| keep(&r.2));
+            v4_ranges.shrink_to_fit();
+        }
+        if self.v6_ranges.iter().any(|r| !keep(&r.2)) {
+            let v6_ranges = Arc::make_mut(&mut self.v6_ranges);
+            v6_ranges.retain(|r| keep(&r.2));
+            v6_ranges.shrink_to_fit();
+        }
+        before - self.len()
+    }
+
+    /// Copy the ranges whose classification satisfies `keep`.
+    fn filtered(&self, keep: impl Fn(&GeoInfo) -> bool) -> GeoIpDb {
+        let mut v4_ranges: Vec<_> = self.v4_ranges.iter().filter(|r| keep(&r.2)).copied().collect();
+        let mut v6_ranges: Vec<_> = self.v6_ranges.iter().filter(|r| keep(&r.2)).copied().collect();
+        v4_ranges.shrink_to_fit();
+        v6_ranges.shrink_to_fit();
+        GeoIpDb {
+            v4_ranges: Arc::new(v4_ranges),
+            v6_ranges: Arc::new(v6_ranges),
+            metadata: self.metadata.clone(),
+            unallocated: Arc::clone(&self.unallocated),
+        }
+    }
+
+    /// Return the total number of IPv4 and IPv6 addresses allocated to `country`.
+	///
+	/// `country` is an ISO-3166 alpha-2 code, matched case-insensitively. The IPv6
+	/// total saturates at `u128::MAX`.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let data = "\
+	/// ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated
+	/// ripencc|FR|ipv4|5.39.0.0|65536|20120120|allocated
+	/// ripencc|FR|ipv6|2a01:cb00::|32|20110118|allocated
+	/// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+	/// ";
+	/// let db = GeoIpDb::from_ripe_delegated_str(data);
+	/// assert_eq!(db.address_count("FR"), (1_048_576 + 65_536, 1 << 96));
+	/// ```
+    pub fn address_count(&self, country: &str) -> (u64, u128) {
+        let matches = |info: &GeoInfo| info.country_code.eq_ignore_ascii_case(country.as_bytes());
+
+        let v4 = self
+            .v4_ranges
+            .iter()
+            .filter(|(_, _, info)| matches(info))
+            .map(|&(start, end, _)| u64::from(end - start) + 1)
+            .sum();
+        let v6 = self
+            .v6_ranges
+            .iter()
+            .filter(|(_, _, info)| matches(info))
+            .fold(0u128, |total, &(start, end, _)| {
+                total.saturating_add((end - start).saturating_add(1))
+            });
+
+        (v4, v6)
+    }
+
+    /// Return range and address counts for every country in the database,
+	/// ordered by country code.
+	///
+	/// This is [`GeoIpDb::address_count`] for all countries at once, for reports
+	/// such as the largest countries by address space.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::GeoIpDb;
+	///
+	/// let data = "\
+	/// ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated
+	/// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+	/// ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated
+	/// ";
+	/// let db = GeoIpDb::from_ripe_delegated_str(data);
+	/// let stats = db.country_stats();
+	/// assert_eq!(stats[0].country_code_str(), "DE");
+	/// assert_eq!((stats[0].v4_addresses, stats[0].v6_ranges), (256, 1));
+	/// assert_eq!(stats[1].v4_addresses, 1_048_576);
+	/// ```
+    pub fn country_stats(&self) -> Vec<CountryStats> {
+        let new = |info: GeoInfo| CountryStats {
+            country_code: info.country_code,
+            region: info.region_enum(),
+            is_eu: info.is_eu,
+            v4_ranges: 0,
+            v6_ranges: 0,
+            v4_addresses: 0,
+            v6_addresses: 0,
+        };
+        let mut countries: BTreeMap<[u8; 2], CountryStats> = BTreeMap::new();
+        for &(start, end, info) in self.v4_ranges.iter() {
+            let stats = countries.entry(info.country_code).or_insert_with(|| new(info));
+            stats.v4_ranges += 1;
+            stats.v4_addresses += u64::from(end - start) + 1;
+        }
+        for &(start, end, info) in self.v6_ranges.iter() {
+            let stats = countries.entry(info.country_code).or_insert_with(|| new(info));
+            stats.v6_ranges += 1;
+            stats.v6_addresses = stats.v6_addresses.saturating_add((end - start).saturating_add(1));
+        }
+        countries.into_values().collect()
+    }
+
+    /// Return the classification of `net` if every address in it maps to the same
+	/// country, otherwise [`None`].
+	///
+	/// The prefix may span several adjacent allocations as long as they all belong
+	/// to one country. Any unallocated gap, or a second country, yields [`None`].
+	/// This tells whether a decision made for one address can safely be cached for
+	/// the whole prefix.
+	///
+	/// # Examples
+	/// ```
+	/// use ip_alloc_lookup::{GeoIpDb, IpNet};
+	///
+	/// let data = "\
+	/// ripencc|DE|ipv4|46.4.0.0|2048|20250101|allocated
+	/// ripencc|DE|ipv4|46.4.8.0|2048|20250101|allocated
+	/// ripencc|FR|ipv4|46.4.16.0|4096|20250101|allocated
+	/// ";
+	/// let db = GeoIpDb::from_ripe_delegated_str(data);
+	///
+	/// let info = db.is_uniform("46.4.0.0/20".parse().unwrap()).unwrap();
+	/// assert_eq!(info.country_code_str(), "DE");
+	/// assert!(db.is_uniform("46.4.0.0/19".parse().unwrap()).is_none());
+	/// ```
+    pub fn is_uniform(&self, net: IpNet) -> Option<GeoInfo> {
+        match (net.network(), net.last()) {
+            (IpAddr::V4(first), IpAddr::V4(last)) => {
+                uniform_over(&self.v4_ranges, first.into(), last.into(), |x: u32| x.checked_add(1))
+            }
+            (IpAddr::V6(first), IpAddr::V6(last)) => {
+                uniform_over(&self.v6_ranges, first.into(), last.into(), |x: u128| x.checked_add(1))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Binary-search the sorted `ranges` for the block containing `ip`.
+fn find_range<T: Copy + Ord>(ranges: &[(T, T, GeoInfo)], ip: T) -> Option<(T, T, GeoInfo)> {
+    let idx = ranges.partition_point(|&(start, _, _)| start <= ip).checked_sub(1)?;
+    let range = ranges[idx];
+    (ip <= range.1).then_some(range)
+}
+
+/// Find the block containing `ip`, checking the block at `last` and its
+/// neighbours before binary-searching. `last` is updated to the block found.
+fn find_near<'a, T: Copy + Ord>(
+    ranges: &'a [(T, T, GeoInfo)],
+    last: &mut usize,
+    ip: T,
+) -> Option<&'a GeoInfo> {
+    let near = last.saturating_sub(1)..(*last + 2).min(ranges.len());
+    let idx = match ranges[near.clone()].iter().position(|r| r.0 <= ip && ip <= r.1) {
+        Some(pos) => near.start + pos,
+        None => ranges.partition_point(|&(start, _, _)| start <= ip).checked_sub(1)?,
+    };
+    *last = idx;
+    let (_, end, info) = &ranges[idx];
+    (ip <= *end).then_some(info)
+}
+
+/// Find the block containing `ip` by galloping forward from `hint`, the
+/// partition point of the previous search, which is updated in place.
+fn find_from<'a, T: Copy + Ord, P>(
+    ranges: &'a [(T, T, P)],
+    hint: &mut usize,
+    ip: T,
+) -> Option<&'a P> {
+    let mut lo = (*hint).min(ranges.len());
+    let idx = if lo > 0 && ranges[lo - 1].0 > ip {
+        ranges[..lo].partition_point(|&(start, _, _)| start <= ip)
+    } else {
+        let mut step = 1;
+        while lo + step <= ranges.len() && ranges[lo + step - 1].0 <= ip {
+            lo += step;
+            step *= 2;
+        }
+        let hi = (lo + step).min(ranges.len());
+        lo + ranges[lo..hi].partition_point(|&(start, _, _)| start <= ip)
+    };
+    *hint = idx;
+
+    let (_, end, info) = ranges.get(idx.checked_sub(1)?)?;
+    (ip <= *end).then_some(info)
+}
+
+/// Prefix length of the `bits`-wide block `[start, end]` if it is a single
+/// aligned CIDR prefix.
+fn exact_prefix_len(start: u128, end: u128, bits: u32) -> Option<u8> {
+    let span = end - start;
+    if span == u128::MAX >> (128 - bits) {
+        return (start == 0).then_some(0);
+    }
+    let size = span + 1;
+    (size.is_power_of_two() && start.is_multiple_of(size)).then(|| (bits - size.trailing_zeros()) as u8)
+}
+
+/// Merge runs of directly adjacent blocks with equal [`GeoInfo`] in the sorted
+/// `ranges`. `next` returns the address after its argument.
+fn coalesce<T: Copy + Eq, C: PartialEq>(ranges: &mut Vec<(T, T, C)>, next: impl Fn(T) -> Option<T>) {
+    ranges.dedup_by(|cur, prev| {
+        let adjacent = next(prev.1) == Some(cur.0) && prev.2 == cur.2;
+        if adjacent {
+            prev.1 = cur.1;
+        }
+        adjacent
+    });
+    ranges.shrink_to_fit();
+}
+
+/// Walk the sorted `ranges` from `first` to `last`, requiring contiguous blocks
+/// of a single country. `next` returns the address after its argument.
+fn uniform_over<T: Copy + Ord>(
+    ranges: &[(T, T, GeoInfo)],
+    first: T,
+    last: T,
+    next: impl Fn(T) -> Option<T>,
+) -> Option<GeoInfo> {
+    let idx = ranges.partition_point(|&(start, _, _)| start <= first).checked_sub(1)?;
+    let (_, mut covered_to, info) = ranges[idx];
+    if covered_to < first {
+        return None;
+    }
+
+    for &(start, end, other) in &ranges[idx + 1..] {
+        if covered_to >= last {
+            break;
+        }
+        if Some(start) != next(covered_to) || other.country_code != info.country_code {
+            return None;
+        }
+        covered_to = end;
+    }
+
+    (covered_to >= last).then_some(info)
+}
+
+impl Default for GeoIpDb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// Summary counts for the database contents.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DbStats {
     pub total_v4_ranges: usize,
     pub total_v6_ranges: usize,
@@ -440,26 +1685,243 @@ pub struct DbStats {
     pub eu_v6_ranges: usize,
     pub non_eu_v4_ranges: usize,
     pub non_eu_v6_ranges: usize,
+    /// Counts per region, ordered by region code; regions without ranges are
+    /// left out.
+    pub regions: Vec<RegionStats>,
+}
+
+impl DbStats {
+    /// The counts of `region`, [`None`] if the database has no ranges in it.
+    pub fn region(&self, region: Region) -> Option<&RegionStats> {
+        self.regions.iter().find(|stats| stats.region == region)
+    }
+
+    /// Share of the IPv4 addresses in the database that are in `region`, in
+    /// percent.
+    pub fn v4_share(&self, region: Region) -> f64 {
+        let total: u64 = self.regions.iter().map(|stats| stats.v4_addresses).sum();
+        let part = self.region(region).map_or(0, |stats| stats.v4_addresses);
+        percent(part as f64, total as f64)
+    }
+
+    /// Share of the IPv6 addresses in the database that are in `region`, in
+    /// percent.
+    pub fn v6_share(&self, region: Region) -> f64 {
+        let total = self
+            .regions
+            .iter()
+            .fold(0u128, |total, stats| total.saturating_add(stats.v6_addresses));
+        let part = self.region(region).map_or(0, |stats| stats.v6_addresses);
+        percent(part as f64, total as f64)
+    }
+
+    /// Share of the IPv4 addresses in the database that are classified as
+    /// EU, in percent.
+    ///
+    /// Unlike the ratio of [`DbStats::eu_v4_ranges`] to
+    /// [`DbStats::total_v4_ranges`], this weighs every range by its size, so
+    /// a single /10 counts for as much as 16,384 /24s.
+    pub fn eu_address_share_v4(&self) -> f64 {
+        self.v4_share(Region::EuropeanUnion)
+    }
+
+    /// Share of the IPv6 addresses in the database that are classified as
+    /// EU, in percent. See [`DbStats::eu_address_share_v4`].
+    pub fn eu_address_share_v6(&self) -> f64 {
+        self.v6_share(Region::EuropeanUnion)
+    }
+}
+
+fn percent(part: f64, total: f64) -> f64 {
+    if total == 0.0 { 0.0 } else { part * 100.0 / total }
+}
+
+/// Ranges and addresses of one region, see [`DbStats::regions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionStats {
+    pub region: Region,
+    pub v4_ranges: usize,
+    pub v6_ranges: usize,
+    pub v4_addresses: u64,
+    /// Saturates at `u128::MAX`.
+    pub v6_addresses: u128,
+}
+
+/// Ranges and addresses of one country, see [`GeoIpDb::country_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountryStats {
+    pub country_code: [u8; 2],
+    pub region: Region,
+    pub is_eu: bool,
+    pub v4_ranges: usize,
+    pub v6_ranges: usize,
+    pub v4_addresses: u64,
+    /// Saturates at `u128::MAX`.
+    pub v6_addresses: u128,
+}
+
+impl CountryStats {
+    /// Return the country code as `&str`, e.g. `"DE"`.
+    pub fn country_code_str(&self) -> &str {
+        std::str::from_utf8(&self.country_code).unwrap_or("??")
+    }
+}
+
+/// The allocation block matched by [`GeoIpDb::lookup_range`].
+#[derive(Debug, Clone, Copy)]
+pub struct RangeMatch {
+    /// First address of the block.
+    pub start: IpAddr,
+    /// Last address of the block (inclusive).
+    pub end: IpAddr,
+    /// Prefix length if the block is exactly one CIDR prefix, e.g. `Some(20)` for
+    /// `46.4.0.0 - 46.4.15.255`. IPv4 allocations need not be aligned powers of
+    /// two, in which case this is `None`.
+    pub prefix_len: Option<u8>,
+    pub info: GeoInfo,
+}
+
+impl RangeMatch {
+    /// Split the block into the smallest list of CIDR prefixes covering it.
+    pub fn to_cidrs(&self) -> Vec<IpNet> {
+        cidrs_covering(self.start, self.end)
+    }
+
+    /// The block as comma-separated CIDR prefixes, e.g. `"46.4.0.0/24"` or
+	/// `"46.4.0.0/23,46.4.2.0/24"` for a block that is not a single prefix.
+    pub fn cidr_string(&self) -> String {
+        let cidrs: Vec<String> = self.to_cidrs().iter().map(IpNet::to_string).collect();
+        cidrs.join(",")
+    }
+}
+
+/// A lookup handle that remembers the block it matched last, created by
+/// [`GeoIpDb::cursor`].
+///
+/// Each lookup first checks the previously matched block and its neighbours
+/// before falling back to a binary search. Packets of one flow, or requests
+/// from one client, hit the same block many times in a row, which this turns
+/// into a couple of comparisons without a cache in front of the database.
+///
+/// A cursor borrows the database, so keep one per connection or per worker.
+#[derive(Clone)]
+pub struct LookupCursor<'a> {
+    db: &'a GeoIpDb,
+    last_v4: usize,
+    last_v6: usize,
+}
+
+impl<'a> LookupCursor<'a> {
+    /// Look up an IP address (IPv4 or IPv6), starting at the last match.
+    pub fn lookup(&mut self, ip: IpAddr) -> Option<&'a GeoInfo> {
+        match ip {
+            IpAddr::V4(v4) => find_near(&self.db.v4_ranges, &mut self.last_v4, v4.into()),
+            IpAddr::V6(v6) => find_near(&self.db.v6_ranges, &mut self.last_v6, v6.into()),
+        }
+    }
+
+    /// Return `true` if the IP is covered by the database and classified as EU.
+    pub fn is_eu(&mut self, ip: IpAddr) -> bool {
+        self.lookup(ip).is_some_and(|info| info.is_eu)
+    }
+}
+
+/// Return `true` if `country` (an ISO-3166 alpha-2 code, any case) is an EU member state.
+///
+/// This is the same classification IP lookups use for [`GeoInfo::is_eu`], so the
+/// registry code `EU` counts as well.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::is_eu_country;
+///
+/// assert!(is_eu_country("DE"));
+/// assert!(is_eu_country("de"));
+/// assert!(!is_eu_country("CH"));
+/// ```
+pub fn is_eu_country(country: &str) -> bool {
+    region_of_country(country) == Region::EuropeanUnion
+}
+
+/// Return the [`Region`] IP lookups assign to `country` (an ISO-3166 alpha-2 code,
+/// any case).
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::{region_of_country, Region};
+///
+/// assert_eq!(region_of_country("TR"), Region::Turkey);
+/// assert_eq!(region_of_country("us"), Region::Americas);
+/// assert_eq!(region_of_country("AQ"), Region::Other);
+/// ```
+pub fn region_of_country(country: &str) -> Region {
+    region_of(country).unwrap_or(Region::Other)
+}
+
+/// Return the [`Region`] the country policy lists `country` (an ISO-3166
+/// alpha-2 code, any case) in, or [`None`] if it is not listed.
+///
+/// Unlike [`region_of_country`], this tells a country the policy puts in
+/// [`Region::Other`] apart from one it does not know, e.g. to validate
+/// country lists in configuration.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::{region_of, Region};
+///
+/// assert_eq!(region_of("ma"), Some(Region::NorthAfrica));
+/// assert_eq!(region_of("XX"), None);
+/// ```
+pub fn region_of(country: &str) -> Option<Region> {
+    let &[a, b] = country.as_bytes() else {
+        return None;
+    };
+    let code = [a.to_ascii_uppercase(), b.to_ascii_uppercase()];
+    listed_region(std::str::from_utf8(&code).ok()?)
+}
+
+/// Return the countries the country policy lists in `region`, ordered by
+/// country code.
+///
+/// The registry code `EU`, which is not a country, is not included.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::{region_members, Region, EU_COUNTRIES};
+///
+/// assert!(region_members(Region::Turkey).eq(["TR"]));
+/// assert_eq!(region_members(Region::EuropeanUnion).count(), EU_COUNTRIES.len());
+/// ```
+pub fn region_members(region: Region) -> impl Iterator<Item = &'static str> {
+    country_regions().filter_map(move |(cc, r)| (r == region).then_some(cc))
+}
+
+/// Return the whole country policy: every listed country with its
+/// [`Region`], ordered by country code.
+///
+/// This is the table behind [`region_of`], [`region_of_country`] and
+/// [`GeoInfo::region`] of lookups.
+pub fn country_regions() -> impl Iterator<Item = (&'static str, Region)> {
+    COUNTRY_REGIONS.iter().map(|&(cc, code)| (cc, Region::from_code(code)))
 }
 
 /// Map a country code to a coarse [`Region`] bucket.
 ///
-/// This mapping is a policy-oriented heuristic and may be adjusted over time.
+/// The mapping comes from `country-policy.txt` at build time; countries it does
+/// not list are [`Region::Other`].
 fn determine_region(country_code: &str) -> Region {
-    if EU_COUNTRIES.contains(&country_code) {
-        Region::EuropeanUnion
-    } else {
-        match country_code {
-            "GB" | "NO" | "CH" | "IS" | "LI" => Region::EuropeNonEu,
-            "RU" | "UA" | "BY" | "MD" => Region::EasternEurope,
-            "TR" => Region::Turkey,
-            "IL" | "PS" => Region::MiddleEast,
-            "EG" | "TN" | "MA" | "DZ" => Region::NorthAfrica,
-            "KZ" | "UZ" | "TM" | "KG" | "TJ" => Region::CentralAsia,
-            "AE" | "SA" | "QA" | "KW" | "BH" | "OM" => Region::GulfStates,
-            _ => Region::Other,
-        }
+    listed_region(country_code).unwrap_or(Region::Other)
+}
+
+/// Region of an upper-case country code in the policy table.
+fn listed_region(country_code: &str) -> Option<Region> {
+    if country_code == UNSPECIFIED_EU_CODE {
+        return Some(Region::EuropeanUnion);
     }
+    COUNTRY_REGIONS
+        .binary_search_by(|&(cc, _)| cc.cmp(country_code))
+        .ok()
+        .map(|idx| Region::from_code(COUNTRY_REGIONS[idx].1))
 }
 
 #[cfg(test)]
@@ -467,6 +1929,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[cfg(feature = "embedded-data")]
     fn test_embedded_db() {
         let db = GeoIpDb::new();
 
@@ -478,9 +1941,19 @@ mod tests {
             stats.total_v6_ranges, stats.eu_v6_ranges, stats.non_eu_v6_ranges);
 
         assert!(stats.total_v4_ranges > 0, "Should have IPv4 ranges");
+
+        // Every range is in exactly one region
+        let region_v4: usize = stats.regions.iter().map(|r| r.v4_ranges).sum();
+        assert_eq!(region_v4, stats.total_v4_ranges);
+        let eu = stats.region(Region::EuropeanUnion).unwrap();
+        assert_eq!((eu.v4_ranges, eu.v6_ranges), (stats.eu_v4_ranges, stats.eu_v6_ranges));
+        let shares: f64 = stats.regions.iter().map(|r| stats.v4_share(r.region)).sum();
+        assert!((shares - 100.0).abs() < 1e-9);
+        assert!(stats.eu_address_share_v4() > 0.0 && stats.eu_address_share_v4() < 100.0);
     }
 
     #[test]
+    #[cfg(feature = "embedded-data")]
     fn test_lookup_german_ipv4() {
         let db = GeoIpDb::new();
         let ip: Ipv4Addr = "46.4.0.1".parse().unwrap();
@@ -527,106 +2000,539 @@ mod tests {
             assert!(db.is_eu(ipv4));
         }
     }
-	
-	#[cfg(feature = "download")]
-	fn serve_once(body: &'static str) -> String {
-		use std::io::{Read, Write};
-		use std::net::TcpListener;
-
-		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
-		let addr = listener.local_addr().unwrap();
-
-		std::thread::spawn(move || {
-			let (mut stream, _) = listener.accept().unwrap();
-
-			// read request (ignore contents)
-			let mut buf = [0u8; 1024];
-			let _ = stream.read(&mut buf);
-
-			let resp = format!(
-				"HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
-				body.as_bytes().len(),
-				body
-			);
-			let _ = stream.write_all(resp.as_bytes());
-			let _ = stream.flush();
-		});
-
-		format!("http://{}", addr)
-	}
-	
-	#[test]
-	#[cfg(feature = "download")]
-	fn test_update_cache_and_load() {
-		use std::net::IpAddr;
-
-		// Minimal delegated content:
-		// - one IPv4 block: 46.4.0.0/24 (256 addrs)
-		// - one IPv6 block: 2a01:4f8::/32
-		let delegated = "\
-	# comment
-	2|ripencc|20250101|0000|summary|whatever
-	ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
-	ripencc|DE|ipv6|2a01:4f8::|32|20250101|allocated
-	";
-
-		let url = serve_once(delegated);
-
-		let dir = tempfile::tempdir().unwrap();
-		let cache_path = dir.path().join("ripe-cache.txt");
-
-		let bytes = GeoIpDb::update_cache_from_url(&cache_path, &url).unwrap();
-		assert!(bytes > 0);
-		assert!(cache_path.exists());
-
-		let db = GeoIpDb::from_ripe_delegated_file(&cache_path).unwrap();
-
-		let ip: IpAddr = "46.4.0.1".parse().unwrap();
-		let info = db.lookup(ip).expect("should find 46.4.0.1");
-		assert_eq!(info.country_code_str(), "DE");
-		assert!(info.is_eu);
-	}
-	
-	#[test]
-	#[cfg(feature = "download")]
-	fn test_update_cache_replaces_existing_file() {
-		let old = "\
-	ripencc|FR|ipv4|46.4.0.0|256|20250101|allocated
-	";
-		let new = "\
-	ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
-	";
 
-		let url = serve_once(new);
+    #[test]
+    fn test_eu_vat_territory() {
+        for (cc, expected) in [("FR", true), ("MC", true), ("AX", false), ("RE", false), ("CH", false)] {
+            assert_eq!(GeoInfo::from_country(cc).is_eu_vat_territory(), expected, "{cc}");
+        }
+        assert!(!GeoInfo::from_country("MC").is_eu);
+    }
 
-		let dir = tempfile::tempdir().unwrap();
-		let cache_path = dir.path().join("ripe-cache.txt");
+    #[test]
+    fn test_country_policy_tables() {
+        assert_eq!(
+            EU_COUNTRIES.len(),
+            region_members(Region::EuropeanUnion).count()
+        );
+        assert!(COUNTRY_REGIONS.windows(2).all(|w| w[0].0 < w[1].0));
+        for cc in EU_COUNTRIES {
+            assert_eq!(determine_region(cc), Region::EuropeanUnion, "{cc}");
+        }
+        assert_eq!(determine_region("TR"), Region::Turkey);
+        assert_eq!(determine_region("NO"), Region::EuropeNonEu);
+        assert_eq!(determine_region("ZZ"), Region::Other);
+        assert_eq!(region_of_country("gb"), Region::EuropeNonEu);
+        assert_eq!(region_of_country("DEU"), Region::Other);
+        assert_eq!(region_of("eu"), Some(Region::EuropeanUnion));
+        assert_eq!(region_of("ZZ"), None);
+
+        // The lookup and iteration APIs agree with each other
+        let mut members = 0;
+        for region in Region::ALL {
+            for cc in region_members(region) {
+                assert_eq!(region_of(cc), Some(region), "{cc}");
+                members += 1;
+            }
+        }
+        assert_eq!(members, COUNTRY_REGIONS.len());
+        let mut eu = EU_COUNTRIES.to_vec();
+        eu.sort_unstable();
+        assert!(region_members(Region::EuropeanUnion).eq(eu));
+        assert!(is_eu_country("fr") && !is_eu_country("FRA"));
+    }
 
-		std::fs::write(&cache_path, old).unwrap();
+    #[test]
+    fn test_is_country_and_country_str() {
+        let db = GeoIpDb::from_ranges(vec![IpRange {
+            start: RangeStart::V4("10.0.0.0".parse().unwrap()),
+            count: 256,
+            country: "DE".to_string(),
+            assigned: false,
+            date: String::new(),
+        }]);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert!(db.is_country(ip, "DE"));
+        assert!(db.is_country(ip, "de"));
+        assert!(!db.is_country(ip, "FR"));
+        assert!(!db.is_country(ip, "DEU"));
+        assert!(!db.is_country("10.0.1.0".parse().unwrap(), "DE"));
+        assert!(!db.is_country("10.0.1.0".parse().unwrap(), "??"));
+
+        assert_eq!(db.country(ip), Some(*b"DE"));
+        assert_eq!(db.country_str(ip), Some("DE"));
+        assert_eq!(db.country_str("10.0.1.0".parse().unwrap()), None);
+    }
 
-		GeoIpDb::update_cache_from_url(&cache_path, &url).unwrap();
+    #[test]
+    fn test_lookup_range() {
+        let db = GeoIpDb::from_ranges(vec![
+            IpRange {
+                start: RangeStart::V4("10.0.0.0".parse().unwrap()),
+                count: 4096,
+                country: "DE".to_string(),
+                assigned: false,
+                date: String::new(),
+            },
+            IpRange {
+                start: RangeStart::V4("10.1.0.0".parse().unwrap()),
+                count: 768,
+                country: "FR".to_string(),
+                assigned: false,
+                date: String::new(),
+            },
+            IpRange {
+                start: RangeStart::V6("2a01:4f8::".parse().unwrap()),
+                count: 1 << 96,
+                country: "DE".to_string(),
+                assigned: false,
+                date: String::new(),
+            },
+        ]);
+
+        let m = db.lookup_range("10.0.15.255".parse().unwrap()).unwrap();
+        assert_eq!(m.start, "10.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(m.end, "10.0.15.255".parse::<IpAddr>().unwrap());
+        assert_eq!(m.prefix_len, Some(20));
+        assert_eq!(m.cidr_string(), "10.0.0.0/20");
+
+        // 768 addresses is not a single prefix
+        let m = db.lookup_range("10.1.2.0".parse().unwrap()).unwrap();
+        assert_eq!(m.info.country_code_str(), "FR");
+        assert_eq!(m.prefix_len, None);
+        assert_eq!(m.cidr_string(), "10.1.0.0/23,10.1.2.0/24");
+
+        let m = db.lookup_range("2a01:4f8::1".parse().unwrap()).unwrap();
+        assert_eq!(m.prefix_len, Some(32));
+        assert_eq!(
+            db.lookup_with_cidr("2a01:4f8::1".parse().unwrap()).unwrap().1,
+            "2a01:4f8::/32"
+        );
+        assert!(db.lookup_range("10.0.16.0".parse().unwrap()).is_none());
+
+        assert_eq!(exact_prefix_len(0, u32::MAX.into(), 32), Some(0));
+        assert_eq!(exact_prefix_len(0, u128::MAX, 128), Some(0));
+        assert_eq!(exact_prefix_len(256, 767, 32), None);
+    }
 
-		let db = GeoIpDb::from_ripe_delegated_file(&cache_path).unwrap();
-		let info = db.lookup("46.4.0.1".parse().unwrap()).unwrap();
-		assert_eq!(info.country_code_str(), "DE");
-	}
-	
-	#[test]
-	#[ignore]
-	#[cfg(feature = "download")]
-	fn smoke_test_real_ripe_download_and_lookup() {
-		let cache = std::path::PathBuf::from("/tmp/ripe-cache.txt");
-
-		// Download real RIPE data
-		let bytes = GeoIpDb::update_cache(&cache).unwrap();
-		assert!(bytes > 1_000_000, "too small, download probably failed");
-
-		// Load from cache
-		let db = GeoIpDb::from_ripe_delegated_file(&cache).unwrap();
-
-		// Known Hetzner range is commonly DE
-		let ip: std::net::IpAddr = "88.198.0.1".parse().unwrap();
-		let info = db.lookup(ip).unwrap();
-		println!("88.198.0.1 -> {}", info.country_code_str());
-	}
-}
\ No newline at end of file
+    #[test]
+    fn test_sorted_and_cursor_lookups_match_lookup() {
+        // every other /24 of 10.0.0.0/16 is allocated, alternating countries
+        let ranges = (0..128u32)
+            .map(|i| IpRange {
+                start: RangeStart::V4(Ipv4Addr::from(0x0A00_0000 + (i << 9))),
+                count: 256,
+                country: if i % 2 == 0 { "DE" } else { "US" }.to_string(),
+                assigned: false,
+                date: String::new(),
+            })
+            .chain((0..16u128).map(|i| IpRange {
+                start: RangeStart::V6(Ipv6Addr::from((0x2a01_04f8u128 << 96) + (i << 80))),
+                count: 1 << 79,
+                country: "FR".to_string(),
+                assigned: false,
+                date: String::new(),
+            }))
+            .collect();
+        let db = GeoIpDb::from_ranges(ranges);
+
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut ips: Vec<IpAddr> = (0..2000)
+            .map(|i| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                if i % 4 == 0 {
+                    IpAddr::V6(Ipv6Addr::from((0x2a01_04f8u128 << 96) + (u128::from(state) << 20)))
+                } else {
+                    IpAddr::V4(Ipv4Addr::from(0x09FF_0000 + (state >> 40) as u32 % 0x0003_0000))
+                }
+            })
+            .collect();
+
+        // arbitrary order still gives the right answers
+        assert_eq!(db.lookup_many_sorted(&ips), db.lookup_many(&ips));
+        ips.sort();
+        let sorted = db.lookup_many_sorted(&ips);
+        assert_eq!(sorted, db.lookup_many(&ips));
+        assert!(sorted.iter().any(Option::is_none));
+        assert!(sorted.iter().flatten().any(|info| info.country_code_str() == "FR"));
+        ips.reverse();
+        assert_eq!(db.lookup_many_sorted(&ips), db.lookup_many(&ips));
+
+        let mut cursor = db.cursor();
+        for &ip in ips.iter().chain(&ips[..10]).chain(ips.iter().rev()) {
+            assert_eq!(cursor.lookup(ip), db.lookup(ip), "{ip}");
+            assert_eq!(cursor.lookup(ip), db.lookup(ip), "{ip}");
+        }
+
+        assert!(GeoIpDb::from_ranges(Vec::new()).lookup_many_sorted(&ips).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_unspecified_eu_code() {
+        let info = GeoInfo::from_country("EU");
+        assert!(info.is_eu);
+        assert!(info.country_unknown());
+        assert_eq!(info.region_enum(), Region::EuropeanUnion);
+        assert_eq!(info.data_transfer_class(), DataTransferClass::Eea);
+        assert!(!info.is_eurozone());
+        assert!(!GeoInfo::from_country("DE").country_unknown());
+        assert!(is_eu_country("eu"));
+        assert!(!EU_COUNTRIES.contains(&"EU"));
+
+        // 138.199.64.0/20 is registered as "EU" in the embedded snapshot
+        #[cfg(feature = "embedded-data")]
+        {
+            let db = GeoIpDb::new();
+            let info = db.lookup("138.199.64.1".parse().unwrap()).unwrap();
+            assert!(info.is_eu && info.country_unknown());
+        }
+    }
+
+    #[test]
+    fn test_trait_impls() {
+        use std::collections::HashMap;
+
+        let de = GeoInfo::from_country("DE");
+        assert_eq!(de.to_string(), "DE (European Union)");
+        assert_eq!(de, GeoInfo::from_country("DE"));
+        assert_ne!(de, GeoInfo::from_country("FR"));
+
+        let mut counts = HashMap::new();
+        *counts.entry(de).or_insert(0) += 1;
+        *counts.entry(GeoInfo::from_country("DE")).or_insert(0) += 1;
+        assert_eq!(counts[&de], 2);
+
+        for region in Region::ALL {
+            assert_eq!(Region::try_from(region as u8), Ok(region));
+            assert_eq!(region.to_string().parse::<Region>(), Ok(region));
+            assert_eq!(format!("{region:?}").parse::<Region>(), Ok(region));
+        }
+        assert!(Region::try_from(0).is_err());
+        assert!(Region::try_from(15).is_err());
+        assert!("Atlantis".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_classify_into() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Eu(bool);
+
+        impl Classify for Eu {
+            fn classify(info: &GeoInfo) -> Self {
+                Eu(info.is_eu)
+            }
+        }
+
+        let db = GeoIpDb::from_ripe_delegated_str("\
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|FR|ipv4|46.4.1.0|256|20250101|allocated
+ripencc|CH|ipv4|46.4.2.0|256|20250101|allocated
+ripencc|DE|ipv6|2a01:4f8::|32|20250101|allocated
+");
+        let eu: GeoIpDb<Eu> = db.classify_into();
+        // DE and FR are merged into one range
+        assert_eq!(eu.v4_ranges.len(), 2);
+        assert_eq!(eu.lookup("46.4.1.1".parse().unwrap()), Some(&Eu(true)));
+        assert_eq!(eu.lookup("46.4.2.1".parse().unwrap()), Some(&Eu(false)));
+        let peer: SocketAddr = "[::ffff:46.4.0.1]:1".parse().unwrap();
+        assert_eq!(eu.lookup_socket(peer), Some(&Eu(true)));
+        assert_eq!(eu.lookup("2a01:4f8::1".parse().unwrap()), Some(&Eu(true)));
+        assert_eq!(eu.lookup("10.0.0.1".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_subset() {
+        let db = GeoIpDb::from_ripe_delegated_str("\
+2|ripencc|1767049199|4|19700101|20251229|+0100
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|FR|ipv4|46.4.1.0|256|20250101|allocated
+ripencc|CH|ipv4|46.4.2.0|256|20250101|allocated
+ripencc|DE|ipv6|2a01:4f8::|32|20250101|allocated
+");
+        let subset = db.subset(&["de", "CH"]);
+        assert_eq!((subset.v4_ranges.len(), subset.v6_ranges.len()), (2, 1));
+        assert_eq!(subset.country_str("46.4.2.1".parse().unwrap()), Some("CH"));
+        assert_eq!(subset.lookup("46.4.1.1".parse().unwrap()), None);
+        assert_eq!(subset.source_metadata(), db.source_metadata());
+        assert!(db.subset(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_without_and_retain_regions() {
+        let mut db = GeoIpDb::from_ripe_delegated_str("\
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|RU|ipv4|46.4.1.0|256|20250101|allocated
+ripencc|CH|ipv4|46.4.2.0|256|20250101|allocated
+ripencc|DE|ipv6|2a01:4f8::|32|20250101|allocated
+ripencc|RU|ipv6|2a02:6b8::|32|20250101|allocated
+");
+        let without = db.without(&["ru"]);
+        assert_eq!((without.v4_ranges.len(), without.v6_ranges.len()), (2, 1));
+        assert_eq!(without.lookup("46.4.1.1".parse().unwrap()), None);
+        assert_eq!(db.without(&[]).len(), db.len());
+
+        let copy = db.clone();
+        assert_eq!(db.retain_regions(&[Region::EuropeanUnion]), 3);
+        assert_eq!(db.country_str("2a01:4f8::1".parse().unwrap()), Some("DE"));
+        assert_eq!(db.lookup("46.4.2.1".parse().unwrap()), None);
+        assert_eq!(copy.len(), 5);
+        assert_eq!(db.retain_regions(&[Region::EuropeanUnion]), 0);
+    }
+
+    #[test]
+    fn test_range_db_build() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let db = RangeDb::build([
+            (ip("10.2.0.0"), ip("10.2.0.255"), 2u16),
+            (ip("10.1.0.0"), ip("10.1.255.255"), 1),
+            (ip("2001:db8::"), ip("2001:db8::ffff"), 3),
+        ])
+        .unwrap();
+        assert_eq!(db.len(), 3);
+        assert_eq!(db.lookup(ip("10.1.7.7")), Some(&1));
+        assert_eq!(db.lookup(ip("10.2.0.255")), Some(&2));
+        assert_eq!(db.lookup(ip("10.2.1.0")), None);
+        assert_eq!(db.lookup_socket(("::ffff:10.2.0.1".parse::<IpAddr>().unwrap(), 80)), Some(&2));
+        assert_eq!(
+            db.lookup_many_sorted(&[ip("10.1.0.1"), ip("10.2.0.1"), ip("2001:db8::1")]),
+            [Some(&1), Some(&2), Some(&3)]
+        );
+
+        let overlap = RangeDb::build([
+            (ip("10.1.0.0"), ip("10.1.255.255"), 1),
+            (ip("10.1.128.0"), ip("10.1.128.255"), 2),
+        ]);
+        assert_eq!(overlap.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        assert!(RangeDb::build([(ip("10.0.0.2"), ip("10.0.0.1"), 1)]).is_err());
+        assert!(RangeDb::build([(ip("10.0.0.1"), ip("::1"), 1)]).is_err());
+        assert!(RangeDb::<u8>::build([]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_memory_bytes() {
+        let db = GeoIpDb::new();
+        let stats = db.stats();
+        let tables = stats.total_v4_ranges * std::mem::size_of::<(u32, u32, GeoInfo)>()
+            + stats.total_v6_ranges * std::mem::size_of::<(u128, u128, GeoInfo)>();
+        assert!(db.memory_bytes() >= tables);
+
+        let empty = GeoIpDb::from_ranges(Vec::new());
+        assert_eq!(empty.memory_bytes(), 0);
+
+        // Unallocated spans and metadata strings count too
+        let mut db = GeoIpDb::from_ripe_delegated_str(
+            "2|ripencc|1767049199|2|19700101|20251229|+0100\n\
+             ripencc||ipv4|46.4.1.0|256||available\n\
+             ripencc||ipv6|2a01:4f9::|32||reserved\n",
+        );
+        db.metadata.as_mut().unwrap().sha256 = Some("0".repeat(64));
+        let header = "2ripencc1970010120251229+0100".len();
+        assert!(db.memory_bytes() >= 8 + 32 + header + 64);
+    }
+
+    #[test]
+    fn test_clone_shares_tables() {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated\n\
+             ripencc|DE|ipv4|46.4.1.0|256|20090101|allocated\n",
+        );
+        let mut copy = db.clone();
+        assert!(Arc::ptr_eq(&db.v4_ranges, &copy.v4_ranges));
+        assert!(Arc::ptr_eq(&db.v6_ranges, &copy.v6_ranges));
+
+        // Modifying a clone leaves the original alone
+        assert_eq!(copy.optimize(), 1);
+        assert!(!Arc::ptr_eq(&db.v4_ranges, &copy.v4_ranges));
+        assert_eq!(db.v4_ranges.len(), 2);
+        assert_eq!(copy.v4_ranges.len(), 1);
+    }
+
+    #[test]
+    fn test_assigned_flag() {
+        let mut db = GeoIpDb::from_ripe_delegated_str("\
+ripencc|DE|ipv4|10.0.0.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.1.0|256|20250101|assigned
+ripencc|DE|ipv6|2a01:4f8::|32|20250101|assigned
+");
+        assert!(!db.lookup("10.0.0.1".parse().unwrap()).unwrap().assigned);
+        assert!(db.lookup("10.0.1.1".parse().unwrap()).unwrap().assigned);
+        assert!(db.lookup("2a01:4f8::1".parse().unwrap()).unwrap().assigned);
+
+        // Allocated and assigned neighbours stay separate blocks
+        assert_eq!(db.optimize(), 0);
+    }
+
+    #[test]
+    fn test_optimize_preserves_lookups() {
+        let data = "\
+ripencc|DE|ipv4|10.0.0.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.1.0|256|20250101|allocated
+ripencc|FR|ipv4|10.0.2.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.3.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.5.0|256|20250101|allocated
+ripencc|DE|ipv6|2a01:4f8::|33|20250101|allocated
+ripencc|DE|ipv6|2a01:4f8:8000::|33|20250101|allocated
+";
+        let original = GeoIpDb::from_ripe_delegated_str(data);
+        let mut db = GeoIpDb::from_ripe_delegated_str(data);
+
+        assert_eq!(db.optimize(), 2);
+        assert_eq!(db.stats().total_v4_ranges, 4);
+        assert_eq!(db.stats().total_v6_ranges, 1);
+        assert_eq!(db.optimize(), 0);
+
+        for ip in ["10.0.0.0", "10.0.1.255", "10.0.2.1", "10.0.4.0", "10.0.5.9", "2a01:4f8:ffff::1"] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(db.lookup(ip), original.lookup(ip), "{ip}");
+        }
+        let m = db.lookup_range("10.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(m.prefix_len, Some(23));
+    }
+
+    #[test]
+    fn test_eurozone() {
+        for (cc, expected) in [("DE", true), ("HR", true), ("BG", true), ("PL", false), ("MC", false)] {
+            assert_eq!(GeoInfo::from_country(cc).is_eurozone(), expected, "{cc}");
+        }
+        assert_eq!(EUROZONE_COUNTRIES.len(), 21);
+        assert!(EUROZONE_COUNTRIES.iter().all(|cc| EU_COUNTRIES.contains(cc)));
+    }
+
+    #[test]
+    fn test_schengen() {
+        for (cc, expected) in [("DE", true), ("CH", true), ("RO", true), ("IE", false), ("CY", false), ("GB", false)] {
+            assert_eq!(GeoInfo::from_country(cc).is_schengen(), expected, "{cc}");
+        }
+        assert_eq!(SCHENGEN_COUNTRIES.len(), 29);
+    }
+
+    #[test]
+    fn test_efta() {
+        for (cc, expected) in [("CH", true), ("NO", true), ("DE", false), ("GB", false)] {
+            assert_eq!(GeoInfo::from_country(cc).is_efta(), expected, "{cc}");
+        }
+        // EFTA minus Switzerland is the non-EU part of the EEA
+        assert!(EEA_EXTRA.iter().all(|cc| EFTA_COUNTRIES.contains(cc)));
+    }
+
+    #[test]
+    fn test_data_transfer_class() {
+        for (cc, expected) in [
+            ("DE", DataTransferClass::Eea),
+            ("NO", DataTransferClass::Eea),
+            ("GB", DataTransferClass::Adequate),
+            ("JP", DataTransferClass::Adequate),
+            ("US", DataTransferClass::ThirdCountry),
+            ("CN", DataTransferClass::ThirdCountry),
+        ] {
+            assert_eq!(GeoInfo::from_country(cc).data_transfer_class(), expected, "{cc}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-data")]
+    fn test_source_metadata() {
+        let db = GeoIpDb::new();
+        let meta = db.source_metadata().expect("embedded snapshot has a header");
+        assert_eq!(meta.registry, "ripencc");
+        assert!(meta.serial > 0);
+        let snapshot = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/ripe-data.txt")).unwrap();
+        assert_eq!(meta.sha256, Some(crate::cache::sha256_hex(&snapshot)));
+
+        let data = "\
+2|ripencc|1767049199|1|19700101|20251229|+0100
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+";
+        let db = GeoIpDb::from_ripe_delegated_str(data);
+        assert_eq!(db.source_metadata().unwrap().serial, 1767049199);
+        assert_eq!(db.source_metadata().unwrap().end_date, "20251229");
+        assert_eq!(db.source_metadata().unwrap().sha256, None);
+
+        let age = db.data_age().unwrap();
+        assert!(age > Duration::from_secs(86_400));
+        assert!(db.is_stale(age - Duration::from_secs(60)));
+        assert!(!db.is_stale(age + Duration::from_secs(60)));
+        // Without a header the age is unknown
+        let headerless = GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n");
+        assert_eq!(headerless.data_age(), None);
+        assert!(headerless.is_stale(Duration::MAX));
+    }
+
+    #[test]
+    fn test_strict_load_rejects_truncated_file() {
+        let complete = "\
+ripencc|*|ipv4|*|2|summary
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated
+";
+        let db = GeoIpDb::from_ripe_delegated_str_strict(complete).unwrap();
+        assert_eq!(db.stats().total_v4_ranges, 2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("truncated.txt");
+        std::fs::write(&path, &complete[..complete.len() - 50]).unwrap();
+
+        let err = GeoIpDb::from_ripe_delegated_file_strict(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // The lenient loader still accepts the same file.
+        assert!(GeoIpDb::from_ripe_delegated_file(&path).is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-data")]
+    fn test_cache_with_mismatched_sidecar_falls_back_to_embedded() {
+        let fetcher = |_: &str| -> io::Result<Vec<u8>> {
+            Ok(b"ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n".to_vec())
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ripe.txt");
+        GeoIpDb::update_cache_with_fetcher(&path, "mem://ripe", &fetcher).unwrap();
+
+        let db = GeoIpDb::from_cache_or_embedded(&path);
+        assert_eq!(db.stats().total_v4_ranges, 1);
+
+        // Replaced behind the sidecar's back: no longer trusted.
+        std::fs::write(&path, "ripencc|FR|ipv4|46.4.0.0|256|20250101|allocated\n").unwrap();
+        let db = GeoIpDb::from_cache_or_embedded(&path);
+        assert!(db.stats().total_v4_ranges > 1);
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-data")]
+    fn test_address_count() {
+        let data = "\
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|DE|ipv4|255.255.255.0|256|20250101|allocated
+ripencc|DE|ipv6|::|0|20250101|allocated
+ripencc|DE|ipv6|2a01:4f8::|32|20250101|allocated
+";
+        let db = GeoIpDb::from_ripe_delegated_str(data);
+        assert_eq!(db.address_count("de"), (512, u128::MAX));
+        assert_eq!(db.address_count("FR"), (0, 0));
+
+        let (v4, v6) = GeoIpDb::new().address_count("DE");
+        assert!(v4 > 1 << 24 && v6 > 0);
+    }
+
+    #[test]
+    fn test_is_uniform() {
+        let data = "\
+ripencc|DE|ipv4|46.4.0.0|2048|20250101|allocated
+ripencc|DE|ipv4|46.4.8.0|2048|20250101|allocated
+ripencc|DE|ipv4|46.4.32.0|4096|20250101|allocated
+ripencc|FR|ipv6|2a01:cb00::|32|20250101|allocated
+";
+        let db = GeoIpDb::from_ripe_delegated_str(data);
+        let uniform = |net: &str| db.is_uniform(net.parse().unwrap()).map(|i| i.country_code);
+
+        assert_eq!(uniform("46.4.0.0/20"), Some(*b"DE"));
+        assert_eq!(uniform("46.4.4.0/22"), Some(*b"DE"));
+        assert_eq!(uniform("46.4.9.1"), Some(*b"DE"));
+        // 46.4.16.0/20 is unallocated
+        assert_eq!(uniform("46.4.0.0/18"), None);
+        assert_eq!(uniform("46.3.0.0/16"), None);
+        assert_eq!(uniform("2a01:cb00:1234::/48"), Some(*b"FR"));
+        assert_eq!(uniform("2a01:cb00::/31"), None);
+    }
+}
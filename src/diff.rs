@@ -0,0 +1,610 @@
+//! Comparing two databases, e.g. the embedded snapshot and a fresh download.
+//!
+//! [`GeoIpDb::diff`] walks the tables of both databases side by side and
+//! reports every stretch of addresses whose classification differs, no matter
+//! how the blocks were split or merged in either file. [`DbDiff::summary`]
+//! condenses that into per-country gains and losses, and [`GeoIpDb::apply`]
+//! brings the old database up to date with it. [`DbDiff::from_delegated`]
+//! computes such a diff from the records of two delegated files, without
+//! building the new database first.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use crate::{GeoInfo, GeoIpDb, IpRange, ParsedDelegated, SourceMetadata};
+
+/// A stretch of addresses classified differently by two databases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeChange {
+    /// First address of the stretch.
+    pub start: IpAddr,
+    /// Last address of the stretch (inclusive).
+    pub end: IpAddr,
+    /// Classification in the old database, [`None`] if it was not covered.
+    pub old: Option<GeoInfo>,
+    /// Classification in the new database, [`None`] if it is no longer covered.
+    pub new: Option<GeoInfo>,
+}
+
+impl RangeChange {
+    /// Number of addresses in the stretch, saturating for the whole IPv6 space.
+    pub fn addresses(&self) -> u128 {
+        match (self.start, self.end) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => {
+                u128::from(u32::from(end) - u32::from(start)) + 1
+            }
+            (IpAddr::V6(start), IpAddr::V6(end)) => {
+                (u128::from(end) - u128::from(start)).saturating_add(1)
+            }
+            _ => unreachable!("a change never spans address families"),
+        }
+    }
+
+    /// Return `true` if the country differs, not only the status.
+    pub fn country_changed(&self) -> bool {
+        self.old.map(|info| info.country_code) != self.new.map(|info| info.country_code)
+    }
+}
+
+/// Differences between two databases, built by [`GeoIpDb::diff`],
+/// [`DbDiff::from_delegated`] or [`DbDiff::from_changes`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DbDiff {
+    /// Changed stretches, IPv4 before IPv6, each family sorted by address.
+    pub changes: Vec<RangeChange>,
+    old_countries: BTreeSet<[u8; 2]>,
+    new_countries: BTreeSet<[u8; 2]>,
+    /// Header of the new database, taken over by [`GeoIpDb::apply`].
+    new_metadata: Option<SourceMetadata>,
+}
+
+impl DbDiff {
+    /// Build a diff from changed stretches computed elsewhere, e.g. received
+    /// from a central service, to be applied with [`GeoIpDb::apply`].
+    ///
+    /// The diff carries no source metadata, and as the full country sets of
+    /// both databases are unknown, [`DiffSummary::appeared`] and
+    /// [`DiffSummary::disappeared`] of its summary stay empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::{DbDiff, GeoIpDb, RangeChange};
+    ///
+    /// let mut db = GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|512|20250101|allocated\n");
+    /// let fr = GeoIpDb::from_ripe_delegated_str("ripencc|FR|ipv4|46.4.1.0|256|20250101|allocated\n");
+    /// let change = RangeChange {
+    ///     start: "46.4.1.0".parse().unwrap(),
+    ///     end: "46.4.1.255".parse().unwrap(),
+    ///     old: db.lookup("46.4.1.0".parse().unwrap()).copied(),
+    ///     new: fr.lookup("46.4.1.0".parse().unwrap()).copied(),
+    /// };
+    /// db.apply(&DbDiff::from_changes(vec![change]));
+    /// assert_eq!(db.country_str("46.4.1.1".parse().unwrap()), Some("FR"));
+    /// ```
+    pub fn from_changes(changes: Vec<RangeChange>) -> Self {
+        DbDiff {
+            changes,
+            ..DbDiff::default()
+        }
+    }
+
+    /// Compute the diff between two versions of a delegated file from their
+    /// records, without building the tables of the new database.
+    ///
+    /// Records present in both files are skipped, so the work is
+    /// proportional to the records that changed. Applied with
+    /// [`GeoIpDb::apply`] to a database holding the old file (alone or
+    /// together with the files of other registries), the diff brings it to
+    /// the new file and takes over its header. Both files are expected to be
+    /// free of overlapping records, as delegated files are.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::{DbDiff, GeoIpDb, ParsedDelegated};
+    ///
+    /// let old = ParsedDelegated::parse("ripencc|DE|ipv4|46.4.0.0|512|20250101|allocated\n");
+    /// let new = ParsedDelegated::parse("\
+    /// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+    /// ripencc|FR|ipv4|46.4.1.0|256|20250101|allocated
+    /// ");
+    /// let mut db = GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|512|20250101|allocated\n");
+    /// let diff = DbDiff::from_delegated(&old, &new);
+    /// assert_eq!(diff.summary().appeared, ["FR"]);
+    /// db.apply(&diff);
+    /// assert_eq!(db.country_str("46.4.1.1".parse().unwrap()), Some("FR"));
+    /// ```
+    pub fn from_delegated(old: &ParsedDelegated, new: &ParsedDelegated) -> Self {
+        // Dates do not change what lookups return
+        let key = |r: &IpRange| (r.start, r.count, r.country.clone(), r.assigned);
+        let old_keys: HashSet<_> = old.ranges.iter().map(key).collect();
+        let new_keys: HashSet<_> = new.ranges.iter().map(key).collect();
+        let changed = |ranges: &[IpRange], other: &HashSet<_>| {
+            let changed = ranges.iter().filter(|r| !other.contains(&key(r)));
+            GeoIpDb::from_ranges(changed.cloned().collect())
+        };
+
+        let countries = |file: &ParsedDelegated| -> BTreeSet<[u8; 2]> {
+            file.ranges
+                .iter()
+                .map(|r| GeoInfo::from_record(&r.country, r.assigned).country_code)
+                .collect()
+        };
+        let mut diff = changed(&old.ranges, &new_keys).diff(&changed(&new.ranges, &old_keys));
+        diff.old_countries = countries(old);
+        diff.new_countries = countries(new);
+        diff.new_metadata = new.metadata().cloned();
+        diff
+    }
+
+    /// Return `true` if both databases classify every address the same way.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Condense the changes into per-country and overall statistics.
+    pub fn summary(&self) -> DiffSummary {
+        let mut countries: BTreeMap<[u8; 2], CountryDelta> = BTreeMap::new();
+        let mut summary = DiffSummary {
+            changed_ranges: self.changes.len(),
+            ..DiffSummary::default()
+        };
+
+        for change in self.changes.iter().filter(|c| c.country_changed()) {
+            let addresses = change.addresses();
+            let is_v4 = change.start.is_ipv4();
+            if is_v4 {
+                summary.v4_churn += addresses as u64;
+            } else {
+                summary.v6_churn = summary.v6_churn.saturating_add(addresses);
+            }
+
+            for (info, gained) in [(change.old, false), (change.new, true)] {
+                let Some(info) = info else { continue };
+                let delta = countries
+                    .entry(info.country_code)
+                    .or_insert_with(|| CountryDelta {
+                        country: info.country_code_str().to_string(),
+                        ..CountryDelta::default()
+                    });
+                match (is_v4, gained) {
+                    (true, true) => delta.v4_gained += addresses as u64,
+                    (true, false) => delta.v4_lost += addresses as u64,
+                    (false, true) => delta.v6_gained = delta.v6_gained.saturating_add(addresses),
+                    (false, false) => delta.v6_lost = delta.v6_lost.saturating_add(addresses),
+                }
+            }
+        }
+
+        let name = |code: &[u8; 2]| std::str::from_utf8(code).unwrap_or("??").to_string();
+        summary.appeared = self
+            .new_countries
+            .difference(&self.old_countries)
+            .map(name)
+            .collect();
+        summary.disappeared = self
+            .old_countries
+            .difference(&self.new_countries)
+            .map(name)
+            .collect();
+        summary.countries = countries.into_values().collect();
+        summary
+    }
+}
+
+/// Address gains and losses of one country, part of a [`DiffSummary`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CountryDelta {
+    pub country: String,
+    pub v4_gained: u64,
+    pub v4_lost: u64,
+    pub v6_gained: u128,
+    pub v6_lost: u128,
+}
+
+impl CountryDelta {
+    /// Net change in IPv4 addresses.
+    pub fn net_v4(&self) -> i64 {
+        self.v4_gained as i64 - self.v4_lost as i64
+    }
+
+    /// Net change in IPv6 addresses, saturating.
+    pub fn net_v6(&self) -> i128 {
+        let clamp = |n: u128| i128::try_from(n).unwrap_or(i128::MAX);
+        clamp(self.v6_gained).saturating_sub(clamp(self.v6_lost))
+    }
+}
+
+/// Statistics of a [`DbDiff`], see [`DbDiff::summary`].
+///
+/// The [`Display`](fmt::Display) output is a plain-text report meant for
+/// change reviews; IPv6 figures are given in /48 networks there.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    /// Countries that gained or lost addresses, sorted by country code.
+    pub countries: Vec<CountryDelta>,
+    /// Countries present only in the new database.
+    pub appeared: Vec<String>,
+    /// Countries present only in the old database.
+    pub disappeared: Vec<String>,
+    /// IPv4 addresses whose country changed, including newly covered and
+    /// dropped ones.
+    pub v4_churn: u64,
+    /// IPv6 addresses whose country changed, including newly covered and
+    /// dropped ones.
+    pub v6_churn: u128,
+    /// Number of changed stretches, including status-only changes.
+    pub changed_ranges: usize,
+}
+
+impl fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |codes: &[String]| match codes {
+            [] => "none".to_string(),
+            codes => codes.join(", "),
+        };
+        let per_48 = |n: i128| n / (1 << 80);
+
+        writeln!(f, "Changed ranges:      {}", self.changed_ranges)?;
+        writeln!(f, "IPv4 churn:          {} addresses", self.v4_churn)?;
+        writeln!(f, "IPv6 churn:          {} /48s", self.v6_churn >> 80)?;
+        writeln!(f, "New countries:       {}", list(&self.appeared))?;
+        writeln!(f, "Removed countries:   {}", list(&self.disappeared))?;
+        if !self.countries.is_empty() {
+            writeln!(
+                f,
+                "{:<8} {:>14} {:>14}",
+                "Country", "IPv4 net", "IPv6 net /48s"
+            )?;
+        }
+        for delta in &self.countries {
+            writeln!(
+                f,
+                "{:<8} {:>+14} {:>+14}",
+                delta.country,
+                delta.net_v4(),
+                per_48(delta.net_v6())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl GeoIpDb {
+    /// Compare this database (the old one) with `new`.
+    ///
+    /// Only what lookups would return is compared: the same addresses split
+    /// into different blocks, or merged by [`GeoIpDb::optimize`], are not a
+    /// change.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let old = GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|512|20250101|allocated\n");
+    /// let new = GeoIpDb::from_ripe_delegated_str("\
+    /// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+    /// ripencc|FR|ipv4|46.4.1.0|256|20250101|allocated
+    /// ");
+    /// let summary = old.diff(&new).summary();
+    /// assert_eq!(summary.v4_churn, 256);
+    /// assert_eq!(summary.appeared, ["FR"]);
+    /// assert_eq!(summary.countries[0].net_v4(), -256);
+    /// ```
+    pub fn diff(&self, new: &GeoIpDb) -> DbDiff {
+        let mut changes = Vec::new();
+        diff_tables(&self.v4_ranges, &new.v4_ranges, &mut changes, |n| {
+            IpAddr::V4(Ipv4Addr::from(n as u32))
+        });
+        diff_tables(&self.v6_ranges, &new.v6_ranges, &mut changes, |n| {
+            IpAddr::V6(Ipv6Addr::from(n))
+        });
+
+        let countries = |db: &GeoIpDb| -> BTreeSet<[u8; 2]> {
+            let v4 = db.v4_ranges.iter().map(|r| r.2.country_code);
+            v4.chain(db.v6_ranges.iter().map(|r| r.2.country_code))
+                .collect()
+        };
+        DbDiff {
+            changes,
+            old_countries: countries(self),
+            new_countries: countries(new),
+            new_metadata: new.metadata.clone(),
+        }
+    }
+
+    /// Bring this database up to date with `diff`, computed by
+    /// [`GeoIpDb::diff`] or [`DbDiff::from_delegated`] against the new data,
+    /// and take over the source metadata of the new database if the diff
+    /// has any.
+    ///
+    /// Each changed stretch is spliced into the tables in place: blocks it
+    /// covers are cut back or removed, and the stretch is inserted with its
+    /// new classification. Afterwards lookups return what they would on the
+    /// new database, without building it a second time; only the blocks may
+    /// be split differently. Changes are applied in order, and changes whose
+    /// ends are reversed or of different families are skipped.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let mut db = GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|512|20250101|allocated\n");
+    /// let new = GeoIpDb::from_ripe_delegated_str("\
+    /// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+    /// ripencc|FR|ipv4|46.4.1.0|256|20250101|allocated
+    /// ");
+    /// let diff = db.diff(&new);
+    /// db.apply(&diff);
+    /// assert_eq!(db.country_str("46.4.1.1".parse().unwrap()), Some("FR"));
+    /// assert!(db.diff(&new).is_empty());
+    /// ```
+    pub fn apply(&mut self, diff: &DbDiff) {
+        for change in &diff.changes {
+            match (change.start, change.end) {
+                (IpAddr::V4(start), IpAddr::V4(end)) if start <= end => splice(
+                    Arc::make_mut(&mut self.v4_ranges),
+                    start.into(),
+                    end.into(),
+                    change.new,
+                    |n| n as u32,
+                ),
+                (IpAddr::V6(start), IpAddr::V6(end)) if start <= end => splice(
+                    Arc::make_mut(&mut self.v6_ranges),
+                    start.into(),
+                    end.into(),
+                    change.new,
+                    |n| n,
+                ),
+                _ => {}
+            }
+        }
+        if diff.new_metadata.is_some() {
+            self.metadata = diff.new_metadata.clone();
+        }
+    }
+}
+
+/// Classify `start..=end` of a sorted, disjoint table as `new` (or remove
+/// it), cutting back the blocks it overlaps.
+fn splice<T: Copy + Ord + Into<u128>>(
+    table: &mut Vec<(T, T, GeoInfo)>,
+    start: T,
+    end: T,
+    new: Option<GeoInfo>,
+    from: fn(u128) -> T,
+) {
+    // The blocks overlapping the stretch are `first..last`
+    let first = table.partition_point(|r| r.1 < start);
+    let last = table.partition_point(|r| r.0 <= end);
+    let mut replacement = Vec::with_capacity(3);
+    if first < last {
+        let head = table[first];
+        if head.0 < start {
+            replacement.push((head.0, from(start.into() - 1), head.2));
+        }
+    }
+    replacement.extend(new.map(|info| (start, end, info)));
+    if first < last {
+        let tail = table[last - 1];
+        if tail.1 > end {
+            replacement.push((from(end.into() + 1), tail.1, tail.2));
+        }
+    }
+    table.splice(first..last, replacement);
+}
+
+/// Append the stretches where two sorted, disjoint tables differ to `out`,
+/// merging neighbouring stretches with the same old and new classification.
+fn diff_tables<T: Copy + Into<u128>>(
+    old: &[(T, T, GeoInfo)],
+    new: &[(T, T, GeoInfo)],
+    out: &mut Vec<RangeChange>,
+    addr: fn(u128) -> IpAddr,
+) {
+    let bounds = |r: &(T, T, GeoInfo)| (r.0.into(), r.1.into(), r.2);
+    let mut old = old.iter().map(bounds).peekable();
+    let mut new = new.iter().map(bounds).peekable();
+    let mut pos = 0u128;
+    let mut last: Option<(u128, u128, Option<GeoInfo>, Option<GeoInfo>)> = None;
+
+    loop {
+        // Ranges ending before the current position are done
+        while old.next_if(|r| r.1 < pos).is_some() {}
+        while new.next_if(|r| r.1 < pos).is_some() {}
+        let (o, n) = (old.peek().copied(), new.peek().copied());
+        if o.is_none() && n.is_none() {
+            break;
+        }
+
+        // The stretch from `pos` ends where either table changes next
+        let mut end = u128::MAX;
+        for (start, stop, _) in [o, n].into_iter().flatten() {
+            end = end.min(if start <= pos { stop } else { start - 1 });
+        }
+        let at = |r: Option<(u128, u128, GeoInfo)>| r.filter(|r| r.0 <= pos).map(|r| r.2);
+        let (before, after) = (at(o), at(n));
+
+        if before != after {
+            match &mut last {
+                Some(l) if l.1 + 1 == pos && l.2 == before && l.3 == after => l.1 = end,
+                _ => {
+                    out.extend(last.map(|l| change(l, addr)));
+                    last = Some((pos, end, before, after));
+                }
+            }
+        }
+        let Some(next) = end.checked_add(1) else {
+            break;
+        };
+        pos = next;
+    }
+    out.extend(last.map(|l| change(l, addr)));
+}
+
+fn change(
+    (start, end, old, new): (u128, u128, Option<GeoInfo>, Option<GeoInfo>),
+    addr: fn(u128) -> IpAddr,
+) -> RangeChange {
+    RangeChange {
+        start: addr(start),
+        end: addr(end),
+        old,
+        new,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_and_summary() {
+        let old_data = "\
+ripencc|DE|ipv4|10.0.0.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.1.0|256|20250101|allocated
+ripencc|NL|ipv4|10.0.3.0|256|20250101|allocated
+ripencc|AT|ipv4|10.0.4.0|256|20250101|allocated
+ripencc|CH|ipv6|2001:db8::|48|20250101|allocated
+";
+        let old = GeoIpDb::from_ripe_delegated_str(old_data);
+        let new = GeoIpDb::from_ripe_delegated_str(
+            "\
+ripencc|DE|ipv4|10.0.0.0|512|20250101|allocated
+ripencc|FR|ipv4|10.0.2.0|512|20250101|allocated
+ripencc|AT|ipv4|10.0.4.0|256|20250101|assigned
+ripencc|CH|ipv6|2001:db8::|47|20250101|allocated
+",
+        );
+
+        // Splitting a block differently is not a change
+        assert!(old.diff(&old).is_empty());
+        let mut merged = GeoIpDb::from_ripe_delegated_str(old_data);
+        merged.optimize();
+        assert!(old.diff(&merged).is_empty());
+
+        let diff = old.diff(&new);
+        let spans: Vec<(String, String)> = diff
+            .changes
+            .iter()
+            .map(|c| (c.start.to_string(), c.end.to_string()))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("10.0.2.0".into(), "10.0.2.255".into()),
+                ("10.0.3.0".into(), "10.0.3.255".into()),
+                ("10.0.4.0".into(), "10.0.4.255".into()),
+                (
+                    "2001:db8:1::".into(),
+                    "2001:db8:1:ffff:ffff:ffff:ffff:ffff".into()
+                ),
+            ]
+        );
+        // Newly covered, moved from NL, and a status-only change
+        assert_eq!(diff.changes[0].old, None);
+        assert_eq!(diff.changes[1].old.unwrap().country_code_str(), "NL");
+        assert!(!diff.changes[2].country_changed());
+
+        let summary = diff.summary();
+        assert_eq!(summary.changed_ranges, 4);
+        assert_eq!(summary.v4_churn, 512);
+        assert_eq!(summary.v6_churn, 1 << 80);
+        assert_eq!(summary.appeared, ["FR"]);
+        assert_eq!(summary.disappeared, ["NL"]);
+        let nets: Vec<_> = summary
+            .countries
+            .iter()
+            .map(|d| (d.country.as_str(), d.net_v4(), d.net_v6()))
+            .collect();
+        assert_eq!(nets, [("CH", 0, 1 << 80), ("FR", 512, 0), ("NL", -256, 0)]);
+
+        let report = summary.to_string();
+        assert!(report.contains("New countries:       FR"));
+        assert!(report.contains("NL                 -256             +0"));
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut db = GeoIpDb::from_ripe_delegated_str(
+            "\
+2|ripencc|20250101|3|19830705|20250101|+0100
+ripencc|DE|ipv4|10.0.0.0|1024|20250101|allocated
+ripencc|NL|ipv4|10.0.8.0|256|20250101|allocated
+ripencc|CH|ipv6|2001:db8::|32|20250101|allocated
+",
+        );
+        let new = GeoIpDb::from_ripe_delegated_str(
+            "\
+2|ripencc|20250201|3|19830705|20250201|+0100
+ripencc|DE|ipv4|10.0.0.0|256|20250101|allocated
+ripencc|FR|ipv4|10.0.1.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.2.0|512|20250101|allocated
+ripencc|CH|ipv6|2001:db8::|48|20250101|allocated
+ripencc|AT|ipv6|2001:db8:1::|48|20250101|allocated
+",
+        );
+
+        let diff = db.diff(&new);
+        db.apply(&diff);
+        assert!(db.diff(&new).is_empty());
+        assert_eq!(db.source_metadata().unwrap().end_date, "20250201");
+        let ip = |s: &str| s.parse().unwrap();
+        assert_eq!(db.country_str(ip("10.0.1.7")), Some("FR"));
+        assert_eq!(db.country_str(ip("10.0.3.255")), Some("DE"));
+        assert_eq!(db.country_str(ip("10.0.8.1")), None);
+        assert_eq!(db.country_str(ip("2001:db8:2::1")), None);
+
+        // A stretch inside a single block splits it in three
+        let mut db =
+            GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|10.0.0.0|1024|20250101|allocated\n");
+        let new = GeoIpDb::from_ripe_delegated_str(
+            "\
+ripencc|DE|ipv4|10.0.0.0|256|20250101|allocated
+ripencc|FR|ipv4|10.0.1.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.2.0|512|20250101|allocated
+",
+        );
+        db.apply(&db.diff(&new));
+        assert_eq!(db.v4_ranges.len(), 3);
+        assert_eq!(db.country_str(ip("10.0.0.255")), Some("DE"));
+        assert_eq!(db.country_str(ip("10.0.1.0")), Some("FR"));
+        assert_eq!(db.country_str(ip("10.0.2.0")), Some("DE"));
+    }
+
+    #[test]
+    fn test_from_delegated() {
+        let old = "\
+2|ripencc|20250101|3|19830705|20250101|+0100
+ripencc|DE|ipv4|10.0.0.0|1024|20250101|allocated
+ripencc|NL|ipv4|10.0.8.0|256|20250101|allocated
+ripencc|CH|ipv6|2001:db8::|32|20250101|allocated
+";
+        let new = "\
+2|ripencc|20250201|3|19830705|20250201|+0100
+ripencc|DE|ipv4|10.0.0.0|256|20250101|allocated
+ripencc|FR|ipv4|10.0.1.0|256|20250101|allocated
+ripencc|DE|ipv4|10.0.2.0|512|20250101|allocated
+ripencc|CH|ipv6|2001:db8::|32|20250115|allocated
+";
+        let (old, new) = (ParsedDelegated::parse(old), ParsedDelegated::parse(new));
+        let diff = DbDiff::from_delegated(&old, &new);
+        // Only the address that changed country; the re-dated block is not a change
+        assert_eq!(diff.changes.len(), 2);
+        let summary = diff.summary();
+        assert_eq!(summary.appeared, ["FR"]);
+        assert_eq!(summary.disappeared, ["NL"]);
+
+        // A database holding another registry's data as well
+        let arin = ParsedDelegated::parse("arin|US|ipv4|10.1.0.0|256|20250101|allocated\n");
+        let mut db = GeoIpDb::from_sources(&[&old, &arin], Default::default()).unwrap();
+        db.apply(&diff);
+        let expected = GeoIpDb::from_sources(&[&new, &arin], Default::default()).unwrap();
+        assert!(db.diff(&expected).is_empty());
+        assert_eq!(db.country_str("10.1.0.1".parse().unwrap()), Some("US"));
+        assert_eq!(db.source_metadata().unwrap().end_date, "20250201");
+
+        assert!(DbDiff::from_delegated(&new, &new).is_empty());
+    }
+}
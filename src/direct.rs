@@ -0,0 +1,147 @@
+//! Direct-indexed IPv4 lookups, trading memory for latency.
+//!
+//! [`DirectIndexV4`] splits the IPv4 ranges of a database at /16 boundaries
+//! and files the pieces under a 65536-entry first-level array. A lookup
+//! indexes the array with the top 16 bits of the address and then only
+//! searches the pieces of that /16, typically a handful, so its cost barely
+//! depends on the size of the database.
+
+use std::net::Ipv4Addr;
+
+use crate::{GeoInfo, GeoIpDb};
+
+/// Number of /16 buckets.
+const BUCKETS: usize = 1 << 16;
+
+/// Buckets with at most this many pieces are scanned instead of bisected.
+const LINEAR_SCAN_MAX: usize = 8;
+
+/// IPv4 lookup table built by [`GeoIpDb::direct_index_v4`].
+///
+/// Every range is stored once per /16 it touches, which comes to about
+/// 1.3 MB for the embedded snapshot, on top of the database. The table is a
+/// snapshot and does not change when the database does.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::GeoIpDb;
+///
+/// let db = GeoIpDb::new();
+/// let index = db.direct_index_v4();
+/// let ip = "46.4.0.1".parse().unwrap();
+/// assert_eq!(index.lookup(ip), db.lookup_v4(ip));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectIndexV4 {
+    /// Bucket `b` holds `pieces[offsets[b]..offsets[b + 1]]`.
+    offsets: Box<[u32]>,
+    /// `(first, last, info)` by the low 16 bits, sorted within each bucket.
+    pieces: Vec<(u16, u16, GeoInfo)>,
+}
+
+impl DirectIndexV4 {
+    /// Look up a single IPv4 address.
+    ///
+    /// Returns the same result as [`GeoIpDb::lookup_v4`] on the database the
+    /// table was built from.
+    #[inline]
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<&GeoInfo> {
+        let ip = u32::from(ip);
+        let bucket = (ip >> 16) as usize;
+        let low = ip as u16;
+        let pieces = &self.pieces[self.offsets[bucket] as usize..self.offsets[bucket + 1] as usize];
+
+        let piece = if pieces.len() <= LINEAR_SCAN_MAX {
+            pieces.iter().find(|p| p.1 >= low)?
+        } else {
+            pieces.get(pieces.partition_point(|p| p.1 < low))?
+        };
+        (piece.0 <= low).then_some(&piece.2)
+    }
+
+    /// Approximate heap usage of the table, in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        self.offsets.len() * size_of::<u32>()
+            + self.pieces.capacity() * size_of::<(u16, u16, GeoInfo)>()
+    }
+}
+
+impl GeoIpDb {
+    /// Expand the IPv4 ranges into a [`DirectIndexV4`] for near-constant-time
+    /// lookups.
+    pub fn direct_index_v4(&self) -> DirectIndexV4 {
+        let mut offsets = Vec::with_capacity(BUCKETS + 1);
+        let mut pieces = Vec::with_capacity(self.v4_ranges.len() + BUCKETS);
+        let mut ranges = self.v4_ranges.iter().peekable();
+
+        for bucket in 0..BUCKETS as u32 {
+            offsets.push(pieces.len() as u32);
+            let (bucket_first, bucket_last) = (bucket << 16, (bucket << 16) | 0xFFFF);
+
+            // Ranges ending in an earlier bucket are done
+            while ranges.next_if(|r| r.1 < bucket_first).is_some() {}
+            for &(start, end, info) in ranges.clone() {
+                if start > bucket_last {
+                    break;
+                }
+                let (first, last) = (start.max(bucket_first), end.min(bucket_last));
+                pieces.push((first as u16, last as u16, info));
+            }
+        }
+        offsets.push(pieces.len() as u32);
+        pieces.shrink_to_fit();
+
+        DirectIndexV4 {
+            offsets: offsets.into_boxed_slice(),
+            pieces,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::IpRange;
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_matches_database_lookups() {
+        let range = |start: &str, count: u128, country: &str| {
+            IpRange::new(start.parse::<IpAddr>().unwrap(), count, country)
+        };
+        let mut ranges = vec![
+            range("0.0.0.0", 1, "US"),
+            range("10.0.0.0", 1 << 24, "DE"),
+            range("11.0.255.0", 512, "FR"),
+            range("255.255.255.255", 1, "AT"),
+        ];
+        // A crowded /16 that is bisected
+        for i in 0..20 {
+            ranges.push(range(&format!("12.0.{i}.0"), 128, "PL"));
+        }
+        let db = GeoIpDb::from_ranges(ranges);
+        let index = db.direct_index_v4();
+
+        let mut ips = vec![0, 1, u32::MAX, u32::MAX - 1];
+        for (start, end, _) in db.v4_ranges.iter() {
+            ips.extend([
+                *start,
+                *end,
+                start.wrapping_sub(1),
+                end.wrapping_add(1),
+                start + (end - start) / 2,
+            ]);
+        }
+        for ip in ips.into_iter().map(Ipv4Addr::from) {
+            assert_eq!(index.lookup(ip), db.lookup_v4(ip), "{ip}");
+        }
+
+        let embedded = GeoIpDb::new();
+        let index = embedded.direct_index_v4();
+        for (start, end, _) in embedded.v4_ranges.iter().step_by(37) {
+            for ip in [*start, *end, end.wrapping_add(1)].map(Ipv4Addr::from) {
+                assert_eq!(index.lookup(ip), embedded.lookup_v4(ip), "{ip}");
+            }
+        }
+    }
+}
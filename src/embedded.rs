@@ -0,0 +1,319 @@
+//! Zero-setup lookups over the tables embedded at build time.
+//!
+//! The functions in this module binary-search the static tables generated by
+//! the build script directly: there is nothing to construct, no allocation and
+//! no start-up cost. They answer exactly what [`GeoIpDb::new`] would, so use
+//! them when the embedded snapshot is all you need, and a [`GeoIpDb`] when data
+//! is loaded or refreshed at runtime.
+//!
+//! Without the `embedded-data` feature (enabled by default) the tables are
+//! empty and every lookup returns [`None`].
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "embedded-data")] {
+//! use ip_alloc_lookup::embedded;
+//!
+//! let ip = "46.4.0.1".parse().unwrap();
+//! assert_eq!(embedded::country(ip), Some("DE"));
+//! assert!(embedded::is_eu(ip));
+//! # }
+//! ```
+//!
+//! [`GeoIpDb`]: crate::GeoIpDb
+//! [`GeoIpDb::new`]: crate::GeoIpDb::new
+
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::GeoInfo;
+use crate::bogon::FreeSpace;
+
+mod generated {
+    // Include the generated data from build.rs
+    include!(concat!(env!("OUT_DIR"), "/generated_data.rs"));
+}
+
+pub(crate) use generated::{
+    COUNTRY_REGIONS, EU_COUNTRIES, SOURCE_FORMAT, SOURCE_HEADER, SOURCE_SHA256,
+};
+
+/// The embedded IPv4 ranges, sorted by start address.
+pub(crate) static IPV4_RANGES: RangeTable<u32> = RangeTable::new(generated::IPV4_RANGES_BLOB);
+/// The /16 index into [`IPV4_RANGES`].
+static IPV4_INDEX: Slash16Index = Slash16Index::new(generated::IPV4_INDEX_BLOB);
+/// The embedded IPv6 ranges, sorted by start address.
+pub(crate) static IPV6_RANGES: RangeTable<u128> = RangeTable::new(generated::IPV6_RANGES_BLOB);
+
+/// The embedded spans of `available` and `reserved` records.
+pub(crate) fn unallocated() -> FreeSpace {
+    fn spans<T: Addr>(bytes: &[u8]) -> Vec<(T, T)> {
+        bytes
+            .chunks_exact(2 * T::SIZE)
+            .map(|pair| (T::from_le(&pair[..T::SIZE]), T::from_le(&pair[T::SIZE..])))
+            .collect()
+    }
+    // Already sorted and merged by the build script
+    FreeSpace {
+        v4: spans(generated::IPV4_UNALLOCATED_BLOB),
+        v6: spans(generated::IPV6_UNALLOCATED_BLOB),
+    }
+}
+
+/// Look up an IPv4 or IPv6 address in the embedded tables.
+///
+/// Returns [`None`] if the address is not covered by any embedded range.
+#[inline]
+pub fn lookup(ip: IpAddr) -> Option<GeoInfo> {
+    match ip {
+        IpAddr::V4(v4) => lookup_v4(v4),
+        IpAddr::V6(v6) => lookup_v6(v6),
+    }
+}
+
+/// Look up a single IPv4 address in the embedded tables.
+#[inline]
+pub fn lookup_v4(ip: Ipv4Addr) -> Option<GeoInfo> {
+    find_v4(u32::from(ip)).map(|(_, _, cc, assigned)| GeoInfo::from_record(cc, assigned))
+}
+
+/// Look up a single IPv6 address in the embedded tables.
+#[inline]
+pub fn lookup_v6(ip: Ipv6Addr) -> Option<GeoInfo> {
+    IPV6_RANGES
+        .find(u128::from(ip))
+        .map(|(_, _, cc, assigned)| GeoInfo::from_record(cc, assigned))
+}
+
+/// Return the country code of the embedded range covering `ip`.
+///
+/// This is the code exactly as it appears in the source data.
+#[inline]
+pub fn country(ip: IpAddr) -> Option<&'static str> {
+    match ip {
+        IpAddr::V4(v4) => find_v4(u32::from(v4)).map(|entry| entry.2),
+        IpAddr::V6(v6) => IPV6_RANGES.find(u128::from(v6)).map(|entry| entry.2),
+    }
+}
+
+/// Return `true` if `ip` is covered by the embedded tables and classified as EU.
+#[inline]
+pub fn is_eu(ip: IpAddr) -> bool {
+    lookup(ip).is_some_and(|info| info.is_eu)
+}
+
+/// Find the IPv4 entry containing `ip`, searching only the entries the /16
+/// index allows.
+#[inline]
+fn find_v4(ip: u32) -> Option<Entry<u32>> {
+    let (lo, hi) = IPV4_INDEX.bounds(ip);
+    IPV4_RANGES.find_between(ip, lo, hi)
+}
+
+/// Per-/16 bounds into the IPv4 table, generated by the build script.
+///
+/// Entry `p` is the number of ranges starting before `p << 16`, and entry
+/// 65536 is the total. For an address in /16 `p`, the first range starting
+/// after it therefore has an index between entries `p` and `p + 1`, and only
+/// the ranges starting inside that /16 are left to binary-search; usually a
+/// handful instead of the whole table.
+struct Slash16Index {
+    bytes: &'static [u8],
+}
+
+impl Slash16Index {
+    const LEN: usize = (1 << 16) + 1;
+
+    const fn new(bytes: &'static [u8]) -> Self {
+        assert!(
+            bytes.len() == Self::LEN * 4,
+            "embedded /16 index has the wrong size"
+        );
+        Slash16Index { bytes }
+    }
+
+    fn get(&self, idx: usize) -> usize {
+        <u32 as Addr>::from_le(&self.bytes[idx * 4..idx * 4 + 4]) as usize
+    }
+
+    /// Search bounds for [`RangeTable::find_between`].
+    fn bounds(&self, ip: u32) -> (usize, usize) {
+        let prefix = (ip >> 16) as usize;
+        (self.get(prefix), self.get(prefix + 1))
+    }
+}
+
+/// An embedded `(start, end, country, assigned)` table entry.
+pub(crate) type Entry<T> = (T, T, &'static str, bool);
+
+/// Address type of a [`RangeTable`].
+pub(crate) trait Addr: Copy + Ord {
+    const SIZE: usize;
+    fn from_le(bytes: &[u8]) -> Self;
+}
+
+impl Addr for u32 {
+    const SIZE: usize = 4;
+    fn from_le(bytes: &[u8]) -> Self {
+        u32::from_le_bytes(bytes.try_into().expect("slice of SIZE bytes"))
+    }
+}
+
+impl Addr for u128 {
+    const SIZE: usize = 16;
+    fn from_le(bytes: &[u8]) -> Self {
+        u128::from_le_bytes(bytes.try_into().expect("slice of SIZE bytes"))
+    }
+}
+
+/// Read-only view of a packed range table written by the build script.
+///
+/// Each entry is `start, end` (little-endian), a two-byte country code (two
+/// zero bytes for an empty code) and an `assigned` byte. Entries are decoded on
+/// access, so the table is searched in place without being copied.
+pub(crate) struct RangeTable<T> {
+    bytes: &'static [u8],
+    _addr: PhantomData<T>,
+}
+
+impl<T: Addr> RangeTable<T> {
+    pub(crate) const ENTRY_SIZE: usize = 2 * T::SIZE + 3;
+
+    const fn new(bytes: &'static [u8]) -> Self {
+        assert!(
+            bytes.len().is_multiple_of(Self::ENTRY_SIZE),
+            "embedded range table has a partial entry"
+        );
+        RangeTable {
+            bytes,
+            _addr: PhantomData,
+        }
+    }
+
+    /// Number of entries.
+    pub(crate) fn len(&self) -> usize {
+        self.bytes.len() / Self::ENTRY_SIZE
+    }
+
+    /// The packed entries.
+    pub(crate) fn as_bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    fn start(&self, idx: usize) -> T {
+        let offset = idx * Self::ENTRY_SIZE;
+        T::from_le(&self.bytes[offset..offset + T::SIZE])
+    }
+
+    /// Decode the entry at `idx`.
+    pub(crate) fn get(&self, idx: usize) -> Entry<T> {
+        let entry = &self.bytes[idx * Self::ENTRY_SIZE..(idx + 1) * Self::ENTRY_SIZE];
+        let (addrs, tail) = entry.split_at(2 * T::SIZE);
+        let country = match &tail[..2] {
+            [0, 0] => "",
+            // The build script only writes ASCII codes
+            cc => std::str::from_utf8(cc).unwrap_or("??"),
+        };
+        (
+            T::from_le(&addrs[..T::SIZE]),
+            T::from_le(&addrs[T::SIZE..]),
+            country,
+            tail[2] != 0,
+        )
+    }
+
+    /// Iterate over all entries in order.
+    pub(crate) fn iter(&self) -> impl ExactSizeIterator<Item = Entry<T>> + '_ {
+        (0..self.len()).map(|idx| self.get(idx))
+    }
+
+    /// Binary-search for the entry containing `ip`.
+    pub(crate) fn find(&self, ip: T) -> Option<Entry<T>> {
+        self.find_between(ip, 0, self.len())
+    }
+
+    /// Like [`RangeTable::find`], given that the first entry starting after
+    /// `ip` lies in `lo..=hi`.
+    fn find_between(&self, ip: T, mut lo: usize, mut hi: usize) -> Option<Entry<T>> {
+        // Index of the first entry starting after `ip`
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.start(mid) <= ip {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        let entry = self.get(lo.checked_sub(1)?);
+        (entry.0 <= ip && ip <= entry.1).then_some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GeoIpDb;
+
+    #[test]
+    fn test_matches_embedded_database() {
+        let db = GeoIpDb::new();
+
+        for ip in [
+            "46.4.0.1",
+            "8.8.8.8",
+            "0.0.0.0",
+            "255.255.255.255",
+            "2a01:4f8::1",
+            "2001:4860:4860::8888",
+            "::",
+        ] {
+            let ip: IpAddr = ip.parse().unwrap();
+            assert_eq!(lookup(ip), db.lookup(ip).copied(), "{ip}");
+            assert_eq!(is_eu(ip), db.is_eu(ip), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_range_table_view() {
+        // 10.0.0.0-10.0.0.255 "DE", 10.0.1.0-10.0.1.255 "" (assigned)
+        static BLOB: &[u8] = &[
+            0, 0, 0, 10, 255, 0, 0, 10, b'D', b'E', 0, //
+            0, 1, 0, 10, 255, 1, 0, 10, 0, 0, 1,
+        ];
+        let table = RangeTable::<u32>::new(BLOB);
+        let ip = |s: &str| u32::from(s.parse::<Ipv4Addr>().unwrap());
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.find(ip("10.0.0.7")),
+            Some((ip("10.0.0.0"), ip("10.0.0.255"), "DE", false))
+        );
+        assert_eq!(
+            table.find(ip("10.0.1.255")).map(|e| (e.2, e.3)),
+            Some(("", true))
+        );
+        assert_eq!(table.find(ip("9.255.255.255")), None);
+        assert_eq!(table.find(ip("10.0.2.0")), None);
+        assert_eq!(table.iter().count(), 2);
+        assert_eq!(RangeTable::<u128>::new(&[]).find(1), None);
+    }
+
+    #[test]
+    fn test_slash16_index_matches_full_search() {
+        let mut ips = vec![0, u32::MAX, 0x2E04_0001, 0x0808_0808];
+        for (start, end, ..) in IPV4_RANGES.iter().step_by(97) {
+            ips.extend([start, end, start.wrapping_sub(1), end.wrapping_add(1)]);
+        }
+        for ip in ips {
+            assert_eq!(find_v4(ip), IPV4_RANGES.find(ip), "{}", Ipv4Addr::from(ip));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-data")]
+    fn test_range_boundaries() {
+        let (start, end, cc, _) = IPV4_RANGES.get(IPV4_RANGES.len() / 2);
+        assert_eq!(country(Ipv4Addr::from(start).into()), Some(cc));
+        assert_eq!(country(Ipv4Addr::from(end).into()), Some(cc));
+    }
+}
@@ -0,0 +1,460 @@
+//! Adding country and region columns to access logs.
+//!
+//! [`enrich_log`] streams a log line by line and appends the classification
+//! of each line's client address, leaving the rest of the line untouched, so
+//! the output can go wherever the log went before. With the `csv` feature,
+//! `enrich_csv` does the same for one column of a CSV file.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "embedded-data")] {
+//! use ip_alloc_lookup::GeoIpDb;
+//! use ip_alloc_lookup::enrich::{enrich_log, LogFormat};
+//!
+//! let log = "46.4.0.1 - - [10/Oct/2025:13:55:36 +0000] \"GET / HTTP/1.1\" 200 612 \"-\" \"curl/8.5\"\n";
+//! let mut out = Vec::new();
+//! enrich_log(&GeoIpDb::new(), LogFormat::Combined, log.as_bytes(), &mut out).unwrap();
+//! assert!(String::from_utf8(out).unwrap().ends_with("\"curl/8.5\" DE \"European Union\"\n"));
+//! # }
+//! ```
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::forwarded::parse_node;
+use crate::{GeoInfo, GeoIpDb};
+
+/// Layout of the log lines read by [`enrich_log`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogFormat {
+    /// nginx `combined` and Apache common or combined logs: the client
+    /// address is the first field. The country code and the quoted region
+    /// label are appended as two more fields, `-` if unknown.
+    Combined,
+    /// One JSON object per line with the client address in a top-level `"ip"`
+    /// field. `"country"` and `"region"` fields are appended to the object,
+    /// `null` if unknown; lines that are not an object are left as they are.
+    JsonLines,
+}
+
+impl LogFormat {
+    pub const ALL: [LogFormat; 2] = [LogFormat::Combined, LogFormat::JsonLines];
+
+    /// Name of the format as accepted by [`FromStr`], e.g. `"combined"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogFormat::Combined => "combined",
+            LogFormat::JsonLines => "json",
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = ParseLogFormatError;
+
+    /// Parse a format name (`combined`, `json`), ignoring ASCII case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        LogFormat::ALL
+            .into_iter()
+            .find(|format| format.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseLogFormatError(s.to_string()))
+    }
+}
+
+/// Error returned when parsing an unknown [`LogFormat`] name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLogFormatError(String);
+
+impl fmt::Display for ParseLogFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown log format: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseLogFormatError {}
+
+/// Line counts of an [`enrich_log`] or `enrich_csv` run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EnrichSummary {
+    /// Lines read, or records for CSV (without the header).
+    pub lines: u64,
+    /// Lines whose address is covered by the database.
+    pub classified: u64,
+    /// Lines without a parseable address.
+    pub without_ip: u64,
+}
+
+impl EnrichSummary {
+    fn count(&mut self, ip: Option<IpAddr>, info: Option<&GeoInfo>) {
+        self.lines += 1;
+        match (ip, info) {
+            (None, _) => self.without_ip += 1,
+            (Some(_), Some(_)) => self.classified += 1,
+            (Some(_), None) => {}
+        }
+    }
+}
+
+/// Copy `input` to `output` line by line, appending the classification of
+/// each line's client address.
+///
+/// Lines are written as soon as they are read, so this works on a log that
+/// is still being written (`tail -f`). Bytes that are not valid UTF-8 are
+/// copied unchanged. Line endings are normalized to `\n`.
+///
+/// # Errors
+/// Returns the first read or write error.
+pub fn enrich_log<R: BufRead, W: Write>(
+    db: &GeoIpDb,
+    format: LogFormat,
+    mut input: R,
+    mut output: W,
+) -> io::Result<EnrichSummary> {
+    let mut summary = EnrichSummary::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let content = line
+            .strip_suffix(b"\n")
+            .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+            .unwrap_or(&line);
+        let ip = std::str::from_utf8(content)
+            .ok()
+            .and_then(|text| match format {
+                LogFormat::Combined => text.split_whitespace().next(),
+                LogFormat::JsonLines => json_ip(text),
+            })
+            .and_then(parse_node);
+        let info = ip.and_then(|ip| db.lookup(ip));
+        summary.count(ip, info);
+
+        match format {
+            LogFormat::Combined => {
+                output.write_all(content)?;
+                match info {
+                    Some(info) => {
+                        write!(output, " {} \"{}\"", info.country_code_str(), region(info))?
+                    }
+                    None => output.write_all(b" - -")?,
+                }
+            }
+            LogFormat::JsonLines => write_json(&mut output, content, info)?,
+        }
+        output.write_all(b"\n")?;
+    }
+    output.flush()?;
+    Ok(summary)
+}
+
+/// A column appended by [`enrich_csv`].
+///
+/// # Feature
+/// Available only when the crate is built with the `csv` feature.
+#[cfg(feature = "csv")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CsvColumn {
+    /// `country`: the ISO-3166 alpha-2 code.
+    Country,
+    /// `is_eu`: `true` or `false`.
+    IsEu,
+    /// `region`: the label of the [`Region`](crate::Region).
+    Region,
+}
+
+#[cfg(feature = "csv")]
+impl CsvColumn {
+    /// Header of the column, e.g. `"is_eu"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            CsvColumn::Country => "country",
+            CsvColumn::IsEu => "is_eu",
+            CsvColumn::Region => "region",
+        }
+    }
+
+    fn value(self, info: &GeoInfo) -> &str {
+        match self {
+            CsvColumn::Country => info.country_code_str(),
+            CsvColumn::IsEu if info.is_eu => "true",
+            CsvColumn::IsEu => "false",
+            CsvColumn::Region => region(info),
+        }
+    }
+}
+
+/// Copy a CSV file with a header row from `reader` to `writer`, appending
+/// `added_columns` with the classification of the address in the column
+/// named `ip_column`.
+///
+/// All other fields are written back unchanged, quoted only where needed.
+/// Rows whose address is missing, malformed or not covered get empty
+/// values. Addresses may carry a port, as in `192.0.2.1:443`.
+///
+/// # Errors
+/// Returns the reader's or writer's error, or an error of kind
+/// [`io::ErrorKind::InvalidData`] if the CSV is malformed or has no column
+/// `ip_column`.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "embedded-data")] {
+/// use ip_alloc_lookup::GeoIpDb;
+/// use ip_alloc_lookup::enrich::{enrich_csv, CsvColumn};
+///
+/// let csv = "user,client_ip\nalice,46.4.0.1\nbob,\n";
+/// let mut out = Vec::new();
+/// enrich_csv(
+///     &GeoIpDb::new(),
+///     csv.as_bytes(),
+///     &mut out,
+///     "client_ip",
+///     &[CsvColumn::Country, CsvColumn::IsEu],
+/// )
+/// .unwrap();
+/// assert_eq!(
+///     String::from_utf8(out).unwrap(),
+///     "user,client_ip,country,is_eu\nalice,46.4.0.1,DE,true\nbob,,,\n"
+/// );
+/// # }
+/// ```
+///
+/// # Feature
+/// Available only when the crate is built with the `csv` feature.
+#[cfg(feature = "csv")]
+pub fn enrich_csv<R: io::Read, W: Write>(
+    db: &GeoIpDb,
+    reader: R,
+    writer: W,
+    ip_column: &str,
+    added_columns: &[CsvColumn],
+) -> io::Result<EnrichSummary> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(writer);
+
+    let header = reader.byte_headers()?.clone();
+    let ip_idx = header
+        .iter()
+        .position(|name| name == ip_column.as_bytes())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("CSV has no column {ip_column:?}"),
+            )
+        })?;
+    writer.write_record(
+        header
+            .iter()
+            .chain(added_columns.iter().map(|column| column.name().as_bytes())),
+    )?;
+
+    let mut summary = EnrichSummary::default();
+    let mut record = csv::ByteRecord::new();
+    while reader.read_byte_record(&mut record)? {
+        let ip = record
+            .get(ip_idx)
+            .and_then(|field| std::str::from_utf8(field).ok())
+            .and_then(parse_node);
+        let info = ip.and_then(|ip| db.lookup(ip));
+        summary.count(ip, info);
+        let added = added_columns
+            .iter()
+            .map(|column| info.map_or("", |info| column.value(info)).as_bytes());
+        writer.write_record(record.iter().chain(added))?;
+    }
+    writer.flush()?;
+    Ok(summary)
+}
+
+fn region(info: &GeoInfo) -> &'static str {
+    info.region_enum().as_str()
+}
+
+/// Write the object `line` with the `country` and `region` fields added.
+fn write_json<W: Write>(out: &mut W, line: &[u8], info: Option<&GeoInfo>) -> io::Result<()> {
+    let trimmed = line.trim_ascii_end();
+    let Some(body) = trimmed
+        .strip_suffix(b"}")
+        .filter(|_| line.trim_ascii_start().starts_with(b"{"))
+    else {
+        return out.write_all(line);
+    };
+    out.write_all(body)?;
+    // `{}` gets no separating comma
+    if body.trim_ascii() != b"{" {
+        out.write_all(b",")?;
+    }
+    match info {
+        Some(info) => write!(
+            out,
+            "\"country\":\"{}\",\"region\":\"{}\"}}",
+            info.country_code_str(),
+            region(info)
+        ),
+        None => out.write_all(b"\"country\":null,\"region\":null}"),
+    }
+}
+
+/// The string value of the top-level `"ip"` field of a JSON object.
+fn json_ip(line: &str) -> Option<&str> {
+    let bytes = line.as_bytes();
+    let (mut idx, mut depth) = (0, 0usize);
+    // The previous token was the key `"ip"` and its colon may follow
+    let mut after_ip_key = false;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'"' => {
+                let end = string_end(bytes, idx + 1)?;
+                let value = &line[idx + 1..end];
+                if after_ip_key {
+                    return Some(value);
+                }
+                after_ip_key =
+                    depth == 1 && value == "ip" && line[end + 1..].trim_start().starts_with(':');
+                idx = end + 1;
+                continue;
+            }
+            b':' => {}
+            b if b.is_ascii_whitespace() => {}
+            // A value that is not a string, e.g. `"ip": null`
+            b => {
+                after_ip_key = false;
+                match b {
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+        }
+        idx += 1;
+    }
+    None
+}
+
+/// Index of the quote closing the string whose content starts at `from`.
+fn string_end(bytes: &[u8], from: usize) -> Option<usize> {
+    let mut idx = from;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'"' => return Some(idx),
+            _ => idx += 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enrich(format: LogFormat, input: &str) -> (String, EnrichSummary) {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+             ripencc|TR|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        );
+        let mut out = Vec::new();
+        let summary = enrich_log(&db, format, input.as_bytes(), &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), summary)
+    }
+
+    #[test]
+    fn test_combined() {
+        let (out, summary) = enrich(
+            LogFormat::Combined,
+            "46.4.0.1 - - [10/Oct/2025:13:55:36 +0000] \"GET / HTTP/1.1\" 200 612\r\n\
+             2a01:4f8::1 - frank [10/Oct/2025:13:55:37 +0000] \"GET /a HTTP/1.1\" 404 0\n\
+             192.0.2.1 - - [10/Oct/2025:13:55:38 +0000] \"GET / HTTP/1.1\" 200 612\n\
+             example.com - - [10/Oct/2025:13:55:39 +0000] \"GET / HTTP/1.1\" 200 612",
+        );
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(
+            lines[0].ends_with(" 612 DE \"European Union\""),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[1].ends_with(" 0 TR \"Turkey\""), "{}", lines[1]);
+        assert!(lines[2].ends_with(" 612 - -"));
+        assert!(lines[3].ends_with(" 612 - -"));
+        assert_eq!(
+            summary,
+            EnrichSummary {
+                lines: 4,
+                classified: 2,
+                without_ip: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let (out, summary) = enrich(
+            LogFormat::JsonLines,
+            "{\"msg\":\"\\\"ip\\\": x\",\"req\":{\"ip\":\"192.0.2.1\"},\"ip\":\"46.4.0.1\"}\n\
+             { \"ip\" : \"[2a01:4f8::1]:443\" }\n\
+             {\"ip\":null}\n\
+             {\"ip\":{\"v\":\"46.4.0.1\"}}\n\
+             {}\n\
+             not json\n",
+        );
+        assert_eq!(
+            out,
+            "{\"msg\":\"\\\"ip\\\": x\",\"req\":{\"ip\":\"192.0.2.1\"},\"ip\":\"46.4.0.1\",\
+             \"country\":\"DE\",\"region\":\"European Union\"}\n\
+             { \"ip\" : \"[2a01:4f8::1]:443\" ,\"country\":\"TR\",\"region\":\"Turkey\"}\n\
+             {\"ip\":null,\"country\":null,\"region\":null}\n\
+             {\"ip\":{\"v\":\"46.4.0.1\"},\"country\":null,\"region\":null}\n\
+             {\"country\":null,\"region\":null}\n\
+             not json\n"
+        );
+        assert_eq!((summary.classified, summary.without_ip), (2, 4));
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::JsonLines));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_enrich_csv() {
+        let db =
+            GeoIpDb::from_ripe_delegated_str("ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n");
+        let input =
+            "id,note,ip\n1,\"a, \"\"quoted\"\" note\",46.4.0.1:443\n2,short\n3,,192.0.2.1\n";
+        let mut out = Vec::new();
+        let summary = enrich_csv(
+            &db,
+            input.as_bytes(),
+            &mut out,
+            "ip",
+            &[CsvColumn::Region, CsvColumn::Country],
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id,note,ip,region,country\n\
+             1,\"a, \"\"quoted\"\" note\",46.4.0.1:443,European Union,DE\n\
+             2,short,,\n\
+             3,,192.0.2.1,,\n"
+        );
+        assert_eq!(
+            summary,
+            EnrichSummary {
+                lines: 3,
+                classified: 1,
+                without_ip: 1
+            }
+        );
+
+        let err = enrich_csv(&db, input.as_bytes(), Vec::new(), "addr", &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
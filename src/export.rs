@@ -0,0 +1,541 @@
+//! Writing the loaded ranges in formats other tools consume.
+//!
+//! [`GeoIpDb::export`] regenerates firewall sets, proxy maps and lookup
+//! databases from whatever data is loaded, optionally restricted to a list of
+//! countries.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::database::{GeoInfo, GeoIpDb};
+use crate::net::{IpNet, cidrs_covering};
+
+/// Output format of [`GeoIpDb::export`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// `start,end,country` rows, readable again with
+    /// [`GeoIpDb::from_csv`] and the default [`CsvSchema`](crate::CsvSchema).
+    Csv,
+    /// An nftables `table inet ip_alloc_lookup` with one interval set per
+    /// country and address family, named like `de_v4`, for `nft -f`.
+    Nftables,
+    /// An HAProxy map file of `prefix country` lines, for `map_ip`.
+    Haproxy,
+    /// A MaxMind DB file with the GeoLite2-Country layout
+    /// (`country.iso_code`, `country.is_in_european_union`), readable by the
+    /// usual MaxMind DB readers.
+    Mmdb,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [
+        ExportFormat::Csv,
+        ExportFormat::Nftables,
+        ExportFormat::Haproxy,
+        ExportFormat::Mmdb,
+    ];
+
+    /// Name of the format as accepted by [`FromStr`], e.g. `"nftables"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Nftables => "nftables",
+            ExportFormat::Haproxy => "haproxy",
+            ExportFormat::Mmdb => "mmdb",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ParseExportFormatError;
+
+    /// Parse a format name (`csv`, `nftables`, `haproxy`, `mmdb`), ignoring
+    /// ASCII case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseExportFormatError(s.to_string()))
+    }
+}
+
+/// Error returned when parsing an unknown [`ExportFormat`] name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseExportFormatError(String);
+
+impl fmt::Display for ParseExportFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown export format: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseExportFormatError {}
+
+impl GeoIpDb {
+    /// Write the ranges of `countries` (all of them if empty) in `format`.
+    ///
+    /// Country codes are matched ignoring ASCII case. Text formats list IPv4
+    /// before IPv6; the prefix-based formats merge directly adjacent ranges of
+    /// a country first, so their output is as short as possible.
+    ///
+    /// # Errors
+    /// Returns the writer's error. [`ExportFormat::Mmdb`] also fails with
+    /// [`io::ErrorKind::InvalidData`] if ranges overlap or the tree does not
+    /// fit 32-bit records.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::{ExportFormat, GeoIpDb};
+    ///
+    /// let db = GeoIpDb::from_ripe_delegated_str(
+    ///     "ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+    ///      ripencc|FR|ipv4|62.0.0.0|256|20090101|allocated\n",
+    /// );
+    /// let mut out = Vec::new();
+    /// db.export(ExportFormat::Haproxy, &["de"], &mut out).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "46.4.0.0/16 DE\n");
+    /// ```
+    pub fn export<W: Write>(
+        &self,
+        format: ExportFormat,
+        countries: &[&str],
+        mut out: W,
+    ) -> io::Result<()> {
+        let ranges = self.export_ranges(countries);
+        match format {
+            ExportFormat::Csv => {
+                for (start, end, info) in &ranges {
+                    writeln!(out, "{start},{end},{}", info.country_code_str())?;
+                }
+            }
+            ExportFormat::Nftables => write_nftables(&mut out, &merge_adjacent(ranges))?,
+            ExportFormat::Haproxy => {
+                for (start, end, info) in merge_adjacent(ranges) {
+                    for net in cidrs_covering(start, end) {
+                        writeln!(out, "{net} {}", info.country_code_str())?;
+                    }
+                }
+            }
+            ExportFormat::Mmdb => {
+                let build_epoch = self
+                    .source_metadata()
+                    .and_then(|meta| meta.published_at())
+                    .unwrap_or_else(SystemTime::now);
+                out.write_all(&mmdb::build(&merge_adjacent(ranges), build_epoch)?)?;
+            }
+        }
+        out.flush()
+    }
+
+    /// The prefixes allocated to `country`, IPv4 first, in address order.
+    ///
+    /// Every allocation block is split into prefixes on its own, so the list
+    /// follows the registry's records. The country code is matched ignoring
+    /// ASCII case.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let db = GeoIpDb::from_ripe_delegated_str(
+    ///     "ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated\n\
+    ///      ripencc|DE|ipv4|46.4.1.0|768|20090101|allocated\n",
+    /// );
+    /// let cidrs: Vec<String> = db.country_cidrs("de").iter().map(|n| n.to_string()).collect();
+    /// assert_eq!(cidrs, ["46.4.0.0/24", "46.4.1.0/24", "46.4.2.0/23"]);
+    /// ```
+    pub fn country_cidrs(&self, country: &str) -> Vec<IpNet> {
+        self.export_ranges(&[country])
+            .into_iter()
+            .flat_map(|(start, end, _)| cidrs_covering(start, end))
+            .collect()
+    }
+
+    /// Like [`GeoIpDb::country_cidrs`], but adjacent blocks are joined first,
+    /// giving the shortest list of prefixes covering the country's addresses.
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let db = GeoIpDb::from_ripe_delegated_str(
+    ///     "ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated\n\
+    ///      ripencc|DE|ipv4|46.4.1.0|768|20090101|allocated\n",
+    /// );
+    /// let cidrs = db.country_cidrs_aggregated("DE");
+    /// assert_eq!(cidrs.len(), 1);
+    /// assert_eq!(cidrs[0].to_string(), "46.4.0.0/22");
+    /// ```
+    pub fn country_cidrs_aggregated(&self, country: &str) -> Vec<IpNet> {
+        merge_adjacent(self.export_ranges(&[country]))
+            .into_iter()
+            .flat_map(|(start, end, _)| cidrs_covering(start, end))
+            .collect()
+    }
+
+    /// The ranges of `countries` (all if empty), IPv4 first, in address order.
+    fn export_ranges(&self, countries: &[&str]) -> Vec<(IpAddr, IpAddr, GeoInfo)> {
+        let wanted = |info: &GeoInfo| {
+            countries.is_empty()
+                || countries
+                    .iter()
+                    .any(|cc| cc.eq_ignore_ascii_case(info.country_code_str()))
+        };
+        let v4 = self
+            .v4_ranges
+            .iter()
+            .filter(|r| wanted(&r.2))
+            .map(|&(start, end, info)| {
+                (
+                    IpAddr::V4(Ipv4Addr::from(start)),
+                    IpAddr::V4(Ipv4Addr::from(end)),
+                    info,
+                )
+            });
+        let v6 = self
+            .v6_ranges
+            .iter()
+            .filter(|r| wanted(&r.2))
+            .map(|&(start, end, info)| {
+                (
+                    IpAddr::V6(Ipv6Addr::from(start)),
+                    IpAddr::V6(Ipv6Addr::from(end)),
+                    info,
+                )
+            });
+        v4.chain(v6).collect()
+    }
+}
+
+/// Merge directly adjacent ranges of the same country. The status flag is
+/// not exported by the prefix-based formats, so it does not keep ranges apart.
+fn merge_adjacent(ranges: Vec<(IpAddr, IpAddr, GeoInfo)>) -> Vec<(IpAddr, IpAddr, GeoInfo)> {
+    let mut merged: Vec<(IpAddr, IpAddr, GeoInfo)> = Vec::with_capacity(ranges.len());
+    for (start, end, info) in ranges {
+        if let Some(last) = merged.last_mut()
+            && last.2.country_code == info.country_code
+            && next_addr(last.1) == Some(start)
+        {
+            last.1 = end;
+        } else {
+            merged.push((start, end, info));
+        }
+    }
+    merged
+}
+
+/// The address after `ip` within its family.
+fn next_addr(ip: IpAddr) -> Option<IpAddr> {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4).checked_add(1).map(|n| IpAddr::V4(n.into())),
+        IpAddr::V6(v6) => u128::from(v6).checked_add(1).map(|n| IpAddr::V6(n.into())),
+    }
+}
+
+fn write_nftables<W: Write>(out: &mut W, ranges: &[(IpAddr, IpAddr, GeoInfo)]) -> io::Result<()> {
+    // (country, is IPv6) -> prefixes, ordered by set name
+    let mut sets: BTreeMap<(String, bool), Vec<IpNet>> = BTreeMap::new();
+    for &(start, end, info) in ranges {
+        sets.entry((
+            info.country_code_str().to_ascii_lowercase(),
+            start.is_ipv6(),
+        ))
+        .or_default()
+        .extend(cidrs_covering(start, end));
+    }
+
+    writeln!(out, "table inet ip_alloc_lookup {{")?;
+    for ((country, v6), nets) in &sets {
+        let (family, addr_type) = if *v6 {
+            ("v6", "ipv6_addr")
+        } else {
+            ("v4", "ipv4_addr")
+        };
+        writeln!(out, "\tset {country}_{family} {{")?;
+        writeln!(out, "\t\ttype {addr_type}")?;
+        writeln!(out, "\t\tflags interval")?;
+        writeln!(out, "\t\telements = {{")?;
+        for net in nets {
+            writeln!(out, "\t\t\t{net},")?;
+        }
+        writeln!(out, "\t\t}}")?;
+        writeln!(out, "\t}}")?;
+    }
+    writeln!(out, "}}")
+}
+
+/// A minimal writer of the MaxMind DB format (version 2.0).
+///
+/// The search tree is an IPv6 tree with 32-bit records; IPv4 networks live
+/// under `::/96`, where readers look them up. There is one data record per
+/// country.
+mod mmdb {
+    use super::*;
+
+    const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+    // Data section type numbers
+    const UTF8_STRING: u8 = 2;
+    const UINT16: u8 = 5;
+    const UINT32: u8 = 6;
+    const MAP: u8 = 7;
+    const UINT64: u8 = 9;
+    const ARRAY: u8 = 11;
+    const BOOLEAN: u8 = 14;
+
+    #[derive(Clone, Copy)]
+    enum Record {
+        Empty,
+        Node(usize),
+        Data(usize),
+    }
+
+    pub(super) fn build(
+        ranges: &[(IpAddr, IpAddr, GeoInfo)],
+        build_epoch: SystemTime,
+    ) -> io::Result<Vec<u8>> {
+        // One data record per country, at its offset in the data section
+        let mut data = Vec::new();
+        let mut offsets = BTreeMap::new();
+        for &(_, _, info) in ranges {
+            offsets.entry(info.country_code).or_insert_with(|| {
+                let offset = data.len();
+                country_record(&mut data, &info);
+                offset
+            });
+        }
+
+        let mut nodes = vec![[Record::Empty; 2]];
+        for &(start, end, info) in ranges {
+            let record = Record::Data(offsets[&info.country_code]);
+            for net in cidrs_covering(start, end) {
+                let (bits, len) = match net.network() {
+                    IpAddr::V4(v4) => (u128::from(u32::from(v4)), 96 + net.prefix_len()),
+                    IpAddr::V6(v6) => (u128::from(v6), net.prefix_len()),
+                };
+                insert(&mut nodes, bits, len, record)?;
+            }
+        }
+
+        let node_count = nodes.len();
+        let value = |record: Record| match record {
+            Record::Empty => node_count,
+            Record::Node(idx) => idx,
+            Record::Data(offset) => node_count + 16 + offset,
+        };
+        if value(Record::Data(data.len())) > u32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "too many ranges for a MaxMind DB with 32-bit records",
+            ));
+        }
+
+        let mut out = Vec::with_capacity(node_count * 8 + 16 + data.len() + 256);
+        for [left, right] in &nodes {
+            out.extend_from_slice(&(value(*left) as u32).to_be_bytes());
+            out.extend_from_slice(&(value(*right) as u32).to_be_bytes());
+        }
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(&data);
+
+        out.extend_from_slice(METADATA_MARKER);
+        map(&mut out, 9);
+        string(&mut out, "binary_format_major_version");
+        uint(&mut out, UINT16, 2);
+        string(&mut out, "binary_format_minor_version");
+        uint(&mut out, UINT16, 0);
+        string(&mut out, "build_epoch");
+        let epoch = build_epoch
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        uint(&mut out, UINT64, epoch);
+        string(&mut out, "database_type");
+        string(&mut out, "ip-alloc-lookup-Country");
+        string(&mut out, "description");
+        map(&mut out, 1);
+        string(&mut out, "en");
+        string(
+            &mut out,
+            "Registry allocations by country, from ip-alloc-lookup",
+        );
+        string(&mut out, "ip_version");
+        uint(&mut out, UINT16, 6);
+        string(&mut out, "languages");
+        array(&mut out, 1);
+        string(&mut out, "en");
+        string(&mut out, "node_count");
+        uint(&mut out, UINT32, node_count as u64);
+        string(&mut out, "record_size");
+        uint(&mut out, UINT16, 32);
+        Ok(out)
+    }
+
+    /// Point the `len`-bit prefix `bits` at `record`, creating nodes on the way.
+    fn insert(nodes: &mut Vec<[Record; 2]>, bits: u128, len: u8, record: Record) -> io::Result<()> {
+        let overlap = || io::Error::new(io::ErrorKind::InvalidData, "overlapping ranges");
+        let bit = |depth: u8| usize::from(bits >> (127 - depth) & 1 == 1);
+
+        let mut node = 0;
+        for depth in 0..len - 1 {
+            node = match nodes[node][bit(depth)] {
+                Record::Node(next) => next,
+                Record::Empty => {
+                    nodes.push([Record::Empty; 2]);
+                    let next = nodes.len() - 1;
+                    nodes[node][bit(depth)] = Record::Node(next);
+                    next
+                }
+                Record::Data(_) => return Err(overlap()),
+            };
+        }
+        match &mut nodes[node][bit(len - 1)] {
+            slot @ Record::Empty => *slot = record,
+            _ => return Err(overlap()),
+        }
+        Ok(())
+    }
+
+    fn country_record(out: &mut Vec<u8>, info: &GeoInfo) {
+        map(out, 1);
+        string(out, "country");
+        map(out, 2);
+        string(out, "iso_code");
+        string(out, info.country_code_str());
+        string(out, "is_in_european_union");
+        control(out, BOOLEAN, usize::from(info.is_eu));
+    }
+
+    /// Write a control byte (with the extended type byte and size bytes it needs).
+    fn control(out: &mut Vec<u8>, type_num: u8, size: usize) {
+        let (kind, extended) = if type_num <= 7 {
+            (type_num, None)
+        } else {
+            (0, Some(type_num - 7))
+        };
+        let (size_bits, extra): (u8, &[u8]) = match size {
+            0..29 => (size as u8, &[]),
+            29..285 => (29, &(size - 29).to_be_bytes()[7..]),
+            285..65821 => (30, &(size - 285).to_be_bytes()[6..]),
+            _ => (31, &(size - 65821).to_be_bytes()[5..]),
+        };
+        out.push(kind << 5 | size_bits);
+        out.extend(extended);
+        out.extend_from_slice(extra);
+    }
+
+    fn string(out: &mut Vec<u8>, s: &str) {
+        control(out, UTF8_STRING, s.len());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    fn map(out: &mut Vec<u8>, pairs: usize) {
+        control(out, MAP, pairs);
+    }
+
+    fn array(out: &mut Vec<u8>, len: usize) {
+        control(out, ARRAY, len);
+    }
+
+    /// Unsigned integers are stored big-endian without leading zero bytes.
+    fn uint(out: &mut Vec<u8>, type_num: u8, value: u64) {
+        let bytes = value.to_be_bytes();
+        let skip = (value.leading_zeros() / 8) as usize;
+        control(out, type_num, bytes.len() - skip);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated
+ripencc|DE|ipv4|46.5.0.0|768|20090101|assigned
+ripencc|FR|ipv4|62.0.0.0|256|20090101|allocated
+ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated
+";
+
+    fn export(format: ExportFormat, countries: &[&str]) -> Vec<u8> {
+        let mut out = Vec::new();
+        GeoIpDb::from_ripe_delegated_str(SAMPLE)
+            .export(format, countries, &mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_text_formats() {
+        let csv = String::from_utf8(export(ExportFormat::Csv, &[])).unwrap();
+        assert_eq!(
+            csv,
+            "46.4.0.0,46.4.255.255,DE\n46.5.0.0,46.5.2.255,DE\n62.0.0.0,62.0.0.255,FR\n\
+             2a01:4f8::,2a01:4f8:ffff:ffff:ffff:ffff:ffff:ffff,DE\n"
+        );
+        let reread = GeoIpDb::from_csv(csv.as_bytes(), crate::CsvSchema::default()).unwrap();
+        assert_eq!(reread.country_str("62.0.0.1".parse().unwrap()), Some("FR"));
+
+        // Adjacent DE ranges are merged before splitting into prefixes
+        let map = String::from_utf8(export(ExportFormat::Haproxy, &["DE"])).unwrap();
+        assert_eq!(
+            map,
+            "46.4.0.0/16 DE\n46.5.0.0/23 DE\n46.5.2.0/24 DE\n2a01:4f8::/32 DE\n"
+        );
+
+        let nft = String::from_utf8(export(ExportFormat::Nftables, &["de", "fr"])).unwrap();
+        assert!(
+            nft.starts_with("table inet ip_alloc_lookup {\n\tset de_v4 {\n\t\ttype ipv4_addr\n")
+        );
+        assert!(nft.contains("\tset de_v6 {\n\t\ttype ipv6_addr\n\t\tflags interval\n\t\telements = {\n\t\t\t2a01:4f8::/32,\n"));
+        assert!(nft.contains("\tset fr_v4 {"));
+        assert!(nft.ends_with("\t}\n}\n"));
+
+        assert!(export(ExportFormat::Csv, &["US"]).is_empty());
+        assert_eq!(
+            "NFTables".parse::<ExportFormat>(),
+            Ok(ExportFormat::Nftables)
+        );
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_mmdb_readable_by_maxminddb() {
+        #[derive(serde::Deserialize)]
+        struct Country {
+            country: Inner,
+        }
+        #[derive(serde::Deserialize)]
+        struct Inner {
+            iso_code: String,
+            is_in_european_union: bool,
+        }
+
+        let reader = maxminddb::Reader::from_source(export(ExportFormat::Mmdb, &[])).unwrap();
+        assert_eq!(reader.metadata.ip_version, 6);
+        assert_eq!(reader.metadata.record_size, 32);
+
+        let country = |ip: &str| {
+            reader
+                .lookup::<Country>(ip.parse().unwrap())
+                .ok()
+                .map(|c| (c.country.iso_code, c.country.is_in_european_union))
+        };
+        assert_eq!(country("46.4.0.1"), Some(("DE".to_string(), true)));
+        assert_eq!(country("46.5.2.255"), Some(("DE".to_string(), true)));
+        assert_eq!(country("62.0.0.7"), Some(("FR".to_string(), true)));
+        assert_eq!(country("2a01:4f8:1::1"), Some(("DE".to_string(), true)));
+        assert_eq!(country("46.5.3.0"), None);
+        assert_eq!(country("8.8.8.8"), None);
+    }
+}
@@ -0,0 +1,322 @@
+//! Extension traits for one-off lookups directly on address types, and for
+//! classifying the addresses of an iterator.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use crate::{GeoInfo, GeoIpDb, LookupCursor, Region, embedded};
+
+/// Look up addresses in the embedded tables with method syntax.
+///
+/// Implemented for [`IpAddr`], [`Ipv4Addr`] and [`Ipv6Addr`], and for the
+/// socket address types, which look up their IP address (the IPv4 address for
+/// IPv4-mapped IPv6 sockets). Every call goes
+/// through the [`embedded`] module, so there is no database to construct or
+/// share; use a [`GeoIpDb`](crate::GeoIpDb) instead when the data is loaded or
+/// refreshed at runtime.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "embedded-data")] {
+/// use std::net::IpAddr;
+/// use ip_alloc_lookup::{IpGeoExt, Region};
+///
+/// let ip: IpAddr = "46.4.0.1".parse().unwrap();
+/// assert_eq!(ip.country(), Some("DE"));
+/// assert!(ip.is_eu());
+/// assert_eq!(ip.region(), Some(Region::EuropeanUnion));
+/// # }
+/// ```
+pub trait IpGeoExt {
+    /// Country code of the embedded range covering this address.
+    fn country(&self) -> Option<&'static str>;
+
+    /// Return `true` if the address is covered and classified as EU.
+    fn is_eu(&self) -> bool;
+
+    /// Region of the embedded range covering this address.
+    fn region(&self) -> Option<Region>;
+}
+
+impl IpGeoExt for IpAddr {
+    fn country(&self) -> Option<&'static str> {
+        embedded::country(*self)
+    }
+
+    fn is_eu(&self) -> bool {
+        embedded::is_eu(*self)
+    }
+
+    fn region(&self) -> Option<Region> {
+        embedded::lookup(*self).map(|info| info.region_enum())
+    }
+}
+
+impl IpGeoExt for Ipv4Addr {
+    fn country(&self) -> Option<&'static str> {
+        IpAddr::V4(*self).country()
+    }
+
+    fn is_eu(&self) -> bool {
+        IpAddr::V4(*self).is_eu()
+    }
+
+    fn region(&self) -> Option<Region> {
+        embedded::lookup_v4(*self).map(|info| info.region_enum())
+    }
+}
+
+impl IpGeoExt for Ipv6Addr {
+    fn country(&self) -> Option<&'static str> {
+        IpAddr::V6(*self).country()
+    }
+
+    fn is_eu(&self) -> bool {
+        IpAddr::V6(*self).is_eu()
+    }
+
+    fn region(&self) -> Option<Region> {
+        embedded::lookup_v6(*self).map(|info| info.region_enum())
+    }
+}
+
+impl IpGeoExt for SocketAddr {
+    fn country(&self) -> Option<&'static str> {
+        self.ip().to_canonical().country()
+    }
+
+    fn is_eu(&self) -> bool {
+        self.ip().to_canonical().is_eu()
+    }
+
+    fn region(&self) -> Option<Region> {
+        self.ip().to_canonical().region()
+    }
+}
+
+impl IpGeoExt for SocketAddrV4 {
+    fn country(&self) -> Option<&'static str> {
+        self.ip().country()
+    }
+
+    fn is_eu(&self) -> bool {
+        self.ip().is_eu()
+    }
+
+    fn region(&self) -> Option<Region> {
+        self.ip().region()
+    }
+}
+
+impl IpGeoExt for SocketAddrV6 {
+    fn country(&self) -> Option<&'static str> {
+        SocketAddr::V6(*self).country()
+    }
+
+    fn is_eu(&self) -> bool {
+        SocketAddr::V6(*self).is_eu()
+    }
+
+    fn region(&self) -> Option<Region> {
+        SocketAddr::V6(*self).region()
+    }
+}
+
+/// Items that carry an IP address, for [`ClassifyExt::classified`].
+///
+/// Implemented for the address and socket address types (sockets give the
+/// IPv4 address for IPv4-mapped IPv6 sockets) and for references to them.
+/// Implement it for your own records to classify them without mapping them
+/// to addresses first.
+pub trait HasIp {
+    /// The address to classify.
+    fn ip(&self) -> IpAddr;
+}
+
+impl HasIp for IpAddr {
+    fn ip(&self) -> IpAddr {
+        *self
+    }
+}
+
+impl HasIp for Ipv4Addr {
+    fn ip(&self) -> IpAddr {
+        IpAddr::V4(*self)
+    }
+}
+
+impl HasIp for Ipv6Addr {
+    fn ip(&self) -> IpAddr {
+        IpAddr::V6(*self)
+    }
+}
+
+impl HasIp for SocketAddr {
+    fn ip(&self) -> IpAddr {
+        SocketAddr::ip(self).to_canonical()
+    }
+}
+
+impl HasIp for SocketAddrV4 {
+    fn ip(&self) -> IpAddr {
+        IpAddr::V4(*SocketAddrV4::ip(self))
+    }
+}
+
+impl HasIp for SocketAddrV6 {
+    fn ip(&self) -> IpAddr {
+        IpAddr::V6(*SocketAddrV6::ip(self)).to_canonical()
+    }
+}
+
+impl<T: HasIp + ?Sized> HasIp for &T {
+    fn ip(&self) -> IpAddr {
+        (**self).ip()
+    }
+}
+
+/// Classify the items of any iterator lazily with method syntax.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "embedded-data")] {
+/// use std::net::IpAddr;
+/// use ip_alloc_lookup::{ClassifyExt, GeoIpDb};
+///
+/// let db = GeoIpDb::new();
+/// let ips: Vec<IpAddr> = vec!["46.4.0.1".parse().unwrap(), "10.0.0.1".parse().unwrap()];
+///
+/// let eu: Vec<&IpAddr> = ips
+///     .iter()
+///     .classified(&db)
+///     .filter(|(_, info)| info.is_some_and(|info| info.is_eu))
+///     .map(|(ip, _)| ip)
+///     .collect();
+/// assert_eq!(eu, [&ips[0]]);
+/// # }
+/// ```
+pub trait ClassifyExt: Iterator + Sized
+where
+    Self::Item: HasIp,
+{
+    /// Pair every item with the classification of its address.
+    ///
+    /// Lookups go through a [`LookupCursor`], so runs of addresses from the
+    /// same block, as in sorted or per-client input, are cheap.
+    fn classified(self, db: &GeoIpDb) -> Classified<'_, Self> {
+        Classified {
+            iter: self,
+            cursor: db.cursor(),
+        }
+    }
+}
+
+impl<I: Iterator> ClassifyExt for I where I::Item: HasIp {}
+
+/// Iterator returned by [`ClassifyExt::classified`].
+#[derive(Clone)]
+pub struct Classified<'a, I> {
+    iter: I,
+    cursor: LookupCursor<'a>,
+}
+
+impl<I: Iterator> Iterator for Classified<'_, I>
+where
+    I::Item: HasIp,
+{
+    type Item = (I::Item, Option<GeoInfo>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.iter.next()?;
+        let info = self.cursor.lookup(item.ip()).copied();
+        Some((item, info))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GeoIpDb;
+
+    #[test]
+    fn test_mapped_socket_uses_ipv4_address() {
+        let db = GeoIpDb::new();
+        let v4: SocketAddrV4 = "46.4.0.1:80".parse().unwrap();
+        let mapped = SocketAddrV6::new(v4.ip().to_ipv6_mapped(), 80, 0, 0);
+
+        assert_eq!(mapped.country(), v4.country());
+        assert_eq!(mapped.region(), v4.ip().region());
+        assert_eq!(db.lookup_socket(mapped), db.lookup_socket(v4));
+    }
+
+    #[test]
+    fn test_matches_database() {
+        let db = GeoIpDb::new();
+
+        for ip in ["46.4.0.1", "8.8.8.8", "0.0.0.0", "2a01:4f8::1", "::"] {
+            let ip: IpAddr = ip.parse().unwrap();
+            let info = db.lookup(ip);
+            assert_eq!(ip.is_eu(), db.is_eu(ip), "{ip}");
+            assert_eq!(ip.region(), info.map(|i| i.region_enum()), "{ip}");
+            assert_eq!(ip.country(), info.map(|i| i.country_code_str()), "{ip}");
+            match ip {
+                IpAddr::V4(v4) => assert_eq!(v4.region(), ip.region()),
+                IpAddr::V6(v6) => assert_eq!(v6.region(), ip.region()),
+            }
+
+            let socket = SocketAddr::new(ip, 443);
+            assert_eq!(socket.country(), ip.country(), "{ip}");
+            assert_eq!(db.is_eu_socket(socket), ip.is_eu(), "{ip}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-data")]
+    fn test_classified() {
+        struct Request {
+            peer: SocketAddr,
+            path: &'static str,
+        }
+        impl HasIp for Request {
+            fn ip(&self) -> IpAddr {
+                self.peer.ip()
+            }
+        }
+
+        let db = GeoIpDb::new();
+        let requests = [
+            Request {
+                peer: "46.4.0.1:443".parse().unwrap(),
+                path: "/a",
+            },
+            Request {
+                peer: "[::ffff:46.4.0.2]:443".parse().unwrap(),
+                path: "/b",
+            },
+            Request {
+                peer: "10.0.0.1:443".parse().unwrap(),
+                path: "/c",
+            },
+        ];
+
+        let classified: Vec<(&str, Option<[u8; 2]>)> = requests
+            .iter()
+            .classified(&db)
+            .map(|(request, info)| (request.path, info.map(|i| i.country_code)))
+            .collect();
+        assert_eq!(classified[0], ("/a", Some(*b"DE")));
+        assert_eq!(classified[2], ("/c", None));
+
+        let sockets: Vec<SocketAddr> = requests.iter().map(|r| r.peer).collect();
+        let infos: Vec<Option<GeoInfo>> = sockets
+            .into_iter()
+            .classified(&db)
+            .map(|(_, info)| info)
+            .collect();
+        assert_eq!(infos[1], infos[0]);
+        assert_eq!(infos.len(), 3);
+    }
+}
@@ -0,0 +1,256 @@
+//! Finding the client address behind reverse proxies.
+//!
+//! Behind a load balancer or CDN the peer address of a connection is the
+//! proxy's, and the client is only named in the `Forwarded` (RFC 7239) or
+//! `X-Forwarded-For` request header. Both list one hop per proxy, the client
+//! first; each proxy appends the address it received the request from. Any
+//! client can send these headers with made-up content, so only the hops added
+//! by proxies you operate can be believed: [`TrustedProxies::client_ip`]
+//! walks the list from the right and stops at the first address that is not
+//! one of your proxies.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "embedded-data")] {
+//! use ip_alloc_lookup::GeoIpDb;
+//! use ip_alloc_lookup::forwarded::{parse_x_forwarded_for, TrustedProxies};
+//!
+//! let proxies = TrustedProxies::new(["10.0.0.0/8".parse().unwrap()]);
+//! let peer = "10.0.0.2".parse().unwrap();
+//!
+//! // "198.51.100.7" was added by the client itself and is not believed
+//! let hops = parse_x_forwarded_for("198.51.100.7, 46.4.0.1, 10.0.0.1");
+//! assert_eq!(proxies.client_ip(peer, &hops), Some("46.4.0.1".parse().unwrap()));
+//!
+//! let db = GeoIpDb::new();
+//! let client = db.classify_client(&proxies, peer, &hops).unwrap();
+//! assert!(client.info.unwrap().is_eu);
+//! # }
+//! ```
+//!
+//! When a request carries several lines of the same header, join their values
+//! with `,` in the order received before parsing.
+
+use std::net::{IpAddr, SocketAddr};
+
+use crate::net::IpNet;
+use crate::{GeoInfo, GeoIpDb};
+
+/// Parse an `X-Forwarded-For` header value into its hops, client first.
+///
+/// Hops that are not an address (`unknown`, garbage) are [`None`]. Ports
+/// (`192.0.2.1:8080`, `[2001:db8::1]:443`) are accepted and dropped.
+pub fn parse_x_forwarded_for(value: &str) -> Vec<Option<IpAddr>> {
+    value.split(',').map(parse_node).collect()
+}
+
+/// Parse a `Forwarded` header value into the `for=` address of each element,
+/// client first.
+///
+/// Parameter names are case-insensitive and values may be quoted, as in
+/// `for="[2001:db8::1]:4711";proto=https`. Elements without a `for` parameter,
+/// or with an `unknown` or obfuscated (`_hidden`) node, are [`None`].
+pub fn parse_forwarded(value: &str) -> Vec<Option<IpAddr>> {
+    split_unquoted(value, ',')
+        .into_iter()
+        .map(|element| {
+            split_unquoted(element, ';').into_iter().find_map(|pair| {
+                let (name, value) = pair.split_once('=')?;
+                name.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| parse_node(unquote(value.trim())))
+            })?
+        })
+        .collect()
+}
+
+/// Parse one node: an address, optionally bracketed and with a port.
+pub(crate) fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    let ip = node
+        .parse::<IpAddr>()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| node.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()?;
+    Some(ip.to_canonical())
+}
+
+/// Split on `delimiter`, except inside double-quoted strings.
+fn split_unquoted(value: &str, delimiter: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut in_quotes, mut escaped) = (0, false, false);
+
+    for (idx, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => {
+                parts.push(&value[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// The address ranges of the proxies in front of the application.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    /// Trust the proxies in `nets`. A single proxy is a `/32` or `/128`.
+    pub fn new<I: IntoIterator<Item = IpNet>>(nets: I) -> Self {
+        TrustedProxies {
+            nets: nets.into_iter().collect(),
+        }
+    }
+
+    /// Return `true` if `ip` belongs to a trusted proxy.
+    ///
+    /// IPv4-mapped IPv6 addresses (`::ffff:10.0.0.1`) match IPv4 ranges.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.nets.iter().any(|net| net.contains(ip))
+    }
+
+    /// Select the client address for a request from `peer` with the given
+    /// forwarding `hops` (client first, as returned by the parsers).
+    ///
+    /// Starting at the peer, every address belonging to a trusted proxy passes
+    /// the decision to the hop it forwarded for; the first untrusted address
+    /// is the client. If every hop is trusted, the first one is returned.
+    ///
+    /// Returns [`None`] when the walk reaches a hop that is not an address:
+    /// a trusted proxy reported an unknown or obfuscated client, and nothing
+    /// further left can be believed.
+    pub fn client_ip(&self, peer: IpAddr, hops: &[Option<IpAddr>]) -> Option<IpAddr> {
+        let mut client = peer.to_canonical();
+        for hop in hops.iter().rev() {
+            if !self.contains(client) {
+                break;
+            }
+            client = (*hop)?;
+        }
+        Some(client)
+    }
+}
+
+/// The client selected by [`GeoIpDb::classify_client`] and its classification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardedClient {
+    /// The client address.
+    pub ip: IpAddr,
+    /// The range covering it, [`None`] if the address is not in the database.
+    pub info: Option<GeoInfo>,
+}
+
+impl GeoIpDb {
+    /// Select the client address with [`TrustedProxies::client_ip`] and look
+    /// it up.
+    ///
+    /// Returns [`None`] if no client address can be determined.
+    pub fn classify_client(
+        &self,
+        proxies: &TrustedProxies,
+        peer: IpAddr,
+        hops: &[Option<IpAddr>],
+    ) -> Option<ForwardedClient> {
+        let ip = proxies.client_ip(peer, hops)?;
+        Some(ForwardedClient {
+            ip,
+            info: self.lookup(ip).copied(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        assert_eq!(
+            parse_x_forwarded_for(" 192.0.2.1:8080,unknown, [2001:db8::1]:443 ,2001:db8::2"),
+            [
+                Some(ip("192.0.2.1")),
+                None,
+                Some(ip("2001:db8::1")),
+                Some(ip("2001:db8::2"))
+            ]
+        );
+        assert_eq!(
+            parse_forwarded(
+                "for=192.0.2.60;proto=http;by=203.0.113.43, For=\"[2001:db8:cafe::17]:4711\", \
+                 for=unknown, for=_hidden, proto=https, by=x;for=\"::ffff:198.51.100.1\""
+            ),
+            [
+                Some(ip("192.0.2.60")),
+                Some(ip("2001:db8:cafe::17")),
+                None,
+                None,
+                None,
+                Some(ip("198.51.100.1"))
+            ]
+        );
+        assert_eq!(
+            split_unquoted(r#"a="x,\"y";b, c"#, ','),
+            [r#"a="x,\"y";b"#, " c"]
+        );
+    }
+
+    #[test]
+    fn test_client_ip_stops_at_first_untrusted_hop() {
+        let proxies = TrustedProxies::new([
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:db8::/32".parse().unwrap(),
+        ]);
+        let hops = [
+            Some(ip("1.1.1.1")),
+            Some(ip("46.4.0.1")),
+            Some(ip("10.1.1.1")),
+        ];
+
+        // Direct connection from a client: headers are ignored
+        assert_eq!(
+            proxies.client_ip(ip("46.4.0.9"), &hops),
+            Some(ip("46.4.0.9"))
+        );
+        assert_eq!(
+            proxies.client_ip(ip("::ffff:10.0.0.1"), &hops),
+            Some(ip("46.4.0.1"))
+        );
+        assert_eq!(
+            proxies.client_ip(ip("2001:db8::1"), &[]),
+            Some(ip("2001:db8::1"))
+        );
+        // Only proxies on the path
+        assert_eq!(
+            proxies.client_ip(
+                ip("10.0.0.1"),
+                &[Some(ip("10.0.0.3")), Some(ip("10.0.0.2"))]
+            ),
+            Some(ip("10.0.0.3"))
+        );
+        // A trusted proxy could not tell who its client was
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), &[Some(ip("1.1.1.1")), None]),
+            None
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! The `GeoLookup` gRPC service, built on [tonic](https://docs.rs/tonic).
+//!
+//! A central instance holding a large dataset (all five RIRs, say) answers
+//! lookups for services in any language, which then do not embed the data
+//! themselves. The service is defined in `proto/ip_alloc_lookup.proto`,
+//! shipped with the crate, from which clients are generated:
+//!
+//! - `Lookup(LookupRequest) -> GeoReply` classifies one address.
+//! - `BulkLookup(stream LookupRequest) -> stream GeoReply` classifies a stream
+//!   of addresses, one reply per request, in order.
+//!
+//! [`GeoLookupServer`] implements the service for tonic's transport (or any
+//! other gRPC server accepting tower services). Addresses that do not parse
+//! fail the call with `INVALID_ARGUMENT`; addresses not in the database are
+//! answered with `found = false`.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "embedded-data")] {
+//! use std::sync::Arc;
+//! use ip_alloc_lookup::GeoIpDb;
+//! use ip_alloc_lookup::grpc::GeoLookupServer;
+//!
+//! let server = GeoLookupServer::new(Arc::new(GeoIpDb::new()));
+//! let reply = server.lookup("46.4.0.1").unwrap();
+//! assert!(reply.found);
+//! assert_eq!(reply.country, "DE");
+//! // `Server::builder().add_service(server).serve(addr)` with tonic's transport
+//! # }
+//! ```
+
+use std::convert::Infallible;
+use std::future::{Ready, ready};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tonic::body::Body;
+use tonic::codegen::tokio_stream::Stream;
+use tonic::codegen::{BoxFuture, Service, StdError, http};
+use tonic::server::{Grpc, NamedService};
+use tonic::{Request, Response, Status, Streaming};
+use tonic_prost::ProstCodec;
+
+use crate::GeoIpDb;
+use crate::forwarded::parse_node;
+
+/// Fully qualified name of the service.
+pub const SERVICE_NAME: &str = "ip_alloc_lookup.v1.GeoLookup";
+
+/// An address to classify.
+#[derive(Clone, PartialEq, Eq, Hash, prost::Message)]
+pub struct LookupRequest {
+    /// An IPv4 or IPv6 address, optionally bracketed and with a port.
+    #[prost(string, tag = "1")]
+    pub ip: String,
+}
+
+/// The classification of one address.
+#[derive(Clone, PartialEq, Eq, Hash, prost::Message)]
+pub struct GeoReply {
+    /// The address looked up; IPv4-mapped IPv6 addresses as their IPv4
+    /// address.
+    #[prost(string, tag = "1")]
+    pub ip: String,
+    /// `false` if the address is not in the database; the other fields are
+    /// then empty.
+    #[prost(bool, tag = "2")]
+    pub found: bool,
+    #[prost(string, tag = "3")]
+    pub country: String,
+    /// [`Region::as_str`](crate::Region::as_str) of the country.
+    #[prost(string, tag = "4")]
+    pub region: String,
+    #[prost(bool, tag = "5")]
+    pub is_eu: bool,
+    /// First address of the delegated block.
+    #[prost(string, tag = "6")]
+    pub range_start: String,
+    /// Last address of the delegated block.
+    #[prost(string, tag = "7")]
+    pub range_end: String,
+    /// The block as comma-separated CIDR prefixes.
+    #[prost(string, tag = "8")]
+    pub cidr: String,
+}
+
+/// The `GeoLookup` service over a shared [`GeoIpDb`].
+#[derive(Clone)]
+pub struct GeoLookupServer {
+    db: Arc<GeoIpDb>,
+}
+
+impl GeoLookupServer {
+    pub fn new(db: Arc<GeoIpDb>) -> Self {
+        GeoLookupServer { db }
+    }
+
+    /// Answer a `Lookup` call for `ip` without going through gRPC.
+    pub fn lookup(&self, ip: &str) -> Result<GeoReply, Status> {
+        reply(&self.db, ip)
+    }
+}
+
+fn reply(db: &GeoIpDb, ip: &str) -> Result<GeoReply, Status> {
+    let ip = parse_node(ip)
+        .ok_or_else(|| Status::invalid_argument(format!("invalid IP address: {ip:?}")))?;
+    Ok(match db.lookup_range(ip) {
+        Some(m) => GeoReply {
+            ip: ip.to_string(),
+            found: true,
+            country: m.info.country_code_str().to_string(),
+            region: m.info.region_enum().as_str().to_string(),
+            is_eu: m.info.is_eu,
+            range_start: m.start.to_string(),
+            range_end: m.end.to_string(),
+            cidr: m.cidr_string(),
+        },
+        None => GeoReply {
+            ip: ip.to_string(),
+            ..GeoReply::default()
+        },
+    })
+}
+
+impl NamedService for GeoLookupServer {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+impl<B> Service<http::Request<B>> for GeoLookupServer
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let db = Arc::clone(&self.db);
+        let method = request
+            .uri()
+            .path()
+            .strip_prefix('/')
+            .and_then(|path| path.strip_prefix(SERVICE_NAME))
+            .and_then(|path| path.strip_prefix('/'));
+        match method {
+            Some("Lookup") => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(Lookup(db), request).await)
+            }),
+            Some("BulkLookup") => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.streaming(BulkLookup(db), request).await)
+            }),
+            _ => Box::pin(async { Ok(Status::unimplemented("unknown method").into_http()) }),
+        }
+    }
+}
+
+/// The `Lookup` method.
+struct Lookup(Arc<GeoIpDb>);
+
+impl Service<Request<LookupRequest>> for Lookup {
+    type Response = Response<GeoReply>;
+    type Error = Status;
+    type Future = Ready<Result<Self::Response, Status>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Status>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<LookupRequest>) -> Self::Future {
+        ready(reply(&self.0, &request.get_ref().ip).map(Response::new))
+    }
+}
+
+/// The `BulkLookup` method.
+struct BulkLookup(Arc<GeoIpDb>);
+
+impl Service<Request<Streaming<LookupRequest>>> for BulkLookup {
+    type Response = Response<BulkReplies>;
+    type Error = Status;
+    type Future = Ready<Result<Self::Response, Status>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Status>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Streaming<LookupRequest>>) -> Self::Future {
+        ready(Ok(Response::new(BulkReplies {
+            db: Arc::clone(&self.0),
+            requests: Some(request.into_inner()),
+        })))
+    }
+}
+
+/// Replies to a `BulkLookup` request stream, ending after the first error.
+struct BulkReplies {
+    db: Arc<GeoIpDb>,
+    requests: Option<Streaming<LookupRequest>>,
+}
+
+impl Stream for BulkReplies {
+    type Item = Result<GeoReply, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(requests) = self.requests.as_mut() else {
+            return Poll::Ready(None);
+        };
+        let item = match Pin::new(requests).poll_next(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(None) => None,
+            Poll::Ready(Some(request)) => Some(request.and_then(|r| reply(&self.db, &r.ip))),
+        };
+        if !matches!(item, Some(Ok(_))) {
+            self.requests = None;
+        }
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+    use prost::Message;
+    use tonic::codegen::Bytes;
+
+    fn server() -> GeoLookupServer {
+        GeoLookupServer::new(Arc::new(GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+             ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        )))
+    }
+
+    /// Length-prefixed gRPC messages.
+    fn frames(ips: &[&str]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for ip in ips {
+            let message = LookupRequest { ip: ip.to_string() }.encode_to_vec();
+            body.push(0);
+            body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+            body.extend_from_slice(&message);
+        }
+        body
+    }
+
+    /// Call `method` with `ips` and return the replies and the `grpc-status`.
+    fn call(method: &str, ips: &[&str]) -> (Vec<GeoReply>, String) {
+        let request = http::Request::builder()
+            .method("POST")
+            .uri(format!("/{SERVICE_NAME}/{method}"))
+            .header("content-type", "application/grpc")
+            .header("te", "trailers")
+            .body(Full::new(Bytes::from(frames(ips))))
+            .unwrap();
+        let future = server().call(request);
+        let mut cx = Context::from_waker(std::task::Waker::noop());
+        let collected = std::pin::pin!(async move {
+            let response = future.await.unwrap();
+            let headers = response.headers().clone();
+            (headers, response.into_body().collect().await.unwrap())
+        });
+        let Poll::Ready((headers, collected)) = collected.poll(&mut cx) else {
+            panic!("the service does no I/O and never waits");
+        };
+        let status = collected
+            .trailers()
+            .or(Some(&headers))
+            .and_then(|t| t.get("grpc-status"))
+            .map(|s| s.to_str().unwrap().to_string())
+            .unwrap();
+
+        let mut body = collected.to_bytes();
+        let mut replies = Vec::new();
+        while !body.is_empty() {
+            let len = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+            replies.push(GeoReply::decode(&body[5..5 + len]).unwrap());
+            body = body.slice(5 + len..);
+        }
+        (replies, status)
+    }
+
+    #[test]
+    fn test_lookup() {
+        let (replies, status) = call("Lookup", &["[::ffff:46.4.0.1]:443"]);
+        assert_eq!(status, "0");
+        assert_eq!(
+            replies,
+            [GeoReply {
+                ip: "46.4.0.1".to_string(),
+                found: true,
+                country: "DE".to_string(),
+                region: "European Union".to_string(),
+                is_eu: true,
+                range_start: "46.4.0.0".to_string(),
+                range_end: "46.4.255.255".to_string(),
+                cidr: "46.4.0.0/16".to_string(),
+            }]
+        );
+
+        let (replies, status) = call("Lookup", &["not an ip"]);
+        assert!(replies.is_empty());
+        assert_eq!(status, (tonic::Code::InvalidArgument as i32).to_string());
+
+        let (_, status) = call("Delete", &["46.4.0.1"]);
+        assert_eq!(status, (tonic::Code::Unimplemented as i32).to_string());
+    }
+
+    #[test]
+    fn test_bulk_lookup() {
+        let (replies, status) = call("BulkLookup", &["2a01:4f8::1", "10.0.0.1", "46.4.0.1"]);
+        assert_eq!(status, "0");
+        let found: Vec<(&str, bool)> = replies.iter().map(|r| (&*r.ip, r.found)).collect();
+        assert_eq!(
+            found,
+            [
+                ("2a01:4f8::1", true),
+                ("10.0.0.1", false),
+                ("46.4.0.1", true)
+            ]
+        );
+        assert_eq!(replies[1].country, "");
+
+        let (replies, status) = call("BulkLookup", &["46.4.0.1", "bogus", "46.4.0.2"]);
+        assert_eq!(replies.len(), 1);
+        assert_eq!(status, (tonic::Code::InvalidArgument as i32).to_string());
+    }
+}
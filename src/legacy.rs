@@ -0,0 +1,104 @@
+//! Flagging RIPE NCC blocks in address space originally allocated elsewhere.
+//!
+//! Before the regional registries existed, class B and C networks were
+//! registered centrally. In the Early Registration Transfer (ERX) project the
+//! records of European holders were moved to the RIPE NCC, and inter-RIR
+//! transfers have moved more blocks since. The RIPE delegated file lists
+//! these blocks like any other, but they sit in /8s IANA gave to other
+//! registries, and their country is more often a leftover of an old
+//! registration. [`GeoIpDb::is_erx`] flags them so that consumers can give
+//! such classifications less weight.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::GeoIpDb;
+
+/// IPv4 /8s that IANA allocated to the RIPE NCC, or whose legacy
+/// registrations it administers (25/8, 51/8, 53/8, 57/8, 141/8, 145/8,
+/// 151/8 and 188/8), sorted.
+pub const RIPE_NCC_SLASH8S: &[u8] = &[
+    2, 5, 25, 31, 37, 46, 51, 53, 57, 62, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90,
+    91, 92, 93, 94, 95, 109, 141, 145, 151, 176, 178, 185, 188, 193, 194, 195, 212, 213, 217,
+];
+
+/// Return `true` if `ip` is in one of the [`RIPE_NCC_SLASH8S`].
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::is_ripe_ncc_space;
+///
+/// assert!(is_ripe_ncc_space("46.4.0.1".parse().unwrap()));
+/// assert!(!is_ripe_ncc_space("130.0.0.1".parse().unwrap()));
+/// ```
+pub fn is_ripe_ncc_space(ip: Ipv4Addr) -> bool {
+    RIPE_NCC_SLASH8S.binary_search(&ip.octets()[0]).is_ok()
+}
+
+impl GeoIpDb {
+    /// Return `true` if `ip` is covered by a block of RIPE NCC data that
+    /// lies outside the [`RIPE_NCC_SLASH8S`]: an early registration
+    /// transfer, or a block transferred from another registry.
+    ///
+    /// The check needs to know that the blocks come from the RIPE NCC, so it
+    /// is `false` for databases whose [`source_metadata`] does not name the
+    /// `ripencc` registry, including those built from several sources; use
+    /// [`is_ripe_ncc_space`] on addresses known to be in RIPE data there.
+    /// IPv6 space was never transferred this way, and IPv4-mapped addresses
+    /// are checked as IPv4.
+    ///
+    /// [`source_metadata`]: GeoIpDb::source_metadata
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "embedded-data")] {
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let db = GeoIpDb::new();
+    /// assert!(!db.is_erx("46.4.0.1".parse().unwrap()));
+    /// assert!(db.is_erx("130.0.0.1".parse().unwrap()));
+    /// # }
+    /// ```
+    pub fn is_erx(&self, ip: IpAddr) -> bool {
+        let IpAddr::V4(ip) = ip.to_canonical() else {
+            return false;
+        };
+        self.metadata
+            .as_ref()
+            .is_some_and(|m| m.registry == "ripencc")
+            && !is_ripe_ncc_space(ip)
+            && self.lookup_v4(ip).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slash8s_sorted() {
+        assert!(RIPE_NCC_SLASH8S.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_is_erx() {
+        let data = "\
+2|ripencc|1767049199|4|19700101|20251229|+0100
+ripencc|DE|ipv4|46.4.0.0|256|20090101|allocated
+ripencc|GB|ipv4|130.0.0.0|2048|20111013|allocated
+ripencc|NL|ipv4|192.16.0.0|256|19900101|assigned
+ripencc|DE|ipv6|2a01:4f8::|32|20050101|allocated
+";
+        let db = GeoIpDb::from_ripe_delegated_str(data);
+        let erx = |ip: &str| db.is_erx(ip.parse().unwrap());
+        assert!(!erx("46.4.0.1"));
+        assert!(erx("130.0.7.255"));
+        assert!(erx("::ffff:192.16.0.1"));
+        assert!(!erx("2a01:4f8::1"));
+        // Not covered at all
+        assert!(!erx("130.1.0.1"));
+
+        let arin = data.replace("ripencc", "arin");
+        let db = GeoIpDb::from_ripe_delegated_str(&arin);
+        assert!(!db.is_erx("130.0.0.1".parse().unwrap()));
+    }
+}
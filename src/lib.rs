@@ -36,100 +36,91 @@
 //!
 //! It reflects allocation data, not actual physical location.
 
+#[cfg(feature = "rkyv")]
+mod archive;
+mod asn;
+mod binary;
+mod bogon;
+mod cache;
+mod compare;
+#[cfg(feature = "roaring")]
+mod compiled;
+#[cfg(feature = "calling-codes")]
+mod calling_codes;
+#[cfg(feature = "centroids")]
+mod centroids;
+mod csv;
 mod database;
+mod diff;
+mod direct;
+mod download;
+pub mod enrich;
+mod export;
+pub mod embedded;
+mod ext;
+pub mod forwarded;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod legacy;
+#[cfg(any(feature = "tower", feature = "hyper"))]
+pub mod middleware;
+mod net;
+mod nir;
+#[cfg(feature = "tokio")]
+mod managed;
+mod parser;
+mod privacy;
+pub mod raw;
+#[cfg(feature = "serde")]
+pub mod serde_helpers;
+mod source;
+#[cfg(feature = "timezones")]
+mod timezones;
 
 // Re-export public API
-pub use database::{GeoIpDb, GeoInfo, DbStats};
+#[cfg(feature = "rkyv")]
+pub use archive::ArchivedGeoIpDb;
+pub use asn::{AsnDb, AsnInfo};
+pub use binary::IncompatibleFormat;
+pub use bogon::{is_special_purpose, Coverage};
+pub use cache::CacheInfo;
+pub use compare::Same;
+#[cfg(feature = "roaring")]
+pub use compiled::CompiledSet;
+pub use csv::CsvSchema;
+pub use database::{
+    country_regions, is_eu_country, region_members, region_of, region_of_country, CountryStats,
+    Classify, DataTransferClass, GeoIpDb, GeoInfo, DbStats, LookupCursor, ParseRegionError, RangeDb, RangeMatch, Region, RegionStats, EU_COUNTRIES,
+};
+pub use diff::{CountryDelta, DbDiff, DiffSummary, RangeChange};
+pub use direct::DirectIndexV4;
+pub use export::{ExportFormat, ParseExportFormatError};
+pub use ext::{ClassifyExt, Classified, HasIp, IpGeoExt};
+pub use legacy::{is_ripe_ncc_space, RIPE_NCC_SLASH8S};
+pub use net::{IpNet, ParseIpNetError};
+pub use nir::Nir;
+pub use privacy::ClassifiedBucket;
+pub use source::{ConflictPolicy, DataSource, FileSource};
+
+pub use download::{
+    cache_file_name, DownloadProgress, DownloadSink, Fetcher, AFRINIC_EXTENDED_LATEST_URL,
+    APNIC_EXTENDED_LATEST_URL, ARIN_EXTENDED_LATEST_URL, DELEGATED_URLS,
+    LACNIC_EXTENDED_LATEST_URL, RIPE_EXTENDED_LATEST_URL,
+};
+#[cfg(feature = "tokio")]
+pub use managed::{GeoIpDbHandle, ManagedConfig, ManagedGeoIpDb, ReloadInfo};
+#[cfg(feature = "download")]
+pub use download::{DownloadOptions, ProxyConfig, ReqwestFetcher};
 
 // We keep the parser public for users who want to work with raw RIPE data
-use std::net::{Ipv4Addr, Ipv6Addr};
-
-/// A single allocation block parsed from a RIPE delegated statistics file.
-///
-/// For IPv4 blocks, `start_v4` is `Some` and `start_v6` is `None`.
-/// For IPv6 blocks, `start_v6` is `Some` and `start_v4` is `None`.
-///
-/// `count` is the number of addresses in the block. For IPv6 lines, RIPE uses a
-/// prefix length in the “count” field; this parser converts that prefix length
-/// into an address count (`2^(128-prefix_len)`).
-#[derive(Debug, Clone, PartialEq)]
-pub struct IpRange {
-    pub start_v4: Option<Ipv4Addr>,
-    pub start_v6: Option<Ipv6Addr>,
-    pub count: u128,
-    pub country: String,
-}
-
-/// Parse RIPE NCC “delegated-*” statistics content into allocation ranges.
-///
-/// This parser is intentionally simple:
-/// - Ignores comment lines (`#...`) and summary/header lines starting with `2`.
-/// - Accepts only `ipv4` and `ipv6` records.
-/// - Keeps the two-letter country code exactly as present in the file.
-///
-/// For IPv4 records, `count` is the number of addresses.
-/// For IPv6 records, RIPE encodes the *prefix length* in the “count” field; this
-/// function converts it to an address count.
-///
-/// # Examples
-/// ```
-/// use offline_ripe_geoip::parse_ripe_delegated;
-///
-/// let data = "ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n";
-/// let ranges = parse_ripe_delegated(data);
-/// assert_eq!(ranges.len(), 1);
-/// assert_eq!(ranges[0].country, "DE");
-/// ```
-///
-/// # Notes
-/// This does not validate that the returned ranges are non-overlapping or sorted.
-pub fn parse_ripe_delegated(content: &str) -> Vec<IpRange> {
-    content
-        .lines()
-        .filter(|line| {
-            !line.starts_with('#')
-                && !line.starts_with('2')
-                && (line.contains("ipv4") || line.contains("ipv6"))
-        })
-        .filter_map(|line| {
-            let parts: Vec<&str> = line.split('|').collect();
-
-            if parts.len() < 7 {
-                return None;
-            }
-
-            let ip_type = parts[2];
-            let country = parts[1].to_string();
-
-            if ip_type == "ipv4" {
-                Some(IpRange {
-                    start_v4: parts[3].parse().ok(),
-                    start_v6: None,
-                    count: parts[4].parse::<u32>().ok()? as u128,
-                    country,
-                })
-            } else if ip_type == "ipv6" {
-                // For IPv6, the count field is actually the prefix length
-                let prefix_len: u32 = parts[4].parse().ok()?;
-                let host_bits = 128 - prefix_len;
-                let count = if host_bits >= 128 {
-                    u128::MAX
-                } else {
-                    1u128 << host_bits
-                };
-
-                Some(IpRange {
-                    start_v4: None,
-                    start_v6: parts[3].parse().ok(),
-                    count,
-                    country,
-                })
-            } else {
-                None
-            }
-        })
-        .collect()
-}
+pub use parser::{
+    parse_ripe_delegated, parse_ripe_delegated_bytes, parse_ripe_delegated_iter,
+    parse_ripe_delegated_reader, DelegatedFormat, IpRange, MalformedLine, MalformedReason, ParseDiagnostics,
+    ParseError, ParsedDelegated, RangeStart, RecordCounts, SourceMetadata, SummaryLine,
+    VerifyError,
+};
+#[cfg(feature = "parallel")]
+pub use parser::PARALLEL_MIN_BYTES;
 
 #[cfg(test)]
 mod tests {
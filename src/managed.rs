@@ -0,0 +1,619 @@
+//! A database that keeps itself up to date inside a tokio runtime.
+//!
+//! [`ManagedGeoIpDb::spawn`] loads the cache (or the embedded data), then runs
+//! a background task that refreshes the cache on a schedule. Downloading and
+//! parsing are blocking, so both run on the runtime's blocking pool and never
+//! stall the async workers. Each refresh builds a complete new database and
+//! swaps it in at once; [`GeoIpDbHandle`]s hand out the current one, and
+//! [`ReloadInfo`] is published on a watch channel for components that react to
+//! new data.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::{Mutex, watch};
+use tokio::task::JoinHandle;
+
+use crate::{CacheInfo, DbStats, Fetcher, GeoIpDb, SourceMetadata};
+
+/// Settings for a [`ManagedGeoIpDb`].
+///
+/// # Examples
+/// ```
+/// use std::io;
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use ip_alloc_lookup::ManagedConfig;
+///
+/// let fetcher = |_: &str| -> io::Result<Vec<u8>> {
+///     Ok(b"ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n".to_vec())
+/// };
+/// let config = ManagedConfig {
+///     refresh_interval: Duration::from_secs(6 * 60 * 60),
+///     ..ManagedConfig::with_fetcher("ripe-cache.txt", "mem://ripe", Arc::new(fetcher))
+/// };
+/// assert_eq!(config.retry_interval, Duration::from_secs(15 * 60));
+/// ```
+#[derive(Clone)]
+pub struct ManagedConfig {
+    /// Cache file to load at start-up and to refresh.
+    pub cache_path: PathBuf,
+    /// URL of the delegated file.
+    pub url: String,
+    /// Time between successful refreshes, 24 hours by default. The first
+    /// refresh is due when the cache is this old, according to its
+    /// [`CacheInfo`] sidecar; without a sidecar it happens right away.
+    pub refresh_interval: Duration,
+    /// Delay before retrying a failed refresh, 15 minutes by default.
+    pub retry_interval: Duration,
+    /// Fetcher used for the downloads. It is only called on the blocking pool.
+    pub fetcher: Arc<dyn Fetcher + Send + Sync>,
+}
+
+impl ManagedConfig {
+    /// Refresh `cache_path` from `url` with `fetcher`, using the default intervals.
+    pub fn with_fetcher<P: Into<PathBuf>>(
+        cache_path: P,
+        url: &str,
+        fetcher: Arc<dyn Fetcher + Send + Sync>,
+    ) -> Self {
+        ManagedConfig {
+            cache_path: cache_path.into(),
+            url: url.to_string(),
+            refresh_interval: Duration::from_secs(24 * 60 * 60),
+            retry_interval: Duration::from_secs(15 * 60),
+            fetcher,
+        }
+    }
+}
+
+#[cfg(feature = "download")]
+impl ManagedConfig {
+    /// Refresh `cache_path` from the RIPE "extended latest" file with
+    /// [`DownloadOptions::default`](crate::DownloadOptions::default).
+    ///
+    /// # Feature
+    /// Available only when the crate is built with the `download` feature.
+    pub fn new<P: Into<PathBuf>>(cache_path: P) -> Self {
+        Self::with_options(
+            cache_path,
+            crate::RIPE_EXTENDED_LATEST_URL,
+            crate::DownloadOptions::default(),
+        )
+    }
+
+    /// Refresh `cache_path` from `url` with a [`ReqwestFetcher`](crate::ReqwestFetcher)
+    /// configured by `options`.
+    ///
+    /// The HTTP client is created on the blocking pool when the first refresh
+    /// runs, since blocking clients cannot be built inside the runtime.
+    ///
+    /// # Feature
+    /// Available only when the crate is built with the `download` feature.
+    pub fn with_options<P: Into<PathBuf>>(
+        cache_path: P,
+        url: &str,
+        options: crate::DownloadOptions,
+    ) -> Self {
+        let fetcher = LazyReqwestFetcher {
+            options,
+            fetcher: std::sync::OnceLock::new(),
+        };
+        Self::with_fetcher(cache_path, url, Arc::new(fetcher))
+    }
+}
+
+/// A [`ReqwestFetcher`](crate::ReqwestFetcher) built on first use.
+#[cfg(feature = "download")]
+struct LazyReqwestFetcher {
+    options: crate::DownloadOptions,
+    fetcher: std::sync::OnceLock<crate::ReqwestFetcher>,
+}
+
+#[cfg(feature = "download")]
+impl LazyReqwestFetcher {
+    fn get(&self) -> io::Result<&crate::ReqwestFetcher> {
+        if let Some(fetcher) = self.fetcher.get() {
+            return Ok(fetcher);
+        }
+        let fetcher = crate::ReqwestFetcher::new(&self.options)?;
+        Ok(self.fetcher.get_or_init(|| fetcher))
+    }
+}
+
+#[cfg(feature = "download")]
+impl Fetcher for LazyReqwestFetcher {
+    fn fetch(&self, url: &str) -> io::Result<Vec<u8>> {
+        self.get()?.fetch(url)
+    }
+
+    fn fetch_to(&self, url: &str, sink: &mut crate::DownloadSink<'_>) -> io::Result<()> {
+        self.get()?.fetch_to(url, sink)
+    }
+}
+
+/// Summary of the database a [`ManagedGeoIpDb`] currently serves, published
+/// on the channel of [`ManagedGeoIpDb::subscribe`] every time it is replaced.
+///
+/// # Feature
+/// Available only when the crate is built with the `tokio` feature.
+#[derive(Debug, Clone)]
+pub struct ReloadInfo {
+    /// Number of databases swapped in so far; `0` for the one loaded at
+    /// start-up.
+    pub generation: u64,
+    /// When the database was swapped in.
+    pub loaded_at: SystemTime,
+    pub stats: DbStats,
+    /// Header of the source file, see [`GeoIpDb::source_metadata`].
+    pub metadata: Option<SourceMetadata>,
+}
+
+impl ReloadInfo {
+    fn new(db: &GeoIpDb, generation: u64) -> Self {
+        ReloadInfo {
+            generation,
+            loaded_at: SystemTime::now(),
+            stats: db.stats(),
+            metadata: db.source_metadata().cloned(),
+        }
+    }
+}
+
+/// State shared by the managed database, its handles and the refresh task.
+struct Shared {
+    cache_path: PathBuf,
+    /// Where refreshes download from; `None` when only watching the cache.
+    config: Option<ManagedConfig>,
+    current: RwLock<Arc<GeoIpDb>>,
+    /// Held for the duration of a refresh, so two never write the cache at once.
+    refreshing: Mutex<()>,
+    reloads: watch::Sender<ReloadInfo>,
+}
+
+impl Shared {
+    fn new(cache_path: PathBuf, config: Option<ManagedConfig>, db: GeoIpDb) -> Arc<Self> {
+        let (reloads, _) = watch::channel(ReloadInfo::new(&db, 0));
+        Arc::new(Shared {
+            cache_path,
+            config,
+            current: RwLock::new(Arc::new(db)),
+            refreshing: Mutex::new(()),
+            reloads,
+        })
+    }
+
+    fn load(&self) -> Arc<GeoIpDb> {
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Download the cache (unless only watching it), load it and swap it in.
+    async fn refresh(self: &Arc<Self>) -> io::Result<()> {
+        let _guard = self.refreshing.lock().await;
+        let shared = Arc::clone(self);
+        let db = run_blocking(move || {
+            if let Some(config) = &shared.config {
+                GeoIpDb::update_cache_with_fetcher(
+                    &shared.cache_path,
+                    &config.url,
+                    &*config.fetcher,
+                )?;
+            }
+            // Unlike at start-up, a missing or incomplete file keeps the
+            // current data rather than falling back to the embedded tables
+            GeoIpDb::from_cache_checked(&shared.cache_path)
+        })
+        .await?;
+
+        let generation = self.reloads.borrow().generation + 1;
+        let info = ReloadInfo::new(&db, generation);
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(db);
+        // Published after the swap, so subscribers that load the database see the new one
+        self.reloads.send_replace(info);
+        Ok(())
+    }
+
+    /// Refresh after `delay`, then every `refresh_interval`.
+    async fn run(
+        self: Arc<Self>,
+        mut delay: Duration,
+        refresh_interval: Duration,
+        retry_interval: Duration,
+    ) {
+        loop {
+            tokio::time::sleep(delay).await;
+            delay = match self.refresh().await {
+                Ok(()) => refresh_interval,
+                // Keep serving the current data and try again later
+                Err(_) => retry_interval,
+            };
+        }
+    }
+
+    /// Reload the cache whenever `watcher` reports a change to it. The watcher
+    /// is owned by the task, so stopping the task stops watching.
+    #[cfg(feature = "watch")]
+    async fn run_watch(
+        self: Arc<Self>,
+        _watcher: notify::RecommendedWatcher,
+        mut changes: tokio::sync::mpsc::UnboundedReceiver<()>,
+    ) {
+        while changes.recv().await.is_some() {
+            // Updaters write the text file, binary cache and sidecar one after
+            // another; reload once they have been quiet for a moment.
+            while let Ok(Some(())) = tokio::time::timeout(WATCH_SETTLE, changes.recv()).await {}
+            // A half-written file fails to load, keeping the current data,
+            // and is picked up by the next change
+            let _ = self.refresh().await;
+        }
+    }
+}
+
+/// Quiet period after a change to a watched cache before it is reloaded.
+#[cfg(feature = "watch")]
+const WATCH_SETTLE: Duration = Duration::from_millis(500);
+
+/// Run `f` on the blocking pool, reporting a panic as an error.
+async fn run_blocking<T, F>(f: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// A [`GeoIpDb`] refreshed in the background of a tokio runtime.
+///
+/// Dropping it stops the refreshes; handles keep the last database.
+///
+/// # Examples
+/// ```no_run
+/// use std::sync::Arc;
+/// use ip_alloc_lookup::{ManagedConfig, ManagedGeoIpDb, RIPE_EXTENDED_LATEST_URL};
+///
+/// # async fn run(fetcher: Arc<dyn ip_alloc_lookup::Fetcher + Send + Sync>) -> std::io::Result<()> {
+/// // With the `download` feature, `ManagedConfig::new("ripe-cache.txt")` uses reqwest
+/// let config = ManagedConfig::with_fetcher("ripe-cache.txt", RIPE_EXTENDED_LATEST_URL, fetcher);
+/// let managed = ManagedGeoIpDb::spawn(config).await?;
+/// let handle = managed.handle();
+///
+/// // In a request handler:
+/// let db = handle.load();
+/// let is_eu = db.is_eu("46.4.0.1".parse().unwrap());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Feature
+/// Available only when the crate is built with the `tokio` feature.
+pub struct ManagedGeoIpDb {
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+impl ManagedGeoIpDb {
+    /// Load the database with [`GeoIpDb::from_cache_or_embedded`] and start
+    /// refreshing it as described by `config`.
+    ///
+    /// Lookups are served from the loaded data while the first refresh runs.
+    ///
+    /// # Errors
+    /// Returns an error if loading panics.
+    ///
+    /// # Panics
+    /// Panics if called outside a tokio runtime.
+    pub async fn spawn(config: ManagedConfig) -> io::Result<Self> {
+        let (refresh_interval, retry_interval) = (config.refresh_interval, config.retry_interval);
+        let cache_path = config.cache_path.clone();
+        let (db, age) = run_blocking(move || {
+            let age = CacheInfo::read(&cache_path)
+                .ok()
+                .and_then(|info| info.age());
+            Ok((GeoIpDb::from_cache_or_embedded(&cache_path), age))
+        })
+        .await?;
+
+        let shared = Shared::new(config.cache_path.clone(), Some(config), db);
+        let first_refresh = age.map_or(Duration::ZERO, |age| refresh_interval.saturating_sub(age));
+        let task =
+            tokio::spawn(Arc::clone(&shared).run(first_refresh, refresh_interval, retry_interval));
+
+        Ok(ManagedGeoIpDb { shared, task })
+    }
+
+    /// Load `cache_path` with [`GeoIpDb::from_cache_or_embedded`] and reload it
+    /// whenever the file changes, without ever downloading.
+    ///
+    /// This leaves updating the cache to an external updater (a cron job, a
+    /// sidecar container, another process calling
+    /// [`GeoIpDb::update_cache_with_fetcher`]) and decouples the data refresh
+    /// from the application. The directory of `cache_path` is watched,
+    /// so a file replaced by renaming a new one over it is noticed, as is one
+    /// created after start-up. A reload happens once the files have been quiet
+    /// for half a second; [`ManagedGeoIpDb::refresh_now`] reloads immediately.
+    /// A file that is missing or incomplete at that point (it must match its
+    /// [`CacheInfo`] sidecar or, without one, its summary lines) is not
+    /// loaded: the current database stays in place until the next change.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use ip_alloc_lookup::ManagedGeoIpDb;
+    ///
+    /// # async fn run() -> std::io::Result<()> {
+    /// let managed = ManagedGeoIpDb::watch("/var/lib/geo/ripe-cache.txt").await?;
+    /// let handle = managed.handle();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    /// Returns an error if the directory cannot be watched or loading panics.
+    ///
+    /// # Panics
+    /// Panics if called outside a tokio runtime.
+    ///
+    /// # Feature
+    /// Available only when the crate is built with the `watch` feature.
+    #[cfg(feature = "watch")]
+    pub async fn watch<P: Into<PathBuf>>(cache_path: P) -> io::Result<Self> {
+        use notify::Watcher;
+
+        let cache_path = cache_path.into();
+        let file_name = cache_path
+            .file_name()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "cache path has no file name")
+            })?
+            .to_owned();
+        let dir = match cache_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let (tx, changes) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else { return };
+                if matches!(
+                    event.kind,
+                    notify::EventKind::Access(_) | notify::EventKind::Remove(_)
+                ) {
+                    return;
+                }
+                // The text file, and the binary cache and sidecar named after it
+                let touches_cache = event.paths.iter().any(|path| {
+                    path.file_name().is_some_and(|name| {
+                        name.as_encoded_bytes()
+                            .starts_with(file_name.as_encoded_bytes())
+                    })
+                });
+                if touches_cache {
+                    let _ = tx.send(());
+                }
+            })
+            .map_err(io::Error::other)?;
+        watcher
+            .watch(&dir, notify::RecursiveMode::NonRecursive)
+            .map_err(io::Error::other)?;
+
+        let path = cache_path.clone();
+        let db = run_blocking(move || Ok(GeoIpDb::from_cache_or_embedded(&path))).await?;
+        let shared = Shared::new(cache_path, None, db);
+        let task = tokio::spawn(Arc::clone(&shared).run_watch(watcher, changes));
+
+        Ok(ManagedGeoIpDb { shared, task })
+    }
+
+    /// A cheap, cloneable handle to the current database.
+    pub fn handle(&self) -> GeoIpDbHandle {
+        GeoIpDbHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// The current database.
+    pub fn load(&self) -> Arc<GeoIpDb> {
+        self.shared.load()
+    }
+
+    /// Subscribe to database reloads.
+    ///
+    /// The channel holds the [`ReloadInfo`] of the current database and is
+    /// updated whenever a refresh swaps in a new one, so metrics exporters and
+    /// cache invalidators can react with [`watch::Receiver::changed`].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # async fn run(managed: ip_alloc_lookup::ManagedGeoIpDb) {
+    /// let mut reloads = managed.subscribe();
+    /// tokio::spawn(async move {
+    ///     while reloads.changed().await.is_ok() {
+    ///         let info = reloads.borrow_and_update().clone();
+    ///         println!("reload #{}: {} IPv4 ranges", info.generation, info.stats.total_v4_ranges);
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> watch::Receiver<ReloadInfo> {
+        self.shared.reloads.subscribe()
+    }
+
+    /// Refresh the cache now instead of waiting for the schedule. A database
+    /// created with [`ManagedGeoIpDb::watch`] reloads the file instead.
+    ///
+    /// # Errors
+    /// Returns the fetcher's error, an error writing the cache, or an error
+    /// if the cache is missing, does not match its [`CacheInfo`] sidecar or,
+    /// without one, fails [`ParsedDelegated::verify`](crate::ParsedDelegated::verify).
+    /// The current database stays in place.
+    pub async fn refresh_now(&self) -> io::Result<()> {
+        self.shared.refresh().await
+    }
+}
+
+impl Drop for ManagedGeoIpDb {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Read access to the database of a [`ManagedGeoIpDb`].
+///
+/// Cloning a handle is cheap, so give one to every task or request handler.
+///
+/// # Feature
+/// Available only when the crate is built with the `tokio` feature.
+#[derive(Clone)]
+pub struct GeoIpDbHandle {
+    shared: Arc<Shared>,
+}
+
+impl GeoIpDbHandle {
+    /// The current database.
+    ///
+    /// The returned database stays valid, and unchanged, even if a refresh
+    /// replaces it in the meantime. Call this again to see newer data.
+    pub fn load(&self) -> Arc<GeoIpDb> {
+        self.shared.load()
+    }
+
+    /// Subscribe to database reloads; see [`ManagedGeoIpDb::subscribe`].
+    pub fn subscribe(&self) -> watch::Receiver<ReloadInfo> {
+        self.shared.reloads.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_refresh_swaps_database() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let fetcher = {
+            let calls = Arc::clone(&calls);
+            move |url: &str| -> io::Result<Vec<u8>> {
+                match calls.fetch_add(1, Ordering::SeqCst) {
+                    0 => Ok(b"ripencc|DE|ipv4|10.0.0.0|256|20250101|allocated\n".to_vec()),
+                    _ => Err(io::Error::new(io::ErrorKind::NotFound, url.to_string())),
+                }
+            }
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let config = ManagedConfig {
+            refresh_interval: Duration::from_secs(3600),
+            ..ManagedConfig::with_fetcher(
+                dir.path().join("ripe.txt"),
+                "mem://ripe",
+                Arc::new(fetcher),
+            )
+        };
+        let ip = "10.0.0.1".parse().unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let managed = ManagedGeoIpDb::spawn(config).await.unwrap();
+            let handle = managed.handle();
+            let before = handle.load();
+            let mut reloads = handle.subscribe();
+            assert_eq!(reloads.borrow_and_update().generation, 0);
+
+            // Without a sidecar the first refresh is due at once
+            while calls.load(Ordering::SeqCst) == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            // Waits for the background refresh to finish; this second fetch fails
+            let _ = managed.refresh_now().await;
+            assert!(handle.load().is_eu(ip));
+            assert_eq!(before.is_eu(ip), GeoIpDb::new().is_eu(ip));
+            assert!(reloads.has_changed().unwrap());
+            let info = reloads.borrow_and_update().clone();
+            assert_eq!(info.generation, 1);
+            assert_eq!(info.stats.total_v4_ranges, 1);
+
+            // A failed refresh keeps the current data
+            let err = managed.refresh_now().await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            assert!(managed.load().is_eu(ip));
+            assert!(!reloads.has_changed().unwrap());
+        });
+    }
+
+    /// A complete delegated file with one IPv4 record in `country`.
+    #[cfg(feature = "watch")]
+    fn watched_file(country: &str) -> String {
+        format!(
+            "ripencc|*|ipv4|*|1|summary\n\
+             ripencc|{country}|ipv4|10.0.0.0|256|20250101|allocated\n"
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "watch")]
+    fn test_watch_reloads_replaced_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ripe.txt");
+        std::fs::write(&path, watched_file("DE")).unwrap();
+        let ip = "10.0.0.1".parse().unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let managed = ManagedGeoIpDb::watch(&path).await.unwrap();
+            let mut reloads = managed.subscribe();
+            assert_eq!(managed.load().country_str(ip), Some("DE"));
+
+            // Replace the file the way updaters do: write elsewhere, rename over it
+            let tmp = dir.path().join("download.tmp");
+            std::fs::write(&tmp, watched_file("FR")).unwrap();
+            std::fs::rename(&tmp, &path).unwrap();
+
+            tokio::time::timeout(Duration::from_secs(10), reloads.changed())
+                .await
+                .expect("no reload after the cache was replaced")
+                .unwrap();
+            assert_eq!(managed.load().country_str(ip), Some("FR"));
+            assert_eq!(reloads.borrow().generation, 1);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "watch")]
+    fn test_watch_keeps_data_when_file_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ripe.txt");
+        std::fs::write(&path, watched_file("DE")).unwrap();
+        let ip = "10.0.0.1".parse().unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let managed = ManagedGeoIpDb::watch(&path).await.unwrap();
+            let reloads = managed.subscribe();
+
+            // Cut off before the record the summary announces
+            std::fs::write(&path, "ripencc|*|ipv4|*|1|summary\nripencc|FR|ipv4|10.0").unwrap();
+            tokio::time::sleep(WATCH_SETTLE * 4).await;
+            assert_eq!(managed.load().country_str(ip), Some("DE"));
+            assert!(!reloads.has_changed().unwrap());
+            assert!(managed.refresh_now().await.is_err());
+
+            std::fs::remove_file(&path).unwrap();
+            assert!(managed.refresh_now().await.is_err());
+            assert_eq!(managed.load().country_str(ip), Some("DE"));
+            assert_eq!(reloads.borrow().generation, 0);
+        });
+    }
+}
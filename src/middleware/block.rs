@@ -0,0 +1,427 @@
+//! [`CountryBlockLayer`]: blocking requests by country in tower services.
+
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::{HeaderName, HeaderValue};
+use http::{HeaderMap, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::classify;
+use crate::GeoIpDb;
+use crate::forwarded::{ForwardedClient, TrustedProxies};
+
+/// What a [`CountryBlockLayer`] does with requests whose client is not in
+/// the database, or whose client address cannot be determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissPolicy {
+    /// Pass the request on.
+    Allow,
+    /// Answer with the [`Rejection`].
+    Reject,
+}
+
+/// The response sent for blocked requests: `403 Forbidden` with an empty
+/// body by default.
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl Rejection {
+    /// A rejection with `status`, no headers and an empty body.
+    pub fn new(status: StatusCode) -> Self {
+        Rejection {
+            status,
+            headers: HeaderMap::new(),
+            body: String::new(),
+        }
+    }
+
+    /// Set the response body.
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Add a response header.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    fn response<B: From<String>>(&self) -> Response<B> {
+        let mut response = Response::new(B::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+impl Default for Rejection {
+    fn default() -> Self {
+        Rejection::new(StatusCode::FORBIDDEN)
+    }
+}
+
+type PeerAddrFn = dyn Fn(&http::Extensions) -> Option<IpAddr> + Send + Sync;
+
+/// A [`Layer`] that blocks requests by the country of their client.
+///
+/// Requests from countries the layer does not admit are answered with the
+/// [`Rejection`] without reaching the inner service. Admitted requests carry
+/// the classification as a [`ForwardedClient`] request extension. The peer
+/// address is taken from a [`SocketAddr`] request extension unless
+/// [`CountryBlockLayer::peer_addr`] says otherwise.
+///
+/// # Examples
+/// ```
+/// use std::sync::Arc;
+/// use http::StatusCode;
+/// use http::header::{CONTENT_TYPE, HeaderValue};
+/// use ip_alloc_lookup::GeoIpDb;
+/// use ip_alloc_lookup::middleware::{CountryBlockLayer, MissPolicy, Rejection};
+///
+/// let layer = CountryBlockLayer::deny(Arc::new(GeoIpDb::new()), &["RU", "BY"])
+///     .on_miss(MissPolicy::Allow)
+///     .rejection(
+///         Rejection::new(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS)
+///             .body("Not available in your region")
+///             .header(CONTENT_TYPE, HeaderValue::from_static("text/plain")),
+///     );
+/// // `ServiceBuilder::new().layer(layer).service(app)` with tower
+/// ```
+#[derive(Clone)]
+pub struct CountryBlockLayer {
+    config: Arc<Config>,
+}
+
+#[derive(Clone)]
+struct Config {
+    db: Arc<GeoIpDb>,
+    countries: Vec<[u8; 2]>,
+    /// `true` for an allow-list, `false` for a deny-list.
+    allow_list: bool,
+    on_miss: MissPolicy,
+    rejection: Rejection,
+    proxies: Option<TrustedProxies>,
+    peer_addr: Arc<PeerAddrFn>,
+}
+
+impl CountryBlockLayer {
+    /// Admit only requests from `countries`. Codes are matched ignoring
+    /// ASCII case.
+    ///
+    /// Misses are rejected unless [`CountryBlockLayer::on_miss`] says
+    /// otherwise.
+    ///
+    /// # Panics
+    /// Panics if a code is not two ASCII letters, naming the code.
+    pub fn allow(db: Arc<GeoIpDb>, countries: &[&str]) -> Self {
+        Self::new(db, countries, true, MissPolicy::Reject)
+    }
+
+    /// Reject requests from `countries` and admit all others.
+    ///
+    /// Misses are admitted unless [`CountryBlockLayer::on_miss`] says
+    /// otherwise.
+    ///
+    /// # Panics
+    /// Panics if a code is not two ASCII letters, naming the code, so that a
+    /// typo does not silently let the country through.
+    pub fn deny(db: Arc<GeoIpDb>, countries: &[&str]) -> Self {
+        Self::new(db, countries, false, MissPolicy::Allow)
+    }
+
+    fn new(db: Arc<GeoIpDb>, countries: &[&str], allow_list: bool, on_miss: MissPolicy) -> Self {
+        let countries = countries
+            .iter()
+            .map(|cc| match <[u8; 2]>::try_from(cc.as_bytes()) {
+                Ok(code) if code.iter().all(u8::is_ascii_alphabetic) => {
+                    code.map(|b| b.to_ascii_uppercase())
+                }
+                _ => panic!("invalid country code {cc:?}: expected two ASCII letters"),
+            })
+            .collect();
+        CountryBlockLayer {
+            config: Arc::new(Config {
+                db,
+                countries,
+                allow_list,
+                on_miss,
+                rejection: Rejection::default(),
+                proxies: None,
+                peer_addr: Arc::new(|extensions| {
+                    extensions.get::<SocketAddr>().map(SocketAddr::ip)
+                }),
+            }),
+        }
+    }
+
+    /// Set what happens to requests whose client is not in the database or
+    /// unknown.
+    pub fn on_miss(self, policy: MissPolicy) -> Self {
+        self.with(|config| config.on_miss = policy)
+    }
+
+    /// Set the response for blocked requests.
+    pub fn rejection(self, rejection: Rejection) -> Self {
+        self.with(|config| config.rejection = rejection)
+    }
+
+    /// Take the client from the forwarding headers when the peer is one of
+    /// `proxies`, see [`TrustedProxies::client_ip`].
+    pub fn trusted_proxies(self, proxies: TrustedProxies) -> Self {
+        self.with(|config| config.proxies = Some(proxies))
+    }
+
+    /// Find the peer address in the request extensions with `f` instead of
+    /// looking for a [`SocketAddr`].
+    pub fn peer_addr<F>(self, f: F) -> Self
+    where
+        F: Fn(&http::Extensions) -> Option<IpAddr> + Send + Sync + 'static,
+    {
+        self.with(|config| config.peer_addr = Arc::new(f))
+    }
+
+    fn with(mut self, update: impl FnOnce(&mut Config)) -> Self {
+        update(Arc::make_mut(&mut self.config));
+        self
+    }
+}
+
+impl<S> Layer<S> for CountryBlockLayer {
+    type Service = CountryBlock<S>;
+
+    fn layer(&self, inner: S) -> CountryBlock<S> {
+        CountryBlock {
+            inner,
+            config: Arc::clone(&self.config),
+        }
+    }
+}
+
+impl fmt::Debug for CountryBlockLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CountryBlockLayer")
+            .field("countries", &self.config.countries)
+            .field("allow_list", &self.config.allow_list)
+            .field("on_miss", &self.config.on_miss)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The service created by [`CountryBlockLayer`].
+#[derive(Clone)]
+pub struct CountryBlock<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl Config {
+    fn client<B>(&self, request: &Request<B>) -> Option<ForwardedClient> {
+        let peer = (self.peer_addr)(request.extensions())?;
+        classify(&self.db, self.proxies.as_ref(), peer, request)
+    }
+
+    fn admits(&self, client: Option<&ForwardedClient>) -> bool {
+        match client.and_then(|client| client.info) {
+            Some(info) => self.countries.contains(&info.country_code) == self.allow_list,
+            None => self.on_miss == MissPolicy::Allow,
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CountryBlock<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: From<String>,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let client = self.config.client(&request);
+        if !self.config.admits(client.as_ref()) {
+            return ResponseFuture::Rejected {
+                response: Some(self.config.rejection.response()),
+            };
+        }
+        if let Some(client) = client {
+            request.extensions_mut().insert(client);
+        }
+        ResponseFuture::Inner {
+            future: self.inner.call(request),
+        }
+    }
+}
+
+pin_project! {
+    /// Response future of [`CountryBlock`].
+    #[project = ResponseFutureProj]
+    pub enum ResponseFuture<F, B> {
+        Inner { #[pin] future: F },
+        Rejected { response: Option<Response<B>> },
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Inner { future } => future.poll(cx),
+            ResponseFutureProj::Rejected { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::{Ready, ready};
+    use std::task::Waker;
+
+    /// Answers with the country the middleware attached, or `-`.
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Response<String>;
+        type Error = ();
+        type Future = Ready<Result<Response<String>, ()>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let client = request.extensions().get::<ForwardedClient>();
+            let country = client
+                .and_then(|client| client.info)
+                .map_or("-".to_string(), |info| info.country_code_str().to_string());
+            ready(Ok(Response::new(country)))
+        }
+    }
+
+    fn db() -> Arc<GeoIpDb> {
+        Arc::new(GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+             ripencc|RU|ipv4|5.3.0.0|65536|20090101|allocated\n",
+        ))
+    }
+
+    fn send(
+        layer: &CountryBlockLayer,
+        peer: Option<&str>,
+        xff: Option<&str>,
+    ) -> (StatusCode, String) {
+        let mut request = Request::new(());
+        if let Some(peer) = peer {
+            request
+                .extensions_mut()
+                .insert(SocketAddr::new(peer.parse().unwrap(), 443));
+        }
+        if let Some(xff) = xff {
+            request
+                .headers_mut()
+                .insert("x-forwarded-for", xff.parse().unwrap());
+        }
+        let mut future = std::pin::pin!(layer.layer(Echo).call(request));
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(Ok(response)) => (response.status(), response.into_body()),
+            _ => panic!("response not ready"),
+        }
+    }
+
+    #[test]
+    fn test_deny_list() {
+        let layer = CountryBlockLayer::deny(db(), &["ru"]).rejection(
+            Rejection::new(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS)
+                .body("blocked")
+                .header(http::header::RETRY_AFTER, HeaderValue::from_static("3600")),
+        );
+        assert_eq!(
+            send(&layer, Some("46.4.0.1"), None),
+            (StatusCode::OK, "DE".to_string())
+        );
+        assert_eq!(
+            send(&layer, Some("5.3.0.1"), None),
+            (
+                StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                "blocked".to_string()
+            )
+        );
+        // Misses are let through by a deny-list
+        assert_eq!(
+            send(&layer, Some("192.0.2.1"), None),
+            (StatusCode::OK, "-".to_string())
+        );
+        assert_eq!(send(&layer, None, None).0, StatusCode::OK);
+
+        let layer = layer.on_miss(MissPolicy::Reject);
+        assert_eq!(
+            send(&layer, None, None).0,
+            StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS
+        );
+    }
+
+    #[test]
+    fn test_allow_list_behind_proxy() {
+        let layer = CountryBlockLayer::allow(db(), &["DE"])
+            .trusted_proxies(TrustedProxies::new(["10.0.0.0/8".parse().unwrap()]));
+        assert_eq!(send(&layer, Some("46.4.0.1"), None).0, StatusCode::OK);
+        assert_eq!(
+            send(&layer, Some("192.0.2.1"), None).0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(&layer, Some("10.0.0.1"), Some("5.3.0.1, 46.4.0.1")).1,
+            "DE"
+        );
+        assert_eq!(
+            send(&layer, Some("10.0.0.1"), Some("5.3.0.1")).0,
+            StatusCode::FORBIDDEN
+        );
+        // Untrusted peers cannot choose their country
+        assert_eq!(
+            send(&layer, Some("5.3.0.1"), Some("46.4.0.1")).0,
+            StatusCode::FORBIDDEN
+        );
+
+        let layer = layer.peer_addr(|_| "46.4.0.9".parse().ok());
+        assert_eq!(send(&layer, None, None).1, "DE");
+    }
+
+    #[test]
+    fn test_invalid_country_codes_are_rejected() {
+        for code in ["RUS", "de ", "", "1A"] {
+            let panic = std::panic::catch_unwind(|| CountryBlockLayer::deny(db(), &["FR", code]));
+            let message = *panic.err().unwrap().downcast::<String>().unwrap();
+            assert!(message.contains(&format!("{code:?}")), "{message}");
+        }
+        let layer = CountryBlockLayer::allow(db(), &["dE"]);
+        assert_eq!(send(&layer, Some("46.4.0.1"), None).0, StatusCode::OK);
+    }
+}
@@ -0,0 +1,77 @@
+//! Classifying and blocking requests in [tower](https://docs.rs/tower) and
+//! hyper services.
+//!
+//! - [`CountryBlockLayer`] (`tower` feature) wraps any tower service taking
+//!   [`http::Request`]s and answers requests from countries it does not admit
+//!   with a configurable [`Rejection`].
+//! - [`ClassifyService`] (`hyper` feature) wraps a plain
+//!   [`hyper::service::Service`], for servers and proxies driving hyper
+//!   connections directly, and only classifies.
+//!
+//! Requests that reach the inner service carry the classification of their
+//! client as a [`ForwardedClient`] request extension.
+//!
+//! The peer address is read from a [`SocketAddr`] request extension by
+//! default, which servers built on hyper insert per connection with a few
+//! lines; frameworks keep it elsewhere (axum: `ConnectInfo<SocketAddr>`), so
+//! [`CountryBlockLayer::peer_addr`] takes a function to find it.
+//! [`ClassifyService`] is created per connection and is given the peer
+//! address directly. Behind reverse proxies, both take [`TrustedProxies`] to
+//! select the client from the `Forwarded` and `X-Forwarded-For` headers.
+//!
+//! [`SocketAddr`]: std::net::SocketAddr
+
+use std::net::IpAddr;
+
+use http::Request;
+
+use crate::GeoIpDb;
+use crate::forwarded::{ForwardedClient, TrustedProxies, parse_forwarded, parse_x_forwarded_for};
+
+#[cfg(feature = "tower")]
+mod block;
+#[cfg(feature = "hyper")]
+mod service;
+
+#[cfg(feature = "tower")]
+pub use block::{CountryBlock, CountryBlockLayer, MissPolicy, Rejection, ResponseFuture};
+#[cfg(feature = "hyper")]
+pub use service::ClassifyService;
+
+/// Select and classify the client of `request`, received from `peer`.
+///
+/// Without `proxies` the client is the peer; otherwise it is selected with
+/// [`TrustedProxies::client_ip`] from the `Forwarded` header hops, or the
+/// `X-Forwarded-For` ones if the request has no `Forwarded` header.
+fn classify<B>(
+    db: &GeoIpDb,
+    proxies: Option<&TrustedProxies>,
+    peer: IpAddr,
+    request: &Request<B>,
+) -> Option<ForwardedClient> {
+    let ip = match proxies {
+        Some(proxies) => {
+            let headers = request.headers();
+            let mut hops = Vec::new();
+            for value in headers.get_all(http::header::FORWARDED) {
+                hops.extend(value.to_str().map(parse_forwarded).unwrap_or_default());
+            }
+            if hops.is_empty() {
+                for value in headers.get_all("x-forwarded-for") {
+                    hops.extend(
+                        value
+                            .to_str()
+                            .map(parse_x_forwarded_for)
+                            .unwrap_or_default(),
+                    );
+                }
+            }
+            proxies.client_ip(peer, &hops)?
+        }
+        None => peer.to_canonical(),
+    };
+    Some(ForwardedClient {
+        ip,
+        info: db.lookup(ip).copied(),
+    })
+}
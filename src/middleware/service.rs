@@ -0,0 +1,178 @@
+//! [`ClassifyService`]: classifying requests in plain hyper services.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use http::Request;
+use hyper::service::Service;
+
+use super::classify;
+use crate::GeoIpDb;
+use crate::forwarded::{ForwardedClient, TrustedProxies};
+
+/// A [`hyper::service::Service`] that attaches the classification of the
+/// client to each request as a [`ForwardedClient`] extension and passes it
+/// on to the inner service.
+///
+/// hyper serves each connection with its own service, so one is created per
+/// accepted connection with the peer address of that connection. Without
+/// [trusted proxies](ClassifyService::trusted_proxies) the client is the
+/// peer, and it is looked up once for the whole connection.
+///
+/// Requests whose client address cannot be determined are passed on without
+/// the extension.
+///
+/// # Examples
+/// ```
+/// use std::convert::Infallible;
+/// use std::sync::Arc;
+/// use http::{Request, Response};
+/// use hyper::service::service_fn;
+/// use ip_alloc_lookup::GeoIpDb;
+/// use ip_alloc_lookup::forwarded::ForwardedClient;
+/// use ip_alloc_lookup::middleware::ClassifyService;
+///
+/// let db = Arc::new(GeoIpDb::new());
+/// let peer = "46.4.0.1:50000".parse().unwrap();
+/// let service = ClassifyService::new(
+///     db,
+///     peer,
+///     service_fn(|request: Request<()>| async move {
+///         let country = request
+///             .extensions()
+///             .get::<ForwardedClient>()
+///             .and_then(|client| client.info)
+///             .map(|info| info.country_code_str().to_string());
+///         Ok::<_, Infallible>(Response::new(country.unwrap_or_default()))
+///     }),
+/// );
+/// // `http1::Builder::new().serve_connection(io, service)` for each accepted
+/// // connection
+/// ```
+#[derive(Clone)]
+pub struct ClassifyService<S> {
+    inner: S,
+    db: Arc<GeoIpDb>,
+    peer: IpAddr,
+    proxies: Option<Arc<TrustedProxies>>,
+    /// The classification of the peer, when it is the client.
+    direct: Option<ForwardedClient>,
+}
+
+impl<S> ClassifyService<S> {
+    /// Wrap `inner` for a connection from `peer`.
+    pub fn new(db: Arc<GeoIpDb>, peer: SocketAddr, inner: S) -> Self {
+        let peer = peer.ip();
+        let ip = peer.to_canonical();
+        let direct = Some(ForwardedClient {
+            ip,
+            info: db.lookup(ip).copied(),
+        });
+        ClassifyService {
+            inner,
+            db,
+            peer,
+            proxies: None,
+            direct,
+        }
+    }
+
+    /// Select the client from the `Forwarded` header, or the
+    /// `X-Forwarded-For` header if there is none, with
+    /// [`TrustedProxies::client_ip`].
+    ///
+    /// Shared between connections, hence the [`Arc`].
+    pub fn trusted_proxies(mut self, proxies: Arc<TrustedProxies>) -> Self {
+        self.proxies = Some(proxies);
+        self.direct = None;
+        self
+    }
+
+    /// The wrapped service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwrap the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for ClassifyService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn call(&self, mut request: Request<B>) -> Self::Future {
+        let client = match &self.proxies {
+            Some(proxies) => classify(&self.db, Some(proxies), self.peer, &request),
+            None => self.direct,
+        };
+        if let Some(client) = client {
+            request.extensions_mut().insert(client);
+        }
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::future::{Ready, ready};
+
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = Option<ForwardedClient>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Infallible>>;
+
+        fn call(&self, request: Request<()>) -> Self::Future {
+            ready(Ok(request.extensions().get::<ForwardedClient>().copied()))
+        }
+    }
+
+    fn db() -> Arc<GeoIpDb> {
+        Arc::new(GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+             ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated\n",
+        ))
+    }
+
+    fn call(service: &ClassifyService<Echo>, request: Request<()>) -> Option<ForwardedClient> {
+        service.call(request).into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_classify_service() {
+        let direct = ClassifyService::new(db(), "[::ffff:46.4.0.1]:1234".parse().unwrap(), Echo);
+        let client = call(&direct, Request::new(())).unwrap();
+        assert_eq!(client.ip.to_string(), "46.4.0.1");
+        assert_eq!(client.info.unwrap().country_code_str(), "DE");
+
+        let proxies = Arc::new(TrustedProxies::new(["10.0.0.0/8".parse().unwrap()]));
+        let proxied = ClassifyService::new(db(), "10.0.0.1:1234".parse().unwrap(), Echo)
+            .trusted_proxies(proxies);
+        let request = Request::builder()
+            .header("x-forwarded-for", "46.4.0.1, 2.0.0.1")
+            .body(())
+            .unwrap();
+        let client = call(&proxied, request).unwrap();
+        assert_eq!(client.info.unwrap().country_code_str(), "FR");
+
+        let request = Request::builder()
+            .header("forwarded", "for=unknown")
+            .body(())
+            .unwrap();
+        assert_eq!(call(&proxied, request), None);
+
+        let client = call(&proxied, Request::new(())).unwrap();
+        assert_eq!(client.ip.to_string(), "10.0.0.1");
+        assert_eq!(client.info, None);
+    }
+}
@@ -0,0 +1,240 @@
+//! IP prefixes (CIDR blocks).
+//!
+//! [`IpNet`] is a minimal IPv4/IPv6 prefix type used by the prefix-level
+//! queries of [`GeoIpDb`](crate::GeoIpDb). It parses and prints the usual
+//! `address/length` notation.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 prefix such as `46.4.0.0/20` or `2a01:4f8::/32`.
+///
+/// The stored address is always the network address: host bits given to
+/// [`IpNet::new`] or in a parsed string are cleared.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::IpNet;
+///
+/// let net: IpNet = "46.4.7.1/20".parse().unwrap();
+/// assert_eq!(net.to_string(), "46.4.0.0/20");
+/// assert_eq!(net.prefix_len(), 20);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    /// Build a prefix from an address and a prefix length, clearing host bits.
+    ///
+    /// Returns `None` if `prefix_len` exceeds 32 (IPv4) or 128 (IPv6).
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let addr = match addr {
+            IpAddr::V4(v4) if prefix_len <= 32 => {
+                IpAddr::V4(Ipv4Addr::from(u32::from(v4) & v4_mask(prefix_len)))
+            }
+            IpAddr::V6(v6) if prefix_len <= 128 => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(v6) & v6_mask(prefix_len)))
+            }
+            _ => return None,
+        };
+        Some(IpNet { addr, prefix_len })
+    }
+
+    /// The network (first) address of the prefix.
+    pub fn network(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The prefix length in bits.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// The last address covered by the prefix.
+    pub fn last(&self) -> IpAddr {
+        match self.addr {
+            IpAddr::V4(v4) => IpAddr::V4(Ipv4Addr::from(u32::from(v4) | !v4_mask(self.prefix_len))),
+            IpAddr::V6(v6) => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(v6) | !v6_mask(self.prefix_len)))
+            }
+        }
+    }
+
+    /// Return `true` if `ip` lies within the prefix.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip) & v4_mask(self.prefix_len) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip) & v6_mask(self.prefix_len) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The smallest list of prefixes covering `first..=last`, in address order.
+///
+/// Both addresses must be of the same family with `first <= last`; otherwise
+/// the result is empty.
+pub(crate) fn cidrs_covering(first: IpAddr, last: IpAddr) -> Vec<IpNet> {
+    match (first, last) {
+        (IpAddr::V4(f), IpAddr::V4(l)) if f <= l => {
+            cover(u32::from(f).into(), u32::from(l).into(), 32, |addr, len| {
+                IpNet::new(IpAddr::V4(Ipv4Addr::from(addr as u32)), len)
+            })
+        }
+        (IpAddr::V6(f), IpAddr::V6(l)) if f <= l => cover(f.into(), l.into(), 128, |addr, len| {
+            IpNet::new(IpAddr::V6(Ipv6Addr::from(addr)), len)
+        }),
+        _ => Vec::new(),
+    }
+}
+
+/// Greedily emit the largest aligned block starting at each position.
+fn cover(
+    mut cur: u128,
+    last: u128,
+    bits: u32,
+    net: impl Fn(u128, u8) -> Option<IpNet>,
+) -> Vec<IpNet> {
+    let mut nets = Vec::new();
+    loop {
+        let align = cur.trailing_zeros().min(bits);
+        let remaining = last - cur;
+        let fit = match remaining.checked_add(1) {
+            Some(size) => 127 - size.leading_zeros(),
+            None => 128,
+        };
+        let host_bits = align.min(fit);
+        nets.extend(net(cur, (bits - host_bits) as u8));
+
+        let block_last = cur + u128::MAX.checked_shr(128 - host_bits).unwrap_or(0);
+        match block_last.checked_add(1) {
+            Some(next) if next <= last => cur = next,
+            _ => break,
+        }
+    }
+    nets
+}
+
+fn v4_mask(prefix_len: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+fn v6_mask(prefix_len: u8) -> u128 {
+    u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Error returned when parsing an [`IpNet`] from a string fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseIpNetError(String);
+
+impl fmt::Display for ParseIpNetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid IP prefix: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseIpNetError {}
+
+impl FromStr for IpNet {
+    type Err = ParseIpNetError;
+
+    /// Parse `address/length`. A bare address is accepted as a host prefix
+    /// (`/32` or `/128`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseIpNetError(s.to_string());
+
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => {
+                let addr: IpAddr = addr.parse().map_err(|_| err())?;
+                (addr, len.parse().map_err(|_| err())?)
+            }
+            None => {
+                let addr: IpAddr = s.parse().map_err(|_| err())?;
+                (addr, if addr.is_ipv4() { 32 } else { 128 })
+            }
+        };
+
+        IpNet::new(addr, prefix_len).ok_or_else(err)
+    }
+}
+
+impl From<IpAddr> for IpNet {
+    /// The host prefix (`/32` or `/128`) of a single address.
+    fn from(addr: IpAddr) -> Self {
+        let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        IpNet { addr, prefix_len }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_bounds() {
+        let net: IpNet = "46.4.7.1/20".parse().unwrap();
+        assert_eq!(net.network(), "46.4.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!(net.last(), "46.4.15.255".parse::<IpAddr>().unwrap());
+        assert!(net.contains("46.4.15.1".parse().unwrap()));
+        assert!(!net.contains("46.4.16.0".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let all: IpNet = "::/0".parse().unwrap();
+        assert_eq!(all.last(), IpAddr::V6(Ipv6Addr::from(u128::MAX)));
+
+        let host: IpNet = "2a01:4f8::1".parse().unwrap();
+        assert_eq!(host.to_string(), "2a01:4f8::1/128");
+    }
+
+    #[test]
+    fn test_cidrs_covering() {
+        let cidrs = |first: &str, last: &str| -> Vec<String> {
+            cidrs_covering(first.parse().unwrap(), last.parse().unwrap())
+                .iter()
+                .map(IpNet::to_string)
+                .collect()
+        };
+
+        assert_eq!(cidrs("10.0.0.0", "10.0.0.255"), ["10.0.0.0/24"]);
+        assert_eq!(
+            cidrs("10.0.0.1", "10.0.0.6"),
+            ["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+        );
+        assert_eq!(cidrs("0.0.0.0", "255.255.255.255"), ["0.0.0.0/0"]);
+        assert_eq!(cidrs("::", "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff"), ["::/0"]);
+        assert_eq!(cidrs("2a01:4f8::", "2a01:4f8:ffff:ffff:ffff:ffff:ffff:ffff"), ["2a01:4f8::/32"]);
+        assert!(cidrs("10.0.0.1", "10.0.0.0").is_empty());
+        assert!(cidrs("10.0.0.1", "::1").is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for bad in [
+            "46.4.0.0/33",
+            "2a01::/129",
+            "46.4.0.0/",
+            "nope/8",
+            "46.4.0.0/-1",
+        ] {
+            assert!(bad.parse::<IpNet>().is_err(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! National Internet Registries (NIRs) below APNIC.
+//!
+//! APNIC hands parts of its space to NIRs, which delegate it within their
+//! country. The APNIC file lists such space as one large block per NIR
+//! allocation; the NIRs publish their own delegated files, in the same
+//! format, with the smaller blocks they delegated.
+//! [`GeoIpDb::from_delegated_with_nirs`] layers those over the RIR file.
+
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use crate::source::{ConflictPolicy, DataSource};
+use crate::{GeoIpDb, ParsedDelegated, RangeDb};
+
+/// A National Internet Registry, by the registry name in its delegated
+/// files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Nir {
+    /// China (`cnnic`).
+    Cnnic,
+    /// Indonesia (`idnic`).
+    Idnic,
+    /// India (`irinn`).
+    Irinn,
+    /// Japan (`jpnic`).
+    Jpnic,
+    /// South Korea (`krnic`).
+    Krnic,
+    /// Taiwan (`twnic`).
+    Twnic,
+    /// Vietnam (`vnnic`).
+    Vnnic,
+}
+
+impl Nir {
+    /// All NIRs, in the order of their registry names.
+    pub const ALL: [Nir; 7] = [
+        Nir::Cnnic,
+        Nir::Idnic,
+        Nir::Irinn,
+        Nir::Jpnic,
+        Nir::Krnic,
+        Nir::Twnic,
+        Nir::Vnnic,
+    ];
+
+    /// The NIR with registry name `registry`, case-insensitively.
+    pub fn from_registry(registry: &str) -> Option<Nir> {
+        Nir::ALL
+            .into_iter()
+            .find(|nir| nir.as_str().eq_ignore_ascii_case(registry))
+    }
+
+    /// The registry name, as in the delegated files (`jpnic`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Nir::Cnnic => "cnnic",
+            Nir::Idnic => "idnic",
+            Nir::Irinn => "irinn",
+            Nir::Jpnic => "jpnic",
+            Nir::Krnic => "krnic",
+            Nir::Twnic => "twnic",
+            Nir::Vnnic => "vnnic",
+        }
+    }
+}
+
+impl fmt::Display for Nir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl GeoIpDb {
+    /// Build a database from the delegated file of an RIR and those of the
+    /// NIRs below it, and record which NIR manages each range.
+    ///
+    /// The more specific record wins where they overlap
+    /// ([`ConflictPolicy::PreferMoreSpecific`]), and a NIR record wins over
+    /// an RIR record for the same block. The second table covers the ranges
+    /// taken from NIR files, with their NIR. The database keeps the source
+    /// metadata of the RIR file.
+    ///
+    /// # Errors
+    /// Returns an error of kind [`io::ErrorKind::InvalidInput`] if one of
+    /// `nirs` has no header line naming a known [`Nir`].
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::{GeoIpDb, Nir, ParsedDelegated};
+    ///
+    /// let apnic = ParsedDelegated::parse("\
+    /// 2|apnic|20250101|1|19830613|20250101|+1000
+    /// apnic|JP|ipv4|133.0.0.0|16777216|19970303|allocated
+    /// ");
+    /// let jpnic = ParsedDelegated::parse("\
+    /// 2|jpnic|20250101|1|19930101|20250101|+0900
+    /// jpnic|JP|ipv4|133.1.0.0|65536|19930101|assigned
+    /// ");
+    /// let (db, nirs) = GeoIpDb::from_delegated_with_nirs(&apnic, &[jpnic]).unwrap();
+    /// let ip = "133.1.2.3".parse().unwrap();
+    /// assert_eq!(db.lookup_range(ip).unwrap().cidr_string(), "133.1.0.0/16");
+    /// assert_eq!(nirs.lookup(ip), Some(&Nir::Jpnic));
+    /// assert_eq!(nirs.lookup("133.2.0.1".parse().unwrap()), None);
+    /// ```
+    pub fn from_delegated_with_nirs(
+        rir: &ParsedDelegated,
+        nirs: &[ParsedDelegated],
+    ) -> io::Result<(GeoIpDb, RangeDb<Nir>)> {
+        let names = nirs
+            .iter()
+            .map(|file| {
+                let registry = file.metadata().map(|meta| meta.registry.as_str());
+                registry.and_then(Nir::from_registry).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("not a NIR delegated file (registry {registry:?})"),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<Nir>>>()?;
+
+        // NIR files first, so that they win ties
+        let mut sources: Vec<&dyn DataSource> = nirs.iter().map(|f| f as &dyn DataSource).collect();
+        sources.push(rir);
+        let (mut db, origins) =
+            GeoIpDb::from_sources_with_origins(&sources, ConflictPolicy::PreferMoreSpecific)?;
+        db.metadata = rir.metadata().cloned();
+
+        let nir = |source: usize| names.get(source).copied();
+        let managed = RangeDb {
+            v4_ranges: Arc::new(
+                origins
+                    .v4_ranges
+                    .iter()
+                    .filter_map(|&(start, end, source)| Some((start, end, nir(source)?)))
+                    .collect(),
+            ),
+            v6_ranges: Arc::new(
+                origins
+                    .v6_ranges
+                    .iter()
+                    .filter_map(|&(start, end, source)| Some((start, end, nir(source)?)))
+                    .collect(),
+            ),
+            metadata: None,
+            unallocated: Arc::default(),
+        };
+        Ok((db, managed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_registry() {
+        assert_eq!(Nir::from_registry("KRNIC"), Some(Nir::Krnic));
+        assert_eq!(Nir::from_registry("apnic"), None);
+        assert!(Nir::ALL.windows(2).all(|w| w[0].as_str() < w[1].as_str()));
+    }
+
+    #[test]
+    fn test_from_delegated_with_nirs() {
+        let apnic = ParsedDelegated::parse(
+            "2|apnic|20250101|3|19830613|20250101|+1000\n\
+             apnic|KR|ipv4|1.208.0.0|524288|20100101|allocated\n\
+             apnic|AU|ipv4|1.0.0.0|256|20110811|assigned\n\
+             apnic|KR|ipv6|2001:220::|32|20000101|allocated\n",
+        );
+        let krnic = ParsedDelegated::parse(
+            "2|krnic|20250101|2|19990101|20250101|+0900\n\
+             krnic|KR|ipv4|1.208.0.0|65536|20100301|assigned\n\
+             krnic|KR|ipv6|2001:220::|32|20000101|allocated\n",
+        );
+        let (db, nirs) = GeoIpDb::from_delegated_with_nirs(&apnic, &[krnic]).unwrap();
+
+        let ip = |s: &str| s.parse().unwrap();
+        assert_eq!(db.source_metadata().unwrap().registry, "apnic");
+        assert_eq!(
+            db.lookup_range(ip("1.208.1.1")).unwrap().cidr_string(),
+            "1.208.0.0/16"
+        );
+        assert!(db.lookup(ip("1.208.1.1")).unwrap().assigned);
+        assert_eq!(nirs.lookup(ip("1.208.1.1")), Some(&Nir::Krnic));
+        // The rest of the APNIC block is not covered by the NIR file
+        assert_eq!(db.country_str(ip("1.209.0.1")), Some("KR"));
+        assert_eq!(nirs.lookup(ip("1.209.0.1")), None);
+        assert_eq!(nirs.lookup(ip("1.0.0.1")), None);
+        // The same block in both files is the NIR's
+        assert_eq!(nirs.lookup(ip("2001:220::1")), Some(&Nir::Krnic));
+
+        let err = GeoIpDb::from_delegated_with_nirs(&apnic, std::slice::from_ref(&apnic))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
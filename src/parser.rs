@@ -0,0 +1,1624 @@
+//! Parsing of RIPE NCC delegated statistics files.
+//!
+//! Delegated files are pipe-separated text with three kinds of lines:
+//!
+//! - a version/header line (`2|ripencc|<serial>|<records>|<start>|<end>|<utc offset>`)
+//! - summary lines (`ripencc|*|ipv4|*|<count>|summary`) announcing how many
+//!   records of each type follow
+//! - records (`ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated[|...]`)
+//!
+//! Records come in two layouts, see [`DelegatedFormat`]. The extended one adds
+//! an opaque id per record and lists the address space a registry has not
+//! delegated (`available` and `reserved` records, without a country); such
+//! records produce no range, but are kept apart in
+//! [`ParsedDelegated::unallocated`].
+//!
+//! [`parse_ripe_delegated`] returns just the IP ranges. [`ParsedDelegated`]
+//! additionally keeps the header and summary lines so callers can verify that a
+//! file is complete before trusting it, plus a [`ParseDiagnostics`] report of
+//! every line that was skipped.
+
+use std::fmt;
+use std::io::{self, BufRead};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::net::{IpNet, cidrs_covering};
+
+/// First address of an [`IpRange`], which also determines its address family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RangeStart {
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+}
+
+impl RangeStart {
+    /// The start address as an [`IpAddr`].
+    pub fn addr(self) -> IpAddr {
+        match self {
+            RangeStart::V4(v4) => IpAddr::V4(v4),
+            RangeStart::V6(v6) => IpAddr::V6(v6),
+        }
+    }
+}
+
+impl From<Ipv4Addr> for RangeStart {
+    fn from(addr: Ipv4Addr) -> Self {
+        RangeStart::V4(addr)
+    }
+}
+
+impl From<Ipv6Addr> for RangeStart {
+    fn from(addr: Ipv6Addr) -> Self {
+        RangeStart::V6(addr)
+    }
+}
+
+impl From<IpAddr> for RangeStart {
+    fn from(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(v4) => RangeStart::V4(v4),
+            IpAddr::V6(v6) => RangeStart::V6(v6),
+        }
+    }
+}
+
+/// A single allocation block parsed from a RIPE delegated statistics file.
+///
+/// `count` is the number of addresses in the block. For IPv6 lines, RIPE uses a
+/// prefix length in the “count” field; this parser converts that prefix length
+/// into an address count (`2^(128-prefix_len)`, saturating at `u128::MAX` for
+/// `::/0`).
+///
+/// `assigned` is `true` for records with status `assigned` (space handed to an
+/// end user, e.g. PI) and `false` for `allocated` records (an LIR's pool) and
+/// any other status.
+///
+/// More fields may be added in later versions, so ranges built in code start
+/// from [`IpRange::new`].
+///
+/// # Examples
+/// ```
+/// use std::net::Ipv4Addr;
+/// use ip_alloc_lookup::IpRange;
+///
+/// let mut range = IpRange::new(Ipv4Addr::new(46, 4, 0, 0), 768, "DE");
+/// range.date = "20250101".to_string();
+/// assert_eq!(range.end(), "46.4.2.255".parse::<std::net::IpAddr>().unwrap());
+/// assert!(range.contains("46.4.1.1".parse().unwrap()));
+///
+/// let cidrs: Vec<String> = range.to_cidrs().iter().map(|n| n.to_string()).collect();
+/// assert_eq!(cidrs, ["46.4.0.0/23", "46.4.2.0/24"]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct IpRange {
+    pub start: RangeStart,
+    pub count: u128,
+    pub country: String,
+    pub assigned: bool,
+    /// Allocation date as in the file (`YYYYMMDD`); empty when the registry
+    /// left it blank or the range did not come from a delegated file.
+    pub date: String,
+}
+
+impl IpRange {
+    /// A block of `count` addresses from `start` in `country`, `allocated`
+    /// and without a date.
+    pub fn new(start: impl Into<RangeStart>, count: u128, country: &str) -> Self {
+        IpRange {
+            start: start.into(),
+            count,
+            country: country.to_string(),
+            assigned: false,
+            date: String::new(),
+        }
+    }
+
+    /// [`date`](IpRange::date) as a calendar date, [`None`] if it is empty
+    /// or not a valid date.
+    ///
+    /// # Feature
+    /// Available only when the crate is built with the `time` feature.
+    #[cfg(feature = "time")]
+    pub fn parsed_date(&self) -> Option<time::Date> {
+        parse_date(&self.date)
+    }
+
+    /// Last address of the block (inclusive).
+    ///
+    /// Saturates at the end of the address space; an empty block (`count == 0`)
+    /// ends at its start.
+    pub fn end(&self) -> IpAddr {
+        let last = self.count.saturating_sub(1);
+        match self.start {
+            RangeStart::V4(v4) => {
+                let span = u32::try_from(last).unwrap_or(u32::MAX);
+                IpAddr::V4(u32::from(v4).saturating_add(span).into())
+            }
+            // A saturated count means "to the end of the address space" (`::/0`)
+            RangeStart::V6(_) if self.count == u128::MAX => IpAddr::V6(Ipv6Addr::from(u128::MAX)),
+            RangeStart::V6(v6) => IpAddr::V6(u128::from(v6).saturating_add(last).into()),
+        }
+    }
+
+    /// Return `true` if `ip` lies within the block.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        match (self.start, ip, self.end()) {
+            (RangeStart::V4(start), IpAddr::V4(ip), IpAddr::V4(end)) => start <= ip && ip <= end,
+            (RangeStart::V6(start), IpAddr::V6(ip), IpAddr::V6(end)) => start <= ip && ip <= end,
+            _ => false,
+        }
+    }
+
+    /// Number of addresses in the block (the `count` field).
+    pub fn len(&self) -> u128 {
+        self.count
+    }
+
+    /// Return `true` if the block covers no addresses.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Split the block into the smallest list of CIDR prefixes covering it.
+    ///
+    /// IPv6 blocks are always a single prefix; IPv4 blocks are counts that need
+    /// not be aligned powers of two and may need several.
+    pub fn to_cidrs(&self) -> Vec<IpNet> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        cidrs_covering(self.start.addr(), self.end())
+    }
+}
+
+/// A `summary` line from the header of a delegated file.
+///
+/// Each summary announces how many records of `record_type` (`ipv4`, `ipv6`
+/// or `asn`) the file contains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryLine {
+    pub registry: String,
+    pub record_type: String,
+    pub count: u64,
+}
+
+/// Identity of a delegated file, taken from its version/header line.
+///
+/// The header looks like `2|ripencc|1767049199|254328|19700101|20251229|+0100`:
+/// format version, registry, serial number, record count, the date range covered
+/// (`YYYYMMDD`) and the UTC offset of the dates. Fields a registry leaves empty
+/// are kept as empty strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMetadata {
+    pub version: String,
+    pub registry: String,
+    pub serial: u64,
+    pub records: u64,
+    pub start_date: String,
+    pub end_date: String,
+    pub utc_offset: String,
+    /// Lowercase hex SHA-256 of the source file, when known: always set for the
+    /// embedded snapshot and for databases loaded from a binary cache.
+    pub sha256: Option<String>,
+    /// Record layout of the file, when it has any records.
+    pub format: Option<DelegatedFormat>,
+}
+
+/// Record layout of a delegated file.
+///
+/// Both layouts share the version line, so the format is detected from the
+/// first record: an opaque id after the status field, or an `available` /
+/// `reserved` record, means the extended format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DelegatedFormat {
+    /// `registry|cc|type|start|value|date|status`, delegated space only.
+    Standard,
+    /// `registry|cc|type|start|value|date|status|opaque-id[|extensions]`, plus
+    /// `available` and `reserved` records (7 fields, no country or date) for
+    /// the space that is not delegated.
+    Extended,
+}
+
+impl DelegatedFormat {
+    /// Name as used in the RIR documentation and file names.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DelegatedFormat::Standard => "standard",
+            DelegatedFormat::Extended => "extended",
+        }
+    }
+
+    /// Inverse of [`DelegatedFormat::as_str`].
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(DelegatedFormat::Standard),
+            "extended" => Some(DelegatedFormat::Extended),
+            _ => None,
+        }
+    }
+
+    /// Format implied by the fields of a record line.
+    fn of_record(parts: &[&str]) -> Self {
+        if parts.len() >= 8 || is_unallocated_status(parts[6]) {
+            DelegatedFormat::Extended
+        } else {
+            DelegatedFormat::Standard
+        }
+    }
+
+    /// Detect the format from the first record among `lines`.
+    fn detect<'a>(lines: impl Iterator<Item = &'a str>) -> Option<Self> {
+        lines.map(normalize_line).find_map(|line| {
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let parts = split_fields(line);
+            (parts.len() >= 7 && !is_version_field(parts[0]) && !is_summary(&parts))
+                .then(|| Self::of_record(&parts))
+        })
+    }
+
+    /// Detect the format of raw content, skipping lines that are not UTF-8.
+    fn detect_bytes(content: &[u8]) -> Option<Self> {
+        Self::detect(byte_lines(content).filter_map(|line| std::str::from_utf8(line).ok()))
+    }
+}
+
+impl fmt::Display for DelegatedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl SourceMetadata {
+    /// Parse a version/header line. Returns `None` if `line` is not one.
+    pub(crate) fn from_header_line(line: &str) -> Option<Self> {
+        let parts = split_fields(normalize_line(line));
+
+        if parts.len() < 6 || !is_version_field(parts[0]) {
+            return None;
+        }
+
+        Some(SourceMetadata {
+            version: parts[0].to_string(),
+            registry: parts[1].to_string(),
+            serial: parts[2].parse().ok()?,
+            records: parts[3].parse().ok()?,
+            start_date: parts[4].to_string(),
+            end_date: parts[5].to_string(),
+            utc_offset: parts.get(6).copied().unwrap_or_default().to_string(),
+            sha256: None,
+            format: None,
+        })
+    }
+
+    /// Render back into a version/header line accepted by `from_header_line`.
+    pub(crate) fn to_header_line(&self) -> String {
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}",
+            self.version,
+            self.registry,
+            self.serial,
+            self.records,
+            self.start_date,
+            self.end_date,
+            self.utc_offset
+        )
+    }
+
+    /// [`start_date`](SourceMetadata::start_date) as a calendar date.
+    ///
+    /// # Feature
+    /// Available only when the crate is built with the `time` feature.
+    #[cfg(feature = "time")]
+    pub fn parsed_start_date(&self) -> Option<time::Date> {
+        parse_date(&self.start_date)
+    }
+
+    /// Midnight UTC at the start of [`end_date`](SourceMetadata::end_date),
+    /// the day the file reflects, or [`None`] if the date is missing or invalid.
+    pub fn published_at(&self) -> Option<SystemTime> {
+        let (year, month, day) = parse_ymd(&self.end_date)?;
+        let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+        UNIX_EPOCH.checked_add(Duration::from_secs(days * 86_400))
+    }
+
+    /// [`end_date`](SourceMetadata::end_date) as a calendar date.
+    ///
+    /// # Feature
+    /// Available only when the crate is built with the `time` feature.
+    #[cfg(feature = "time")]
+    pub fn parsed_end_date(&self) -> Option<time::Date> {
+        parse_date(&self.end_date)
+    }
+}
+
+/// Split a `YYYYMMDD` date field into year, month and day. Registries use
+/// `00000000` and empty fields for unknown dates; those and impossible dates
+/// give [`None`].
+fn parse_ymd(field: &str) -> Option<(i32, u8, u8)> {
+    if field.len() != 8 || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let year: i32 = field[..4].parse().ok()?;
+    let month: u8 = field[4..6].parse().ok()?;
+    let day: u8 = field[6..].parse().ok()?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    (1..=days_in_month)
+        .contains(&day)
+        .then_some((year, month, day))
+}
+
+/// Days from 1970-01-01 to the given proleptic Gregorian date.
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    // Count years from March so the leap day ends the year
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse a `YYYYMMDD` date field, see [`parse_ymd`].
+#[cfg(feature = "time")]
+fn parse_date(field: &str) -> Option<time::Date> {
+    let (year, month, day) = parse_ymd(field)?;
+    time::Date::from_calendar_date(year, time::Month::try_from(month).ok()?, day).ok()
+}
+
+/// Number of records seen per type while parsing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordCounts {
+    pub ipv4: u64,
+    pub ipv6: u64,
+    pub asn: u64,
+}
+
+impl RecordCounts {
+    /// Return the count for a delegated record type (`ipv4`, `ipv6`, `asn`).
+    ///
+    /// Unknown types return `None`.
+    pub fn get(&self, record_type: &str) -> Option<u64> {
+        match record_type {
+            "ipv4" => Some(self.ipv4),
+            "ipv6" => Some(self.ipv6),
+            "asn" => Some(self.asn),
+            _ => None,
+        }
+    }
+}
+
+/// Reason a parsed file failed [`ParsedDelegated::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The content has no summary lines, so completeness cannot be checked.
+    MissingSummary,
+    /// A summary line announced a different number of records than were parsed.
+    CountMismatch {
+        record_type: String,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::MissingSummary => {
+                write!(f, "delegated data has no summary lines to verify against")
+            }
+            VerifyError::CountMismatch {
+                record_type,
+                expected,
+                found,
+            } => write!(
+                f,
+                "summary announces {expected} {record_type} records but {found} were parsed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Maximum number of malformed lines kept as samples in [`ParseDiagnostics`].
+const MAX_MALFORMED_SAMPLES: usize = 10;
+
+/// Content size from which [`ParsedDelegated::parse`] parses on several threads.
+#[cfg(feature = "parallel")]
+pub const PARALLEL_MIN_BYTES: usize = 1 << 20;
+
+/// Why a record line could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedReason {
+    /// Fewer than the 7 pipe-separated fields every record has, or a delegated
+    /// record without the opaque id (8th field) of the extended format.
+    TooFewFields,
+    /// The start address is not a valid address of the record's type.
+    InvalidStart,
+    /// The count (IPv4) or prefix length (IPv6) field is not a valid number.
+    InvalidCount,
+    /// The line is not valid UTF-8 (only possible when parsing bytes).
+    InvalidEncoding,
+}
+
+impl fmt::Display for MalformedReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MalformedReason::TooFewFields => "too few fields",
+            MalformedReason::InvalidStart => "invalid start address",
+            MalformedReason::InvalidCount => "invalid count/prefix length",
+            MalformedReason::InvalidEncoding => "not valid UTF-8",
+        })
+    }
+}
+
+/// A record line [`parse_ripe_delegated_iter`] could not turn into a range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number within the parsed content.
+    pub line_number: usize,
+    pub line: String,
+    pub reason: MalformedReason,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} ({}): {}",
+            self.line_number, self.reason, self.line
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A malformed line kept as a sample in [`ParseDiagnostics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedLine {
+    /// 1-based line number within the parsed content.
+    pub line_number: usize,
+    pub line: String,
+    pub reason: MalformedReason,
+}
+
+/// Counts of the lines seen while parsing, broken down by how they were handled.
+///
+/// Every line falls into exactly one bucket, so the buckets sum to `total_lines`.
+/// Up to 10 malformed lines are kept in `malformed_samples` for inspection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseDiagnostics {
+    pub total_lines: u64,
+    /// `ipv4`/`ipv6` records turned into ranges.
+    pub records: u64,
+    pub blank: u64,
+    pub comments: u64,
+    pub headers: u64,
+    pub summaries: u64,
+    /// Well-formed records of other types (e.g. `asn`).
+    pub non_ip_records: u64,
+    /// `available` and `reserved` records of the extended format: space a
+    /// registry has not delegated, which therefore has no country.
+    pub unallocated: u64,
+    /// `ipv4`/`ipv6` records of an extended file without its opaque id, e.g.
+    /// from a standard file appended to it. They are parsed like the others
+    /// and included in `records`.
+    pub without_id: u64,
+    pub malformed: u64,
+    pub malformed_samples: Vec<MalformedLine>,
+}
+
+impl ParseDiagnostics {
+    /// Number of lines that did not produce a range.
+    pub fn skipped(&self) -> u64 {
+        self.total_lines - self.records
+    }
+
+    fn record_malformed(&mut self, line_number: usize, line: &str, reason: MalformedReason) {
+        self.malformed += 1;
+        if self.malformed_samples.len() < MAX_MALFORMED_SAMPLES {
+            self.malformed_samples.push(MalformedLine {
+                line_number,
+                line: line.to_string(),
+                reason,
+            });
+        }
+    }
+}
+
+impl fmt::Display for ParseDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} lines, {} ranges parsed, {} skipped:",
+            self.total_lines,
+            self.records,
+            self.skipped()
+        )?;
+        writeln!(f, "  blank:          {}", self.blank)?;
+        writeln!(f, "  comments:       {}", self.comments)?;
+        writeln!(f, "  headers:        {}", self.headers)?;
+        writeln!(f, "  summaries:      {}", self.summaries)?;
+        writeln!(f, "  non-ip records: {}", self.non_ip_records)?;
+        writeln!(f, "  unallocated:    {}", self.unallocated)?;
+        if self.without_id > 0 {
+            writeln!(f, "  parsed without the extended id: {}", self.without_id)?;
+        }
+        write!(f, "  malformed:      {}", self.malformed)?;
+        for sample in &self.malformed_samples {
+            write!(
+                f,
+                "\n    line {} ({}): {}",
+                sample.line_number, sample.reason, sample.line
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of parsing a delegated file, including its summary lines.
+///
+/// Use [`ParsedDelegated::verify`] to detect truncated or otherwise incomplete
+/// files: a download that was cut short still carries the original summary
+/// counts in its header, but yields fewer records.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedDelegated {
+    pub ranges: Vec<IpRange>,
+    unallocated: Vec<IpRange>,
+    format: Option<DelegatedFormat>,
+    metadata: Option<SourceMetadata>,
+    summaries: Vec<SummaryLine>,
+    record_counts: RecordCounts,
+    diagnostics: ParseDiagnostics,
+}
+
+impl ParsedDelegated {
+    /// Parse delegated statistics content, keeping summary lines and record counts.
+    ///
+    /// Ranges are produced exactly as by [`parse_ripe_delegated`].
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::ParsedDelegated;
+    ///
+    /// let data = "\
+    /// ripencc|*|ipv4|*|1|summary
+    /// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+    /// ";
+    /// let parsed = ParsedDelegated::parse(data);
+    /// assert_eq!(parsed.ranges.len(), 1);
+    /// assert!(parsed.verify().is_ok());
+    /// ```
+    ///
+    /// # Parallelism
+    /// With the `parallel` feature, content of 1 MiB (`PARALLEL_MIN_BYTES`) or
+    /// more is split into chunks at line boundaries and parsed on the `rayon`
+    /// thread pool. The chunk results are merged in file order, so the output is
+    /// identical to a sequential parse.
+    pub fn parse(content: &str) -> Self {
+        Self::parse_bytes(content.as_bytes())
+    }
+
+    /// Parse delegated statistics content from raw bytes, e.g. as returned by
+    /// [`std::fs::read`].
+    ///
+    /// The format is plain ASCII, so there is no need to validate the whole file
+    /// as UTF-8 up front: each line is checked on its own while it is parsed, and
+    /// a line that is not valid UTF-8 is reported as malformed
+    /// ([`MalformedReason::InvalidEncoding`]). Otherwise the result is the same as
+    /// [`ParsedDelegated::parse`].
+    pub fn parse_bytes(content: &[u8]) -> Self {
+        let format = DelegatedFormat::detect_bytes(content);
+
+        #[cfg(feature = "parallel")]
+        if content.len() >= PARALLEL_MIN_BYTES {
+            return Self::parse_chunked(content, format, rayon::current_num_threads() * 4);
+        }
+
+        Self::parse_sequential(content, format)
+    }
+
+    /// Parse `content` in at most `chunks` pieces on the `rayon` thread pool.
+    #[cfg(feature = "parallel")]
+    fn parse_chunked(content: &[u8], format: Option<DelegatedFormat>, chunks: usize) -> Self {
+        use rayon::prelude::*;
+
+        split_at_lines(content, chunks)
+            .par_iter()
+            .map(|chunk| Self::parse_sequential(chunk, format))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .fold(Self::default(), |mut merged, part| {
+                merged.append(part);
+                merged
+            })
+    }
+
+    /// Append the result of parsing the content that directly follows `self`'s.
+    #[cfg(feature = "parallel")]
+    fn append(&mut self, mut other: Self) {
+        let line_offset = self.diagnostics.total_lines as usize;
+
+        self.ranges.append(&mut other.ranges);
+        self.unallocated.append(&mut other.unallocated);
+        self.format = self.format.or(other.format);
+        if self.metadata.is_none() {
+            self.metadata = other.metadata;
+        }
+        self.summaries.append(&mut other.summaries);
+        self.record_counts.ipv4 += other.record_counts.ipv4;
+        self.record_counts.ipv6 += other.record_counts.ipv6;
+        self.record_counts.asn += other.record_counts.asn;
+
+        let (diag, theirs) = (&mut self.diagnostics, other.diagnostics);
+        diag.total_lines += theirs.total_lines;
+        diag.records += theirs.records;
+        diag.blank += theirs.blank;
+        diag.comments += theirs.comments;
+        diag.headers += theirs.headers;
+        diag.summaries += theirs.summaries;
+        diag.non_ip_records += theirs.non_ip_records;
+        diag.unallocated += theirs.unallocated;
+        diag.without_id += theirs.without_id;
+        diag.malformed += theirs.malformed;
+        let room = MAX_MALFORMED_SAMPLES.saturating_sub(diag.malformed_samples.len());
+        diag.malformed_samples
+            .extend(
+                theirs
+                    .malformed_samples
+                    .into_iter()
+                    .take(room)
+                    .map(|mut sample| {
+                        sample.line_number += line_offset;
+                        sample
+                    }),
+            );
+    }
+
+    fn parse_sequential(content: &[u8], format: Option<DelegatedFormat>) -> Self {
+        let mut parsed = ParsedDelegated {
+            format,
+            ..ParsedDelegated::default()
+        };
+        let diag = &mut parsed.diagnostics;
+
+        for (idx, bytes) in byte_lines(content).enumerate() {
+            diag.total_lines += 1;
+
+            let Ok(line) = std::str::from_utf8(bytes) else {
+                let line = String::from_utf8_lossy(bytes);
+                diag.record_malformed(idx + 1, &line, MalformedReason::InvalidEncoding);
+                continue;
+            };
+
+            match classify_line(line, format) {
+                Line::Blank => diag.blank += 1,
+                Line::Comment => diag.comments += 1,
+                Line::Header => {
+                    diag.headers += 1;
+                    if parsed.metadata.is_none() {
+                        parsed.metadata = SourceMetadata::from_header_line(line)
+                            .map(|meta| SourceMetadata { format, ..meta });
+                    }
+                }
+                Line::Summary(summary) => {
+                    diag.summaries += 1;
+                    parsed.summaries.extend(summary);
+                }
+                Line::Record {
+                    record_type,
+                    range: Some(range),
+                    without_id,
+                } => {
+                    if without_id {
+                        diag.without_id += 1;
+                    }
+                    if record_type == "ipv4" {
+                        parsed.record_counts.ipv4 += 1;
+                    } else {
+                        parsed.record_counts.ipv6 += 1;
+                    }
+                    diag.records += 1;
+                    parsed.ranges.push(range);
+                }
+                Line::Record {
+                    record_type,
+                    range: None,
+                    ..
+                } => {
+                    if record_type == "asn" {
+                        parsed.record_counts.asn += 1;
+                    }
+                    diag.non_ip_records += 1;
+                }
+                Line::Unallocated { record_type, range } => {
+                    // Summary lines count these records too
+                    match record_type {
+                        "ipv4" => parsed.record_counts.ipv4 += 1,
+                        "ipv6" => parsed.record_counts.ipv6 += 1,
+                        "asn" => parsed.record_counts.asn += 1,
+                        _ => {}
+                    }
+                    diag.unallocated += 1;
+                    parsed.unallocated.extend(range);
+                }
+                Line::Malformed(reason) => diag.record_malformed(idx + 1, line, reason),
+            }
+        }
+
+        parsed
+    }
+
+    /// Record layout of the content, or `None` if it has no records.
+    pub fn format(&self) -> Option<DelegatedFormat> {
+        self.format
+    }
+
+    /// `available` and `reserved` records of the extended format: space the
+    /// registry manages but has not delegated, in file order. Their
+    /// [`country`](IpRange::country) is empty.
+    pub fn unallocated(&self) -> &[IpRange] {
+        &self.unallocated
+    }
+
+    /// Version/header information, if the content started with a header line.
+    pub fn metadata(&self) -> Option<&SourceMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Summary lines found in the file header, in file order.
+    pub fn summaries(&self) -> &[SummaryLine] {
+        &self.summaries
+    }
+
+    /// Report of which lines were skipped during parsing, and why.
+    pub fn diagnostics(&self) -> &ParseDiagnostics {
+        &self.diagnostics
+    }
+
+    /// Number of records actually parsed per type.
+    pub fn record_counts(&self) -> RecordCounts {
+        self.record_counts
+    }
+
+    /// Check that every summary line matches the number of records parsed.
+    ///
+    /// # Errors
+    /// Returns [`VerifyError::MissingSummary`] if the content had no summary lines,
+    /// or [`VerifyError::CountMismatch`] for the first summary whose announced
+    /// count differs from the parsed record count.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        if self.summaries.is_empty() {
+            return Err(VerifyError::MissingSummary);
+        }
+
+        for summary in &self.summaries {
+            // Summary types we don't track (none today) can't be verified.
+            let Some(found) = self.record_counts.get(&summary.record_type) else {
+                continue;
+            };
+            if found != summary.count {
+                return Err(VerifyError::CountMismatch {
+                    record_type: summary.record_type.clone(),
+                    expected: summary.count,
+                    found,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse RIPE NCC “delegated-*” statistics content into allocation ranges.
+///
+/// This parser is intentionally simple:
+/// - Ignores comment lines (`#...`), the version/header line and `summary` lines.
+/// - Accepts only `ipv4` and `ipv6` records.
+/// - Keeps the two-letter country code exactly as present in the file.
+///
+/// For IPv4 records, `count` is the number of addresses.
+/// For IPv6 records, RIPE encodes the *prefix length* in the “count” field; this
+/// function converts it to an address count.
+///
+/// # Leniency
+///
+/// The parser never fails. Each line is split into its `|`-separated fields
+/// first and classified by field contents, never by line prefixes or substrings:
+///
+/// - the header is recognised by a numeric version in the first field,
+/// - summaries by `*` in the country field and `summary` in the sixth field,
+/// - records by the type in the third field (`ipv4`, `ipv6`, `asn`, ...).
+///
+/// The registry field is not checked, so the delegated files of all five RIRs
+/// (AFRINIC, APNIC, ARIN, LACNIC, RIPE NCC) are parsed the same way. The
+/// [`DelegatedFormat`] is detected from the first record; in the extended
+/// format, `available` and `reserved` records are skipped since that space
+/// belongs to no country, and records missing the opaque id are malformed.
+/// Records of other types and malformed lines are skipped; use
+/// [`ParsedDelegated::diagnostics`] to see what was dropped.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::parse_ripe_delegated;
+///
+/// let data = "ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n";
+/// let ranges = parse_ripe_delegated(data);
+/// assert_eq!(ranges.len(), 1);
+/// assert_eq!(ranges[0].country, "DE");
+/// ```
+///
+/// # Notes
+/// This does not validate that the returned ranges are non-overlapping or sorted.
+/// Use [`ParsedDelegated`] if you also need to check the file is complete.
+pub fn parse_ripe_delegated(content: &str) -> Vec<IpRange> {
+    parse_ripe_delegated_iter(content)
+        .filter_map(Result::ok)
+        .collect()
+}
+
+/// Parse RIPE delegated statistics content from raw bytes.
+///
+/// Same as [`parse_ripe_delegated`], but without validating the whole content as
+/// UTF-8 first; lines that are not valid UTF-8 are skipped like any other
+/// malformed line.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::parse_ripe_delegated_bytes;
+///
+/// let data = b"ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n";
+/// assert_eq!(parse_ripe_delegated_bytes(data).len(), 1);
+/// ```
+pub fn parse_ripe_delegated_bytes(content: &[u8]) -> Vec<IpRange> {
+    let format = DelegatedFormat::detect_bytes(content);
+    byte_lines(content)
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .filter_map(|line| match classify_line(line, format) {
+            Line::Record { range, .. } => range,
+            _ => None,
+        })
+        .collect()
+}
+
+/// Parse RIPE delegated statistics content line by line from a reader.
+///
+/// Only one line is held in memory at a time besides the ranges produced, so a
+/// file can be parsed straight from a [`std::io::BufReader`] without reading it
+/// into a string first. Lines that are not valid UTF-8 are skipped.
+///
+/// # Errors
+/// Returns an error if reading from `reader` fails.
+pub fn parse_ripe_delegated_reader<R: BufRead>(mut reader: R) -> io::Result<Vec<IpRange>> {
+    let mut ranges = Vec::new();
+    let mut buf = Vec::new();
+    let mut format = None;
+
+    while reader.read_until(b'\n', &mut buf)? > 0 {
+        let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if let Ok(line) = std::str::from_utf8(line) {
+            format = format.or_else(|| DelegatedFormat::detect(std::iter::once(line)));
+            if let Line::Record {
+                range: Some(range), ..
+            } = classify_line(line, format)
+            {
+                ranges.push(range);
+            }
+        }
+        buf.clear();
+    }
+
+    Ok(ranges)
+}
+
+/// Split bytes into lines exactly like [`str::lines`]: on `\n`, with a trailing
+/// `\r` removed and no empty line after a final newline.
+fn byte_lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let trimmed = content.strip_suffix(b"\n").unwrap_or(content);
+    (!content.is_empty())
+        .then(|| trimmed.split(|&b| b == b'\n'))
+        .into_iter()
+        .flatten()
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+/// Lazily parse RIPE delegated statistics content, one record at a time.
+///
+/// Yields a range for every `ipv4`/`ipv6` record and a [`ParseError`] for every
+/// malformed line, in file order. Headers, summaries, comments, blank lines and
+/// records of other types are skipped. Nothing is collected, so records can be
+/// filtered or transformed without building the full list first.
+///
+/// # Examples
+/// ```
+/// use ip_alloc_lookup::parse_ripe_delegated_iter;
+///
+/// let data = "\
+/// ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+/// ripencc|FR|ipv4|not-an-ip|256|20250101|allocated
+/// ripencc|FR|ipv6|2a01:e00::|26|20250101|allocated
+/// ";
+/// let german: Vec<_> = parse_ripe_delegated_iter(data)
+///     .filter_map(Result::ok)
+///     .filter(|r| r.country == "DE")
+///     .collect();
+/// assert_eq!(german.len(), 1);
+///
+/// let errors: Vec<_> = parse_ripe_delegated_iter(data).filter_map(Result::err).collect();
+/// assert_eq!(errors[0].line_number, 2);
+/// ```
+pub fn parse_ripe_delegated_iter(
+    content: &str,
+) -> impl Iterator<Item = Result<IpRange, ParseError>> + '_ {
+    let format = DelegatedFormat::detect(content.lines());
+    content
+        .lines()
+        .enumerate()
+        .filter_map(move |(idx, line)| match classify_line(line, format) {
+            Line::Record { range, .. } => range.map(Ok),
+            Line::Malformed(reason) => Some(Err(ParseError {
+                line_number: idx + 1,
+                line: line.to_string(),
+                reason,
+            })),
+            _ => None,
+        })
+}
+
+/// How a single line of delegated content was classified.
+enum Line<'a> {
+    Blank,
+    Comment,
+    Header,
+    /// A summary line; `None` if its count field is not a number.
+    Summary(Option<SummaryLine>),
+    /// A well-formed record; `range` is `None` for non-IP types such as `asn`.
+    /// `without_id` is set for a record lacking the opaque id of an extended
+    /// file, which is parsed all the same.
+    Record {
+        record_type: &'a str,
+        range: Option<IpRange>,
+        without_id: bool,
+    },
+    /// An `available` or `reserved` record; `range` is `None` for non-IP
+    /// types or if the address fields cannot be parsed.
+    Unallocated {
+        record_type: &'a str,
+        range: Option<IpRange>,
+    },
+    Malformed(MalformedReason),
+}
+
+/// Split `content` into at most `chunks` pieces of similar size, each ending
+/// right after a newline (except possibly the last).
+#[cfg(feature = "parallel")]
+fn split_at_lines(content: &[u8], chunks: usize) -> Vec<&[u8]> {
+    let target = content.len().div_ceil(chunks.max(1)).max(1);
+
+    let mut pieces = Vec::with_capacity(chunks);
+    let mut start = 0;
+    while start < content.len() {
+        let guess = (start + target).min(content.len());
+        let end = content[guess..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(content.len(), |pos| guess + pos + 1);
+        pieces.push(&content[start..end]);
+        start = end;
+    }
+    pieces
+}
+
+/// Strip what mirrors and Windows tools add around a line: a UTF-8 byte order
+/// mark, a stray `\r` and surrounding whitespace.
+fn normalize_line(line: &str) -> &str {
+    line.strip_prefix('\u{FEFF}').unwrap_or(line).trim()
+}
+
+/// Split a line into its `|`-separated fields, ignoring padding around them.
+fn split_fields(line: &str) -> Vec<&str> {
+    line.split('|').map(str::trim).collect()
+}
+
+/// Classify a line by its fields rather than by its prefix.
+///
+/// Lines are normalized first, so CRLF endings, a byte order mark, trailing
+/// whitespace and padded fields do not make a record malformed. Records are
+/// checked against `format`, the layout detected for the whole content.
+fn classify_line(line: &str, format: Option<DelegatedFormat>) -> Line<'_> {
+    let line = normalize_line(line);
+    if line.is_empty() {
+        return Line::Blank;
+    }
+    if line.starts_with('#') {
+        return Line::Comment;
+    }
+
+    let parts = split_fields(line);
+
+    if is_version_field(parts[0]) {
+        return Line::Header;
+    }
+
+    if is_summary(&parts) {
+        return Line::Summary(parts[4].parse::<u64>().ok().map(|count| SummaryLine {
+            registry: parts[0].to_string(),
+            record_type: parts[2].to_string(),
+            count,
+        }));
+    }
+
+    if parts.len() < 7 {
+        return Line::Malformed(MalformedReason::TooFewFields);
+    }
+    if is_unallocated_status(parts[6]) {
+        return Line::Unallocated {
+            record_type: parts[2],
+            range: parse_record(&parts).ok().flatten(),
+        };
+    }
+    match parse_record(&parts) {
+        Ok(range) => Line::Record {
+            record_type: parts[2],
+            without_id: format == Some(DelegatedFormat::Extended) && parts.len() < 8,
+            range,
+        },
+        Err(reason) => Line::Malformed(reason),
+    }
+}
+
+fn is_summary(parts: &[&str]) -> bool {
+    parts.len() >= 6 && parts[1] == "*" && parts[5] == "summary"
+}
+
+/// Status of the records listing space that is not delegated.
+fn is_unallocated_status(status: &str) -> bool {
+    matches!(status, "available" | "reserved")
+}
+
+/// The header's first field is a format version such as `2` or `2.3`.
+fn is_version_field(field: &str) -> bool {
+    !field.is_empty() && field.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+}
+
+/// Parse the fields of a single record line.
+///
+/// Returns `Ok(None)` for well-formed records that are not `ipv4`/`ipv6`.
+/// `parts` has at least 7 fields.
+fn parse_record(parts: &[&str]) -> Result<Option<IpRange>, MalformedReason> {
+    let ip_type = parts[2];
+    let country = parts[1].to_string();
+    let assigned = parts[6] == "assigned";
+    let date = parts[5].to_string();
+
+    if ip_type == "ipv4" {
+        let start: Ipv4Addr = parts[3]
+            .parse()
+            .map_err(|_| MalformedReason::InvalidStart)?;
+        let count = parts[4]
+            .parse::<u32>()
+            .map_err(|_| MalformedReason::InvalidCount)?;
+
+        Ok(Some(IpRange {
+            start: RangeStart::V4(start),
+            count: count as u128,
+            country,
+            assigned,
+            date,
+        }))
+    } else if ip_type == "ipv6" {
+        let start: Ipv6Addr = parts[3]
+            .parse()
+            .map_err(|_| MalformedReason::InvalidStart)?;
+        // For IPv6, the count field is actually the prefix length
+        let prefix_len: u32 = parts[4]
+            .parse()
+            .ok()
+            .filter(|&len| len <= 128)
+            .ok_or(MalformedReason::InvalidCount)?;
+        let host_bits = 128 - prefix_len;
+        let count = if host_bits >= 128 {
+            u128::MAX
+        } else {
+            1u128 << host_bits
+        };
+
+        Ok(Some(IpRange {
+            start: RangeStart::V6(start),
+            count,
+            country,
+            assigned,
+            date,
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+2|ripencc|1767049199|4|19700101|20251229|+0100
+ripencc|*|ipv4|*|2|summary
+ripencc|*|asn|*|1|summary
+ripencc|*|ipv6|*|1|summary
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|FR|ipv4|2.0.0.0|1048576|20100712|allocated
+ripencc|DE|asn|3320|1|19930901|allocated
+ripencc|DE|ipv6|2a01:4f8::|32|20050614|allocated
+";
+
+    #[test]
+    fn test_tolerates_crlf_bom_and_padding() {
+        // As served by some mirrors or saved by Windows editors
+        let messy = "\u{FEFF}2|ripencc|1767049199|4|19700101|20251229|+0100\r\n\
+ripencc|*|ipv4|*|2|summary \r\n\
+ripencc|*|asn|*|1|summary\r\n\
+ ripencc|*|ipv6|*|1|summary\r\n\
+ripencc| DE |ipv4| 46.4.0.0 |256|20250101|allocated\t\r\n\
+\r\n\
+   \r\n\
+  # indented comment\r\n\
+ripencc|FR|ipv4|2.0.0.0|1048576 |20100712| allocated \r\n\
+ripencc|DE|asn|3320|1|19930901|allocated\r\n\
+ripencc|DE|ipv6|2a01:4f8::|32\t|20050614|allocated   \r\n";
+
+        let clean = ParsedDelegated::parse(SAMPLE);
+        let parsed = ParsedDelegated::parse(messy);
+        assert_eq!(parsed.ranges, clean.ranges);
+        assert_eq!(parsed.metadata, clean.metadata);
+        assert_eq!(parsed.summaries, clean.summaries);
+        assert_eq!(parsed.diagnostics.malformed, 0);
+        assert_eq!(parsed.diagnostics.blank, 2);
+        assert_eq!(parsed.diagnostics.comments, 1);
+        assert!(parsed.verify().is_ok());
+
+        assert_eq!(parse_ripe_delegated(messy), clean.ranges);
+        assert_eq!(parse_ripe_delegated_bytes(messy.as_bytes()), clean.ranges);
+        assert_eq!(
+            parse_ripe_delegated_reader(messy.as_bytes()).unwrap(),
+            clean.ranges
+        );
+    }
+
+    #[test]
+    fn test_ip_range_helpers() {
+        let all_v6 = IpRange {
+            start: RangeStart::V6(Ipv6Addr::UNSPECIFIED),
+            count: u128::MAX,
+            country: "ZZ".to_string(),
+            assigned: false,
+            date: String::new(),
+        };
+        assert_eq!(all_v6.end(), IpAddr::V6(Ipv6Addr::from(u128::MAX)));
+        assert!(all_v6.contains("2a01:4f8::1".parse().unwrap()));
+        assert!(!all_v6.contains("46.4.0.1".parse().unwrap()));
+        assert_eq!(all_v6.to_cidrs().len(), 1);
+
+        let empty = IpRange {
+            start: RangeStart::V4(Ipv4Addr::new(10, 0, 0, 0)),
+            count: 0,
+            country: "ZZ".to_string(),
+            assigned: false,
+            date: String::new(),
+        };
+        assert!(empty.is_empty());
+        assert!(!empty.contains("10.0.0.0".parse().unwrap()));
+        assert!(empty.to_cidrs().is_empty());
+    }
+
+    #[test]
+    fn test_iter_matches_diagnostics() {
+        let content = "\
+2|ripencc|1|4|19700101|20250101|+0100
+ripencc|*|ipv4|*|3|summary
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated
+ripencc|DE|ipv4|46.4.1.0|lots|20250101|allocated
+ripencc|DE|asn|24940|1|20250101|allocated
+ripencc|DE|ipv6
+ripencc|DE|ipv6|2a01:4f8::|32|20250101|allocated
+";
+        let parsed = ParsedDelegated::parse(content);
+        let (ranges, errors): (Vec<_>, Vec<_>) =
+            parse_ripe_delegated_iter(content).partition(Result::is_ok);
+
+        let ranges: Vec<IpRange> = ranges.into_iter().map(Result::unwrap).collect();
+        assert_eq!(ranges, parsed.ranges);
+
+        let errors: Vec<ParseError> = errors.into_iter().map(Result::unwrap_err).collect();
+        assert_eq!(errors.len() as u64, parsed.diagnostics().malformed);
+        for (err, sample) in errors.iter().zip(&parsed.diagnostics().malformed_samples) {
+            assert_eq!(
+                (err.line_number, err.reason),
+                (sample.line_number, sample.reason)
+            );
+        }
+        assert_eq!(
+            errors[0].to_string(),
+            "line 4 (invalid count/prefix length): ripencc|DE|ipv4|46.4.1.0|lots|20250101|allocated"
+        );
+    }
+
+    #[test]
+    fn test_bytes_and_reader_match_str_parse() {
+        let content =
+            format!("{SAMPLE}garbage\r\nripencc|DE|ipv4|46.4.9.0|256|20250101|allocated\r\n\n");
+        let from_str = ParsedDelegated::parse(&content);
+        assert_eq!(ParsedDelegated::parse_bytes(content.as_bytes()), from_str);
+        assert_eq!(
+            parse_ripe_delegated_bytes(content.as_bytes()),
+            from_str.ranges
+        );
+        assert_eq!(
+            parse_ripe_delegated_reader(content.as_bytes()).unwrap(),
+            from_str.ranges
+        );
+
+        for edge in ["", "\n", "\r\n", "a\n\nb", "a\r\nb\n"] {
+            let expected: Vec<&str> = edge.lines().collect();
+            let lines: Vec<&[u8]> = byte_lines(edge.as_bytes()).collect();
+            assert_eq!(
+                lines,
+                expected.iter().map(|l| l.as_bytes()).collect::<Vec<_>>(),
+                "{edge:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_bytes_parse_reports_invalid_utf8_lines() {
+        let mut content = b"ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n".to_vec();
+        content.extend_from_slice(b"ripencc|D\xff|ipv4|46.4.1.0|256|20250101|allocated\n");
+
+        let parsed = ParsedDelegated::parse_bytes(&content);
+        assert_eq!(parsed.ranges.len(), 1);
+        assert_eq!(parsed.diagnostics().malformed, 1);
+        let sample = &parsed.diagnostics().malformed_samples[0];
+        assert_eq!(
+            (sample.line_number, sample.reason),
+            (2, MalformedReason::InvalidEncoding)
+        );
+        assert_eq!(
+            parse_ripe_delegated_reader(&content[..]).unwrap(),
+            parsed.ranges
+        );
+    }
+
+    #[test]
+    fn test_parse_summary_lines() {
+        let parsed = ParsedDelegated::parse(SAMPLE);
+
+        assert_eq!(parsed.summaries().len(), 3);
+        assert_eq!(parsed.summaries()[0].record_type, "ipv4");
+        assert_eq!(parsed.summaries()[0].count, 2);
+        assert_eq!(
+            parsed.record_counts(),
+            RecordCounts {
+                ipv4: 2,
+                ipv6: 1,
+                asn: 1
+            }
+        );
+        assert_eq!(parsed.ranges, parse_ripe_delegated(SAMPLE));
+        assert!(parsed.verify().is_ok());
+    }
+
+    #[test]
+    fn test_parse_header_metadata() {
+        let parsed = ParsedDelegated::parse(SAMPLE);
+        let meta = parsed.metadata().expect("sample has a header line");
+
+        assert_eq!(meta.version, "2");
+        assert_eq!(meta.registry, "ripencc");
+        assert_eq!(meta.serial, 1767049199);
+        assert_eq!(meta.records, 4);
+        assert_eq!(meta.start_date, "19700101");
+        assert_eq!(meta.end_date, "20251229");
+        assert_eq!(meta.utc_offset, "+0100");
+
+        // Header-less content (e.g. a hand-written fixture) has no metadata.
+        assert!(
+            ParsedDelegated::parse("ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n")
+                .metadata()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_classify_skipped_lines() {
+        let data = format!(
+            "# leading comment\n\n{SAMPLE}ripencc|DE|ipv4|not-an-ip|256|20250101|allocated\nripencc|DE|ipv4|46.4.\n"
+        );
+        let parsed = ParsedDelegated::parse(&data);
+        let diag = parsed.diagnostics();
+
+        assert_eq!(diag.total_lines, 12);
+        assert_eq!(diag.records, 3);
+        assert_eq!(diag.blank, 1);
+        assert_eq!(diag.comments, 1);
+        assert_eq!(diag.headers, 1);
+        assert_eq!(diag.summaries, 3);
+        assert_eq!(diag.non_ip_records, 1);
+        assert_eq!(diag.malformed, 2);
+        assert_eq!(diag.skipped(), 9);
+
+        assert_eq!(diag.malformed_samples[0].line_number, 11);
+        assert_eq!(
+            diag.malformed_samples[0].reason,
+            MalformedReason::InvalidStart
+        );
+        assert_eq!(
+            diag.malformed_samples[1].reason,
+            MalformedReason::TooFewFields
+        );
+        assert!(diag.to_string().contains("line 12 (too few fields)"));
+    }
+
+    /// One excerpt per RIR, in each registry's own header/record style.
+    const RIR_SAMPLES: &[(&str, &str)] = &[
+        (
+            "afrinic",
+            "\
+2|afrinic|20251229|3|00000000|20251229|+00
+afrinic|*|ipv4|*|2|summary
+afrinic|*|ipv6|*|1|summary
+afrinic|ZA|ipv4|41.0.0.0|2097152|20071126|allocated|F36DB31F
+afrinic|EG|ipv4|41.32.0.0|1048576|20060718|allocated|F3640B17
+afrinic|ZA|ipv6|2c0f:f000::|32|20070808|allocated|F36DB31F
+",
+        ),
+        (
+            "apnic",
+            "\
+# APNIC statistics comments may mention ipv4 and ipv6
+2|apnic|20251229|4|19830613|20251228|+1000
+apnic|*|asn|*|1|summary
+apnic|*|ipv4|*|2|summary
+apnic|*|ipv6|*|1|summary
+apnic|JP|asn|173|1|20020801|allocated
+apnic|AU|ipv4|1.0.0.0|256|20110811|assigned
+apnic|CN|ipv4|1.0.1.0|256|20110414|allocated
+apnic|JP|ipv6|2001:200::|35|19990813|allocated
+",
+        ),
+        (
+            "arin",
+            "\
+2.3|arin|1767049199|3|19700101|20251229|-0500
+arin|*|ipv4|*|2|summary
+arin|*|ipv6|*|1|summary
+arin|US|ipv4|3.0.0.0|4194304|19880223|allocated|e5e3b9c13678dfc483fb1f819d70883c
+arin|CA|ipv4|24.36.0.0|262144|19990115|allocated|9d0fd0d7a7cdf8e4b3acc53e2c6be5ee
+arin|US|ipv6|2001:400::|32|19990913|allocated|c4c4c1d3d2c6e9e1e1a6c6e3d3b6c7a1
+",
+        ),
+        (
+            "lacnic",
+            "\
+2|lacnic|20251229|3|19870101|20251228|-0300
+lacnic|*|ipv4|*|2|summary
+lacnic|*|ipv6|*|1|summary
+lacnic|BR|ipv4|131.0.72.0|1024|20140408|allocated
+lacnic|AR|ipv4|168.0.0.0|512|20140620|allocated
+lacnic|BR|ipv6|2001:1280::|32|20000426|allocated
+",
+        ),
+        (
+            "ripencc",
+            "\
+2|ripencc|1767049199|3|19700101|20251229|+0100
+ripencc|*|ipv4|*|2|summary
+ripencc|*|ipv6|*|1|summary
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated|40096f0b-e041-4cc0-83c9-e437919efe56
+ripencc|PS|ipv4|1.178.112.0|4096|20071126|allocated|40096f0b-e041-4cc0-83c9-e437919efe56
+ripencc|DE|ipv6|2a01:4f8::|32|20050614|allocated|40096f0b-e041-4cc0-83c9-e437919efe56
+",
+        ),
+    ];
+
+    #[test]
+    fn test_lenient_parser_handles_all_rir_styles() {
+        for (registry, content) in RIR_SAMPLES {
+            let parsed = ParsedDelegated::parse(content);
+
+            assert_eq!(parsed.ranges.len(), 3, "{registry}");
+            assert_eq!(parsed.ranges, parse_ripe_delegated(content), "{registry}");
+            assert_eq!(parsed.metadata().unwrap().registry, *registry);
+            assert_eq!(parsed.diagnostics().malformed, 0, "{registry}");
+            assert!(parsed.verify().is_ok(), "{registry}");
+        }
+    }
+
+    #[test]
+    fn test_classification_ignores_prefixes_and_substrings() {
+        // An asn record whose opaque id contains "ipv4" is not an IP range, and a
+        // record from a registry whose name starts with a digit is not a header.
+        let data = "\
+ripencc|DE|asn|3320|1|19930901|allocated|ipv4-ipv6-opaque
+2registry|DE|ipv4|46.4.0.0|256|20250101|allocated|opaque
+";
+        let parsed = ParsedDelegated::parse(data);
+
+        assert_eq!(parsed.ranges.len(), 1);
+        assert_eq!(
+            parsed.ranges[0].start,
+            RangeStart::V4(Ipv4Addr::new(46, 4, 0, 0))
+        );
+        assert_eq!(parsed.record_counts().asn, 1);
+        assert_eq!(parsed.diagnostics().headers, 0);
+        assert_eq!(parse_ripe_delegated(data), parsed.ranges);
+    }
+
+    #[test]
+    fn test_verify_detects_truncation() {
+        // Drop the last record, as a cut-off download would.
+        let truncated = SAMPLE.trim_end().rsplit_once('\n').unwrap().0;
+        let parsed = ParsedDelegated::parse(truncated);
+
+        assert_eq!(
+            parsed.verify(),
+            Err(VerifyError::CountMismatch {
+                record_type: "ipv6".to_string(),
+                expected: 1,
+                found: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_requires_summary() {
+        let parsed = ParsedDelegated::parse("ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated\n");
+        assert_eq!(parsed.verify(), Err(VerifyError::MissingSummary));
+    }
+
+    #[test]
+    fn test_verify_bundled_snapshot() {
+        let content =
+            std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/ripe-data.txt")).unwrap();
+        assert!(ParsedDelegated::parse(&content).verify().is_ok());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_chunked_parse_matches_sequential() {
+        let content = format!(
+            "{SAMPLE}garbage\r\n\n{SAMPLE}ripencc|DE|ipv4|not-an-ip|256|20250101|allocated"
+        );
+        let format = DelegatedFormat::detect_bytes(content.as_bytes());
+        let sequential = ParsedDelegated::parse_sequential(content.as_bytes(), format);
+        assert_eq!(sequential.diagnostics().malformed, 2);
+
+        for chunks in 1..=content.lines().count() + 1 {
+            assert_eq!(
+                ParsedDelegated::parse_chunked(content.as_bytes(), format, chunks),
+                sequential,
+                "{chunks} chunks"
+            );
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_chunked_parse_of_bundled_snapshot() {
+        let content = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/ripe-data.txt")).unwrap();
+        let format = DelegatedFormat::detect_bytes(&content);
+        assert_eq!(format, Some(DelegatedFormat::Extended));
+        assert_eq!(
+            ParsedDelegated::parse_chunked(&content, format, 16),
+            ParsedDelegated::parse_sequential(&content, format)
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_parsed_dates() {
+        use time::{Date, Month};
+
+        let parsed = ParsedDelegated::parse(SAMPLE);
+        assert_eq!(parsed.ranges[0].date, "20250101");
+        assert_eq!(
+            parsed.ranges[0].parsed_date(),
+            Some(Date::from_calendar_date(2025, Month::January, 1).unwrap())
+        );
+        let meta = parsed.metadata().unwrap();
+        assert_eq!(
+            meta.parsed_start_date(),
+            Some(Date::from_calendar_date(1970, Month::January, 1).unwrap())
+        );
+        assert_eq!(
+            meta.parsed_end_date(),
+            Some(Date::from_calendar_date(2025, Month::December, 29).unwrap())
+        );
+
+        for field in ["", "00000000", "20250230", "2025-01-01", "+2025010"] {
+            assert_eq!(parse_date(field), None, "{field:?}");
+        }
+    }
+
+    #[test]
+    fn test_published_at() {
+        let meta = ParsedDelegated::parse(SAMPLE).metadata().cloned().unwrap();
+        assert_eq!(
+            meta.published_at(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_766_966_400))
+        );
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(parse_ymd("20240229"), Some((2024, 2, 29)));
+        assert_eq!(parse_ymd("20250229"), None);
+
+        let unknown = SourceMetadata {
+            end_date: "00000000".to_string(),
+            ..meta
+        };
+        assert_eq!(unknown.published_at(), None);
+    }
+
+    #[test]
+    fn test_extended_format_skips_unallocated_space() {
+        let extended = "\
+2|ripencc|1767049199|6|19700101|20251229|+0100
+ripencc|*|ipv4|*|3|summary
+ripencc|*|ipv6|*|2|summary
+ripencc|DE|ipv4|46.4.0.0|256|20250101|allocated|3cbd9d5e-0b4a-4c1d-9b1b-7e1d5d2e0d21
+ripencc||ipv4|5.159.192.0|1024||reserved
+ripencc||ipv4|5.160.0.0|256||available
+ripencc|NL|ipv6|2001:67c::|32|20050614|assigned|1e0d2a5c-8d33-4f6b-a3b4-2c1e0b9d8f77|e-stats
+ripencc||ipv6|2001:67d::|32||reserved
+";
+        let parsed = ParsedDelegated::parse(extended);
+        assert_eq!(parsed.format(), Some(DelegatedFormat::Extended));
+        assert_eq!(
+            parsed.metadata().unwrap().format,
+            Some(DelegatedFormat::Extended)
+        );
+        let countries: Vec<_> = parsed.ranges.iter().map(|r| r.country.as_str()).collect();
+        assert_eq!(countries, ["DE", "NL"]);
+        assert_eq!(parsed.diagnostics().unallocated, 3);
+        let free: Vec<_> = parsed.unallocated().iter().map(|r| r.end()).collect();
+        assert_eq!(
+            free,
+            [
+                "5.159.195.255".parse::<IpAddr>().unwrap(),
+                "5.160.0.255".parse().unwrap(),
+                "2001:67d:ffff:ffff:ffff:ffff:ffff:ffff".parse().unwrap(),
+            ]
+        );
+        assert!(parsed.unallocated().iter().all(|r| r.country.is_empty()));
+        assert_eq!(parsed.diagnostics().malformed, 0);
+        assert!(parsed.verify().is_ok());
+        assert_eq!(parse_ripe_delegated(extended).len(), 2);
+
+        // A standard record appended to an extended file is still parsed
+        let missing_id = format!("{extended}ripencc|DE|ipv4|46.5.0.0|256|20250101|allocated\n");
+        let parsed = ParsedDelegated::parse(&missing_id);
+        assert_eq!(parsed.ranges.len(), 3);
+        assert_eq!(parsed.diagnostics().malformed, 0);
+        assert_eq!(parsed.diagnostics().without_id, 1);
+        assert_eq!(parse_ripe_delegated(&missing_id).len(), 3);
+
+        let standard = ParsedDelegated::parse(SAMPLE);
+        assert_eq!(standard.format(), Some(DelegatedFormat::Standard));
+        assert_eq!(standard.diagnostics().malformed, 0);
+        assert_eq!(ParsedDelegated::parse("").format(), None);
+    }
+}
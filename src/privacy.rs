@@ -0,0 +1,231 @@
+//! Working without full client addresses: bucketed classification and
+//! pseudonymization.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use sha2::{Digest, Sha256};
+
+use crate::{GeoInfo, GeoIpDb, IpNet};
+
+/// Rounds of the Feistel network permuting addresses within a block.
+const ROUNDS: u8 = 8;
+
+/// The classification of an address bucket, returned by
+/// [`GeoIpDb::classify_bucket`].
+///
+/// Only the bucket is kept, never the address it was computed from, so the
+/// value can be stored or logged where full client addresses must not be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClassifiedBucket {
+    /// The address truncated to [`ClassifiedBucket::V4_PREFIX_LEN`] or
+    /// [`ClassifiedBucket::V6_PREFIX_LEN`] bits.
+    pub prefix: IpNet,
+    /// The range covering the bucket, [`None`] if it is not in the database.
+    pub info: Option<GeoInfo>,
+}
+
+impl ClassifiedBucket {
+    /// Length of IPv4 buckets: a `/24`, 256 addresses.
+    pub const V4_PREFIX_LEN: u8 = 24;
+    /// Length of IPv6 buckets: a `/64`, one subnet.
+    pub const V6_PREFIX_LEN: u8 = 64;
+
+    /// Country code of the bucket, if covered.
+    pub fn country(&self) -> Option<&str> {
+        self.info.as_ref().map(GeoInfo::country_code_str)
+    }
+}
+
+impl GeoIpDb {
+    /// Truncate `ip` to its `/24` (IPv4) or `/64` (IPv6) and classify the
+    /// truncated prefix.
+    ///
+    /// IPv4-mapped IPv6 addresses are bucketed as IPv4. Registries delegate
+    /// nothing smaller than these buckets in practice, so the result is the
+    /// classification of `ip` itself; where a bucket does straddle two
+    /// blocks, it is that of the bucket's first address.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "embedded-data")] {
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let db = GeoIpDb::new();
+    /// let bucket = db.classify_bucket("46.4.7.201".parse().unwrap());
+    /// assert_eq!(bucket.prefix.to_string(), "46.4.7.0/24");
+    /// assert_eq!(bucket.country(), Some("DE"));
+    /// # }
+    /// ```
+    pub fn classify_bucket(&self, ip: IpAddr) -> ClassifiedBucket {
+        let ip = ip.to_canonical();
+        let prefix_len = match ip {
+            IpAddr::V4(_) => ClassifiedBucket::V4_PREFIX_LEN,
+            IpAddr::V6(_) => ClassifiedBucket::V6_PREFIX_LEN,
+        };
+        // Both lengths are valid for their family
+        let prefix = IpNet::new(ip, prefix_len).expect("bucket prefix length");
+        ClassifiedBucket {
+            prefix,
+            info: self.lookup(prefix.network()).copied(),
+        }
+    }
+
+    /// Replace `ip` with a pseudonym: another address in the same delegated
+    /// block, chosen by a permutation of the block keyed by `key`.
+    ///
+    /// The pseudonym has the classification of `ip`, so country and region
+    /// statistics of a dataset are unchanged. The mapping is deterministic
+    /// for a key and one-to-one within each block: the same client always
+    /// gets the same pseudonym and distinct clients stay distinct, but
+    /// without the key pseudonyms cannot be linked back to the addresses.
+    /// Use a secret, random key of at least 16 bytes, and keep in mind that
+    /// the block itself is not hidden; in small blocks that alone can narrow
+    /// down the client.
+    ///
+    /// IPv4-mapped IPv6 addresses are treated as IPv4. Returns [`None`] if
+    /// the address is not in the database.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "embedded-data")] {
+    /// use ip_alloc_lookup::GeoIpDb;
+    ///
+    /// let db = GeoIpDb::new();
+    /// let ip = "46.4.0.1".parse().unwrap();
+    /// let pseudonym = db.pseudonymize(ip, b"0123456789abcdef").unwrap();
+    /// assert_eq!(pseudonym, db.pseudonymize(ip, b"0123456789abcdef").unwrap());
+    /// assert_eq!(db.lookup(pseudonym), db.lookup(ip));
+    /// # }
+    /// ```
+    pub fn pseudonymize(&self, ip: IpAddr, key: &[u8]) -> Option<IpAddr> {
+        let ip = ip.to_canonical();
+        let block = self.lookup_range(ip)?;
+        Some(match (block.start, block.end, ip) {
+            (IpAddr::V4(start), IpAddr::V4(end), IpAddr::V4(ip)) => {
+                let (start, end) = (u32::from(start), u32::from(end));
+                let offset = permute(
+                    key,
+                    start.into(),
+                    (end - start).into(),
+                    (u32::from(ip) - start).into(),
+                );
+                // The offset is at most `end - start`
+                IpAddr::V4(Ipv4Addr::from(start + offset as u32))
+            }
+            (IpAddr::V6(start), IpAddr::V6(end), IpAddr::V6(ip)) => {
+                let (start, end) = (u128::from(start), u128::from(end));
+                let offset = permute(key, start, end - start, u128::from(ip) - start);
+                IpAddr::V6(Ipv6Addr::from(start + offset))
+            }
+            // The block matched `ip`, so it is of the same family
+            _ => return None,
+        })
+    }
+}
+
+/// Apply a permutation of `0..=max` keyed by `key` and the `block` start to
+/// `offset`.
+///
+/// A balanced Feistel network permutes the smallest even number of bits
+/// holding `max`; results above `max` are encrypted again (cycle walking)
+/// until they fall in range, which keeps the mapping one-to-one on `0..=max`.
+fn permute(key: &[u8], block: u128, max: u128, offset: u128) -> u128 {
+    if max == 0 {
+        return 0;
+    }
+    let half = (128 - max.leading_zeros()).div_ceil(2);
+    let mask = (1u128 << half) - 1;
+    let mut x = offset;
+    loop {
+        let (mut left, mut right) = (x >> half, x & mask);
+        for round in 0..ROUNDS {
+            let f = round_function(key, block, round, right) & mask;
+            (left, right) = (right, left ^ f);
+        }
+        x = (left << half) | right;
+        if x <= max {
+            return x;
+        }
+    }
+}
+
+fn round_function(key: &[u8], block: u128, round: u8, value: u128) -> u128 {
+    let digest = Sha256::new()
+        .chain_update(key)
+        .chain_update(block.to_be_bytes())
+        .chain_update([round])
+        .chain_update(value.to_be_bytes())
+        .finalize();
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    u128::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_bucket() {
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|65536|20090101|allocated\n\
+             ripencc|FR|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        );
+
+        let bucket = db.classify_bucket("::ffff:46.4.200.17".parse().unwrap());
+        assert_eq!(bucket.prefix.to_string(), "46.4.200.0/24");
+        assert_eq!(bucket.country(), Some("DE"));
+
+        let bucket = db.classify_bucket("2a01:4f8:1:2:aaaa:bbbb:cccc:dddd".parse().unwrap());
+        assert_eq!(bucket.prefix.to_string(), "2a01:4f8:1:2::/64");
+        assert_eq!(bucket.country(), Some("FR"));
+
+        let bucket = db.classify_bucket("10.1.2.3".parse().unwrap());
+        assert_eq!(bucket.prefix.to_string(), "10.1.2.0/24");
+        assert_eq!(bucket.info, None);
+    }
+
+    #[test]
+    fn test_pseudonymize() {
+        // 768 addresses: not a power of two, so cycle walking is exercised
+        let db = GeoIpDb::from_ripe_delegated_str(
+            "ripencc|DE|ipv4|46.4.0.0|768|20090101|allocated\n\
+             ripencc|CH|ipv4|46.4.3.0|1|20090101|allocated\n\
+             ripencc|FR|ipv6|2a01:4f8::|32|20050101|allocated\n",
+        );
+        let key = b"0123456789abcdef";
+
+        let mut seen = std::collections::HashSet::new();
+        for i in 0..768u32 {
+            let ip = IpAddr::V4(Ipv4Addr::from(0x2e04_0000 + i));
+            let pseudonym = db.pseudonymize(ip, key).unwrap();
+            assert_eq!(db.lookup(pseudonym).unwrap().country_code_str(), "DE");
+            seen.insert(pseudonym);
+        }
+        // One-to-one: every address of the block is used exactly once
+        assert_eq!(seen.len(), 768);
+
+        let ip: IpAddr = "46.4.1.77".parse().unwrap();
+        let pseudonym = db.pseudonymize(ip, key);
+        assert_eq!(
+            db.pseudonymize("::ffff:46.4.1.77".parse().unwrap(), key),
+            pseudonym
+        );
+        assert_ne!(db.pseudonymize(ip, b"another key, 16b"), pseudonym);
+
+        let single: IpAddr = "46.4.3.0".parse().unwrap();
+        assert_eq!(db.pseudonymize(single, key), Some(single));
+
+        let ip: IpAddr = "2a01:4f8:1::1".parse().unwrap();
+        let pseudonym = db.pseudonymize(ip, key).unwrap();
+        assert_ne!(pseudonym, ip);
+        assert!(
+            "2a01:4f8::/32"
+                .parse::<IpNet>()
+                .unwrap()
+                .contains(pseudonym)
+        );
+
+        assert_eq!(db.pseudonymize("10.0.0.1".parse().unwrap(), key), None);
+    }
+}
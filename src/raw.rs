@@ -0,0 +1,81 @@
+//! Direct access to the range tables embedded at build time.
+//!
+//! [`GeoIpDb`](crate::GeoIpDb) and the [`embedded`](crate::embedded) lookups
+//! cover the common cases. This module hands out the underlying data for
+//! callers that build their own structures from it (tries, bitmaps, GPU
+//! buffers, ...).
+//!
+//! Both tables are sorted by start address and their ranges do not overlap.
+//! Each entry is `(start, end, country, assigned)`: the inclusive bounds, the
+//! country code exactly as it appears in the source data (empty for
+//! unassigned space), and whether the record's status was `assigned`.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "embedded-data")] {
+//! use ip_alloc_lookup::raw;
+//!
+//! // Collect the German IPv4 ranges
+//! let de: Vec<(u32, u32)> = raw::ipv4_ranges()
+//!     .filter(|&(_, _, cc, _)| cc == "DE")
+//!     .map(|(start, end, _, _)| (start, end))
+//!     .collect();
+//! assert!(!de.is_empty());
+//! # }
+//! ```
+
+use crate::embedded::{IPV4_RANGES, IPV6_RANGES, RangeTable};
+
+/// Size in bytes of one entry of [`ipv4_bytes`].
+pub const IPV4_ENTRY_SIZE: usize = RangeTable::<u32>::ENTRY_SIZE;
+
+/// Size in bytes of one entry of [`ipv6_bytes`].
+pub const IPV6_ENTRY_SIZE: usize = RangeTable::<u128>::ENTRY_SIZE;
+
+/// The embedded IPv4 ranges, in ascending order.
+pub fn ipv4_ranges() -> impl ExactSizeIterator<Item = (u32, u32, &'static str, bool)> {
+    IPV4_RANGES.iter()
+}
+
+/// The embedded IPv6 ranges, in ascending order.
+pub fn ipv6_ranges() -> impl ExactSizeIterator<Item = (u128, u128, &'static str, bool)> {
+    IPV6_RANGES.iter()
+}
+
+/// The packed IPv4 table as embedded in the binary.
+///
+/// Entries of [`IPV4_ENTRY_SIZE`] bytes: `start: u32` and `end: u32`
+/// (little-endian), the country code as two ASCII bytes (`[0, 0]` if empty)
+/// and an `assigned` byte (`0` or `1`).
+pub fn ipv4_bytes() -> &'static [u8] {
+    IPV4_RANGES.as_bytes()
+}
+
+/// The packed IPv6 table as embedded in the binary.
+///
+/// Same layout as [`ipv4_bytes`] with `start: u128` and `end: u128`, so
+/// entries are [`IPV6_ENTRY_SIZE`] bytes.
+pub fn ipv6_bytes() -> &'static [u8] {
+    IPV6_RANGES.as_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables_are_consistent() {
+        assert_eq!(ipv4_bytes().len(), ipv4_ranges().len() * IPV4_ENTRY_SIZE);
+        assert_eq!(ipv6_bytes().len(), ipv6_ranges().len() * IPV6_ENTRY_SIZE);
+
+        let v4: Vec<_> = ipv4_ranges().collect();
+        assert!(v4.windows(2).all(|w| w[0].1 < w[1].0));
+        if let Some(&(start, end, cc, assigned)) = v4.first() {
+            let entry = &ipv4_bytes()[..IPV4_ENTRY_SIZE];
+            assert_eq!(entry[..4], start.to_le_bytes());
+            assert_eq!(entry[4..8], end.to_le_bytes());
+            assert_eq!(entry[10] == 1, assigned);
+            assert!(cc.is_empty() || entry[8..10] == *cc.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Classifying addresses while deserializing with [serde](https://serde.rs).
+//!
+//! Put [`ClassifiedIp`] in a record instead of [`IpAddr`], or annotate an
+//! existing field with `#[serde(with = "ip_alloc_lookup::serde_helpers::classified")]`,
+//! and the address is looked up in the embedded tables as the record is
+//! read. Ingest pipelines then get the classification with the data, without
+//! a separate pass or a database to pass around.
+//!
+//! Without the `embedded-data` feature the embedded tables are empty, so
+//! [`ClassifiedIp::info`] is always [`None`] and only the address is kept.
+//!
+//! # Examples
+//! ```
+//! # #[cfg(feature = "embedded-data")] {
+//! use serde::Deserialize;
+//! use ip_alloc_lookup::serde_helpers::ClassifiedIp;
+//!
+//! #[derive(Deserialize)]
+//! struct Event {
+//!     #[serde(with = "ip_alloc_lookup::serde_helpers::classified")]
+//!     client: ClassifiedIp,
+//! }
+//!
+//! let event: Event = serde_json::from_str(r#"{"client": "46.4.0.1"}"#).unwrap();
+//! assert_eq!(event.client.ip.to_string(), "46.4.0.1");
+//! assert!(event.client.is_eu());
+//! # }
+//! ```
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{GeoInfo, embedded};
+
+/// An address together with its classification from the embedded tables.
+///
+/// Deserializes from an address string; a port (`192.0.2.1:443`,
+/// `[2001:db8::1]:443`) is accepted and dropped, and an IPv4-mapped IPv6
+/// address is classified as its IPv4 address. Serializes back to the address
+/// string, so records round-trip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClassifiedIp {
+    pub ip: IpAddr,
+    /// [`None`] if the embedded tables do not cover the address.
+    pub info: Option<GeoInfo>,
+}
+
+impl ClassifiedIp {
+    /// Classify `ip` with the embedded tables.
+    pub fn new(ip: IpAddr) -> Self {
+        ClassifiedIp {
+            ip,
+            info: embedded::lookup(ip),
+        }
+    }
+
+    /// Country code of the address, if covered.
+    pub fn country(&self) -> Option<&str> {
+        self.info.as_ref().map(GeoInfo::country_code_str)
+    }
+
+    /// Return `true` if the address is covered and classified as EU.
+    pub fn is_eu(&self) -> bool {
+        self.info.is_some_and(|info| info.is_eu)
+    }
+}
+
+impl Serialize for ClassifiedIp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        classified::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ClassifiedIp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        classified::deserialize(deserializer)
+    }
+}
+
+/// `#[serde(with = "ip_alloc_lookup::serde_helpers::classified")]` for
+/// [`ClassifiedIp`] fields.
+pub mod classified {
+    use std::borrow::Cow;
+
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::ClassifiedIp;
+    use crate::forwarded::parse_node;
+
+    /// Serialize the address as a string.
+    pub fn serialize<S: Serializer>(
+        value: &ClassifiedIp,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&value.ip)
+    }
+
+    /// Parse an address string and classify it.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ClassifiedIp, D::Error> {
+        let text = Cow::<str>::deserialize(deserializer)?;
+        parse_node(&text)
+            .map(ClassifiedIp::new)
+            .ok_or_else(|| D::Error::custom(format!("invalid IP address: {text:?}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::IntoDeserializer;
+    use serde::de::value::{Error, MapDeserializer};
+
+    #[test]
+    fn test_classified() {
+        let ip = ClassifiedIp::deserialize(IntoDeserializer::<Error>::into_deserializer(
+            "[::ffff:46.4.0.1]:443",
+        ))
+        .unwrap();
+        assert_eq!(ip.ip.to_string(), "46.4.0.1");
+        // Without the embedded data nothing is classified
+        #[cfg(feature = "embedded-data")]
+        assert_eq!(ip.country(), Some("DE"));
+
+        #[derive(Deserialize)]
+        struct Row {
+            #[serde(with = "classified")]
+            client: ClassifiedIp,
+            #[allow(dead_code)]
+            path: String,
+        }
+        let row = Row::deserialize(MapDeserializer::<_, Error>::new(
+            [("client", "10.0.0.1"), ("path", "/")].into_iter(),
+        ))
+        .unwrap();
+        assert_eq!(row.client.info, None);
+        assert!(!row.client.is_eu());
+
+        let err =
+            ClassifiedIp::deserialize(IntoDeserializer::<Error>::into_deserializer("not an ip"))
+                .unwrap_err();
+        assert!(err.to_string().contains("\"not an ip\""), "{err}");
+
+        assert_eq!(
+            serde_json::to_string(&ClassifiedIp::new("2a01:4f8::1".parse().unwrap())).unwrap(),
+            "\"2a01:4f8::1\""
+        );
+    }
+}
@@ -0,0 +1,625 @@
+//! Building one database from several data sources.
+//!
+//! Every importer of the crate (delegated files, CSV files, DB-IP, geofeeds,
+//! ranges given in code) implements [`DataSource`], and
+//! [`GeoIpDb::from_sources`] combines any number of them. Where sources cover
+//! the same addresses, a [`ConflictPolicy`] decides which one wins, and
+//! [`GeoIpDb::from_sources_with_origins`] also tells which source each
+//! address was taken from.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bogon::FreeSpace;
+use crate::csv::{DBIP_UNKNOWN_COUNTRY, read_geofeed, read_ranges};
+use crate::parser::{IpRange, ParsedDelegated, RangeStart};
+use crate::{CsvSchema, GeoInfo, GeoIpDb, RangeDb};
+
+/// Anything that yields allocation ranges for [`GeoIpDb::from_sources`].
+///
+/// # Examples
+/// ```
+/// use std::io;
+/// use ip_alloc_lookup::{DataSource, IpRange};
+///
+/// /// Ranges kept in a configuration service.
+/// struct Remote;
+///
+/// impl DataSource for Remote {
+///     fn ranges(&self) -> io::Result<Vec<IpRange>> {
+///         Ok(ip_alloc_lookup::parse_ripe_delegated(
+///             "ripencc|DE|ipv4|10.0.0.0|256|20250101|assigned\n",
+///         ))
+///     }
+/// }
+/// ```
+pub trait DataSource {
+    /// Read the ranges of the source.
+    ///
+    /// # Errors
+    /// Returns an error if the source cannot be read or is invalid.
+    fn ranges(&self) -> io::Result<Vec<IpRange>>;
+
+    /// Read the space the source lists as not delegated, such as the
+    /// `available` and `reserved` records of extended delegated files.
+    ///
+    /// [`GeoIpDb::coverage`] uses it to tell unallocated space from that of
+    /// registries whose data is not loaded. The default lists none.
+    ///
+    /// # Errors
+    /// Returns an error if the source cannot be read or is invalid.
+    fn unallocated_ranges(&self) -> io::Result<Vec<IpRange>> {
+        Ok(Vec::new())
+    }
+
+    /// Read both the ranges and the unallocated space of the source.
+    ///
+    /// [`GeoIpDb::from_sources`] calls this once per source. The default calls
+    /// [`DataSource::ranges`] and [`DataSource::unallocated_ranges`]; sources
+    /// that would read a file twice that way override it.
+    ///
+    /// # Errors
+    /// Returns an error if the source cannot be read or is invalid.
+    fn ranges_and_unallocated(&self) -> io::Result<(Vec<IpRange>, Vec<IpRange>)> {
+        Ok((self.ranges()?, self.unallocated_ranges()?))
+    }
+}
+
+/// Ranges given in code, e.g. local overrides.
+impl DataSource for [IpRange] {
+    fn ranges(&self) -> io::Result<Vec<IpRange>> {
+        Ok(self.to_vec())
+    }
+}
+
+impl DataSource for Vec<IpRange> {
+    fn ranges(&self) -> io::Result<Vec<IpRange>> {
+        Ok(self.clone())
+    }
+}
+
+/// The records of an already parsed delegated file.
+impl DataSource for ParsedDelegated {
+    fn ranges(&self) -> io::Result<Vec<IpRange>> {
+        Ok(self.ranges.clone())
+    }
+
+    fn unallocated_ranges(&self) -> io::Result<Vec<IpRange>> {
+        Ok(self.unallocated().to_vec())
+    }
+}
+
+/// The tables of a database, e.g. the embedded snapshot from [`GeoIpDb::new`].
+///
+/// Allocation dates are not kept in a database, so
+/// [`IpRange::date`](crate::IpRange::date) is empty.
+impl DataSource for GeoIpDb {
+    fn ranges(&self) -> io::Result<Vec<IpRange>> {
+        let range = |start: RangeStart, count: u128, info: &GeoInfo| IpRange {
+            start,
+            count,
+            country: info.country_code_str().to_string(),
+            assigned: info.assigned,
+            date: String::new(),
+        };
+        let v4 = self.v4_ranges.iter().map(|&(start, end, ref info)| {
+            range(
+                RangeStart::V4(start.into()),
+                u128::from(end - start) + 1,
+                info,
+            )
+        });
+        let v6 = self.v6_ranges.iter().map(|&(start, end, ref info)| {
+            range(
+                RangeStart::V6(start.into()),
+                (end - start).saturating_add(1),
+                info,
+            )
+        });
+        Ok(v4.chain(v6).collect())
+    }
+
+    fn unallocated_ranges(&self) -> io::Result<Vec<IpRange>> {
+        Ok(self.unallocated.to_records())
+    }
+}
+
+/// A data file in one of the formats the crate can import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileSource {
+    /// A delegated statistics file of any RIR, as read by
+    /// [`GeoIpDb::from_ripe_delegated_file`].
+    Delegated(PathBuf),
+    /// A CSV file, as read by [`GeoIpDb::from_csv`].
+    Csv(PathBuf, CsvSchema),
+    /// A DB-IP Country Lite CSV, as read by
+    /// [`GeoIpDb::from_dbip_country_lite`].
+    DbIpCountryLite(PathBuf),
+    /// An RFC 8805 geofeed (`prefix,country,region,city,postal_code`).
+    /// Prefixes without a country are skipped.
+    Geofeed(PathBuf),
+}
+
+impl DataSource for FileSource {
+    fn ranges(&self) -> io::Result<Vec<IpRange>> {
+        match self {
+            FileSource::Delegated(path) => Ok(read_delegated(path)?.ranges),
+            FileSource::Csv(path, schema) => read_ranges(File::open(path)?, schema),
+            FileSource::DbIpCountryLite(path) => {
+                let mut ranges = read_ranges(File::open(path)?, &CsvSchema::default())?;
+                ranges.retain(|range| range.country != DBIP_UNKNOWN_COUNTRY);
+                Ok(ranges)
+            }
+            FileSource::Geofeed(path) => read_geofeed(File::open(path)?),
+        }
+    }
+
+    /// Only delegated files list unallocated space.
+    fn unallocated_ranges(&self) -> io::Result<Vec<IpRange>> {
+        match self {
+            FileSource::Delegated(path) => Ok(read_delegated(path)?.unallocated().to_vec()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Parses a delegated file once for both.
+    fn ranges_and_unallocated(&self) -> io::Result<(Vec<IpRange>, Vec<IpRange>)> {
+        match self {
+            FileSource::Delegated(path) => {
+                let parsed = read_delegated(path)?;
+                let unallocated = parsed.unallocated().to_vec();
+                Ok((parsed.ranges, unallocated))
+            }
+            _ => Ok((self.ranges()?, Vec::new())),
+        }
+    }
+}
+
+fn read_delegated(path: &Path) -> io::Result<ParsedDelegated> {
+    Ok(ParsedDelegated::parse_bytes(&fs::read(path)?))
+}
+
+/// What [`GeoIpDb::from_sources`] does with addresses several ranges cover.
+///
+/// Where the preferred range covers only part of another one, the other
+/// range keeps the rest of its addresses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The earlier source in the list wins; list overrides first. Within one
+    /// source, the range listed first wins.
+    #[default]
+    PreferSourceOrder,
+    /// The range with the latest [`IpRange::date`] wins, e.g. the registry
+    /// a block was transferred to over the one it came from. Ranges without
+    /// a date (geofeeds, databases) lose to dated ones; ties go by source
+    /// order.
+    PreferNewestDate,
+    /// The smaller range wins, e.g. a reassigned /24 over the /16 it was
+    /// carved from; ties go by source order.
+    PreferMoreSpecific,
+    /// Fail with [`io::ErrorKind::InvalidData`] if any two ranges overlap.
+    Reject,
+}
+
+impl GeoIpDb {
+    /// Build a database from several sources.
+    ///
+    /// The result never has overlapping ranges: where sources cover the same
+    /// addresses, `policy` decides which range they belong to, and a range
+    /// that loses only part of its addresses keeps the rest.
+    ///
+    /// # Errors
+    /// Returns the first error of a source, or an error of kind
+    /// [`io::ErrorKind::InvalidData`] naming two overlapping ranges under
+    /// [`ConflictPolicy::Reject`].
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "embedded-data")] {
+    /// use ip_alloc_lookup::{ConflictPolicy, GeoIpDb, parse_ripe_delegated};
+    ///
+    /// // Re-home one /24 of the embedded data
+    /// let overrides = parse_ripe_delegated("local|AT|ipv4|46.4.0.0|256||assigned\n");
+    /// let db = GeoIpDb::from_sources(
+    ///     &[&overrides, &GeoIpDb::new()],
+    ///     ConflictPolicy::PreferSourceOrder,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(db.country_str("46.4.0.1".parse().unwrap()), Some("AT"));
+    /// assert_eq!(db.country_str("46.4.1.1".parse().unwrap()), Some("DE"));
+    /// # }
+    /// ```
+    pub fn from_sources(sources: &[&dyn DataSource], policy: ConflictPolicy) -> io::Result<Self> {
+        Self::from_sources_with_origins(sources, policy).map(|(db, _)| db)
+    }
+
+    /// Build a database from several sources like [`GeoIpDb::from_sources`],
+    /// and record which source each range was taken from.
+    ///
+    /// The second table has the same ranges as the database, each with the
+    /// index in `sources` of the source that won it.
+    ///
+    /// # Errors
+    /// See [`GeoIpDb::from_sources`].
+    ///
+    /// # Examples
+    /// ```
+    /// use ip_alloc_lookup::{ConflictPolicy, GeoIpDb, parse_ripe_delegated};
+    ///
+    /// // A block transferred from RIPE to ARIN, still listed by both
+    /// let ripe = parse_ripe_delegated("ripencc|DE|ipv4|10.0.0.0|256|20090101|allocated\n");
+    /// let arin = parse_ripe_delegated("arin|US|ipv4|10.0.0.0|256|20240301|allocated\n");
+    /// let (db, origins) = GeoIpDb::from_sources_with_origins(
+    ///     &[&ripe, &arin],
+    ///     ConflictPolicy::PreferNewestDate,
+    /// )
+    /// .unwrap();
+    /// let ip = "10.0.0.1".parse().unwrap();
+    /// assert_eq!(db.country_str(ip), Some("US"));
+    /// assert_eq!(origins.lookup(ip), Some(&1));
+    /// ```
+    pub fn from_sources_with_origins(
+        sources: &[&dyn DataSource],
+        policy: ConflictPolicy,
+    ) -> io::Result<(Self, RangeDb<usize>)> {
+        let (mut v4, mut v6) = (Vec::new(), Vec::new());
+        let mut free = FreeSpace::default();
+        for (index, source) in sources.iter().enumerate() {
+            let (ranges, unallocated) = source.ranges_and_unallocated()?;
+            push_spans(ranges, index, &mut v4, &mut v6);
+            free = free.union(&FreeSpace::from_records(&unallocated));
+        }
+
+        match policy {
+            ConflictPolicy::PreferSourceOrder => {}
+            ConflictPolicy::PreferNewestDate => {
+                v4.sort_by_key(|span| Reverse(span.2.date));
+                v6.sort_by_key(|span| Reverse(span.2.date));
+            }
+            ConflictPolicy::PreferMoreSpecific => {
+                v4.sort_by_key(|span| span.1 - span.0);
+                v6.sort_by_key(|span| span.1 - span.0);
+            }
+            ConflictPolicy::Reject => {
+                reject_overlaps(&v4, |n| IpAddr::from((n as u32).to_be_bytes()))?;
+                reject_overlaps(&v6, |n| IpAddr::from(n.to_be_bytes()))?;
+            }
+        }
+        let (mut db, origins) = Self::from_layers(v4, v6);
+        db.unallocated = Arc::new(free);
+        Ok((db, origins))
+    }
+
+    /// Load the embedded snapshot with `extra` layered on top.
+    ///
+    /// Ranges of `extra` take precedence: where they overlap the embedded
+    /// data, the embedded blocks keep only the addresses outside of them. Use
+    /// this for overrides or for data the snapshot lacks, such as the
+    /// delegated file of another RIR. To let the snapshot win instead and
+    /// only fill its gaps, use [`GeoIpDb::from_sources`] with the embedded
+    /// database listed first.
+    ///
+    /// [`GeoIpDb::source_metadata`] still describes the embedded snapshot.
+    ///
+    /// # Errors
+    /// Returns the error of `extra` if it cannot be read.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "embedded-data")] {
+    /// use ip_alloc_lookup::{FileSource, GeoIpDb, parse_ripe_delegated};
+    ///
+    /// let overrides = parse_ripe_delegated("local|AT|ipv4|46.4.0.0|256||assigned\n");
+    /// let db = GeoIpDb::new_with_supplement(&overrides).unwrap();
+    /// assert_eq!(db.country_str("46.4.0.1".parse().unwrap()), Some("AT"));
+    /// assert_eq!(db.country_str("46.4.1.1".parse().unwrap()), Some("DE"));
+    ///
+    /// // e.g. ARIN data on top of the embedded RIPE snapshot
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # let path = dir.path().join("delegated-arin-extended-latest.txt");
+    /// # std::fs::write(&path, "arin|US|ipv4|8.8.8.0|256|19921201|allocated|id\n").unwrap();
+    /// let db = GeoIpDb::new_with_supplement(&FileSource::Delegated(path)).unwrap();
+    /// assert_eq!(db.country_str("8.8.8.8".parse().unwrap()), Some("US"));
+    /// # }
+    /// ```
+    pub fn new_with_supplement(extra: &dyn DataSource) -> io::Result<Self> {
+        let (mut v4, mut v6) = (Vec::new(), Vec::new());
+        let (ranges, unallocated) = extra.ranges_and_unallocated()?;
+        push_spans(ranges, 0, &mut v4, &mut v6);
+
+        let base = GeoIpDb::new();
+        let origin = |info| Origin {
+            info,
+            source: 1,
+            date: 0,
+        };
+        v4.extend(
+            base.v4_ranges
+                .iter()
+                .map(|&(start, end, info)| (start.into(), end.into(), origin(info))),
+        );
+        v6.extend(
+            base.v6_ranges
+                .iter()
+                .map(|&(start, end, info)| (start, end, origin(info))),
+        );
+
+        let extra_free = FreeSpace::from_records(&unallocated);
+        let (mut db, _) = Self::from_layers(v4, v6);
+        db.metadata = base.metadata;
+        db.unallocated = Arc::new(base.unallocated.union(&extra_free));
+        Ok(db)
+    }
+
+    /// Build the tables, and the table of winning sources, from spans in
+    /// priority order, see [`layer`].
+    fn from_layers(v4: Vec<Span>, v6: Vec<Span>) -> (Self, RangeDb<usize>) {
+        let (v4, v6) = (layer(v4), layer(v6));
+        let db = GeoIpDb {
+            v4_ranges: Arc::new(
+                v4.iter()
+                    .map(|&(start, end, origin)| (start as u32, end as u32, origin.info))
+                    .collect(),
+            ),
+            v6_ranges: Arc::new(
+                v6.iter()
+                    .map(|&(start, end, origin)| (start, end, origin.info))
+                    .collect(),
+            ),
+            metadata: None,
+            unallocated: Arc::default(),
+        };
+        let origins = RangeDb {
+            v4_ranges: Arc::new(
+                v4.iter()
+                    .map(|&(start, end, origin)| (start as u32, end as u32, origin.source))
+                    .collect(),
+            ),
+            v6_ranges: Arc::new(
+                v6.iter()
+                    .map(|&(start, end, origin)| (start, end, origin.source))
+                    .collect(),
+            ),
+            metadata: None,
+            unallocated: Arc::default(),
+        };
+        (db, origins)
+    }
+}
+
+/// Append `ranges` of the source at `index` as spans to the table of their
+/// family.
+fn push_spans(ranges: Vec<IpRange>, index: usize, v4: &mut Vec<Span>, v6: &mut Vec<Span>) {
+    for range in ranges {
+        let info = Origin {
+            info: GeoInfo::from_record(&range.country, range.assigned),
+            source: index,
+            // `YYYYMMDD`, so numeric order is date order; missing dates sort first
+            date: range.date.parse().unwrap_or(0),
+        };
+        match (range.start, range.end()) {
+            (RangeStart::V4(start), IpAddr::V4(end)) => {
+                v4.push((u32::from(start).into(), u32::from(end).into(), info))
+            }
+            (RangeStart::V6(start), IpAddr::V6(end)) => v6.push((start.into(), end.into(), info)),
+            _ => unreachable!("IpRange::end has the family of its start"),
+        }
+    }
+}
+
+/// A range on the `u128` number line shared by both address families.
+type Span = (u128, u128, Origin);
+
+/// The classification of a span and where it comes from.
+#[derive(Clone, Copy)]
+struct Origin {
+    info: GeoInfo,
+    /// Index of the source in the list.
+    source: usize,
+    /// Allocation date as `YYYYMMDD`, 0 if unknown.
+    date: u32,
+}
+
+/// Fail if two of `ranges` overlap.
+fn reject_overlaps(ranges: &[Span], addr: fn(u128) -> IpAddr) -> io::Result<()> {
+    let mut sorted: Vec<&Span> = ranges.iter().collect();
+    sorted.sort_by_key(|r| (r.0, r.1));
+    match sorted.windows(2).find(|w| w[1].0 <= w[0].1) {
+        Some(w) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "range {}-{} overlaps {}-{}",
+                addr(w[1].0),
+                addr(w[1].1),
+                addr(w[0].0),
+                addr(w[0].1)
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Resolve overlaps by priority: each range, in order, keeps only the
+/// addresses no earlier range has claimed. Returns disjoint ranges sorted by
+/// start.
+pub(crate) fn layer<P: Copy>(
+    ranges: impl IntoIterator<Item = (u128, u128, P)>,
+) -> Vec<(u128, u128, P)> {
+    // Claimed ranges by start address
+    let mut claimed: BTreeMap<u128, (u128, P)> = BTreeMap::new();
+
+    for (start, end, info) in ranges {
+        let mut gaps = Vec::new();
+        // Next unclaimed candidate address; `None` once past the address space
+        let mut pos = Some(start);
+        if let Some((_, &(prev_end, _))) = claimed.range(..start).next_back()
+            && prev_end >= start
+        {
+            pos = prev_end.checked_add(1);
+        }
+        for (&claimed_start, &(claimed_end, _)) in claimed.range(start..=end) {
+            let Some(from) = pos else { break };
+            if claimed_start > from {
+                gaps.push((from, claimed_start - 1));
+            }
+            pos = claimed_end.checked_add(1);
+        }
+        if let Some(from) = pos
+            && from <= end
+        {
+            gaps.push((from, end));
+        }
+        for (gap_start, gap_end) in gaps {
+            claimed.insert(gap_start, (gap_end, info));
+        }
+    }
+
+    claimed
+        .into_iter()
+        .map(|(start, (end, info))| (start, end, info))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_ripe_delegated;
+
+    #[test]
+    fn test_layer_keeps_unclaimed_parts() {
+        let info = |cc: &str| GeoInfo::from_record(cc, false);
+        let layered = layer([
+            (10, 19, info("AT")),
+            (0, 29, info("DE")),
+            (25, 40, info("FR")),
+            (u128::MAX - 1, u128::MAX, info("NL")),
+            (u128::MAX - 5, u128::MAX, info("PL")),
+        ]);
+        let spans: Vec<_> = layered
+            .iter()
+            .map(|&(start, end, info)| (start, end, info.country_code_str().to_string()))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (0, 9, "DE".to_string()),
+                (10, 19, "AT".to_string()),
+                (20, 29, "DE".to_string()),
+                (30, 40, "FR".to_string()),
+                (u128::MAX - 5, u128::MAX - 2, "PL".to_string()),
+                (u128::MAX - 1, u128::MAX, "NL".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-data")]
+    fn test_supplement_over_embedded() {
+        let embedded = GeoIpDb::new();
+        let block = embedded.lookup_range("46.4.0.1".parse().unwrap()).unwrap();
+        let overrides = parse_ripe_delegated("local|AT|ipv4|46.4.0.0|1||assigned\n");
+        let db = GeoIpDb::new_with_supplement(&overrides).unwrap();
+
+        assert_eq!(db.country_str(block.start), Some("AT"));
+        // The rest of the overridden block keeps its embedded classification
+        assert_eq!(db.lookup(block.end), embedded.lookup(block.end));
+        assert_eq!(
+            db.stats().total_v4_ranges,
+            embedded.stats().total_v4_ranges + 1
+        );
+        assert_eq!(db.diff(&embedded).changes.len(), 1);
+        assert_eq!(db.source_metadata(), embedded.source_metadata());
+    }
+
+    #[test]
+    fn test_from_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let geofeed = dir.path().join("geofeed.csv");
+        fs::write(
+            &geofeed,
+            "# prefix,country,region,city,postal\n\
+             10.0.0.0/24,PL,PL-MZ,Warsaw,\n\
+             10.0.1.0/24,,,,\n\
+             2001:db8::/48,se,,,\n",
+        )
+        .unwrap();
+        let delegated = dir.path().join("delegated.txt");
+        fs::write(
+            &delegated,
+            "arin|US|ipv4|10.0.0.0|65536|20250101|allocated\n\
+             arin||ipv4|10.1.0.0|65536||available\n",
+        )
+        .unwrap();
+        let delegated = FileSource::Delegated(delegated);
+        let (ranges, unallocated) = delegated.ranges_and_unallocated().unwrap();
+        assert_eq!(ranges, delegated.ranges().unwrap());
+        assert_eq!(unallocated, delegated.unallocated_ranges().unwrap());
+        assert_eq!((ranges.len(), unallocated.len()), (1, 1));
+
+        let overrides = parse_ripe_delegated("local|DE|ipv4|10.0.0.128|16|20250101|assigned\n");
+        let sources: [&dyn DataSource; 3] = [&overrides, &FileSource::Geofeed(geofeed), &delegated];
+        let db = GeoIpDb::from_sources(&sources, ConflictPolicy::PreferSourceOrder).unwrap();
+
+        let country = |ip: &str| db.country_str(ip.parse().unwrap()).map(str::to_string);
+        assert_eq!(country("10.0.0.127").as_deref(), Some("PL"));
+        assert_eq!(country("10.0.0.130").as_deref(), Some("DE"));
+        assert_eq!(country("10.0.0.200").as_deref(), Some("PL"));
+        // The geofeed leaves 10.0.1.0/24 undisclosed
+        assert_eq!(country("10.0.1.1").as_deref(), Some("US"));
+        assert_eq!(country("2001:db8::1").as_deref(), Some("SE"));
+        assert_eq!(db.stats().total_v4_ranges, 4);
+
+        let err = GeoIpDb::from_sources(&sources, ConflictPolicy::Reject)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("overlaps"), "{err}");
+
+        let (_, origins) =
+            GeoIpDb::from_sources_with_origins(&sources, ConflictPolicy::PreferSourceOrder)
+                .unwrap();
+        let origin = |ip: &str| origins.lookup(ip.parse().unwrap()).copied();
+        assert_eq!(origin("10.0.0.130"), Some(0));
+        assert_eq!(origin("10.0.0.1"), Some(1));
+        assert_eq!(origin("10.0.1.1"), Some(2));
+        assert_eq!(origins.len(), db.stats().total_v4_ranges + 1);
+
+        // A database round-trips as a source
+        let copy = GeoIpDb::from_sources(&[&db], ConflictPolicy::Reject).unwrap();
+        assert!(db.diff(&copy).is_empty());
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        let transferred = parse_ripe_delegated(
+            "ripencc|DE|ipv4|10.0.0.0|65536|20090101|allocated\n\
+             ripencc|AT|ipv4|10.1.0.0|256|20100101|allocated\n",
+        );
+        let newer = parse_ripe_delegated(
+            "arin|US|ipv4|10.0.0.0|256|20240301|allocated\n\
+             arin|CA|ipv4|10.1.0.0|65536|20230101|allocated\n",
+        );
+        let sources: [&dyn DataSource; 2] = [&transferred, &newer];
+        // Country and index of the winning source per address
+        let build = |policy| {
+            let (db, origins) = GeoIpDb::from_sources_with_origins(&sources, policy).unwrap();
+            ["10.0.0.1", "10.0.1.1", "10.1.0.1", "10.1.1.1"].map(|ip| {
+                let ip = ip.parse().unwrap();
+                let source = origins.lookup(ip).unwrap();
+                format!("{}{source}", db.country_str(ip).unwrap())
+            })
+        };
+
+        let order = build(ConflictPolicy::PreferSourceOrder);
+        assert_eq!(order, ["DE0", "DE0", "AT0", "CA1"]);
+        let newest = build(ConflictPolicy::PreferNewestDate);
+        assert_eq!(newest, ["US1", "DE0", "CA1", "CA1"]);
+        let specific = build(ConflictPolicy::PreferMoreSpecific);
+        assert_eq!(specific, ["US1", "DE0", "AT0", "CA1"]);
+    }
+}